@@ -8,6 +8,7 @@
 #![expect(clippy::missing_panics_doc)]
 use crate::{
     Position,
+    marker::{Marker, Markers},
     metric::{BufferMetrics, Metric},
 };
 use get_size2::GetSize;
@@ -38,6 +39,10 @@ pub struct Buffer {
     /// A mapping between byte and character positions. Doesn't account for the gap.
     metrics: BufferMetrics,
     new_gap_size: usize,
+    /// Positions that are adjusted on insertion and deletion.
+    markers: Markers,
+    /// Incremented every time the text is changed.
+    modified_tick: usize,
 }
 
 impl Debug for Buffer {
@@ -55,6 +60,8 @@ impl Debug for Buffer {
             .field("metrics", &self.metrics)
             .field("total_chars", &self.total.chars)
             .field("new_gap_size", &self.new_gap_size)
+            .field("markers", &self.markers)
+            .field("modified_tick", &self.modified_tick)
             .finish()
     }
 }
@@ -168,6 +175,8 @@ impl From<String> for Buffer {
             total,
             metrics,
             new_gap_size: calc_start_gap_size(len),
+            markers: Markers::default(),
+            modified_tick: 0,
         }
     }
 }
@@ -195,6 +204,8 @@ impl From<&str> for Buffer {
             total: metrics.len(),
             new_gap_size,
             metrics,
+            markers: Markers::default(),
+            modified_tick: 0,
         }
     }
 }
@@ -299,9 +310,22 @@ impl Buffer {
     /// Insert the text into the buffer at the cursor.
     #[inline]
     pub fn insert(&mut self, slice: &str) {
+        self.insert_inner(slice, false);
+    }
+
+    /// Insert the text into the buffer at the cursor. Unlike
+    /// [`insert`](Self::insert), all markers at the cursor end up after the
+    /// new text, regardless of their insertion type.
+    #[inline]
+    pub fn insert_before_markers(&mut self, slice: &str) {
+        self.insert_inner(slice, true);
+    }
+
+    fn insert_inner(&mut self, slice: &str, before_markers: bool) {
         if slice.is_empty() {
             return;
         }
+        let pos = self.cursor.chars;
         self.metrics.insert(self.to_abs_pos(self.cursor), MetricBuilder::new(slice));
         if self.gap_len() < slice.len() {
             self.grow(slice);
@@ -318,6 +342,8 @@ impl Buffer {
             self.cursor.chars += new.chars;
             self.total += new;
         }
+        self.markers.insert(pos, self.cursor.chars - pos, before_markers);
+        self.modified_tick += 1;
     }
 
     /// Delete backwards from the cursor `size` characters.
@@ -352,6 +378,8 @@ impl Buffer {
             let end = GapMetric { bytes: end_bytes, chars: end_chars };
             self.metrics.delete(self.to_abs_pos(beg), self.to_abs_pos(end));
             self.delete_byte_range(beg, end);
+            self.markers.delete(beg_chars, end_chars);
+            self.modified_tick += 1;
         }
     }
 
@@ -471,6 +499,40 @@ impl Buffer {
         }
     }
 
+    /// Create a new marker at the character position `pos`. If `advances` is
+    /// true, text inserted at the marker will be placed before it.
+    pub fn add_marker(&mut self, pos: usize, advances: bool) -> Marker {
+        self.markers.add(pos.min(self.total.chars), advances)
+    }
+
+    /// Move `marker` to the character position `pos`. The marker must belong
+    /// to this buffer.
+    pub fn set_marker(&mut self, marker: &Marker, pos: usize) {
+        self.markers.set(marker, pos.min(self.total.chars));
+    }
+
+    /// Remove `marker` from this buffer. The marker will no longer have a
+    /// position.
+    pub fn remove_marker(&mut self, marker: &Marker) {
+        self.markers.remove(marker);
+    }
+
+    /// The number of markers in this buffer.
+    pub fn marker_count(&self) -> usize {
+        self.markers.len()
+    }
+
+    /// Remove all markers from this buffer.
+    pub fn clear_markers(&mut self) {
+        self.markers.clear();
+    }
+
+    /// A counter that changes every time text is inserted or deleted. This
+    /// can be used to tell if data derived from the text is out of date.
+    pub fn modified_tick(&self) -> usize {
+        self.modified_tick
+    }
+
     /// Get the cursor position.
     #[inline]
     pub fn cursor(&self) -> Position {
@@ -863,6 +925,52 @@ mod test {
         assert_eq!(buffer, "");
     }
 
+    #[test]
+    fn markers() {
+        let mut buffer = Buffer::from("hello world");
+        let start = buffer.add_marker(6, false);
+        let end = buffer.add_marker(6, true);
+        let last = buffer.add_marker(20, false);
+        assert_eq!(last.position(), Some(11));
+        buffer.set_cursor(6);
+        buffer.insert("big ");
+        assert_eq!(buffer, "hello big world");
+        assert_eq!(start.position(), Some(6));
+        assert_eq!(end.position(), Some(10));
+        assert_eq!(last.position(), Some(15));
+        buffer.delete_range(4, 8);
+        assert_eq!(buffer, "hellg world");
+        assert_eq!(start.position(), Some(4));
+        assert_eq!(end.position(), Some(6));
+        assert_eq!(last.position(), Some(11));
+        buffer.set_marker(&start, 0);
+        buffer.set_cursor(0);
+        buffer.insert_char('x');
+        assert_eq!(start.position(), Some(0));
+        buffer.insert_before_markers("y");
+        assert_eq!(buffer, "xyhellg world");
+        assert_eq!(start.position(), Some(0));
+        buffer.set_cursor(0);
+        buffer.insert_before_markers("z");
+        assert_eq!(start.position(), Some(1));
+        buffer.remove_marker(&start);
+        assert_eq!(start.position(), None);
+    }
+
+    #[test]
+    fn modified_tick() {
+        let mut buffer = Buffer::from("hello");
+        let tick = buffer.modified_tick();
+        buffer.insert("");
+        buffer.delete_range(2, 2);
+        assert_eq!(buffer.modified_tick(), tick);
+        buffer.insert("x");
+        assert_ne!(buffer.modified_tick(), tick);
+        let tick = buffer.modified_tick();
+        buffer.delete_range(0, 1);
+        assert_ne!(buffer.modified_tick(), tick);
+    }
+
     #[test]
     fn test_delete() {
         let world = "world";
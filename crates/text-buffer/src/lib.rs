@@ -1,6 +1,8 @@
 mod buffer;
+mod marker;
 mod metric;
 mod position;
 
 pub use buffer::*;
+pub use marker::Marker;
 pub use position::*;
@@ -0,0 +1,181 @@
+//! Markers are positions in a buffer that are adjusted as text is inserted and
+//! deleted around them.
+use get_size2::GetSize;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Sentinel position for a marker that no longer points into any buffer.
+const DETACHED: usize = usize::MAX;
+
+#[derive(Debug)]
+struct MarkerCell {
+    /// Character position of the marker
+    chars: AtomicUsize,
+    /// If true, text inserted at the marker position goes before the marker.
+    advances: AtomicBool,
+}
+
+/// A handle to a marker created with [`Buffer::add_marker`](crate::Buffer::add_marker).
+///
+/// The position is shared with the buffer that created it, so it can be read
+/// without access to the buffer. All updates to the position happen through
+/// the buffer.
+#[derive(Debug, Clone)]
+pub struct Marker(Arc<MarkerCell>);
+
+impl Marker {
+    fn new(chars: usize, advances: bool) -> Self {
+        Self(Arc::new(MarkerCell {
+            chars: AtomicUsize::new(chars),
+            advances: AtomicBool::new(advances),
+        }))
+    }
+
+    /// The character position of the marker, or `None` if the marker has been
+    /// removed from its buffer.
+    pub fn position(&self) -> Option<usize> {
+        match self.0.chars.load(Ordering::Relaxed) {
+            DETACHED => None,
+            chars => Some(chars),
+        }
+    }
+
+    /// Whether the marker advances when text is inserted at its position.
+    pub fn insertion_type(&self) -> bool {
+        self.0.advances.load(Ordering::Relaxed)
+    }
+
+    /// Set whether the marker advances when text is inserted at its position.
+    pub fn set_insertion_type(&self, advances: bool) {
+        self.0.advances.store(advances, Ordering::Relaxed);
+    }
+
+    fn set(&self, chars: usize) {
+        self.0.chars.store(chars, Ordering::Relaxed);
+    }
+
+    /// True if the buffer holds the only handle to this marker, so the
+    /// position can never be read again.
+    fn is_released(&self) -> bool {
+        Arc::strong_count(&self.0) == 1
+    }
+}
+
+impl PartialEq for Marker {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Marker {}
+
+impl GetSize for Marker {
+    fn get_heap_size(&self) -> usize {
+        size_of::<MarkerCell>()
+    }
+}
+
+/// The set of markers that belong to a buffer. Markers whose handles have all
+/// been dropped are removed the next time the set is walked.
+#[derive(Debug, Default, GetSize)]
+pub(crate) struct Markers(Vec<Marker>);
+
+impl Markers {
+    pub(crate) fn add(&mut self, chars: usize, advances: bool) -> Marker {
+        if self.0.len() == self.0.capacity() {
+            self.0.retain(|x| !x.is_released());
+        }
+        let marker = Marker::new(chars, advances);
+        self.0.push(marker.clone());
+        marker
+    }
+
+    pub(crate) fn set(&mut self, marker: &Marker, chars: usize) {
+        assert!(self.0.contains(marker), "marker does not belong to this buffer");
+        marker.set(chars);
+    }
+
+    pub(crate) fn remove(&mut self, marker: &Marker) {
+        if let Some(idx) = self.0.iter().position(|x| x == marker) {
+            self.0.swap_remove(idx).set(DETACHED);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for marker in self.0.drain(..) {
+            marker.set(DETACHED);
+        }
+    }
+
+    /// Adjust the markers for `len` characters inserted at `pos`. If
+    /// `before_markers` is true, markers at `pos` advance regardless of their
+    /// insertion type.
+    pub(crate) fn insert(&mut self, pos: usize, len: usize, before_markers: bool) {
+        self.0.retain(|marker| {
+            let chars = marker.0.chars.load(Ordering::Relaxed);
+            if chars > pos || (chars == pos && (before_markers || marker.insertion_type())) {
+                marker.set(chars + len);
+            }
+            !marker.is_released()
+        });
+    }
+
+    /// Adjust the markers for the characters between `beg` and `end` being
+    /// deleted.
+    pub(crate) fn delete(&mut self, beg: usize, end: usize) {
+        self.0.retain(|marker| {
+            let chars = marker.0.chars.load(Ordering::Relaxed);
+            if chars > end {
+                marker.set(chars - (end - beg));
+            } else if chars > beg {
+                marker.set(beg);
+            }
+            !marker.is_released()
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn adjust() {
+        let mut markers = Markers::default();
+        let before = markers.add(2, false);
+        let after = markers.add(2, true);
+        let end = markers.add(6, false);
+        markers.insert(2, 3, false);
+        assert_eq!(before.position(), Some(2));
+        assert_eq!(after.position(), Some(5));
+        assert_eq!(end.position(), Some(9));
+        markers.delete(1, 4);
+        assert_eq!(before.position(), Some(1));
+        assert_eq!(after.position(), Some(2));
+        assert_eq!(end.position(), Some(6));
+        markers.remove(&after);
+        assert_eq!(after.position(), None);
+        markers.clear();
+        assert_eq!(end.position(), None);
+    }
+
+    #[test]
+    fn release() {
+        let mut markers = Markers::default();
+        let kept = markers.add(1, false);
+        for i in 0..10 {
+            markers.add(i, false);
+        }
+        assert!(markers.len() < 11);
+        markers.insert(0, 1, false);
+        assert_eq!(markers.len(), 1);
+        markers.remove(&kept);
+        assert_eq!(markers.len(), 0);
+    }
+}
@@ -1,10 +1,14 @@
 //! builtin lisp data structures.
+use crate::buffer::BUFFERS;
 use crate::core::cons::Cons;
-use crate::core::gc::Context;
+use crate::core::env::{Env, INTERNED_SYMBOLS, sym};
+use crate::core::gc::{Context, HeapKind, Rt};
 use crate::core::object::{
-    ByteFn, ByteString, FnArgs, Gc, IntoObject, LispVec, NIL, Object, RecordBuilder, Symbol,
+    ByteFn, ByteString, FnArgs, Gc, IntoObject, LispVec, NIL, Object, ObjectType, RecordBuilder,
+    Symbol,
 };
 use anyhow::{Result, ensure};
+use rune_core::macros::list;
 use rune_macros::{defun, elprop};
 
 #[defun]
@@ -91,12 +95,141 @@ fn make_symbol<'ob>(name: &str, cx: &'ob Context) -> Symbol<'ob> {
     Symbol::new_uninterned(name, cx)
 }
 
+/// Read `gc-cons-threshold` and `gc-cons-percentage` and pass them on to the
+/// collector.
+fn set_gc_tuning(env: &Rt<Env>, cx: &mut Context) {
+    let threshold = match env.var(sym::GC_CONS_THRESHOLD, cx).map(|x| x.untag()) {
+        Some(ObjectType::Int(x)) => usize::try_from(x).unwrap_or(0),
+        _ => Context::DEFAULT_CONS_THRESHOLD,
+    };
+    let percentage = match env.var(sym::GC_CONS_PERCENTAGE, cx).map(|x| x.untag()) {
+        Some(ObjectType::Float(x)) => **x,
+        Some(ObjectType::Int(x)) => x as f64,
+        _ => Context::DEFAULT_CONS_PERCENTAGE,
+    };
+    cx.set_gc_tuning(threshold, percentage);
+}
+
+/// Update `gcs-done` and `gc-elapsed` after a collection.
+fn set_gc_vars(env: &mut Rt<Env>, cx: &Context) {
+    let stats = cx.gc_stats();
+    env.set_var(sym::GCS_DONE, cx.add(stats.gcs_done)).unwrap();
+    env.set_var(sym::GC_ELAPSED, cx.add(stats.elapsed.as_secs_f64())).unwrap();
+}
+
+/// Run a collection if enough has been allocated since the last one, as set
+/// by `gc-cons-threshold` and `gc-cons-percentage`.
+pub(crate) fn maybe_garbage_collect(env: &mut Rt<Env>, cx: &mut Context) {
+    set_gc_tuning(env, cx);
+    let gcs_done = cx.gc_stats().gcs_done;
+    cx.garbage_collect(false);
+    if cx.gc_stats().gcs_done != gcs_done {
+        set_gc_vars(env, cx);
+    }
+}
+
+/// The size of an object slot.
+const WORD_SIZE: usize = size_of::<Object>();
+
+/// Objects that Emacs would count as vectors.
+const VECTOR_KINDS: [HeapKind; 7] = [
+    HeapKind::Vec,
+    HeapKind::Record,
+    HeapKind::HashTable,
+    HeapKind::ByteFn,
+    HeapKind::CharTable,
+    HeapKind::Marker,
+    HeapKind::Overlay,
+];
+
+/// Run a full collection and return a list of what is still live, in the
+/// same format as Emacs. Each element is (NAME SIZE USED FREE), where SIZE is
+/// the size of one object in bytes.
 #[defun]
-fn garbage_collect(cx: &mut Context) -> bool {
+fn garbage_collect<'ob>(env: &mut Rt<Env>, cx: &'ob mut Context) -> Object<'ob> {
+    set_gc_tuning(env, cx);
     cx.garbage_collect(true);
-    true
+    set_gc_vars(env, cx);
+
+    let live = &cx.gc_stats().live;
+    let strings = live.objects(HeapKind::String) + live.objects(HeapKind::ByteString);
+    let string_bytes = live.data(HeapKind::String) + live.data(HeapKind::ByteString);
+    let vectors: usize = VECTOR_KINDS.iter().map(|x| live.objects(*x)).sum();
+    let vector_slots = (live.data(HeapKind::Vec) + live.data(HeapKind::Record)) / WORD_SIZE;
+    // Interned symbols and buffers are not part of the collected heap
+    let symbols = live.objects(HeapKind::Symbol) + INTERNED_SYMBOLS.lock().unwrap().len();
+    let buffers = BUFFERS.lock().unwrap().len();
+    let conses = live.objects(HeapKind::Cons);
+    let floats = live.objects(HeapKind::Float);
+    let heap_kb = cx.heap_size() / 1024;
+    // The heap does not keep free lists, so nothing is ever counted as free
+    list![
+        list![sym::CONSES, HeapKind::Cons.size(), conses, 0; cx],
+        list![sym::SYMBOLS, HeapKind::Symbol.size(), symbols, 0; cx],
+        list![sym::STRINGS, HeapKind::String.size(), strings, 0; cx],
+        list![sym::STRING_BYTES, 1, string_bytes; cx],
+        list![sym::VECTORS, HeapKind::Vec.size(), vectors; cx],
+        list![sym::VECTOR_SLOTS, WORD_SIZE, vector_slots, 0; cx],
+        list![sym::FLOATS, HeapKind::Float.size(), floats, 0; cx],
+        list![sym::BUFFERS, HeapKind::Buffer.size(), buffers; cx],
+        list![sym::HEAP, 1024, heap_kb, 0; cx];
+        cx
+    ]
 }
 
+/// Return the number of objects that have been allocated, as a list of
+/// (CONSES FLOATS VECTOR-CELLS SYMBOLS STRING-CHARS INTERVALS STRINGS).
+#[defun]
+fn memory_use_counts<'ob>(cx: &'ob Context) -> Object<'ob> {
+    let allocated = &cx.allocated;
+    let vector_bytes: usize = VECTOR_KINDS.iter().map(|x| allocated.bytes(*x)).sum();
+    let strings = allocated.objects(HeapKind::String) + allocated.objects(HeapKind::ByteString);
+    let string_chars = allocated.data(HeapKind::String) + allocated.data(HeapKind::ByteString);
+    list![
+        allocated.objects(HeapKind::Cons),
+        allocated.objects(HeapKind::Float),
+        vector_bytes / WORD_SIZE,
+        allocated.objects(HeapKind::Symbol),
+        string_chars,
+        0,
+        strings;
+        cx
+    ]
+}
+
+/// Return a list of (TOTAL-RAM FREE-RAM TOTAL-SWAP FREE-SWAP) in KiB, or nil
+/// if the information is not available.
+#[defun]
+fn memory_info<'ob>(cx: &'ob Context) -> Object<'ob> {
+    let Ok(meminfo) = std::fs::read_to_string("/proc/meminfo") else {
+        return NIL;
+    };
+    let field = |name: &str| -> Option<i64> {
+        let line = meminfo.lines().find_map(|x| x.strip_prefix(name)?.strip_prefix(':'))?;
+        line.trim().trim_end_matches("kB").trim().parse().ok()
+    };
+    let fields = ["MemTotal", "MemAvailable", "SwapTotal", "SwapFree"].map(field);
+    match fields {
+        [Some(total), Some(free), Some(swap), Some(swap_free)] => {
+            list![total, free, swap, swap_free; cx]
+        }
+        _ => NIL,
+    }
+}
+
+defsym!(CONSES);
+defsym!(SYMBOLS);
+defsym!(STRINGS);
+defsym!(VECTORS);
+defsym!(VECTOR_SLOTS);
+defsym!(FLOATS);
+defsym!(BUFFERS);
+defsym!(HEAP);
+defvar!(GC_CONS_THRESHOLD, 800_000);
+defvar!(GC_CONS_PERCENTAGE, 0.1);
+defvar!(GC_ELAPSED, 0.0);
+defvar!(GCS_DONE, 0);
+
 #[cfg(test)]
 mod test {
     use rune_core::macros::root;
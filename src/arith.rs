@@ -1,16 +1,56 @@
 //! Arithmetic operators.
-use crate::core::object::{Gc, IntoObject, Number, NumberType, ObjectType};
+use crate::core::object::{
+    Gc, IntOrMarker, IntOrMarkerType, Integer, IntegerType, IntoObject, LispBigInt, MAX_FIXNUM,
+    MIN_FIXNUM, Number, NumberOrMarker, NumberOrMarkerType, NumberType, ObjectType,
+};
 use float_cmp::ApproxEq;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use rune_macros::defun;
 use std::cmp::PartialEq;
-use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::ops::{Add, BitAnd, BitOr, Div, Mul, Neg, Rem, Sub};
 
 /// Similar to the object type [NumberType], but contains a float instead of a
 /// reference to a float. This makes it easier to construct and mutate.
-#[derive(Debug, PartialEq, Copy, Clone)]
+///
+/// Integers that fit in an `i64` are always stored as `Int`, and only larger
+/// values use `Big`. When converted to an object the value is demoted to a
+/// fixnum if possible, or promoted to a bignum if it is outside of the fixnum
+/// range.
+#[derive(Debug, PartialEq, Clone)]
 pub(crate) enum NumberValue {
     Int(i64),
     Float(f64),
+    Big(BigInt),
+}
+
+impl From<BigInt> for NumberValue {
+    fn from(value: BigInt) -> Self {
+        match value.to_i64() {
+            Some(x) => NumberValue::Int(x),
+            None => NumberValue::Big(value),
+        }
+    }
+}
+
+impl NumberValue {
+    /// Convert to a float, rounding if needed.
+    pub(crate) fn to_f64(&self) -> f64 {
+        match self {
+            NumberValue::Int(x) => *x as f64,
+            NumberValue::Float(x) => *x,
+            NumberValue::Big(x) => x.to_f64().unwrap_or(f64::NAN),
+        }
+    }
+
+    /// Convert an integer value to a bignum. Must not be called on floats.
+    pub(crate) fn into_big(self) -> BigInt {
+        match self {
+            NumberValue::Int(x) => x.into(),
+            NumberValue::Big(x) => x,
+            NumberValue::Float(_) => unreachable!("float can't be converted to a bignum"),
+        }
+    }
 }
 
 impl Number<'_> {
@@ -18,33 +58,188 @@ impl Number<'_> {
         match self.untag() {
             NumberType::Int(x) => NumberValue::Int(x),
             NumberType::Float(x) => NumberValue::Float(**x),
+            NumberType::BigInt(x) => x.value().into(),
         }
     }
 }
 
+impl NumberOrMarker<'_> {
+    pub(crate) fn val(self) -> NumberValue {
+        match self.untag() {
+            NumberOrMarkerType::Int(x) => NumberValue::Int(x),
+            NumberOrMarkerType::Float(x) => NumberValue::Float(**x),
+            NumberOrMarkerType::BigInt(x) => x.value().into(),
+            NumberOrMarkerType::Marker(x) => NumberValue::Int(marker_position(x)),
+        }
+    }
+}
+
+impl IntOrMarker<'_> {
+    /// The value as an `i64`. Bignums are clamped, which is enough to put them
+    /// out of range of any buffer position.
+    pub(crate) fn int(self) -> i64 {
+        match self.untag() {
+            IntOrMarkerType::Int(x) => x,
+            IntOrMarkerType::BigInt(x) => bigint_clamp(x),
+            IntOrMarkerType::Marker(x) => marker_position(x),
+        }
+    }
+
+    fn big(self) -> BigInt {
+        match self.untag() {
+            IntOrMarkerType::BigInt(x) => x.value(),
+            _ => self.int().into(),
+        }
+    }
+}
+
+impl Integer<'_> {
+    pub(crate) fn big(self) -> BigInt {
+        match self.untag() {
+            IntegerType::Int(x) => x.into(),
+            IntegerType::BigInt(x) => x.value(),
+        }
+    }
+}
+
+fn bigint_clamp(x: &LispBigInt) -> i64 {
+    if x.is_negative() { i64::MIN } else { i64::MAX }
+}
+
+fn marker_position(marker: &crate::core::object::LispMarker) -> i64 {
+    // Markers that don't point anywhere are rejected when converting from an
+    // object.
+    marker.position().expect("marker does not point anywhere") as i64
+}
+
 impl IntoObject for NumberValue {
     type Out<'ob> = ObjectType<'ob>;
 
     fn into_obj<const C: bool>(self, block: &crate::core::gc::Block<C>) -> Gc<Self::Out<'_>> {
         match self {
-            NumberValue::Int(x) => x.into(),
+            NumberValue::Int(x) if (MIN_FIXNUM..=MAX_FIXNUM).contains(&x) => x.into(),
+            NumberValue::Int(x) => block.add(BigInt::from(x)),
             NumberValue::Float(x) => block.add(x),
+            NumberValue::Big(x) => block.add(x),
+        }
+    }
+}
+
+/// Parse an integer with an optional sign, returning a bignum if it does not
+/// fit in an i64.
+pub(crate) fn parse_integer(string: &str, radix: u32) -> Option<NumberValue> {
+    let digits = string.strip_prefix(['+', '-']).unwrap_or(string);
+    if digits.is_empty() || !digits.chars().all(|x| x.is_digit(radix)) {
+        return None;
+    }
+    match i64::from_str_radix(string, radix) {
+        Ok(x) => Some(NumberValue::Int(x)),
+        Err(_) => BigInt::parse_bytes(string.as_bytes(), radix).map(Into::into),
+    }
+}
+
+/// The bits of a NaN that hold its payload. This is everything in the
+/// significand except the quiet bit.
+pub(crate) const NAN_PAYLOAD_MASK: u64 = (1 << 51) - 1;
+
+/// Build a quiet NaN with the given payload, like `1.0e+NaN`.
+fn make_nan(payload: u64) -> f64 {
+    f64::from_bits(f64::NAN.to_bits() | (payload & NAN_PAYLOAD_MASK))
+}
+
+/// Parse a number in Emacs syntax that takes up the whole string.
+pub(crate) fn parse_number(string: &str) -> Option<NumberValue> {
+    match parse_number_prefix(string, 10) {
+        Some((num, len)) if len == string.len() => Some(num),
+        _ => None,
+    }
+}
+
+/// Parse the longest prefix of `string` that is a number in Emacs syntax,
+/// returning the number and the length of the prefix. This follows
+/// `string_to_number` in lread.c:
+///
+/// - Integers can have a trailing `.`, so `1.` is the integer 1.
+/// - A float needs digits after the `.` or an exponent, like `.5` or `1e3`.
+/// - `1.0e+INF` is infinity and `N.0e+NaN` is a NaN with payload `N`.
+/// - Floats are only recognized when `radix` is 10.
+pub(crate) fn parse_number_prefix(string: &str, radix: u32) -> Option<(NumberValue, usize)> {
+    let bytes = string.as_bytes();
+    let digits_end = |start: usize, radix| {
+        start + bytes[start..].iter().take_while(|x| char::from(**x).is_digit(radix)).count()
+    };
+    let negative = bytes.first() == Some(&b'-');
+    let start = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    let lead_end = digits_end(start, radix);
+    let has_lead = lead_end > start;
+    let mut end = lead_end;
+    let mut is_float = false;
+    let mut special = None;
+    if radix == 10 {
+        if bytes.get(end) == Some(&b'.') {
+            end += 1;
+        }
+        let trail_end = digits_end(end, 10);
+        let has_trail = trail_end > end;
+        end = trail_end;
+        let mut has_exp = false;
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let exp_start = end + 1 + usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+            let exp_end = digits_end(exp_start, 10);
+            if exp_end > exp_start {
+                has_exp = true;
+                end = exp_end;
+            } else if bytes[exp_start - 1] == b'+' {
+                // The payload is the leading integer, wrapped to fit
+                let payload = string[start..lead_end]
+                    .bytes()
+                    .fold(0u64, |acc, x| acc.wrapping_mul(10).wrapping_add(u64::from(x - b'0')));
+                let rest = &string[exp_start..];
+                if rest.starts_with("INF") {
+                    special = Some(f64::INFINITY);
+                } else if rest.starts_with("NaN") {
+                    special = Some(make_nan(payload));
+                }
+                if special.is_some() {
+                    has_exp = true;
+                    end = exp_start + 3;
+                }
+            }
         }
+        is_float = has_trail || (has_lead && has_exp);
+    }
+    if is_float {
+        let value = match special {
+            Some(x) => x,
+            None => string[start..end].parse().ok()?,
+        };
+        let value = if negative { -value } else { value };
+        Some((NumberValue::Float(value), end))
+    } else if has_lead {
+        Some((parse_integer(&string[..lead_end], radix)?, end))
+    } else {
+        None
     }
 }
 
+/// Apply an arithmetic operation. Integer operations that overflow are redone
+/// with bignums, and if either side is a float the result is a float.
 fn arith(
     cur: NumberValue,
     next: NumberValue,
-    int_fn: fn(i64, i64) -> i64,
+    int_fn: fn(i64, i64) -> Option<i64>,
+    big_fn: fn(BigInt, BigInt) -> BigInt,
     float_fn: fn(f64, f64) -> f64,
 ) -> NumberValue {
     use NumberValue as N;
     match (cur, next) {
-        (N::Int(l), N::Int(r)) => N::Int(int_fn(l, r)),
-        (N::Int(l), N::Float(r)) => N::Float(float_fn(l as f64, r)),
-        (N::Float(l), N::Int(r)) => N::Float(float_fn(l, r as f64)),
-        (N::Float(l), N::Float(r)) => N::Float(float_fn(l, r)),
+        (N::Int(l), N::Int(r)) => match int_fn(l, r) {
+            Some(x) => N::Int(x),
+            None => big_fn(l.into(), r.into()).into(),
+        },
+        (N::Float(l), r) => N::Float(float_fn(l, r.to_f64())),
+        (l, N::Float(r)) => N::Float(float_fn(l.to_f64(), r)),
+        (l, r) => big_fn(l.into_big(), r.into_big()).into(),
     }
 }
 
@@ -56,8 +251,12 @@ impl Neg for NumberValue {
     type Output = Self;
     fn neg(self) -> Self::Output {
         match self {
-            NumberValue::Int(x) => NumberValue::Int(-x),
+            NumberValue::Int(x) => match x.checked_neg() {
+                Some(x) => NumberValue::Int(x),
+                None => (-BigInt::from(x)).into(),
+            },
             NumberValue::Float(x) => NumberValue::Float(-x),
+            NumberValue::Big(x) => (-x).into(),
         }
     }
 }
@@ -65,78 +264,86 @@ impl Neg for NumberValue {
 impl Add for NumberValue {
     type Output = Self;
     fn add(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Add::add, Add::add)
+        arith(self, rhs, i64::checked_add, Add::add, Add::add)
     }
 }
 
 impl Sub for NumberValue {
     type Output = Self;
     fn sub(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Sub::sub, Sub::sub)
+        arith(self, rhs, i64::checked_sub, Sub::sub, Sub::sub)
     }
 }
 
 impl Mul for NumberValue {
     type Output = Self;
     fn mul(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Mul::mul, Mul::mul)
+        arith(self, rhs, i64::checked_mul, Mul::mul, Mul::mul)
     }
 }
 
 impl Div for NumberValue {
     type Output = Self;
     fn div(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Div::div, Div::div)
+        arith(self, rhs, i64::checked_div, Div::div, Div::div)
     }
 }
 
 impl Rem for NumberValue {
     type Output = Self;
     fn rem(self, rhs: Self) -> Self::Output {
-        arith(self, rhs, Rem::rem, Rem::rem)
+        arith(self, rhs, i64::checked_rem, Rem::rem, Rem::rem)
     }
 }
 
-impl PartialEq<i64> for Number<'_> {
+impl PartialEq<i64> for NumberOrMarker<'_> {
     fn eq(&self, other: &i64) -> bool {
         match self.val() {
             NumberValue::Int(num) => num == *other,
             NumberValue::Float(num) => num == *other as f64,
+            NumberValue::Big(_) => false,
         }
     }
 }
 
-impl PartialEq<f64> for Number<'_> {
+impl PartialEq<f64> for NumberOrMarker<'_> {
     fn eq(&self, other: &f64) -> bool {
         match self.val() {
-            NumberValue::Int(num) => num as f64 == *other,
             NumberValue::Float(num) => num.approx_eq(*other, (f64::EPSILON, 2)),
+            num => num.to_f64() == *other,
+        }
+    }
+}
+
+impl PartialEq<BigInt> for NumberOrMarker<'_> {
+    fn eq(&self, other: &BigInt) -> bool {
+        match self.val() {
+            NumberValue::Int(_) => false,
+            NumberValue::Float(num) => Some(num) == other.to_f64(),
+            NumberValue::Big(num) => num == *other,
         }
     }
 }
 
 impl PartialOrd for NumberValue {
     fn partial_cmp(&self, other: &NumberValue) -> Option<std::cmp::Ordering> {
-        match self {
-            NumberValue::Int(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(rhs),
-                NumberValue::Float(rhs) => (*lhs as f64).partial_cmp(rhs),
-            },
-            NumberValue::Float(lhs) => match other {
-                NumberValue::Int(rhs) => lhs.partial_cmp(&(*rhs as f64)),
-                NumberValue::Float(rhs) => lhs.partial_cmp(rhs),
-            },
+        use NumberValue as N;
+        match (self, other) {
+            (N::Int(lhs), N::Int(rhs)) => lhs.partial_cmp(rhs),
+            (N::Float(lhs), rhs) => lhs.partial_cmp(&rhs.to_f64()),
+            (lhs, N::Float(rhs)) => lhs.to_f64().partial_cmp(rhs),
+            (lhs, rhs) => lhs.clone().into_big().partial_cmp(&rhs.clone().into_big()),
         }
     }
 }
 
 #[defun(name = "+")]
-pub(crate) fn add(vars: &[Number]) -> NumberValue {
+pub(crate) fn add(vars: &[NumberOrMarker]) -> NumberValue {
     vars.iter().fold(NumberValue::Int(0), |acc, x| acc + x.val())
 }
 
 #[defun(name = "-")]
-pub(crate) fn sub(number: Option<Number>, numbers: &[Number]) -> NumberValue {
+pub(crate) fn sub(number: Option<NumberOrMarker>, numbers: &[NumberOrMarker]) -> NumberValue {
     match number {
         Some(num) => {
             let num = num.val();
@@ -151,108 +358,142 @@ pub(crate) fn sub(number: Option<Number>, numbers: &[Number]) -> NumberValue {
 }
 
 #[defun(name = "*")]
-pub(crate) fn mul(numbers: &[Number]) -> NumberValue {
+pub(crate) fn mul(numbers: &[NumberOrMarker]) -> NumberValue {
     numbers.iter().fold(NumberValue::Int(1), |acc, x| acc * x.val())
 }
 
 #[defun(name = "/")]
-pub(crate) fn div(number: Number, divisors: &[Number]) -> NumberValue {
+pub(crate) fn div(number: NumberOrMarker, divisors: &[NumberOrMarker]) -> NumberValue {
     divisors.iter().fold(number.val(), |acc, x| acc / x.val())
 }
 
 #[defun(name = "1+")]
-pub(crate) fn add_one(number: Number) -> NumberValue {
+pub(crate) fn add_one(number: NumberOrMarker) -> NumberValue {
     number.val() + NumberValue::Int(1)
 }
 
 #[defun(name = "1-")]
-pub(crate) fn sub_one(number: Number) -> NumberValue {
+pub(crate) fn sub_one(number: NumberOrMarker) -> NumberValue {
     number.val() - NumberValue::Int(1)
 }
 
 #[defun(name = "=")]
-pub(crate) fn num_eq(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn num_eq(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x == num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x == num),
+        NumberValue::Big(num) => numbers.iter().all(|&x| x == num),
     }
 }
 
 #[defun(name = "/=")]
-pub(crate) fn num_ne(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn num_ne(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     match number.val() {
         NumberValue::Int(num) => numbers.iter().all(|&x| x != num),
         NumberValue::Float(num) => numbers.iter().all(|&x| x != num),
+        NumberValue::Big(num) => numbers.iter().all(|&x| x != num),
     }
 }
 
-fn cmp(number: Number, numbers: &[Number], cmp: fn(&NumberValue, &NumberValue) -> bool) -> bool {
+fn cmp(
+    number: NumberOrMarker,
+    numbers: &[NumberOrMarker],
+    cmp: fn(&NumberValue, &NumberValue) -> bool,
+) -> bool {
     numbers
         .iter()
-        .try_fold(number.val(), |acc, &x| cmp(&acc, &x.val()).then_some(NumberValue::Int(0)))
+        .try_fold(number.val(), |acc, &x| {
+            let x = x.val();
+            cmp(&acc, &x).then_some(x)
+        })
         .is_some()
 }
 
 #[defun(name = "<")]
-pub(crate) fn less_than(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn less_than(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     cmp(number, numbers, NumberValue::lt)
 }
 
 #[defun(name = "<=")]
-pub(crate) fn less_than_or_eq(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn less_than_or_eq(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     cmp(number, numbers, NumberValue::le)
 }
 
 #[defun(name = ">")]
-pub(crate) fn greater_than(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn greater_than(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     cmp(number, numbers, NumberValue::gt)
 }
 
 #[defun(name = ">=")]
-pub(crate) fn greater_than_or_eq(number: Number, numbers: &[Number]) -> bool {
+pub(crate) fn greater_than_or_eq(number: NumberOrMarker, numbers: &[NumberOrMarker]) -> bool {
     cmp(number, numbers, NumberValue::ge)
 }
 
+/// Apply a bitwise operation to all the integers. Bignums are only used if one
+/// of the arguments is a bignum.
+fn bitwise(
+    ints: &[IntOrMarker],
+    init: i64,
+    int_fn: fn(i64, i64) -> i64,
+    big_fn: fn(BigInt, BigInt) -> BigInt,
+) -> NumberValue {
+    if ints.iter().any(|x| matches!(x.untag(), IntOrMarkerType::BigInt(_))) {
+        ints.iter().fold(BigInt::from(init), |acc, x| big_fn(acc, x.big())).into()
+    } else {
+        NumberValue::Int(ints.iter().fold(init, |acc, x| int_fn(acc, x.int())))
+    }
+}
+
 #[defun]
-pub(crate) fn logior(ints_or_markers: &[Gc<i64>]) -> i64 {
-    ints_or_markers.iter().fold(0, |acc, x| acc | x.untag())
+pub(crate) fn logior(ints_or_markers: &[IntOrMarker]) -> NumberValue {
+    bitwise(ints_or_markers, 0, BitOr::bitor, BitOr::bitor)
 }
 
 #[defun]
-fn logand(int_or_markers: &[Gc<i64>]) -> i64 {
-    int_or_markers.iter().fold(-1, |accum, x| accum & x.untag())
+fn logand(int_or_markers: &[IntOrMarker]) -> NumberValue {
+    bitwise(int_or_markers, -1, BitAnd::bitand, BitAnd::bitand)
 }
 
 #[defun(name = "mod")]
-pub(crate) fn modulo(x: Number, y: Number) -> NumberValue {
+pub(crate) fn modulo(x: NumberOrMarker, y: NumberOrMarker) -> NumberValue {
     x.val() % y.val()
 }
 
 #[defun(name = "%")]
-pub(crate) fn remainder(x: i64, y: i64) -> i64 {
-    // TODO: Handle markers
-    x % y
+pub(crate) fn remainder(x: IntOrMarker, y: IntOrMarker) -> NumberValue {
+    match (x.untag(), y.untag()) {
+        (IntOrMarkerType::BigInt(_), _) | (_, IntOrMarkerType::BigInt(_)) => {
+            (x.big() % y.big()).into()
+        }
+        _ => NumberValue::Int(x.int() % y.int()),
+    }
 }
 
 #[expect(clippy::trivially_copy_pass_by_ref)]
-fn max_val(x: NumberValue, y: &Number) -> NumberValue {
+fn max_val(x: NumberValue, y: &NumberOrMarker) -> NumberValue {
     let y = y.val();
     if x > y { x } else { y }
 }
 
 #[expect(clippy::trivially_copy_pass_by_ref)]
-fn min_val(x: NumberValue, y: &Number) -> NumberValue {
+fn min_val(x: NumberValue, y: &NumberOrMarker) -> NumberValue {
     let y = y.val();
     if x < y { x } else { y }
 }
 
 #[defun]
-pub(crate) fn max(number_or_marker: Number, number_or_markers: &[Number]) -> NumberValue {
+pub(crate) fn max(
+    number_or_marker: NumberOrMarker,
+    number_or_markers: &[NumberOrMarker],
+) -> NumberValue {
     number_or_markers.iter().fold(number_or_marker.val(), max_val)
 }
 
 #[defun]
-pub(crate) fn min(number_or_marker: Number, number_or_markers: &[Number]) -> NumberValue {
+pub(crate) fn min(
+    number_or_marker: NumberOrMarker,
+    number_or_markers: &[NumberOrMarker],
+) -> NumberValue {
     number_or_markers.iter().fold(number_or_marker.val(), min_val)
 }
 
@@ -325,18 +566,63 @@ mod test {
         let cx = &Context::new(roots);
         assert_eq!(
             max(cx.add_as(1.0), &[cx.add_as(2.1), cx.add_as(1.1), cx.add_as(1.0)]),
-            cx.add_as(2.1).val()
+            NumberValue::Float(2.1)
         );
         assert_eq!(
             min(cx.add_as(1.1), &[cx.add_as(1.0), cx.add_as(2.1), cx.add_as(1.0)]),
-            cx.add_as(1.0).val()
+            NumberValue::Float(1.0)
         );
     }
 
     #[test]
-    fn test_other() {
+    fn test_markers() {
+        use crate::core::{env::Env, object::Object};
+        use rune_core::macros::root;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello");
+        let marker = cx.add(crate::marker::make_marker());
+        assert!(NumberOrMarker::try_from(marker).is_err());
+        crate::marker::set_marker(marker.try_into().unwrap(), Some(3.into()), None, env, cx)
+            .unwrap();
+        let marker: NumberOrMarker = marker.try_into().unwrap();
+        assert_eq!(add(&[marker, 2.into()]), NumberValue::Int(5));
+        assert!(less_than(1.into(), &[marker, 4.into()]));
+        assert!(num_eq(marker, &[3.into()]));
+        let int: IntOrMarker = Object::from(marker).try_into().unwrap();
+        assert_eq!(logior(&[int, 4.into()]), NumberValue::Int(7));
+    }
+
+    #[test]
+    fn test_bignum() {
+        use crate::core::object::Object;
         let roots = &RootSet::default();
         let cx = &Context::new(roots);
-        assert_eq!(logand(&[258.into_obj(cx), 255.into_obj(cx)]), 2);
+        let next = add(&[MAX_FIXNUM.into(), 1.into()]);
+        assert_eq!(next, NumberValue::Int(MAX_FIXNUM + 1));
+        let big = cx.add(next);
+        assert!(matches!(big.untag(), ObjectType::BigInt(_)));
+        let big: NumberOrMarker = big.try_into().unwrap();
+        let fixnum = cx.add(sub(Some(big), &[1.into()]));
+        assert!(matches!(fixnum.untag(), ObjectType::Int(MAX_FIXNUM)));
+
+        let square = mul(&[big, big]);
+        assert_eq!(square, NumberValue::Big(BigInt::from(MAX_FIXNUM + 1).pow(2)));
+        let square: NumberOrMarker = cx.add(square).try_into().unwrap();
+        assert!(less_than(1.into(), &[big, square]));
+        assert!(!less_than(square, &[big]));
+        assert!(num_eq(square, &[cx.add(mul(&[big, big])).try_into().unwrap()]));
+        assert!(num_eq(big, &[cx.add_as((MAX_FIXNUM + 1) as f64)]));
+        assert_eq!(-NumberValue::Int(i64::MIN), NumberValue::Big(-BigInt::from(i64::MIN)));
+        assert_eq!(
+            logand(&[Object::from(big).try_into().unwrap(), (-1).into()]),
+            NumberValue::Int(MAX_FIXNUM + 1)
+        );
+    }
+
+    #[test]
+    fn test_other() {
+        assert_eq!(logand(&[258.into(), 255.into()]), NumberValue::Int(2));
     }
 }
@@ -1,10 +1,11 @@
 //! Buffer operations.
 use crate::{
     core::{
+        cons::Cons,
         env::{Env, INTERNED_SYMBOLS},
         error::{Type, TypeError},
         gc::{Context, Rt},
-        object::{Gc, LispBuffer, NIL, Object, ObjectType, OptionalFlag},
+        object::{Gc, LispBuffer, NIL, Object, ObjectType, OpenBuffer, OptionalFlag},
     },
     fns::slice_into_list,
 };
@@ -29,6 +30,11 @@ pub(crate) fn set_buffer<'ob>(
     Ok(cx.add(buffer))
 }
 
+#[defun]
+pub(crate) fn current_buffer<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Object<'ob> {
+    cx.add(env.current_buffer.get().lisp_buffer(cx))
+}
+
 fn resolve_buffer<'ob>(buffer_or_name: Object, cx: &'ob Context) -> Result<&'ob LispBuffer> {
     match buffer_or_name.untag() {
         ObjectType::Buffer(b) => Ok(b),
@@ -194,6 +200,29 @@ fn kill_buffer(buffer_or_name: Option<Object>, cx: &Context, env: &mut Rt<Env>)
     }
 }
 
+#[defun]
+fn buffer_local_variables<'ob>(
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    // Void local bindings are listed as just the symbol
+    let collect = |b: &OpenBuffer| -> Vec<Object> {
+        let locals = b.locals(cx);
+        locals
+            .map(|(sym, val)| match val {
+                Some(val) => Cons::new(sym, val, cx).into(),
+                None => sym.into(),
+            })
+            .collect()
+    };
+    let locals = match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), collect)?,
+        None => collect(env.current_buffer.get()),
+    };
+    Ok(slice_into_list(&locals, None, cx))
+}
+
 #[defun]
 fn buffer_base_buffer(_buffer: OptionalFlag) -> bool {
     // TODO: implement indirect buffers
@@ -1,21 +1,23 @@
 //! The main bytecode interpeter.
-use crate::core::cons::Cons;
-use crate::core::env::{CallFrame, Env, sym};
+use crate::core::env::{ArgSlice, CallFrame, Env};
+use crate::core::error::SignalError;
 use crate::core::gc::{Context, IntoRoot, Rt, Rto, Slot};
 use crate::core::object::{
     ByteFn, ByteString, FnArgs, Function, FunctionType, Gc, LispVec, NIL, Object, ObjectType,
     Symbol, WithLifetime,
 };
 use crate::data::LispError;
-use crate::eval::{ErrorType, EvalError, EvalResult};
+use crate::eval::{ErrorType, EvalError, EvalResult, before_unwind, handles_error};
 use anyhow::{Result, bail};
-use rune_core::macros::{bail_err, rebind, root};
+use rune_core::macros::{rebind, root};
 use rune_macros::{Trace, defun};
 
 mod opcode;
 
 /// An program counter. This is implemented as a bound checked range pointer.
-// TODO: If the GC moves the bytecode, this will be invalid. We need to fix this
+/// The op codes of a function live outside of the GC heap, so the counter stays
+/// valid when a collection moves the function. Everything else the function
+/// holds, like its constants, is read through the rooted `func` slot.
 #[derive(Clone, Debug)]
 struct ProgramCounter {
     /// Valid range for this instruction pointer.
@@ -133,8 +135,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
     fn varref(&mut self, idx: u16, cx: &'ob Context) -> Result<()> {
         let symbol = self.get_const(idx as usize, cx);
         if let ObjectType::Symbol(sym) = symbol.untag() {
-            let Some(var) = self.env.vars.get(sym) else { bail!("Void Variable: {sym}") };
-            let var = var.bind(cx);
+            let Some(var) = self.env.var(sym, cx) else { bail!(SignalError::void_variable(sym)) };
             self.env.stack.push(var);
             Ok(())
         } else {
@@ -235,18 +236,25 @@ impl<'ob> RootedVM<'_, '_, '_> {
             let prev_fn = self.func.bind(cx);
             self.set_current_frame(next_fn, 0);
             let frame_start = len - (arg_cnt + 1);
-            self.env
-                .stack
-                .push_bytecode_frame(frame_start, next_fn.depth, prev_fn, pc_offset);
+            let binding_depth = self.env.binding_depth();
+            self.env.stack.push_bytecode_frame(
+                frame_start,
+                next_fn.depth,
+                prev_fn,
+                pc_offset,
+                next_fn.into(),
+                binding_depth,
+            );
             self.prepare_lisp_args(next_fn, arg_cnt, &name, cx)?;
         } else {
             // Otherwise, call the function directly.
+            self.env.stack.set_frame_pc(self.pc.as_offset());
             let mut frame = CallFrame::new_with_args(self.env, arg_cnt);
             root!(func, cx);
             let result = func.call(&mut frame, Some(&name), cx)?;
             drop(frame); // removes the arguments from the stack
             self.env.stack.top().set(result);
-            cx.garbage_collect(false);
+            crate::alloc::maybe_garbage_collect(self.env, cx);
         }
         Ok(())
     }
@@ -258,33 +266,19 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 Err(e) => e,
             };
 
-            // we will fix this once we can handle different error types
-            #[expect(clippy::never_loop)]
+            self.env.stack.set_frame_pc(self.pc.as_offset());
+            let err = before_unwind(err, self.env, cx);
+            if matches!(err.error, ErrorType::Throw(_)) {
+                return Err(err);
+            }
+            let error = err.error_object(self.env, cx);
+            root!(error, cx);
             while let Some(handler) = self.handlers.bind_mut(cx).pop() {
-                match handler.condition.untag() {
-                    ObjectType::Symbol(sym::ERROR) => {}
-                    ObjectType::Cons(conditions) => {
-                        for condition in conditions {
-                            let condition = condition?;
-                            // TODO: Handle different error symbols
-                            if condition != sym::DEBUG && condition != sym::ERROR {
-                                bail_err!("non-error conditions {condition} not yet supported")
-                            }
-                        }
-                    }
-                    x => bail_err!("Invalid condition handler: {x}"),
+                self.env.condition_stack.pop();
+                if !handles_error(*handler.condition, error.bind(cx), self.env, cx)? {
+                    continue;
                 }
-
-                let error = if let EvalError { error: ErrorType::Signal(id), .. } = err {
-                    let Some((sym, data)) = self.env.get_exception(id) else {
-                        unreachable!("Exception not found")
-                    };
-                    Cons::new(sym, data, cx)
-                } else {
-                    // TODO: Need to remove the anyhow branch once
-                    // full errors are implemented
-                    Cons::new(sym::ERROR, format!("{err}"), cx)
-                };
+                let error = error.bind(cx);
                 self.unwind(handler.stack_frame, cx);
                 self.env.stack.truncate(handler.stack_size);
                 self.env.stack.push(Object::from(error));
@@ -298,7 +292,9 @@ impl<'ob> RootedVM<'_, '_, '_> {
     #[expect(clippy::too_many_lines)]
     /// The main bytecode execution loop.
     fn execute_bytecode(&mut self, cx: &'ob mut Context) -> EvalResult<'ob> {
-        use crate::{alloc, arith, data, fns};
+        use crate::{
+            alloc, arith, buffer, casefiddle, cmds, data, editfns, fns, indent, marker, syntax,
+        };
         use opcode::OpCode as op;
         loop {
             let op = match self.pc.next().try_into() {
@@ -410,6 +406,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                 }
                 op::PopHandler => {
                     self.handlers.pop();
+                    self.env.condition_stack.pop();
                 }
                 op::PushCondtionCase => {
                     // pop before getting stack size
@@ -421,6 +418,7 @@ impl<'ob> RootedVM<'_, '_, '_> {
                         condition: Slot::new(condition),
                     };
                     self.handlers.push(handler);
+                    self.env.condition_stack.push(condition);
                 }
                 op::PushCatch => todo!("PushCatch bytecode"),
                 op::Nth => {
@@ -600,34 +598,136 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     let args = &[top.bind_as(cx)?, arg1.try_into()?];
                     top.set(cx.add(arith::mul(args)));
                 }
-                op::Point => todo!("Point bytecode"),
-                op::GotoChar => todo!("GotoChar bytecode"),
-                op::Insert => todo!("Insert bytecode"),
-                op::PointMax => todo!("PointMax bytecode"),
-                op::PointMin => todo!("PointMin bytecode"),
-                op::CharAfter => todo!("CharAfter bytecode"),
-                op::FollowingChar => todo!("FollowingChar bytecode"),
-                op::PrecedingChar => todo!("PrecedingChar bytecode"),
-                op::CurrentColumn => todo!("CurrentColumn bytecode"),
-                op::IndentTo => todo!("IndentTo bytecode"),
-                op::EndOfLineP => todo!("EndOfLineP bytecode"),
-                op::EndOfBufferP => todo!("EndOfBufferP bytecode"),
-                op::BeginningOfLineP => todo!("BeginningOfLineP bytecode"),
-                op::BeginningOfBufferP => todo!("BeginningOfBufferP bytecode"),
-                op::CurrentBuffer => todo!("CurrentBuffer bytecode"),
-                op::SetBuffer => todo!("SetBuffer bytecode"),
-                op::SaveCurrentBuffer1 => todo!("SaveCurrentBuffer1 bytecode"),
-                op::ForwardChar => todo!("ForwardChar bytecode"),
-                op::ForwardWord => todo!("ForwardWord bytecode"),
-                op::SkipCharsForward => todo!("SkipCharsForward bytecode"),
-                op::SkipCharsBackward => todo!("SkipCharsBackward bytecode"),
-                op::ForwardLine => todo!("ForwardLine bytecode"),
-                op::CharSyntax => todo!("CharSyntax bytecode"),
-                op::BufferSubstring => todo!("BufferSubstring bytecode"),
-                op::DeleteRegion => todo!("DeleteRegion bytecode"),
-                op::NarrowToRegion => todo!("NarrowToRegion bytecode"),
-                op::Widen => todo!("Widen bytecode"),
-                op::EndOfLine => todo!("EndOfLine bytecode"),
+                op::Point => {
+                    let point = editfns::point(self.env);
+                    self.env.stack.push(cx.add(point));
+                }
+                op::GotoChar => {
+                    let top = self.env.stack.top().bind_as(cx)?;
+                    let pos = editfns::goto_char(top, self.env);
+                    self.env.stack.top().set::<Object>(pos.into());
+                }
+                op::Insert => {
+                    let mut frame = CallFrame::new_with_args(self.env, 1);
+                    editfns::insert(ArgSlice::new(1), &mut frame, cx)?;
+                    drop(frame);
+                    self.env.stack.push(NIL);
+                }
+                op::PointMax => {
+                    let max = editfns::point_max(self.env);
+                    self.env.stack.push(cx.add(max));
+                }
+                op::PointMin => {
+                    let min = editfns::point_min(self.env);
+                    self.env.stack.push(cx.add(min));
+                }
+                op::CharAfter => {
+                    let pos = Gc::try_from_option(self.env.stack.top().bind(cx))?;
+                    let chr = editfns::char_after(pos, self.env);
+                    self.env.stack.top().set(cx.add(chr));
+                }
+                op::FollowingChar => {
+                    let chr = editfns::following_char(self.env);
+                    self.env.stack.push(cx.add(chr));
+                }
+                op::PrecedingChar => {
+                    let chr = editfns::preceding_char(self.env);
+                    self.env.stack.push(cx.add(chr));
+                }
+                op::CurrentColumn => {
+                    let col = indent::current_column(self.env, cx);
+                    self.env.stack.push(cx.add(col));
+                }
+                op::IndentTo => {
+                    let column = self.env.stack.top().bind_as(cx)?;
+                    let col = indent::indent_to(column, None, self.env, cx);
+                    self.env.stack.top().set(cx.add(col));
+                }
+                op::EndOfLineP => {
+                    let eolp = editfns::eolp(self.env);
+                    self.env.stack.push(eolp);
+                }
+                op::EndOfBufferP => {
+                    let eobp = editfns::eobp(self.env);
+                    self.env.stack.push(eobp);
+                }
+                op::BeginningOfLineP => {
+                    let bolp = editfns::bolp(self.env);
+                    self.env.stack.push(bolp);
+                }
+                op::BeginningOfBufferP => {
+                    let bobp = editfns::bobp(self.env);
+                    self.env.stack.push(bobp);
+                }
+                op::CurrentBuffer => {
+                    let buffer = buffer::current_buffer(self.env, cx);
+                    self.env.stack.push(buffer);
+                }
+                op::SetBuffer => {
+                    let top = self.env.stack.top().bind(cx);
+                    let buffer = buffer::set_buffer(top, self.env, cx)?;
+                    self.env.stack.top().set(buffer);
+                }
+                op::SaveCurrentBuffer1 => self.env.save_current_buffer(),
+                op::ForwardChar => {
+                    let n = Gc::try_from_option(self.env.stack.top().bind(cx))?;
+                    cmds::forward_char(n, self.env)?;
+                    self.env.stack.top().set(NIL);
+                }
+                op::ForwardWord => {
+                    let n = Gc::try_from_option(self.env.stack.top().bind(cx))?;
+                    let found = syntax::forward_word(n, self.env, cx);
+                    self.env.stack.top().set(found);
+                }
+                op::SkipCharsForward => {
+                    let lim = Gc::try_from_option(self.env.stack.pop(cx))?;
+                    let string = self.env.stack.top().bind(cx);
+                    let moved = syntax::skip_chars_forward(string.try_into()?, lim, self.env)?;
+                    self.env.stack.top().set(cx.add(moved));
+                }
+                op::SkipCharsBackward => {
+                    let lim = Gc::try_from_option(self.env.stack.pop(cx))?;
+                    let string = self.env.stack.top().bind(cx);
+                    let moved = syntax::skip_chars_backward(string.try_into()?, lim, self.env)?;
+                    self.env.stack.top().set(cx.add(moved));
+                }
+                op::ForwardLine => {
+                    let n = Gc::try_from_option(self.env.stack.top().bind(cx))?;
+                    let shortage = cmds::forward_line(n, self.env);
+                    self.env.stack.top().set(cx.add(shortage));
+                }
+                op::CharSyntax => {
+                    let chr = self.env.stack.top().bind(cx).try_into()?;
+                    let class = syntax::char_syntax(chr, self.env, cx);
+                    self.env.stack.top().set(cx.add(class));
+                }
+                op::BufferSubstring => {
+                    let end = self.env.stack.pop(cx).try_into()?;
+                    let start = self.env.stack.top().bind_as(cx)?;
+                    let string = editfns::buffer_substring(start, end, self.env, cx)?;
+                    self.env.stack.top().set(string);
+                }
+                op::DeleteRegion => {
+                    let end = self.env.stack.pop(cx).try_into()?;
+                    let start = self.env.stack.top().bind_as(cx)?;
+                    editfns::delete_region(start, end, self.env, cx)?;
+                    self.env.stack.top().set(NIL);
+                }
+                op::NarrowToRegion => {
+                    let end = self.env.stack.pop(cx).try_into()?;
+                    let start = self.env.stack.top().bind_as(cx)?;
+                    editfns::narrow_to_region(start, end, self.env)?;
+                    self.env.stack.top().set(NIL);
+                }
+                op::Widen => {
+                    editfns::widen(self.env);
+                    self.env.stack.push(NIL);
+                }
+                op::EndOfLine => {
+                    let n = Gc::try_from_option(self.env.stack.top().bind(cx))?;
+                    cmds::end_of_line(n, self.env);
+                    self.env.stack.top().set(NIL);
+                }
                 op::ConstantN2 => {
                     let idx = self.pc.arg2();
                     let cnst = self.get_const(idx.into(), cx);
@@ -699,13 +799,28 @@ impl<'ob> RootedVM<'_, '_, '_> {
                     self.env.stack.push(top);
                 }
                 op::SaveExcursion => todo!("SaveExcursion bytecode"),
-                op::SaveRestriction => todo!("SaveRestriction bytecode"),
+                op::SaveRestriction => self.env.save_restriction(),
                 op::UnwindProtect => todo!("UnwindProtect bytecode"),
-                op::SetMarker => todo!("SetMarker bytecode"),
+                op::SetMarker => {
+                    let buffer = Gc::try_from_option(self.env.stack.pop(cx))?;
+                    let position = Gc::try_from_option(self.env.stack.pop(cx))?;
+                    let top = self.env.stack.top();
+                    let marker = top.bind(cx).try_into()?;
+                    let marker = marker::set_marker(marker, position, buffer, self.env, cx)?;
+                    self.env.stack.top().set(cx.add(marker));
+                }
                 op::MatchBeginning => todo!("MatchBeginning bytecode"),
                 op::MatchEnd => todo!("MatchEnd bytecode"),
-                op::Upcase => todo!("Upcase bytecode"),
-                op::Downcase => todo!("Downcase bytecode"),
+                op::Upcase => {
+                    let top = self.env.stack.top().bind(cx).try_into()?;
+                    let cased = casefiddle::upcase(top, self.env, cx);
+                    self.env.stack.top().set(cased);
+                }
+                op::Downcase => {
+                    let top = self.env.stack.top().bind(cx).try_into()?;
+                    let cased = casefiddle::downcase(top, self.env, cx);
+                    self.env.stack.top().set(cased);
+                }
                 op::StringEqlSign => todo!("StringEqlSign bytecode"),
                 op::StringLessThan => todo!("StringLessThan bytecode"),
                 op::Equal => {
@@ -924,12 +1039,25 @@ pub(crate) fn call<'ob>(
     };
     root!(vm, cx);
     vm.prepare_lisp_args(func, arg_cnt, name, cx)?;
-    vm.run(cx).map_err(|e| e.add_trace(name, vm.env.stack.current_args()))
+    let depth = vm.env.condition_stack.len();
+    let frame = vm.env.stack.current_frame();
+    let result = vm.run(cx);
+    if result.is_err() {
+        // remove the frames of any bytecode functions that the error escaped
+        // from, along with their active handlers
+        if vm.env.stack.current_frame() > frame {
+            vm.env.stack.unwind_frames(frame);
+        }
+        vm.env.condition_stack.truncate(depth);
+    }
+    result
 }
 
 #[cfg(test)]
 mod test {
     use crate::core::{
+        cons::Cons,
+        env::sym,
         gc::RootSet,
         object::{HashTable, IntoObject},
     };
@@ -1264,4 +1392,356 @@ mod test {
         root!(inner, cx);
         check_bytecode!(outer, [inner], 7, cx);
     }
+
+    #[test]
+    fn test_handler_conditions() {
+        use OpCode as O;
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        let outer_err = Cons::new1(sym::WRONG_TYPE_ARGUMENT, cx);
+        let inner_err = Cons::new1(sym::VOID_VARIABLE, cx);
+
+        // (lambda (x)
+        //   (condition-case err
+        //       (condition-case nil
+        //           (car x)
+        //         (void-variable 1))
+        //     (wrong-type-argument err)))
+        make_bytecode!(
+            bytecode,
+            257,
+            [
+                O::Constant0,
+                O::PushCondtionCase,
+                0x11,
+                0x0,
+                O::Constant1,
+                O::PushCondtionCase,
+                0x0E,
+                0x0,
+                O::Constant2,
+                O::StackRef1,
+                O::Call1,
+                O::PopHandler,
+                O::PopHandler,
+                O::Return,
+                O::Discard,
+                O::Constant3,
+                O::Return,
+                O::Return
+            ],
+            [outer_err, inner_err, sym::CAR, 1],
+            cx
+        );
+        let list = list![sym::WRONG_TYPE_ARGUMENT, sym::LISTP, 2; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [2], list, cx);
+    }
+
+    #[test]
+    fn test_collect_during_call() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        // (lambda (x)
+        //   (while (< 0 x)
+        //     (garbage-collect)
+        //     (setq x (1- x)))
+        //   "done")
+        make_bytecode!(
+            bytecode,
+            257,
+            [
+                Constant0, StackRef1, LessThan, GotoIfNil, 0x0D, 0x00, Constant1, Call0, Discard,
+                Sub1, Goto, 0x00, 0x00, Constant2, Return
+            ],
+            [0, sym::GARBAGE_COLLECT, "done"],
+            cx
+        );
+        check_bytecode!(bytecode, [3], "done", cx);
+    }
+
+    #[test]
+    fn test_buffer_position() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+
+        // (lambda ()
+        //   (insert "hello\nworld")
+        //   (goto-char 3)
+        //   (list (point) (char-after) (preceding-char) (point-max)))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                Constant1,
+                GotoChar,
+                Discard,
+                Point,
+                Constant2,
+                CharAfter,
+                PrecedingChar,
+                PointMax,
+                List4,
+                Return
+            ],
+            ["hello\nworld", 3, NIL],
+            cx
+        );
+        let list = list![3, 'l', 'e', 12; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+
+        // (lambda ()
+        //   (insert "foo bar\nbaz")
+        //   (list (eobp) (forward-line -1) (bobp) (bolp)))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                EndOfBufferP,
+                Constant1,
+                ForwardLine,
+                BeginningOfBufferP,
+                BeginningOfLineP,
+                List4,
+                Return
+            ],
+            ["foo bar\nbaz", -1],
+            cx
+        );
+        let list = list![true, 0, true, true; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+    }
+
+    #[test]
+    fn test_buffer_motion() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+
+        // (lambda ()
+        //   (insert "foo bar\nbaz")
+        //   (goto-char 1)
+        //   (list (forward-word 1) (point) (progn (end-of-line nil) (eolp)) (current-column)))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                Constant1,
+                GotoChar,
+                Discard,
+                Constant1,
+                ForwardWord,
+                Point,
+                Constant2,
+                EndOfLine,
+                Discard,
+                EndOfLineP,
+                CurrentColumn,
+                List4,
+                Return
+            ],
+            ["foo bar\nbaz", 1, NIL],
+            cx
+        );
+        let list = list![true, 4, true, 7; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+
+        // (lambda ()
+        //   (insert "abc def")
+        //   (goto-char 1)
+        //   (list (skip-chars-forward "a-z")
+        //         (skip-chars-backward "a-c" nil)
+        //         (forward-char 2)
+        //         (char-syntax (following-char))))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                Constant1,
+                GotoChar,
+                Discard,
+                Constant2,
+                Constant3,
+                SkipCharsForward,
+                Constant4,
+                Constant3,
+                SkipCharsBackward,
+                Constant5,
+                ForwardChar,
+                FollowingChar,
+                CharSyntax,
+                List4,
+                Return
+            ],
+            ["abc def", 1, "a-z", NIL, "a-c", 2],
+            cx
+        );
+        let list = list![3, -3, NIL, 'w'; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+    }
+
+    #[test]
+    fn test_buffer_editing() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+
+        // (lambda ()
+        //   (insert "hello world")
+        //   (delete-region 1 7)
+        //   (indent-to 8)
+        //   (list (buffer-substring 1 (point-max))
+        //         (point-min)
+        //         (narrow-to-region 1 2)
+        //         (widen)))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                Constant1,
+                Constant2,
+                DeleteRegion,
+                Discard,
+                Constant3,
+                IndentTo,
+                Discard,
+                Constant1,
+                PointMax,
+                BufferSubstring,
+                PointMin,
+                Constant1,
+                Constant4,
+                NarrowToRegion,
+                Widen,
+                List4,
+                Return
+            ],
+            ["hello world", 1, 7, 8, 2],
+            cx
+        );
+        let list = list!["world   ", 1, NIL, NIL; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+    }
+
+    #[test]
+    fn test_set_buffer() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let name = cx.add("test_bytecode_set_buffer");
+        let buffer = crate::buffer::get_buffer_create(name, None, cx).unwrap();
+        root!(buffer, cx);
+
+        // (lambda (buf)
+        //   (insert "a")
+        //   (save-current-buffer
+        //     (set-buffer buf)
+        //     (insert "bc"))
+        //   (list (buffer-substring 1 (point-max))
+        //         (eq (current-buffer) buf)
+        //         (save-current-buffer
+        //           (set-buffer buf)
+        //           (buffer-substring 1 (point-max)))))
+        make_bytecode!(
+            bytecode,
+            257,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                SaveCurrentBuffer1,
+                Duplicate,
+                SetBuffer,
+                Discard,
+                Constant1,
+                Insert,
+                Unbind1,
+                Discard,
+                Constant2,
+                PointMax,
+                BufferSubstring,
+                CurrentBuffer,
+                StackRef2,
+                Eq,
+                SaveCurrentBuffer1,
+                StackRef2,
+                SetBuffer,
+                Discard,
+                Constant2,
+                PointMax,
+                BufferSubstring,
+                Unbind1,
+                List3,
+                Return
+            ],
+            ["a", "bc", 1],
+            cx
+        );
+        let list = list!["a", NIL, "bc"; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [buffer], list, cx);
+    }
+
+    #[test]
+    fn test_save_restriction() {
+        use OpCode::*;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+
+        // (lambda ()
+        //   (insert "hello world")
+        //   (list (save-restriction
+        //           (narrow-to-region 3 8)
+        //           (point-max))
+        //         (point-max)))
+        make_bytecode!(
+            bytecode,
+            0,
+            [
+                Constant0,
+                Insert,
+                Discard,
+                SaveRestriction,
+                Constant1,
+                Constant2,
+                NarrowToRegion,
+                Discard,
+                PointMax,
+                Unbind1,
+                PointMax,
+                List2,
+                Return
+            ],
+            ["hello world", 3, 8],
+            cx
+        );
+        let list = list![8, 12; cx];
+        root!(list, cx);
+        check_bytecode!(bytecode, [], list, cx);
+    }
 }
@@ -1,278 +1,681 @@
-//! String and character case conversion.
-use std::ops::Range;
-
+//! String, character and buffer case conversion.
 use crate::core::{
-    gc::Rt,
-    object::{NIL, Object},
+    env::{Env, sym},
+    error::SignalError,
+    gc::{Context, Rt},
+    object::{
+        CharTable, CharTableInner, IntOrMarker, IntoObject, NIL, Object, ObjectType, OptionalFlag,
+    },
 };
+use crate::data::LispError;
 use crate::fns::StringOrChar;
-use crate::{Context, Env};
+use crate::syntax::{self, SyntaxClass, SyntaxTable};
+use crate::textprops::verify_modification;
+use anyhow::{Result, bail};
+use rune_core::macros::list;
 use rune_macros::defun;
-use text_buffer::Buffer as TextBuffer;
+
+defsym!(CASE_TABLE);
 
 #[defun]
-fn capitalize<'ob>(string_or_char: StringOrChar<'ob>, cx: &'ob Context<'ob>) -> Object<'ob> {
-    match string_or_char {
-        StringOrChar::String(s) => cx.add(casify_string(s, CaseMode::Capitalize)),
-        StringOrChar::Char(c) => cx.add(casify_char(c, char::to_uppercase)),
-    }
+pub(crate) fn capitalize<'ob>(
+    string_or_char: StringOrChar<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    casify_object(string_or_char, CaseMode::Capitalize, env, cx)
 }
 
 #[defun]
-fn upcase<'ob>(string_or_char: StringOrChar<'ob>, cx: &'ob Context<'ob>) -> Object<'ob> {
-    match string_or_char {
-        StringOrChar::String(s) => cx.add(casify_string(s, CaseMode::Upcase)),
-        StringOrChar::Char(c) => cx.add(casify_char(c, char::to_uppercase)),
-    }
+pub(crate) fn upcase<'ob>(
+    string_or_char: StringOrChar<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    casify_object(string_or_char, CaseMode::Upcase, env, cx)
 }
 
 #[defun]
-fn downcase<'ob>(string_or_char: StringOrChar<'ob>, cx: &'ob Context<'ob>) -> Object<'ob> {
-    match string_or_char {
-        StringOrChar::String(s) => cx.add(casify_string(s, CaseMode::Downcase)),
-        StringOrChar::Char(c) => cx.add(casify_char(c, char::to_lowercase)),
-    }
+pub(crate) fn downcase<'ob>(
+    string_or_char: StringOrChar<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    casify_object(string_or_char, CaseMode::Downcase, env, cx)
 }
 
 #[defun]
-fn upcase_initials<'ob>(string_or_char: StringOrChar<'ob>, cx: &'ob Context<'ob>) -> Object<'ob> {
+fn upcase_initials<'ob>(
+    string_or_char: StringOrChar<'ob>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    casify_object(string_or_char, CaseMode::UpcaseInitials, env, cx)
+}
+
+fn casify_object<'ob>(
+    string_or_char: StringOrChar<'ob>,
+    mode: CaseMode,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let casing = Casing::current(env, cx);
     match string_or_char {
-        StringOrChar::String(s) => cx.add(casify_string(s, CaseMode::UpcaseInitials)),
-        StringOrChar::Char(c) => cx.add(casify_char(c, char::to_uppercase)),
+        StringOrChar::String(s) => cx.add(casing.string(s, mode)),
+        StringOrChar::Char(c) => cx.add(casing.char(c, mode)),
     }
 }
 
 #[defun]
-fn upcase_word<'ob>(offset: i64, env: &mut Rt<Env>) -> Object<'ob> {
-    let text_buf = &mut env.current_buffer.get_mut().text;
-    let forward_upcase = offset >= 0;
-    let range = if forward_upcase {
-        find_forward_word(text_buf)
-    } else {
-        find_backward_word(text_buf)
-    };
-    let (start, end) = (range.start, range.end);
-    let (a, b) = text_buf.slice(range);
-    let upcase = |x| casify_string(x, CaseMode::Upcase);
-    let upcased = upcase(a) + &upcase(b);
-    text_buf.delete_range(start, end);
-    text_buf.insert(&upcased);
-    NIL
+fn upcase_word(arg: i64, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    casify_word(arg, CaseMode::Upcase, env, cx)
 }
 
 #[defun]
-fn downcase_word<'ob>(offset: i64, env: &mut Rt<Env>) -> Object<'ob> {
-    let text_buf = &mut env.current_buffer.get_mut().text;
-    let forward_downcase = offset >= 0;
-    let range = if forward_downcase {
-        find_forward_word(text_buf)
-    } else {
-        find_backward_word(text_buf)
-    };
-    let (start, end) = (range.start, range.end);
-    let (a, b) = text_buf.slice(range);
-    let downcase = |x| casify_string(x, CaseMode::Downcase);
-    let downcased = downcase(a) + &downcase(b);
-    text_buf.delete_range(start, end);
-    text_buf.insert(&downcased);
-    NIL
+fn downcase_word(arg: i64, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    casify_word(arg, CaseMode::Downcase, env, cx)
 }
 
 #[defun]
-fn capitalize_word<'ob>(offset: i64, env: &mut Rt<Env>) -> Object<'ob> {
-    let text_buf = &mut env.current_buffer.get_mut().text;
-    let forward_capitalize = offset >= 0;
-    let range = if forward_capitalize {
-        find_forward_word(text_buf)
-    } else {
-        find_backward_word(text_buf)
-    };
-    let (start, end) = (range.start, range.end);
-    let (a, b) = text_buf.slice(range);
-    let capitalize = |x| casify_string(x, CaseMode::Capitalize);
-    let capitalized = capitalize(a) + &capitalize(b);
-    text_buf.delete_range(start, end);
-    text_buf.insert(&capitalized);
-    NIL
-}
-
-fn casify_string(s: &str, mode: CaseMode) -> String {
-    let mut out = String::with_capacity(s.len());
-
-    for word in s.split_inclusive(|c: char| precedes_capitalization(c)) {
-        let mut chars = word.chars();
-        if let Some(c) = chars.next() {
-            match mode {
-                CaseMode::Downcase => out.extend(c.to_lowercase()),
-                CaseMode::Upcase | CaseMode::Capitalize | CaseMode::UpcaseInitials => {
-                    out.extend(c.to_uppercase())
-                }
-            }
-        }
-        for c in chars {
-            match mode {
-                CaseMode::Upcase => out.extend(c.to_uppercase()),
-                CaseMode::Downcase | CaseMode::Capitalize => out.extend(c.to_lowercase()),
-                CaseMode::UpcaseInitials => out.push(c),
-            }
-        }
+fn capitalize_word(arg: i64, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    casify_word(arg, CaseMode::Capitalize, env, cx)
+}
+
+/// Convert the case of the text from point to `arg` words away. Point is moved
+/// past the words when `arg` is positive.
+fn casify_word(arg: i64, mode: CaseMode, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    let point = env.current_buffer.get().text.cursor().chars();
+    let far_end = syntax::scan_words(arg, env, cx);
+    let end = casify_region(point, far_end, mode, env, cx)?;
+    env.current_buffer.get_mut().text.set_cursor(end);
+    Ok(())
+}
+
+#[defun]
+fn upcase_region(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    _region_noncontiguous_p: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (beg, end) = region(beg, end, env, cx)?;
+    casify_region(beg, end, CaseMode::Upcase, env, cx).map(|_| ())
+}
+
+#[defun]
+fn downcase_region(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    _region_noncontiguous_p: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (beg, end) = region(beg, end, env, cx)?;
+    casify_region(beg, end, CaseMode::Downcase, env, cx).map(|_| ())
+}
+
+#[defun]
+fn capitalize_region(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    _region_noncontiguous_p: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (beg, end) = region(beg, end, env, cx)?;
+    casify_region(beg, end, CaseMode::Capitalize, env, cx).map(|_| ())
+}
+
+#[defun]
+fn upcase_initials_region(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    _region_noncontiguous_p: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (beg, end) = region(beg, end, env, cx)?;
+    casify_region(beg, end, CaseMode::UpcaseInitials, env, cx).map(|_| ())
+}
+
+/// Convert the lisp positions `beg` and `end` to 0-based positions, checking
+/// that they are in the accessible portion of the current buffer.
+fn region(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<(usize, usize)> {
+    let buffer = env.current_buffer.get();
+    let accessible = buffer.begv() as i64 + 1..=buffer.zv() as i64 + 1;
+    let (beg, end) = (beg.int(), end.int());
+    if !accessible.contains(&beg) || !accessible.contains(&end) {
+        bail!(SignalError::args_out_of_range(&[cx.add(beg), cx.add(end)]));
     }
-    out
+    Ok((beg as usize - 1, end as usize - 1))
 }
 
-fn precedes_capitalization(c: char) -> bool {
-    !c.is_alphanumeric()
+/// Convert the case of the text between the 0-based positions `beg` and `end`,
+/// which may be in either order. Only the characters whose case changes are
+/// replaced, and point stays with the text around it. Returns the new end of
+/// the region.
+fn casify_region(
+    beg: usize,
+    end: usize,
+    mode: CaseMode,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<usize> {
+    let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+    if beg == end {
+        return Ok(end);
+    }
+    let casing = Casing::current(env, cx);
+    let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+    let buffer = env.current_buffer.get_mut();
+    verify_modification(buffer.get_mut(), beg + 1, end + 1, inhibit_read_only)?;
+    let data = buffer.get_mut();
+    let (front, back) = data.text.slice(beg..end);
+    let changes = casing.changes(&[front, back].concat(), mode);
+
+    let mut point = data.text.cursor().chars();
+    let mut new_end = end;
+    for (run_beg, run_end, text) in changes.into_iter().rev() {
+        let (run_beg, run_end) = (beg + run_beg, beg + run_end);
+        let len = text.chars().count();
+        data.text.delete_range(run_beg, run_end);
+        data.text.set_cursor(run_beg);
+        data.text.insert(&text);
+        new_end = new_end + len - (run_end - run_beg);
+        if point >= run_end {
+            point = point + len - (run_end - run_beg);
+        } else if point > run_beg {
+            point = point.min(run_beg + len);
+        }
+    }
+    data.text.set_cursor(point);
+    Ok(new_end)
 }
 
-enum CaseMode {
+#[derive(Clone, Copy)]
+pub(crate) enum CaseMode {
     Downcase,
     Upcase,
     Capitalize,
     UpcaseInitials,
 }
 
-fn casify_char<T>(c: u64, f: impl Fn(char) -> T) -> u64
-where
-    T: Iterator<Item = char>,
-{
-    // emacs uses an identity function for invalid codepoints
-    if c > crate::lisp::CHAR_MODIFIER_MASK {
-        return c;
+/// The case a single character is converted to.
+#[derive(Clone, Copy, PartialEq)]
+enum Case {
+    Down,
+    Up,
+    Title,
+}
+
+/// Characters whose titlecase form is neither their uppercase nor their
+/// lowercase form, as (uppercase, titlecase, lowercase).
+const TITLECASE_DIGRAPHS: [(char, char, char); 4] =
+    [('Ǆ', 'ǅ', 'ǆ'), ('Ǉ', 'ǈ', 'ǉ'), ('Ǌ', 'ǋ', 'ǌ'), ('Ǳ', 'ǲ', 'ǳ')];
+
+fn titlecase_digraph(chr: char) -> Option<char> {
+    TITLECASE_DIGRAPHS
+        .iter()
+        .find(|(upper, title, lower)| [*upper, *title, *lower].contains(&chr))
+        .map(|x| x.1)
+}
+
+/// The single character a case mapping produces, or `chr` if it produces
+/// more than one.
+fn single(mut cased: impl ExactSizeIterator<Item = char>, chr: char) -> char {
+    if cased.len() == 1 { cased.next().unwrap() } else { chr }
+}
+
+/// The case conversion of the current buffer. The case table maps characters
+/// to their lowercase form, and its first extra slot is a table mapping them
+/// to their uppercase form. Characters that a table does not map use their
+/// Unicode case mapping, so the standard case table starts out empty.
+#[derive(Clone, Copy)]
+pub(crate) struct CaseTable<'ob> {
+    down: Option<&'ob CharTable>,
+    up: Option<&'ob CharTable>,
+}
+
+impl<'ob> CaseTable<'ob> {
+    /// The case table of the current buffer.
+    pub(crate) fn current(env: &Rt<Env>, cx: &'ob Context) -> Self {
+        let table = env.current_buffer.get().case_table(cx);
+        let down = table.or_else(|| created_standard_table(env, cx));
+        let up = down.and_then(|table| char_table(table.extra_slot(0)?));
+        Self { down, up }
     }
-    let Ok(u) = u32::try_from(c) else { return c };
-    let Ok(chr) = char::try_from(u) else { return c };
-    let mut cased = f(chr);
-    let first = cased.next().unwrap();
-    // if the char changes case to multiple characters, don't change case
-    match cased.next() {
-        Some(_) => c,
-        None => first as u64,
+
+    /// The character that `table` maps `chr` to, if any.
+    fn lookup(table: Option<&CharTable>, chr: char) -> Option<char> {
+        match table?.get(chr as usize).untag() {
+            ObjectType::Int(code) => char::from_u32(u32::try_from(code).ok()?),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn downcase(self, chr: char) -> char {
+        Self::lookup(self.down, chr).unwrap_or_else(|| single(chr.to_lowercase(), chr))
+    }
+
+    pub(crate) fn upcase(self, chr: char) -> char {
+        Self::lookup(self.up, chr).unwrap_or_else(|| single(chr.to_uppercase(), chr))
+    }
+
+    fn titlecase(self, chr: char) -> char {
+        match Self::lookup(self.up, chr) {
+            Some(up) => up,
+            None => titlecase_digraph(chr).unwrap_or_else(|| self.upcase(chr)),
+        }
+    }
+
+    fn convert(self, chr: char, case: Case) -> char {
+        match case {
+            Case::Down => self.downcase(chr),
+            Case::Up => self.upcase(chr),
+            Case::Title => self.titlecase(chr),
+        }
+    }
+
+    pub(crate) fn is_uppercase(self, chr: char) -> bool {
+        self.downcase(chr) != chr
+    }
+
+    pub(crate) fn is_lowercase(self, chr: char) -> bool {
+        !self.is_uppercase(chr) && self.upcase(chr) != chr
+    }
+
+    /// The canonical form of `chr`, which is shared by the characters that
+    /// differ from it only in case, and its uppercase form. Searches use this
+    /// when `case-fold-search` is non-nil.
+    pub(crate) fn variants(self, chr: char) -> (char, char) {
+        let canon = self.downcase(self.upcase(self.downcase(chr)));
+        (canon, self.upcase(canon))
+    }
+
+    /// The conversion of `chr` to more than one character, following the
+    /// `special-uppercase`, `special-lowercase` and `special-titlecase`
+    /// character properties. This is how `ß` upcases to `SS` but capitalizes
+    /// to `Ss`. A case table that maps `chr` takes precedence.
+    fn special(self, chr: char, case: Case) -> Option<String> {
+        let table = if case == Case::Down { self.down } else { self.up };
+        if Self::lookup(table, chr).is_some() {
+            return None;
+        }
+        let cased: String = match case {
+            Case::Down => chr.to_lowercase().collect(),
+            Case::Up | Case::Title => chr.to_uppercase().collect(),
+        };
+        if cased.chars().count() < 2 {
+            return None;
+        }
+        if case != Case::Title {
+            return Some(cased);
+        }
+        // Only the first letter of the titlecase form is uppercase
+        let split = match cased.char_indices().find(|(_, chr)| chr.is_uppercase()) {
+            Some((idx, chr)) => idx + chr.len_utf8(),
+            None => cased.len(),
+        };
+        let (initial, rest) = cased.split_at(split);
+        Some(initial.to_owned() + &rest.to_lowercase())
+    }
+}
+
+/// Converts the case of text with the case table of the current buffer, using
+/// its syntax table to find the words.
+#[derive(Clone, Copy)]
+pub(crate) struct Casing<'ob> {
+    pub(crate) table: CaseTable<'ob>,
+    pub(crate) syntax: SyntaxTable<'ob>,
+}
+
+impl<'ob> Casing<'ob> {
+    pub(crate) fn current(env: &Rt<Env>, cx: &'ob Context) -> Self {
+        Self { table: CaseTable::current(env, cx), syntax: SyntaxTable::current(env, cx) }
+    }
+
+    pub(crate) fn string(self, text: &str, mode: CaseMode) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut inword = false;
+        for chr in text.chars() {
+            self.push(chr, mode, inword, &mut out);
+            inword = self.syntax.class(chr) == SyntaxClass::Word;
+        }
+        out
+    }
+
+    /// The runs of characters in `text` whose case changes, as their character
+    /// offsets in `text` and their replacement.
+    fn changes(self, text: &str, mode: CaseMode) -> Vec<(usize, usize, String)> {
+        let mut changes: Vec<(usize, usize, String)> = Vec::new();
+        let mut inword = false;
+        let mut cased = String::new();
+        for (idx, chr) in text.chars().enumerate() {
+            cased.clear();
+            self.push(chr, mode, inword, &mut cased);
+            inword = self.syntax.class(chr) == SyntaxClass::Word;
+            if cased.chars().eq([chr]) {
+                continue;
+            }
+            match changes.last_mut() {
+                Some((_, end, text)) if *end == idx => {
+                    *end += 1;
+                    text.push_str(&cased);
+                }
+                _ => changes.push((idx, idx + 1, cased.clone())),
+            }
+        }
+        changes
+    }
+
+    /// Push `chr` to `out` with its case converted. `inword` is whether it
+    /// follows a word constituent, so that it is not the initial of a word.
+    fn push(self, chr: char, mode: CaseMode, inword: bool, out: &mut String) {
+        let case = match mode {
+            CaseMode::Downcase => Case::Down,
+            CaseMode::Upcase => Case::Up,
+            CaseMode::Capitalize if inword => Case::Down,
+            CaseMode::UpcaseInitials if inword => return out.push(chr),
+            CaseMode::Capitalize | CaseMode::UpcaseInitials => Case::Title,
+        };
+        match self.table.special(chr, case) {
+            Some(special) => out.push_str(&special),
+            None => out.push(self.table.convert(chr, case)),
+        }
+    }
+
+    /// Convert the case of the character `c`. Unlike text, a character never
+    /// changes to more than one character.
+    fn char(self, c: u64, mode: CaseMode) -> u64 {
+        // emacs uses an identity function for invalid codepoints
+        if c > crate::lisp::CHAR_MODIFIER_MASK {
+            return c;
+        }
+        let Ok(u) = u32::try_from(c) else { return c };
+        let Ok(chr) = char::try_from(u) else { return c };
+        let case = match mode {
+            CaseMode::Downcase => Case::Down,
+            CaseMode::Upcase => Case::Up,
+            CaseMode::Capitalize | CaseMode::UpcaseInitials => Case::Title,
+        };
+        self.table.convert(chr, case) as u64
+    }
+}
+
+fn char_table<'ob>(object: Object<'ob>) -> Option<&'ob CharTable> {
+    match object.untag() {
+        ObjectType::CharTable(table) => Some(table),
+        _ => None,
+    }
+}
+
+fn created_standard_table<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Option<&'ob CharTable> {
+    char_table(env.standard_case_table.bind(cx))
+}
+
+/// A new case table that maps every character with its Unicode case mapping.
+fn new_case_table<'ob>(cx: &'ob Context) -> &'ob CharTable {
+    let table = CharTableInner::new(sym::CASE_TABLE.into(), None, 3).into_obj(cx).untag();
+    let up = CharTableInner::new(sym::CASE_TABLE.into(), None, 3).into_obj(cx).untag();
+    table.set_extra_slot(0, up.into());
+    table
+}
+
+/// The standard case table, which is created the first time it is needed.
+fn standard_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    if let Some(table) = created_standard_table(env, cx) {
+        return table;
+    }
+    let table = new_case_table(cx);
+    env.standard_case_table.set(Object::from(table));
+    table
+}
+
+fn check_case_table<'ob>(table: &'ob CharTable, cx: &'ob Context) -> Result<&'ob CharTable> {
+    if case_table_p(table.into()) {
+        Ok(table)
+    } else {
+        let error = list![sym::WRONG_TYPE_ARGUMENT, sym::CASE_TABLE_P, table; cx];
+        bail!(LispError::new(error.try_into()?))
     }
 }
 
-fn find_forward_word(buf: &TextBuffer) -> Range<usize> {
-    let cursor = buf.cursor().chars();
-    let (s1, s2) = buf.slice(cursor..);
-    let end = cursor
-        + s1.chars()
-            .chain(s2.chars())
-            .enumerate()
-            .skip_while(|(_, c)| !c.is_alphanumeric())
-            .find(|(_, c)| c.is_whitespace())
-            .map(|(idx, _)| idx)
-            .unwrap_or_else(|| buf.len_chars() - cursor);
-    cursor..end
-}
-
-fn find_backward_word(buf: &TextBuffer) -> Range<usize> {
-    let cursor = buf.cursor().chars();
-    let (s1, s2) = buf.slice(..cursor);
-    let start = cursor
-        - s1.chars()
-            .chain(s2.chars())
-            .rev()
-            .enumerate()
-            .skip_while(|(_, c)| !c.is_alphanumeric())
-            .find(|(_, c)| c.is_whitespace())
-            .map(|(idx, _)| idx)
-            .unwrap_or(0);
-    start..cursor
+#[defun]
+fn case_table_p(object: Object) -> bool {
+    let ObjectType::CharTable(table) = object.untag() else { return false };
+    let slot_ok = |n| {
+        table
+            .extra_slot(n)
+            .is_some_and(|x| x.is_nil() || matches!(x.untag(), ObjectType::CharTable(_)))
+    };
+    table.purpose() == sym::CASE_TABLE && (0..3).all(slot_ok)
+}
+
+#[defun]
+fn standard_case_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    standard_table(env, cx)
+}
+
+#[defun]
+fn current_case_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    match env.current_buffer.get().case_table(cx) {
+        Some(table) => table,
+        None => standard_table(env, cx),
+    }
+}
+
+#[defun]
+fn set_case_table<'ob>(
+    table: &'ob CharTable,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob CharTable> {
+    let table = check_case_table(table, cx)?;
+    env.current_buffer.get_mut().set_case_table(table);
+    Ok(table)
+}
+
+#[defun]
+fn set_standard_case_table<'ob>(
+    table: &'ob CharTable,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob CharTable> {
+    let table = check_case_table(table, cx)?;
+    env.standard_case_table.set(Object::from(table));
+    Ok(table)
+}
+
+/// Make `uc` and `lc` the uppercase and lowercase forms of each other in
+/// `table`, and give them word syntax in the standard syntax table.
+#[defun]
+fn set_case_syntax_pair(
+    uc: char,
+    lc: char,
+    table: &CharTable,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let table = check_case_table(table, cx)?;
+    table.set(uc as usize, cx.add(lc));
+    table.set(lc as usize, cx.add(lc));
+    let up = match table.extra_slot(0).and_then(char_table) {
+        Some(up) => up,
+        None => {
+            let up = CharTableInner::new(sym::CASE_TABLE.into(), None, 3).into_obj(cx).untag();
+            table.set_extra_slot(0, up.into());
+            up
+        }
+    };
+    up.set(uc as usize, cx.add(uc));
+    up.set(lc as usize, cx.add(uc));
+    // The canonicalize and equivalences tables are out of date
+    table.set_extra_slot(1, NIL);
+    table.set_extra_slot(2, NIL);
+    let standard = syntax::standard_table(env, cx);
+    for chr in [lc, uc] {
+        syntax::modify_syntax_entry(cx.add(chr), "w", Some(standard), env, cx)?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::RootSet;
+    use crate::core::gc::RootSet;
+    use rune_core::macros::root;
 
     #[test]
     fn test_downcase() {
         let roots = &RootSet::default();
-        let cx = &Context::new(roots);
-        assert_eq!(downcase("The cat in the hat".into(), cx), "the cat in the hat");
-        assert_eq!(downcase('x'.into(), cx), 'x');
-        assert_eq!(downcase('X'.into(), cx), 'x');
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(downcase("The cat in the hat".into(), env, cx), "the cat in the hat");
+        assert_eq!(downcase('x'.into(), env, cx), 'x');
+        assert_eq!(downcase('X'.into(), env, cx), 'x');
     }
 
     #[test]
     fn test_upcase() {
         let roots = &RootSet::default();
-        let cx = &Context::new(roots);
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
         // Emacs Doc Tests
-        assert_eq!(upcase("The cat in the hat".into(), cx), "THE CAT IN THE HAT");
-        assert_eq!(upcase("ﬁ".into(), cx), "FI");
-        assert_eq!(upcase('ﬁ'.into(), cx), 'ﬁ');
-        assert_eq!(upcase('x'.into(), cx), 'X');
-        assert_eq!(upcase('X'.into(), cx), 'X');
+        assert_eq!(upcase("The cat in the hat".into(), env, cx), "THE CAT IN THE HAT");
+        assert_eq!(upcase("ﬁ".into(), env, cx), "FI");
+        assert_eq!(upcase('ﬁ'.into(), env, cx), 'ﬁ');
+        assert_eq!(upcase('x'.into(), env, cx), 'X');
+        assert_eq!(upcase('X'.into(), env, cx), 'X');
 
         // Basic escape characters
-        assert_eq!(upcase("\n".into(), cx), "\n");
-        assert_eq!(upcase("\t".into(), cx), "\t");
-        assert_eq!(upcase("\r".into(), cx), "\r");
+        assert_eq!(upcase("\n".into(), env, cx), "\n");
+        assert_eq!(upcase("\t".into(), env, cx), "\t");
+        assert_eq!(upcase("\r".into(), env, cx), "\r");
 
         // Control characters
-        assert_eq!(upcase("\u{0}".into(), cx), "\u{0}");
-        assert_eq!(upcase("\u{1B}".into(), cx), "\u{1B}");
-        assert_eq!(upcase("\u{7F}".into(), cx), "\u{7F}");
+        assert_eq!(upcase("\u{0}".into(), env, cx), "\u{0}");
+        assert_eq!(upcase("\u{1B}".into(), env, cx), "\u{1B}");
+        assert_eq!(upcase("\u{7F}".into(), env, cx), "\u{7F}");
 
         // Non-ASCII characters
-        assert_eq!(upcase("αβγ".into(), cx), "ΑΒΓ");
-        assert_eq!(upcase("åäö".into(), cx), "ÅÄÖ");
+        assert_eq!(upcase("αβγ".into(), env, cx), "ΑΒΓ");
+        assert_eq!(upcase("åäö".into(), env, cx), "ÅÄÖ");
 
         // Mixed content
-        assert_eq!(upcase("hello\nworld".into(), cx), "HELLO\nWORLD");
-        assert_eq!(upcase("foo\tbar".into(), cx), "FOO\tBAR");
-        assert_eq!(upcase("path\\to\\file\"name\"".into(), cx), "PATH\\TO\\FILE\"NAME\"");
+        assert_eq!(upcase("hello\nworld".into(), env, cx), "HELLO\nWORLD");
+        assert_eq!(upcase("foo\tbar".into(), env, cx), "FOO\tBAR");
+        assert_eq!(upcase("path\\to\\file\"name\"".into(), env, cx), "PATH\\TO\\FILE\"NAME\"");
 
         // Invalid code points
-        assert_eq!(upcase(StringOrChar::Char(0xD800), cx), 0xD800);
-        assert_eq!(upcase(StringOrChar::Char(u64::MAX), cx), cx.add(u64::MAX));
+        assert_eq!(upcase(StringOrChar::Char(0xD800), env, cx), 0xD800);
+        assert_eq!(upcase(StringOrChar::Char(u64::MAX), env, cx), cx.add(u64::MAX));
     }
 
     #[test]
     fn test_capitalize() {
         let roots = &RootSet::default();
-        let cx = &Context::new(roots);
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
 
         // Emacs doc tests
-        assert_eq!(capitalize("The cat in the hat".into(), cx), "The Cat In The Hat");
-        assert_eq!(capitalize("THE 77TH-HATTED CAT".into(), cx), "The 77th-Hatted Cat");
-        assert_eq!(capitalize('x'.into(), cx), 'X');
-        assert_eq!(capitalize('X'.into(), cx), 'X');
-        assert_eq!(capitalize('ß'.into(), cx), 'ß');
-        assert_eq!(capitalize("ß".into(), cx), "SS");
+        assert_eq!(capitalize("The cat in the hat".into(), env, cx), "The Cat In The Hat");
+        assert_eq!(capitalize("THE 77TH-HATTED CAT".into(), env, cx), "The 77th-Hatted Cat");
+        assert_eq!(capitalize('x'.into(), env, cx), 'X');
+        assert_eq!(capitalize('X'.into(), env, cx), 'X');
+        assert_eq!(capitalize('ß'.into(), env, cx), 'ß');
+        assert_eq!(capitalize("ß".into(), env, cx), "Ss");
+        assert_eq!(upcase("ß".into(), env, cx), "SS");
+        assert_eq!(capitalize("ǆemal".into(), env, cx), "ǅemal");
+        assert_eq!(capitalize('ǆ'.into(), env, cx), 'ǅ');
 
         // from elprop
-        // TODO: implement syntax tables so it's known whether a character makes a word or symbol
-        // // U+1D100 MUSICAL SYMBOL SINGLE BARLINE (Other-Symbol)
-        // // U+0041 LATIN CAPITAL LETTER A
-        // assert_eq!(capitalize("𝄀A", cx), Ok("𝄀a"));
-        // // U+0024 DOLLAR SIGN (Currency-Symbol)
-        // // U+0041 LATIN CAPITAL LETTER A
-        // assert_eq!(capitalize("$A", cx), Ok("$a"));
-        // // U+002D HYPHEN-MINUS (Dash-Punctuation)
-        // // U+0041 LATIN CAPITAL LETTER A
-        // assert_eq!(capitalize("-A", cx), Ok("-A"));
-        // // U+005E CIRCUMFLEX ACCENT (Modifier-Symbol)
-        // // U+0041 LATIN CAPITAL LETTER A
-        // assert_eq!(capitalize("^A", cx), Ok("^A"));
+        // U+1D100 MUSICAL SYMBOL SINGLE BARLINE (Other-Symbol)
+        // U+0041 LATIN CAPITAL LETTER A
+        assert_eq!(capitalize("𝄀A".into(), env, cx), "𝄀a");
+        // U+0024 DOLLAR SIGN (Currency-Symbol)
+        // U+0041 LATIN CAPITAL LETTER A
+        assert_eq!(capitalize("$A".into(), env, cx), "$a");
+        // U+002D HYPHEN-MINUS (Dash-Punctuation)
+        // U+0041 LATIN CAPITAL LETTER A
+        assert_eq!(capitalize("-A".into(), env, cx), "-A");
+        // U+005E CIRCUMFLEX ACCENT (Modifier-Symbol)
+        // U+0041 LATIN CAPITAL LETTER A
+        assert_eq!(capitalize("^A".into(), env, cx), "^A");
+        // TODO: the standard syntax table gives all non-ASCII characters word
+        // syntax, but Emacs makes this one a symbol
         // // U+0FBE TIBETAN KU RU KHA (Other-Symbol)
         // // U+0041 LATIN CAPITAL LETTER A
         // assert_eq!(capitalize("྾A", cx), Ok("྾A"));
-        // // U+10A50 KHAROSHTHI PUNCTUATION DOT (Other-Punctuation)
-        // // U+104B0 OSAGE CAPITAL LETTER A
-        // // (becomes) U+104D8 OSAGE SMALL LETTER A
-        // assert_eq!(capitalize("𐩐𐒰", cx), Ok("𐩐𐓘"));
+        // U+10A50 KHAROSHTHI PUNCTUATION DOT (Other-Punctuation)
+        // U+104B0 OSAGE CAPITAL LETTER A
+        // (becomes) U+104D8 OSAGE SMALL LETTER A
+        assert_eq!(capitalize("𐩐𐒰".into(), env, cx), "𐩐𐓘");
     }
 
     #[test]
     fn test_upcase_initials() {
         let roots = &RootSet::default();
-        let cx = &Context::new(roots);
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
 
         // Emacs Doc Tests
-        assert_eq!(upcase_initials("The CAT in the hAt".into(), cx), "The CAT In The HAt");
-        assert_eq!(upcase_initials('x'.into(), cx), 'X');
-        assert_eq!(upcase_initials('X'.into(), cx), 'X');
+        assert_eq!(upcase_initials("The CAT in the hAt".into(), env, cx), "The CAT In The HAt");
+        assert_eq!(upcase_initials('x'.into(), env, cx), 'X');
+        assert_eq!(upcase_initials('X'.into(), env, cx), 'X');
+    }
+
+    #[test]
+    #[cfg(not(miri))] // Uses SIMD
+    fn test_casify_region() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("the straße is NARROW");
+        env.current_buffer.get_mut().text.set_cursor(18);
+        upcase_region(5.into(), 11.into(), None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text, "the STRASSE is NARROW");
+        // point moves with the text after it
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 19);
+        capitalize_region(22.into(), 1.into(), None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text, "The Strasse Is Narrow");
+        downcase_region(1.into(), 12.into(), None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text, "the strasse Is Narrow");
+        upcase_initials_region(1.into(), 22.into(), None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text, "The Strasse Is Narrow");
+        assert!(upcase_region(1.into(), 30.into(), None, env, cx).is_err());
+    }
+
+    #[test]
+    #[cfg(not(miri))] // Uses SIMD
+    fn test_case_syntax_pair() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert!(case_table_p(standard_case_table(env, cx).into()));
+        assert!(!case_table_p(syntax::standard_table(env, cx).into()));
+
+        // Turkish dotted and dotless i
+        let table = new_case_table(cx);
+        set_case_syntax_pair('İ', 'i', table, env, cx).unwrap();
+        set_case_syntax_pair('I', 'ı', table, env, cx).unwrap();
+        assert_eq!(upcase("istanbul".into(), env, cx), "ISTANBUL");
+        set_case_table(table, env, cx).unwrap();
+        assert_eq!(upcase("istanbul".into(), env, cx), "İSTANBUL");
+        assert_eq!(downcase("IİSTANBUL".into(), env, cx), "ıistanbul");
+        assert_eq!(upcase('ı'.into(), env, cx), 'I');
+        assert_eq!(capitalize("ırmak".into(), env, cx), "Irmak");
+        assert_eq!(CaseTable::current(env, cx).variants('İ'), ('i', 'İ'));
+        assert_eq!(CaseTable::current(env, cx).variants('I'), ('ı', 'I'));
+
+        env.current_buffer.get_mut().text.insert("iı");
+        upcase_region(1.into(), 3.into(), None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text, "İI");
     }
 
     #[cfg(not(miri))] // Uses SIMD
@@ -293,17 +696,17 @@ mod tests {
             // ^-----
             env.current_buffer.get_mut().text.insert("αβγ word");
             env.current_buffer.get_mut().text.set_cursor(0);
-            upcase_word(1, env);
+            upcase_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "ΑΒΓ word");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("ΑΒΓ woRd");
             env.current_buffer.get_mut().text.set_cursor(0);
-            downcase_word(1, env);
+            downcase_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "αβγ woRd");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("αΒΓ wORD");
             env.current_buffer.get_mut().text.set_cursor(0);
-            capitalize_word(1, env);
+            capitalize_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "Αβγ wORD");
         }
 
@@ -317,17 +720,17 @@ mod tests {
             //        -------^
             env.current_buffer.get_mut().text.insert("upcase αβγword ");
             env.current_buffer.get_mut().text.set_cursor(15);
-            upcase_word(-1, env);
+            upcase_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "upcase ΑΒΓWORD ");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("dOwNcAsE αΒΓWord ");
             env.current_buffer.get_mut().text.set_cursor(17);
-            downcase_word(-1, env);
+            downcase_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "dOwNcAsE αβγword ");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("cAPITALIZE αΒΓWORD ");
             env.current_buffer.get_mut().text.set_cursor(19);
-            capitalize_word(-1, env);
+            capitalize_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "cAPITALIZE Αβγword ");
         }
 
@@ -341,17 +744,17 @@ mod tests {
             //  ^----
             env.current_buffer.get_mut().text.insert("upcase word");
             env.current_buffer.get_mut().text.set_cursor(2);
-            upcase_word(1, env);
+            upcase_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "upCASE word");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("DOWNCASE WORD");
             env.current_buffer.get_mut().text.set_cursor(2);
-            downcase_word(1, env);
+            downcase_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "DOwncase WORD");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("capitalize word");
             env.current_buffer.get_mut().text.set_cursor(2);
-            capitalize_word(1, env);
+            capitalize_word(1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "caPitalize word");
         }
 
@@ -365,17 +768,17 @@ mod tests {
             //        --^
             env.current_buffer.get_mut().text.insert("upcase word");
             env.current_buffer.get_mut().text.set_cursor(9);
-            upcase_word(-1, env);
+            upcase_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "upcase WOrd");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("downcase WORD");
             env.current_buffer.get_mut().text.set_cursor(11);
-            downcase_word(-1, env);
+            downcase_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "downcase woRD");
             env.current_buffer.get_mut().text = text_buffer::Buffer::default();
             env.current_buffer.get_mut().text.insert("capitalize word");
             env.current_buffer.get_mut().text.set_cursor(13);
-            capitalize_word(-1, env);
+            capitalize_word(-1, env, cx).unwrap();
             assert_eq!(env.current_buffer.get().text, "capitalize Word");
         }
     }
@@ -1,9 +1,34 @@
-use crate::core::object::{CharTable, CharTableInner, Object, Symbol};
+use crate::core::{
+    env::{Env, sym},
+    error::SignalError,
+    gc::{Context, Rt},
+    object::{CharTable, CharTableInner, Object, ObjectType, Symbol},
+};
+use anyhow::{Result, bail};
 use rune_macros::defun;
 
+defsym!(CHAR_TABLE_EXTRA_SLOTS);
+
+/// The most extra slots a char table can have.
+const MAX_EXTRA_SLOTS: i64 = 10;
+
 #[defun]
-fn make_char_table<'ob>(_purpose: Symbol<'ob>, init: Option<Object<'ob>>) -> CharTableInner<'ob> {
-    CharTableInner::new(init)
+fn make_char_table<'ob>(
+    purpose: Symbol<'ob>,
+    init: Option<Object<'ob>>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<CharTableInner<'ob>> {
+    let slots = match crate::data::get(purpose, sym::CHAR_TABLE_EXTRA_SLOTS, env, cx).untag() {
+        ObjectType::Int(n) => n,
+        // Case tables have their slots even if the property was never set
+        _ if purpose == sym::CASE_TABLE => 3,
+        _ => 0,
+    };
+    if !(0..=MAX_EXTRA_SLOTS).contains(&slots) {
+        bail!(SignalError::args_out_of_range(&[cx.add(slots)]));
+    }
+    Ok(CharTableInner::new(purpose.into(), init, slots as usize))
 }
 
 #[defun]
@@ -14,3 +39,39 @@ fn set_char_table_parent<'ob>(
     table.set_parent(parent);
     parent
 }
+
+#[defun]
+fn char_table_parent(table: &CharTable) -> Option<&CharTable> {
+    table.parent()
+}
+
+#[defun]
+fn char_table_subtype(table: &CharTable) -> Object {
+    table.purpose()
+}
+
+#[defun]
+fn char_table_extra_slot<'ob>(
+    char_table: &'ob CharTable,
+    n: i64,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    match usize::try_from(n).ok().and_then(|n| char_table.extra_slot(n)) {
+        Some(value) => Ok(value),
+        None => bail!(SignalError::args_out_of_range(&[char_table.into(), cx.add(n)])),
+    }
+}
+
+#[defun]
+fn set_char_table_extra_slot<'ob>(
+    char_table: &'ob CharTable,
+    n: i64,
+    value: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    if usize::try_from(n).is_ok_and(|n| char_table.set_extra_slot(n, value)) {
+        Ok(value)
+    } else {
+        bail!(SignalError::args_out_of_range(&[char_table.into(), cx.add(n)]))
+    }
+}
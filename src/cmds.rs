@@ -0,0 +1,186 @@
+//! Simple buffer motion commands.
+use crate::core::{env::Env, error::SignalError, gc::Rt};
+use anyhow::{Result, bail};
+use rune_macros::defun;
+use text_buffer::Buffer as TextBuffer;
+
+#[defun]
+pub(crate) fn forward_char(n: Option<i64>, env: &mut Rt<Env>) -> Result<()> {
+    let data = env.current_buffer.get_mut();
+    let (begv, zv) = (data.begv(), data.zv());
+    let text = &mut data.text;
+    let n = n.unwrap_or(1);
+    let target = text.cursor().chars() as i64 + n;
+    if target < begv as i64 {
+        text.set_cursor(begv);
+        bail!(SignalError::BeginningOfBuffer);
+    } else if target > zv as i64 {
+        text.set_cursor(zv);
+        bail!(SignalError::EndOfBuffer);
+    }
+    text.set_cursor(target as usize);
+    Ok(())
+}
+
+#[defun]
+fn backward_char(n: Option<i64>, env: &mut Rt<Env>) -> Result<()> {
+    forward_char(Some(-n.unwrap_or(1)), env)
+}
+
+#[defun]
+pub(crate) fn forward_line(n: Option<i64>, env: &mut Rt<Env>) -> i64 {
+    let data = env.current_buffer.get_mut();
+    let (begv, zv) = (data.begv(), data.zv());
+    let text = &mut data.text;
+    let n = n.unwrap_or(1);
+    let start = text.cursor().chars();
+    if n > 0 {
+        let (pos, found) = find_newline_forward(text, start, zv, n.unsigned_abs());
+        text.set_cursor(pos);
+        let mut shortage = n - found as i64;
+        // A partial line at the end of the buffer counts as a line moved over.
+        if shortage > 0 && pos != start && text.char_at(pos - 1) != Some('\n') {
+            shortage -= 1;
+        }
+        shortage
+    } else {
+        let count = n.unsigned_abs() + 1;
+        let (pos, found) = find_newline_backward(text, start, begv, count);
+        text.set_cursor(pos);
+        // Moving to the start of the current line is not counted as a shortage.
+        -(count as i64 - found as i64 - 1).max(0)
+    }
+}
+
+#[defun]
+fn beginning_of_line(n: Option<i64>, env: &mut Rt<Env>) {
+    let n = n.unwrap_or(1);
+    forward_line(Some(n - 1), env);
+}
+
+#[defun]
+pub(crate) fn end_of_line(n: Option<i64>, env: &mut Rt<Env>) {
+    let n = n.unwrap_or(1);
+    if n != 1 {
+        forward_line(Some(n - 1), env);
+    }
+    let data = env.current_buffer.get_mut();
+    let zv = data.zv();
+    let text = &mut data.text;
+    let start = text.cursor().chars();
+    let (pos, found) = find_newline_forward(text, start, zv, 1);
+    text.set_cursor(if found == 1 { pos - 1 } else { pos });
+}
+
+/// Search forward from `start` to `end` for `count` newlines. Returns the
+/// position after the last newline found (or `end`) and the number found.
+pub(crate) fn find_newline_forward(
+    text: &TextBuffer,
+    start: usize,
+    end: usize,
+    count: u64,
+) -> (usize, u64) {
+    let mut found = 0;
+    let (a, b) = text.slice(start..end);
+    for (idx, chr) in a.chars().chain(b.chars()).enumerate() {
+        if chr == '\n' {
+            found += 1;
+            if found == count {
+                return (start + idx + 1, found);
+            }
+        }
+    }
+    (end, found)
+}
+
+/// Search backward from `start` to `beg` for `count` newlines. Returns the
+/// position after the last newline found (or `beg`) and the number found.
+pub(crate) fn find_newline_backward(
+    text: &TextBuffer,
+    start: usize,
+    beg: usize,
+    count: u64,
+) -> (usize, u64) {
+    let mut found = 0;
+    let (a, b) = text.slice(beg..start);
+    for (idx, chr) in a.chars().chain(b.chars()).rev().enumerate() {
+        if chr == '\n' {
+            found += 1;
+            if found == count {
+                return (start - idx, found);
+            }
+        }
+    }
+    (beg, found)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_forward_char_signals() {
+        use crate::interpreter::assert_lisp;
+        assert_lisp("(condition-case nil (forward-char) (end-of-buffer 'end))", "end");
+        assert_lisp("(condition-case nil (backward-char) (beginning-of-buffer 'beg))", "beg");
+        assert_lisp("(condition-case err (forward-char) (error err))", "(end-of-buffer)");
+    }
+
+    #[test]
+    fn test_forward_line() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo\nbar\nbaz");
+        env.current_buffer.get_mut().text.set_cursor(1);
+        assert_eq!(forward_line(Some(1), env), 0);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 4);
+        assert_eq!(forward_line(Some(5), env), 3);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 11);
+        assert_eq!(forward_line(Some(0), env), 0);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 8);
+        assert_eq!(forward_line(Some(-1), env), 0);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 4);
+        assert_eq!(forward_line(Some(-3), env), -2);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 0);
+    }
+
+    #[test]
+    fn test_end_of_line() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo\nbar\nbaz");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        end_of_line(None, env);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 3);
+        end_of_line(Some(3), env);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 11);
+        beginning_of_line(None, env);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 8);
+        assert!(forward_char(Some(-9), env).is_err());
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 0);
+    }
+
+    #[test]
+    fn test_narrowed_motion() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo\nbar\nbaz");
+        env.current_buffer.get_mut().narrow(5, 9);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 9);
+        assert_eq!(forward_line(Some(-2), env), -1);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 5);
+        end_of_line(None, env);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 7);
+        assert_eq!(forward_line(Some(2), env), 0);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 9);
+        assert!(forward_char(Some(1), env).is_err());
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 9);
+        assert!(forward_char(Some(-5), env).is_err());
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 5);
+    }
+}
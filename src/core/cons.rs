@@ -16,7 +16,6 @@ pub(crate) struct Cons(GcHeap<ConsInner>);
 derive_GcMoveable!(Cons);
 
 struct ConsInner {
-    mutable: bool,
     car: ObjCell,
     cdr: ObjCell,
 }
@@ -35,7 +34,7 @@ impl Cons {
     // the stack. Otherwise it could outlive it's objects since it has no
     // lifetimes.
     unsafe fn new_unchecked(car: Object, cdr: Object) -> ConsInner {
-        ConsInner { mutable: true, car: ObjCell::new(car), cdr: ObjCell::new(cdr) }
+        ConsInner { car: ObjCell::new(car), cdr: ObjCell::new(cdr) }
     }
 
     /// Create a new cons cell
@@ -66,10 +65,6 @@ impl Cons {
         let cons = unsafe { Cons::new_unchecked(car, NIL) };
         Cons(GcHeap::new(cons, C)).into_obj(cx).untag()
     }
-
-    pub(in crate::core) fn mark_const(&mut self) {
-        self.0.mutable = false;
-    }
 }
 
 impl Cons {
@@ -96,7 +91,7 @@ impl Cons {
     }
 
     pub(crate) fn set_car(&self, new_car: Object) -> Result<()> {
-        if self.0.mutable {
+        if self.0.write_barrier() {
             unsafe { self.0.car.as_mut().set(new_car) }
             Ok(())
         } else {
@@ -105,7 +100,7 @@ impl Cons {
     }
 
     pub(crate) fn set_cdr(&self, new_cdr: Object) -> Result<()> {
-        if self.0.mutable {
+        if self.0.write_barrier() {
             unsafe { self.0.cdr.as_mut().set(new_cdr) }
             Ok(())
         } else {
@@ -1,8 +1,9 @@
-use super::gc::{Context, ObjectMap, Rto, Slot};
-use super::object::{LispBuffer, Object, OpenBuffer, Symbol, WithLifetime};
+use super::gc::{Context, GcState, IntoRoot, ObjectMap, RootedDeref, Rt, Rto, Slot, Trace};
+use super::object::{LispBuffer, Object, OpenBuffer, SavedRestriction, Symbol, WithLifetime};
 use anyhow::{Result, anyhow};
 use rune_macros::Trace;
 use std::cell::OnceCell;
+use std::ops::Range;
 
 mod stack;
 mod symbol_map;
@@ -15,16 +16,57 @@ pub(crate) struct Env<'a> {
     pub(crate) vars: ObjectMap<Slot<Symbol<'a>>, Slot<Object<'a>>>,
     pub(crate) props: PropertyMap<'a>,
     pub(crate) catch_stack: Vec<Slot<Object<'a>>>,
+    /// The conditions of the active `condition-case` handlers, innermost last.
+    /// This is used to decide if the debugger should be entered for an error.
+    pub(crate) condition_stack: Vec<Slot<Object<'a>>>,
     exception: (Slot<Object<'a>>, Slot<Object<'a>>),
     #[no_trace]
     exception_id: u32,
-    binding_stack: Vec<(Slot<Symbol<'a>>, Option<Slot<Object<'a>>>)>,
+    binding_stack: Vec<Binding<'a>>,
     pub(crate) match_data: Slot<Object<'a>>,
+    /// The buffer that the match data refers to, or `None` if the last match
+    /// was in a string.
     #[no_trace]
+    pub(crate) match_buffer: Option<&'a LispBuffer>,
+    /// The standard syntax table, or nil until it is first needed.
+    pub(crate) standard_syntax_table: Slot<Object<'a>>,
+    /// The standard case table, or nil until it is first needed.
+    pub(crate) standard_case_table: Slot<Object<'a>>,
     pub(crate) current_buffer: CurrentBuffer<'a>,
     pub(crate) stack: LispStack<'a>,
 }
 
+/// An entry in the dynamic binding stack. These are undone in reverse order by
+/// [`unbind`](RootedEnv::unbind).
+#[derive(Debug, Trace)]
+enum Binding<'a> {
+    /// A dynamically bound variable and its previous value, if any.
+    Var(Slot<Symbol<'a>>, Option<Slot<Object<'a>>>),
+    /// A let binding of a buffer-local variable, along with the buffer it was
+    /// bound in.
+    Local(Slot<Symbol<'a>>, Option<Slot<Object<'a>>>, &'a LispBuffer),
+    /// A buffer to make current again. Pushed by `save-current-buffer`.
+    #[no_trace]
+    Buffer(&'a LispBuffer),
+    /// A buffer restriction to reinstate. Pushed by `save-restriction`.
+    #[no_trace]
+    Restriction(SavedRestriction),
+}
+
+impl<'new> IntoRoot<Binding<'new>> for Binding<'_> {
+    unsafe fn into_root(self) -> Binding<'new> {
+        self.with_lifetime()
+    }
+}
+
+impl<'old, 'new> WithLifetime<'new> for Binding<'old> {
+    type Out = Binding<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<Binding<'old>, Binding<'new>>(self)
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct CurrentBuffer<'a> {
     buffer: OnceCell<OpenBuffer<'a>>,
@@ -44,6 +86,39 @@ impl Default for CurrentBuffer<'_> {
     }
 }
 
+impl Trace for CurrentBuffer<'_> {
+    fn trace(&self, state: &mut GcState) {
+        match self.buffer.get() {
+            Some(buffer) => buffer.get().trace(state),
+            None => {
+                if let Ok(buffer) = self.buf_ref.lock() {
+                    buffer.get().trace(state);
+                }
+            }
+        }
+        // Buffers are not traced as objects, so the ones that are not current
+        // are kept alive through the buffer list.
+        for buffer in crate::buffer::BUFFERS.lock().unwrap().values() {
+            if *buffer != self.buf_ref {
+                buffer.trace_contents(state);
+            }
+        }
+    }
+}
+
+impl<'a> RootedDeref for CurrentBuffer<'a> {
+    type Target = CurrentBuffer<'a>;
+
+    fn rooted_deref(rooted: &Rt<Self>) -> &Self::Target {
+        // SAFETY: The buffer data is only accessed through `get` and `get_mut`
+        unsafe { &*(rooted as *const Rt<Self>).cast::<Self>() }
+    }
+
+    fn rooted_derefmut(rooted: &mut Rt<Self>) -> &mut Self::Target {
+        unsafe { &mut *(rooted as *mut Rt<Self>).cast::<Self>() }
+    }
+}
+
 impl<'a> CurrentBuffer<'a> {
     fn lock(&self) -> OpenBuffer<'a> {
         unsafe { self.buf_ref.lock().unwrap().with_lifetime() }
@@ -81,7 +156,35 @@ impl PartialEq<LispBuffer> for CurrentBuffer<'_> {
 
 // RootedEnv created by #[derive(Trace)]
 impl<'a> RootedEnv<'a> {
+    /// The value of `sym` in the current buffer. This is the buffer-local value
+    /// if there is one, and the default value otherwise.
+    pub(crate) fn var<'ob>(&self, sym: Symbol, cx: &'ob Context) -> Option<Object<'ob>> {
+        match self.current_buffer.get().local(sym, cx) {
+            Some(local) => local,
+            None => self.vars.get(sym).map(|x| x.bind(cx)),
+        }
+    }
+
+    /// Set the value of `sym` in the current buffer. If the variable is
+    /// automatically buffer-local, this creates a local binding unless the
+    /// default value is let-bound.
     pub(crate) fn set_var(&mut self, sym: Symbol, value: Object) -> Result<()> {
+        if sym.is_const() {
+            return Err(anyhow!("Attempt to set a constant symbol: {sym}"));
+        }
+        let make_local = sym.is_buffer_local() && !self.let_binds_default(sym);
+        let buffer = self.current_buffer.get_mut();
+        if make_local || buffer.has_local(sym) {
+            buffer.set_local(sym, Some(value));
+        } else {
+            self.vars.insert(sym, value);
+        }
+        Ok(())
+    }
+
+    /// Set the default value of `sym`, which is seen in buffers that do not
+    /// have a local binding.
+    pub(crate) fn set_default(&mut self, sym: Symbol, value: Object) -> Result<()> {
         if sym.is_const() {
             Err(anyhow!("Attempt to set a constant symbol: {sym}"))
         } else {
@@ -90,6 +193,12 @@ impl<'a> RootedEnv<'a> {
         }
     }
 
+    fn let_binds_default(&self, var: Symbol) -> bool {
+        self.binding_stack
+            .iter()
+            .any(|binding| matches!(&**binding, RootedBinding::Var(sym, _) if *sym == var))
+    }
+
     pub(crate) fn set_prop(&mut self, symbol: Symbol, propname: Symbol, value: Object) {
         match self.props.get_mut(symbol) {
             Some(plist) => match plist.iter_mut().find(|x| x.0 == propname) {
@@ -114,36 +223,156 @@ impl<'a> RootedEnv<'a> {
     }
 
     pub(crate) fn varbind(&mut self, var: Symbol, value: Object, cx: &Context) {
-        let prev_value = self.vars.get(var).map(|x| x.bind(cx));
-        self.binding_stack.push((var, prev_value));
+        // If the variable is local to the current buffer, only that binding is
+        // affected, even if a different buffer is current when it is unbound.
+        let buffer = self.current_buffer.get_mut();
+        if let Some(prev_value) = buffer.local(var, cx) {
+            buffer.set_local(var, Some(value));
+            let buffer = self.current_buffer.buf_ref;
+            let binding = Binding::Local(Slot::new(var), prev_value.map(Slot::new), buffer);
+            self.binding_stack.push(binding);
+            return;
+        }
+        let prev_value = self.vars.get(var).map(|x| Slot::new(x.bind(cx)));
+        self.binding_stack.push(Binding::Var(Slot::new(var), prev_value));
         self.vars.insert(var, value);
     }
 
+    /// Record the buffer that the match data refers to.
+    pub(crate) fn set_match_buffer(&mut self, buffer: Option<&LispBuffer>) {
+        // SAFETY: buffers are allocated in the global block and never move
+        self.match_buffer = buffer.map(|x| unsafe { x.with_lifetime() });
+    }
+
+    /// Record the current buffer so that it is made current again when this
+    /// entry is unbound.
+    pub(crate) fn save_current_buffer(&mut self) {
+        let buffer = self.current_buffer.buf_ref;
+        self.binding_stack.push(Binding::Buffer(buffer));
+    }
+
+    /// Record the restriction of the current buffer so that it is reinstated
+    /// when this entry is unbound.
+    pub(crate) fn save_restriction(&mut self) {
+        let saved = self.current_restriction();
+        self.binding_stack.push(Binding::Restriction(saved));
+    }
+
+    pub(crate) fn current_restriction(&mut self) -> SavedRestriction {
+        // SAFETY: buffers are allocated in the global block and never move
+        let buffer = unsafe { self.current_buffer.buf_ref.with_lifetime() };
+        let bounds = self.current_buffer.get_mut().save_restriction();
+        SavedRestriction { buffer, bounds }
+    }
+
+    pub(crate) fn restore_restriction(&mut self, saved: &SavedRestriction) {
+        // If the buffer was killed there is nothing to restore
+        let _ = self.with_buffer_mut(saved.buffer, |b| b.restore_restriction(&saved.bounds));
+    }
+
     pub(crate) fn unbind(&mut self, count: u16, cx: &Context) {
         for _ in 0..count {
             match self.binding_stack.bind_mut(cx).pop() {
-                Some((sym, val)) => match val {
+                Some(Binding::Var(sym, val)) => match val {
                     Some(val) => self.vars.insert(*sym, *val),
                     None => self.vars.remove(*sym),
                 },
+                Some(Binding::Local(sym, val, buffer)) => {
+                    // If the local binding was killed there is nothing to restore
+                    let _ = self.with_buffer_mut(buffer, |b| {
+                        if b.has_local(*sym) {
+                            b.set_local(*sym, val.as_ref().map(|x| **x));
+                        }
+                    });
+                }
+                Some(Binding::Buffer(buffer)) => self.set_buffer(buffer),
+                Some(Binding::Restriction(saved)) => self.restore_restriction(&saved),
                 None => panic!("Binding stack was empty"),
             }
         }
     }
 
+    pub(crate) fn binding_depth(&self) -> usize {
+        self.binding_stack.len()
+    }
+
+    /// The variables that were dynamically bound in `range` of the binding
+    /// stack, oldest first.
+    pub(crate) fn bound_vars<'ob>(
+        &self,
+        range: Range<usize>,
+        cx: &'ob Context,
+    ) -> Vec<Symbol<'ob>> {
+        self.binding_stack[range]
+            .iter()
+            .filter_map(|binding| match &**binding {
+                RootedBinding::Var(sym, _) | RootedBinding::Local(sym, _, _) => Some(sym.bind(cx)),
+                RootedBinding::Buffer(_) | RootedBinding::Restriction(_) => None,
+            })
+            .collect()
+    }
+
+    /// Swap the values of the bindings made above `depth` with the values they
+    /// shadow, so that variables have the values they had at `depth`. Calling
+    /// this again with `rewind` unset reinstates the bindings.
+    pub(crate) fn swap_bindings(&mut self, depth: usize, rewind: bool, cx: &Context) {
+        let len = self.binding_stack.len();
+        if rewind {
+            (depth..len).rev().for_each(|idx| self.swap_binding(idx, cx));
+        } else {
+            (depth..len).for_each(|idx| self.swap_binding(idx, cx));
+        }
+    }
+
+    fn swap_binding(&mut self, idx: usize, cx: &Context) {
+        match &*self.binding_stack[idx] {
+            RootedBinding::Var(sym, prev) => {
+                let sym = sym.bind(cx);
+                let value = prev.as_ref().map(|x| x.bind(cx));
+                let current = self.vars.get(sym).map(|x| x.bind(cx));
+                match value {
+                    Some(value) => self.vars.insert(sym, value),
+                    None => self.vars.remove(sym),
+                }
+                if let RootedBinding::Var(_, prev) = &mut *self.binding_stack[idx] {
+                    prev.set(current);
+                }
+            }
+            RootedBinding::Local(sym, prev, buffer) => {
+                let sym = sym.bind(cx);
+                let value = prev.as_ref().map(|x| x.bind(cx));
+                let buffer = *buffer.bind_ref(cx);
+                let current = self.with_buffer_mut(buffer, |b| {
+                    let current = b.local(sym, cx)?;
+                    b.set_local(sym, value);
+                    Some(current)
+                });
+                if let (Ok(Some(current)), RootedBinding::Local(_, prev, _)) =
+                    (current, &mut *self.binding_stack[idx])
+                {
+                    prev.set(current);
+                }
+            }
+            RootedBinding::Buffer(_) | RootedBinding::Restriction(_) => {}
+        }
+    }
+
     pub(crate) fn defvar(&mut self, var: Symbol, value: Object) -> Result<()> {
         // TOOD: Handle `eval-sexp` on defvar, which should always update the
         // value
         if self.vars.get(var).is_none() {
-            self.set_var(var, value)?;
+            self.set_default(var, value)?;
             var.make_special();
         }
 
         // If this variable was unbound previously in the binding stack,
         // we will bind it to the new value
         for binding in &mut *self.binding_stack {
-            if binding.0 == var && binding.1.is_none() {
-                binding.1.set(Some(value));
+            match &mut **binding {
+                RootedBinding::Var(sym, prev) if *sym == var && prev.is_none() => {
+                    prev.set(Some(value));
+                }
+                _ => {}
             }
         }
         Ok(())
@@ -153,7 +382,7 @@ impl<'a> RootedEnv<'a> {
         if buffer == self.current_buffer.buf_ref {
             return;
         }
-        self.current_buffer.set(buffer);
+        (*self.current_buffer).set(buffer);
     }
 
     pub(crate) fn with_buffer<T>(
@@ -161,7 +390,7 @@ impl<'a> RootedEnv<'a> {
         buffer: &LispBuffer,
         mut func: impl FnMut(&OpenBuffer) -> T,
     ) -> Result<T> {
-        if self.current_buffer == *buffer {
+        if *self.current_buffer == *buffer {
             Ok(func(self.current_buffer.get()))
         } else {
             let buffer = buffer.lock()?;
@@ -174,7 +403,7 @@ impl<'a> RootedEnv<'a> {
         buffer: &LispBuffer,
         mut func: impl FnMut(&mut OpenBuffer) -> T,
     ) -> Result<T> {
-        if self.current_buffer == *buffer {
+        if *self.current_buffer == *buffer {
             Ok(func(self.current_buffer.get_mut()))
         } else {
             let mut buffer = buffer.lock()?;
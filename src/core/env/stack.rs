@@ -3,6 +3,7 @@ use crate::core::{
     object::{ByteFn, NIL, Object, WithLifetime},
 };
 use rune_macros::Trace;
+use std::fmt::{self, Display};
 use std::ops::{Deref, DerefMut, Index, IndexMut, RangeBounds, RangeTo};
 
 /// The stack of lisp objects used to pass and store arguments in the bytecode
@@ -19,6 +20,9 @@ pub(crate) struct LispStack<'a> {
     #[no_trace]
     current: Frame,
     frames: Vec<FrameStore<'a>>,
+    /// The function called in each frame above the base frame, used for
+    /// backtraces. This is always the same length as `frames`.
+    calls: Vec<FrameCall<'a>>,
 }
 
 /// A function call frame. These mirror the lisp call stack and are used to
@@ -56,6 +60,68 @@ struct FrameStore<'a> {
     bytecode: Option<ByteFrame<'a>>,
 }
 
+/// The function that a frame is calling, along with the depth of the binding
+/// stack when it was called. The function is `nil` until the arguments are
+/// finalized.
+#[derive(Debug, Clone, Trace)]
+struct FrameCall<'a> {
+    func: Slot<Object<'a>>,
+    #[no_trace]
+    evaluated: bool,
+    #[no_trace]
+    binding_depth: usize,
+    /// The last recorded bytecode offset of the function, if it is bytecode.
+    #[no_trace]
+    pc: Option<usize>,
+}
+
+impl<'ob> FrameCall<'ob> {
+    fn new(func: Object<'ob>, binding_depth: usize) -> Self {
+        Self { func: Slot::new(func), evaluated: true, binding_depth, pc: None }
+    }
+}
+
+impl<'new> IntoRoot<FrameCall<'new>> for FrameCall<'_> {
+    unsafe fn into_root(self) -> FrameCall<'new> {
+        std::mem::transmute::<FrameCall<'_>, FrameCall<'new>>(self)
+    }
+}
+
+/// A function call that is active on the stack.
+pub(crate) struct Backtrace<'brw, 'a> {
+    pub(crate) func: &'brw Rto<Object<'a>>,
+    pub(crate) args: &'brw [Rto<Object<'a>>],
+    /// False if `args` are the unevaluated argument forms, such as for a macro.
+    pub(crate) evaluated: bool,
+    /// The depth of the binding stack when the function was called.
+    pub(crate) binding_depth: usize,
+    /// The offset into the bytecode where the function was last executing.
+    pub(crate) pc: Option<usize>,
+}
+
+impl Display for Backtrace<'_, '_> {
+    /// Frames are shown as `func(args...)` like Emacs, or `(func args...)` if
+    /// the arguments were not evaluated.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.evaluated {
+            write!(f, "{}(", self.func)?;
+        } else {
+            write!(f, "({}", self.func)?;
+        }
+        for (idx, arg) in self.args.iter().enumerate() {
+            if idx > 0 || !self.evaluated {
+                write!(f, " ")?;
+            }
+            write!(f, "{arg}")?;
+        }
+        write!(f, ")")?;
+        if let Some(pc) = self.pc {
+            write!(f, " [pc {pc}]")?;
+        }
+        Ok(())
+    }
+}
+
 impl<'new> IntoRoot<FrameStore<'new>> for FrameStore<'_> {
     unsafe fn into_root(self) -> FrameStore<'new> {
         self.with_lifetime()
@@ -130,16 +196,23 @@ impl<'a> Index<RangeTo<usize>> for RootedLispStack<'a> {
 }
 
 impl<'a> RootedLispStack<'a> {
+    /// Push a frame for a bytecode function called directly from the VM. The
+    /// bottom of the frame holds `callee` and is followed by the arguments.
+    /// `func` and `pc` are the caller's position to return to.
     pub(crate) fn push_bytecode_frame(
         &mut self,
         start: usize,
         depth: usize,
         func: &ByteFn,
         pc: usize,
+        callee: Object,
+        binding_depth: usize,
     ) {
         assert!(start <= self.len());
         assert!(self.current.start <= start);
         self.frames.push(FrameStore::new_bytecode(self.current, func, pc));
+        self.set_frame_pc(pc);
+        self.calls.push(FrameCall::new(callee, binding_depth));
         let end = start + depth;
         // allocate space so that we don't have to reallocate later. This will
         // also let us do unchecked pushes later.
@@ -155,6 +228,7 @@ impl<'a> RootedLispStack<'a> {
         let start = self.len() - arg_cnt;
         assert!(self.current.start <= start);
         self.frames.push(FrameStore::new(self.current));
+        self.calls.push(FrameCall::new(NIL, 0));
         self.current =
             Frame { start, arg_cnt: (u16::try_from(arg_cnt).unwrap(), false), ..Frame::default() };
     }
@@ -165,6 +239,54 @@ impl<'a> RootedLispStack<'a> {
         self.vec.truncate(self.current.start);
         self.current = self.frames.last().unwrap().frame;
         self.frames.pop();
+        self.calls.pop();
+    }
+
+    /// Record the function called by the current frame, if it has not already
+    /// been set. Functions called through a symbol keep the symbol.
+    /// `evaluated` is false if the arguments are unevaluated forms.
+    pub(crate) fn set_frame_function(
+        &mut self,
+        func: Object,
+        evaluated: bool,
+        binding_depth: usize,
+    ) {
+        if let Some(call) = self.calls.last_mut().filter(|call| call.func == NIL) {
+            call.func.set(func);
+            call.evaluated = evaluated;
+            call.binding_depth = binding_depth;
+        }
+    }
+
+    /// Record the bytecode offset that the current frame is executing.
+    pub(crate) fn set_frame_pc(&mut self, pc: usize) {
+        if let Some(call) = self.calls.last_mut() {
+            call.pc = Some(pc);
+        }
+    }
+
+    /// Iterate over the active function calls, starting with the innermost.
+    pub(crate) fn backtrace(&self) -> impl Iterator<Item = Backtrace<'_, 'a>> {
+        (1..=self.frames.len()).rev().filter_map(|idx| {
+            let call = &self.calls[idx - 1];
+            if call.func == NIL {
+                return None;
+            }
+            let frame = match self.frames.get(idx) {
+                Some(store) => store.frame,
+                None => self.current,
+            };
+            let start = frame.start + usize::from(self.frames[idx - 1].bytecode.is_some());
+            let end = (start + usize::from(frame.arg_cnt.0)).min(self.len());
+            let args: &[Rto<Object>] = &self.vec[start.min(end)..end];
+            Some(Backtrace {
+                func: &call.func,
+                args,
+                evaluated: call.evaluated,
+                binding_depth: call.binding_depth,
+                pc: call.pc,
+            })
+        })
     }
 
     pub(crate) fn get_bytecode_frame(&self, idx: usize) -> Option<(&Rto<&'a ByteFn>, usize)> {
@@ -190,6 +312,7 @@ impl<'a> RootedLispStack<'a> {
         assert!(frame < self.current_frame());
         self.current = self.frames[frame].frame;
         self.frames.truncate(frame);
+        self.calls.truncate(frame);
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -285,11 +408,6 @@ impl<'a> RootedLispStack<'a> {
         self.len() - self.current.start
     }
 
-    pub(crate) fn current_args(&self) -> &[Rto<Object<'a>>] {
-        // index as vec
-        &self.vec[self.current.start..]
-    }
-
     pub(crate) fn arg_slice(&self, arg_slice: ArgSlice) -> &[Rto<Object<'a>>] {
         // index as stack
         &self[..arg_slice.0]
@@ -336,10 +454,6 @@ impl<'brw, 'rt> CallFrame<'brw, 'rt> {
         count1
     }
 
-    pub(crate) fn arg_slice(&self) -> &[Rto<Object<'rt>>] {
-        &self.env.stack[..self.arg_count()]
-    }
-
     /// Push a slice of arguments onto the stack as part of this call frame.
     pub(crate) fn push_arg_slice(&mut self, src: &[Object]) {
         self.env.stack.extend_from_slice(src);
@@ -64,6 +64,11 @@ impl SymbolMap {
         unsafe { symbol.set_func(new_func) }
     }
 
+    /// The number of interned symbols.
+    pub(crate) fn len(&self) -> usize {
+        self.map.map.len()
+    }
+
     pub(crate) fn global_block(&self) -> &Block<true> {
         &self.block
     }
@@ -1,4 +1,9 @@
+use super::env::sym;
+use super::gc::{Context, GcState, Slot, Trace};
+use super::object::{Object, ObjectType, Symbol, TagType, WithLifetime};
+use std::cell::RefCell;
 use std::fmt::{Display, Formatter};
+use std::thread::ThreadId;
 
 #[derive(Debug, PartialEq)]
 pub(crate) enum Type {
@@ -20,6 +25,39 @@ pub(crate) enum Type {
     List,
     Buffer,
     CharTable,
+    Marker,
+    Overlay,
+    NumberOrMarker,
+    IntOrMarker,
+}
+
+impl Type {
+    /// The predicate that is reported in a `wrong-type-argument` signal.
+    pub(crate) fn predicate(&self) -> Symbol<'static> {
+        match self {
+            Type::Int => sym::INTEGERP,
+            Type::Char => sym::CHARACTERP,
+            Type::Cons => sym::CONSP,
+            Type::Vec => sym::VECTORP,
+            Type::Record => sym::RECORDP,
+            Type::HashTable => sym::HASH_TABLE_P,
+            Type::Sequence => sym::SEQUENCEP,
+            Type::BufferOrName | Type::String => sym::STRINGP,
+            Type::BufferOrString => sym::BUFFER_OR_STRING_P,
+            Type::StringOrChar => sym::CHAR_OR_STRING_P,
+            Type::Symbol => sym::SYMBOLP,
+            Type::Float => sym::FLOATP,
+            Type::Func => sym::FUNCTIONP,
+            Type::Number => sym::NUMBERP,
+            Type::List => sym::LISTP,
+            Type::Buffer => sym::BUFFERP,
+            Type::CharTable => sym::CHAR_TABLE_P,
+            Type::Marker => sym::MARKERP,
+            Type::Overlay => sym::OVERLAYP,
+            Type::NumberOrMarker => sym::NUMBER_OR_MARKER_P,
+            Type::IntOrMarker => sym::INTEGER_OR_MARKER_P,
+        }
+    }
 }
 
 /// Error provided if object was the wrong type
@@ -27,15 +65,15 @@ pub(crate) enum Type {
 pub(crate) struct TypeError {
     expect: Type,
     actual: Type,
-    print: String,
+    value: ErrorData,
 }
 
 impl std::error::Error for TypeError {}
 
 impl Display for TypeError {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        let Self { expect, actual, print } = self;
-        write!(f, "expected {expect:?}, found {actual:?}: {print}")
+        let Self { expect, actual, value } = self;
+        write!(f, "expected {expect:?}, found {actual:?}: {value}")
     }
 }
 
@@ -46,6 +84,191 @@ impl TypeError {
         T: Into<super::object::ObjectType<'ob>>,
     {
         let obj = obj.into();
-        Self { expect, actual: obj.get_type(), print: obj.to_string() }
+        let value = ErrorData::new(&[obj.tag()]);
+        Self { expect, actual: obj.get_type(), value }
+    }
+
+    /// The data of the `wrong-type-argument` signal for this error.
+    pub(crate) fn data<'ob>(&self, cx: &'ob Context) -> (Symbol<'static>, Object<'ob>) {
+        (self.expect.predicate(), self.value.bind(cx)[0])
+    }
+}
+
+/// Standard errors that don't need any allocation to create. They are converted
+/// to a lisp signal like `(void-variable foo)` when they are handled.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SignalError {
+    VoidVariable(ErrorData),
+    VoidFunction(ErrorData),
+    ArgsOutOfRange(ErrorData),
+    Overflow,
+    BeginningOfBuffer,
+    EndOfBuffer,
+    /// An attempt to modify text with a `read-only` property. Holds the value
+    /// of the property if it is a string.
+    TextReadOnly(Option<ErrorData>),
+}
+
+impl std::error::Error for SignalError {}
+
+impl Display for SignalError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            SignalError::VoidVariable(sym) => write!(f, "Void variable: {sym}"),
+            SignalError::VoidFunction(sym) => write!(f, "Void function: {sym}"),
+            SignalError::ArgsOutOfRange(args) => write!(f, "Args out of range: {args}"),
+            SignalError::Overflow => write!(f, "Arithmetic overflow error"),
+            SignalError::BeginningOfBuffer => write!(f, "Beginning of buffer"),
+            SignalError::EndOfBuffer => write!(f, "End of buffer"),
+            SignalError::TextReadOnly(None) => write!(f, "Text is read-only"),
+            SignalError::TextReadOnly(Some(msg)) => write!(f, "Text is read-only: {msg}"),
+        }
+    }
+}
+
+impl SignalError {
+    pub(crate) fn void_variable(var: Symbol) -> Self {
+        Self::VoidVariable(ErrorData::new(&[var.into()]))
+    }
+
+    pub(crate) fn void_function(func: Symbol) -> Self {
+        Self::VoidFunction(ErrorData::new(&[func.into()]))
+    }
+
+    pub(crate) fn args_out_of_range(args: &[Object]) -> Self {
+        Self::ArgsOutOfRange(ErrorData::new(args))
+    }
+
+    pub(crate) fn text_read_only(value: Object) -> Self {
+        let value = matches!(value.untag(), ObjectType::String(_)).then_some(value);
+        Self::TextReadOnly(value.map(|x| ErrorData::new(&[x])))
+    }
+
+    /// The error symbol that this error is signaled with.
+    pub(crate) fn symbol(&self) -> Symbol<'static> {
+        match self {
+            SignalError::VoidVariable(_) => sym::VOID_VARIABLE,
+            SignalError::VoidFunction(_) => sym::VOID_FUNCTION,
+            SignalError::ArgsOutOfRange(_) => sym::ARGS_OUT_OF_RANGE,
+            SignalError::Overflow => sym::OVERFLOW_ERROR,
+            SignalError::BeginningOfBuffer => sym::BEGINNING_OF_BUFFER,
+            SignalError::EndOfBuffer => sym::END_OF_BUFFER,
+            SignalError::TextReadOnly(_) => sym::TEXT_READ_ONLY,
+        }
+    }
+
+    /// The data of the signal, which will be consed onto the error symbol.
+    pub(crate) fn data<'ob>(&self, cx: &'ob Context) -> Vec<Object<'ob>> {
+        match self {
+            SignalError::VoidVariable(data)
+            | SignalError::VoidFunction(data)
+            | SignalError::ArgsOutOfRange(data)
+            | SignalError::TextReadOnly(Some(data)) => data.bind(cx),
+            SignalError::Overflow
+            | SignalError::BeginningOfBuffer
+            | SignalError::EndOfBuffer
+            | SignalError::TextReadOnly(None) => Vec::new(),
+        }
+    }
+}
+
+type ErrorObjects = Box<[Slot<Object<'static>>]>;
+
+thread_local! {
+    /// The lisp objects held by errors that are still live on this thread.
+    /// Free entries are `None`.
+    static ERROR_DATA: RefCell<Vec<Option<ErrorObjects>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Trace the objects held by every live error.
+pub(in crate::core) fn trace_error_data(state: &mut GcState) {
+    ERROR_DATA.with_borrow(|table| {
+        for objects in table.iter().flatten() {
+            objects.trace(state);
+        }
+    });
+}
+
+/// Lisp objects carried by an error raised from Rust. The objects live in a
+/// table that is traced by the collector, so they stay valid if the error is
+/// held across a garbage collection, like when cleanup forms run while it
+/// unwinds.
+#[derive(Debug)]
+pub(crate) struct ErrorData {
+    index: usize,
+    thread: ThreadId,
+}
+
+impl ErrorData {
+    pub(crate) fn new(objects: &[Object]) -> Self {
+        let objects = objects.iter().map(|x| Slot::new(unsafe { x.with_lifetime() })).collect();
+        let index =
+            ERROR_DATA.with_borrow_mut(|table| match table.iter().position(Option::is_none) {
+                Some(i) => {
+                    table[i] = Some(objects);
+                    i
+                }
+                None => {
+                    table.push(Some(objects));
+                    table.len() - 1
+                }
+            });
+        Self { index, thread: std::thread::current().id() }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&[Slot<Object<'static>>]) -> R) -> R {
+        assert_eq!(self.thread, std::thread::current().id(), "error data used on another thread");
+        ERROR_DATA.with_borrow(|table| f(table[self.index].as_deref().unwrap()))
+    }
+
+    pub(crate) fn bind<'ob>(&self, cx: &'ob Context) -> Vec<Object<'ob>> {
+        self.with(|objects| objects.iter().map(|x| cx.bind(**x)).collect())
+    }
+}
+
+impl Drop for ErrorData {
+    fn drop(&mut self) {
+        // The table is only reachable from the thread that raised the error
+        if self.thread == std::thread::current().id() {
+            let _ = ERROR_DATA.try_with(|table| table.borrow_mut()[self.index] = None);
+        }
+    }
+}
+
+impl PartialEq for ErrorData {
+    fn eq(&self, other: &Self) -> bool {
+        self.with(|x| other.with(|y| x == y))
+    }
+}
+
+impl Display for ErrorData {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        self.with(|objects| {
+            for (i, obj) in objects.iter().enumerate() {
+                if i != 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", **obj)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::{cons::Cons, gc::RootSet};
+    use rune_core::macros::list;
+
+    #[test]
+    fn type_error_display() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let cons = Cons::new(1, list![2, 3; cx], cx);
+        let err = TypeError::new(Type::Int, Object::from(cons));
+        // The value is printed when the error is displayed, not when it is made
+        cons.set_car(cx.add("one")).unwrap();
+        assert_eq!(err.to_string(), r#"expected Int, found Cons: ("one" 2 3)"#);
     }
 }
@@ -3,7 +3,11 @@ mod trace;
 #[macro_use]
 mod context;
 mod heap;
+mod immix;
+mod stats;
 pub(crate) use context::*;
 pub(crate) use heap::*;
+pub(in crate::core) use immix::*;
 pub(crate) use root::*;
+pub(crate) use stats::*;
 pub(crate) use trace::*;
@@ -1,13 +1,15 @@
-use super::GcState;
 use super::Trace;
+use super::{AllocState, GcState, OldSpace, clear_remembered_set, trace_remembered_set};
+use super::{GcStats, HeapCounts};
 use crate::core::object::GcString;
-use crate::core::object::LispHashTable;
 use crate::core::object::{Gc, IntoObject, Object, UninternedSymbolMap, WithLifetime};
+use crate::core::object::{LispHashTable, LispMarker, LispString};
 use bumpalo::collections::Vec as GcVec;
 use std::cell::{Cell, RefCell};
 use std::fmt::Debug;
 use std::ops::Deref;
-use std::sync::atomic::AtomicBool;
+use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::time::Instant;
 
 /// A global store of all gc roots. This struct should be passed to the [Context]
 /// when it is created.
@@ -21,6 +23,7 @@ pub(crate) struct RootSet {
 pub(in crate::core) enum DropStackElem {
     String(String),
     ByteString(Vec<u8>),
+    BigInt(Vec<u32>),
     Vec(Vec<Object<'static>>),
 }
 
@@ -39,7 +42,15 @@ pub(crate) struct Block<const CONST: bool> {
     // track of the memory and free it only after the table is garbage
     // collected. Kind of a hack.
     pub(in crate::core) lisp_hashtables: RefCell<Vec<*const LispHashTable>>,
+    // Strings with text properties, which own their interval tree in the same
+    // way.
+    pub(in crate::core) string_props: RefCell<Vec<*const LispString>>,
+    // Markers hold a handle to their position in a buffer. The buffer stops
+    // updating the position once the handle is dropped.
+    pub(in crate::core) lisp_markers: RefCell<Vec<*const LispMarker>>,
     pub(in crate::core) uninterned_symbol_map: UninternedSymbolMap,
+    /// Every object that has been allocated in this block.
+    pub(crate) allocated: HeapCounts,
 }
 
 unsafe impl<const C: bool> Send for Block<C> {}
@@ -47,16 +58,27 @@ unsafe impl<const C: bool> Send for Block<C> {}
 /// Owns all allocations and creates objects. All objects have
 /// a lifetime tied to the borrow of their `Context`. When the
 /// `Context` goes out of scope, no objects should be accessible.
+///
+/// The heap has two generations. New objects are allocated in the nursery
+/// (`block`), and a minor collection copies everything that is still live into
+/// the old generation. A major collection marks the old generation in place
+/// and frees anything that is no longer reachable.
 pub(crate) struct Context<'rt> {
     pub(crate) block: Block<false>,
+    old: OldSpace,
     root_set: &'rt RootSet,
-    next_limit: usize,
+    /// Size of the old generation after the last major collection.
+    major_base: usize,
+    cons_threshold: usize,
+    cons_percentage: f64,
+    stress: bool,
+    stats: GcStats,
 }
 
 impl Drop for Context<'_> {
     fn drop(&mut self) {
         self.garbage_collect(true);
-        if self.block.objects.allocated_bytes() == 0 {
+        if self.old.is_empty() {
             return;
         }
         if std::thread::panicking() {
@@ -70,8 +92,14 @@ impl Drop for Context<'_> {
 thread_local! {
     /// Ensure there is only one context per thread.
     static SINGLETON_CHECK: Cell<bool> = const { Cell::new(false) };
+    /// The id of the last context created on this thread.
+    static CONTEXT_ID: Cell<u64> = const { Cell::new(0) };
 }
 
+/// Every context gets a new id, so that data kept outside of the heap can tell
+/// which context its objects were allocated by.
+static NEXT_CONTEXT_ID: AtomicU64 = AtomicU64::new(1);
+
 /// Ensure there is only one global context.
 static GLOBAL_CHECK: AtomicBool = AtomicBool::new(false);
 
@@ -84,6 +112,10 @@ impl Block<true> {
 }
 
 impl Block<false> {
+    /// Byte that freed nursery memory is filled with in stress mode. Objects
+    /// read from it have an invalid tag.
+    const POISON: u8 = 0xA5;
+
     pub(crate) fn new_local() -> Self {
         Self::assert_unique();
         Self::default()
@@ -93,6 +125,19 @@ impl Block<false> {
         Self::default()
     }
 
+    /// Overwrite everything allocated in the nursery. This is done after a
+    /// collection, so that a pointer to an object that was moved out of the
+    /// nursery fails as soon as it is used, instead of reading the stale copy.
+    fn poison_nursery(&mut self) {
+        // SAFETY: Nothing is allocated while iterating, and every object left
+        // in the nursery is garbage after a collection.
+        unsafe {
+            for (ptr, len) in self.objects.iter_allocated_chunks_raw() {
+                std::ptr::write_bytes(ptr, Self::POISON, len);
+            }
+        }
+    }
+
     pub(crate) fn assert_unique() {
         SINGLETON_CHECK.with(|x| {
             assert!(!x.get(), "There was already and active context when this context was created");
@@ -138,15 +183,74 @@ impl<const CONST: bool> Block<CONST> {
 }
 
 impl<'ob, 'rt> Context<'rt> {
-    const MIN_GC_BYTES: usize = 2000;
-    const GC_GROWTH_FACTOR: usize = 12; // divide by 10
+    /// Default value of `gc-cons-threshold`.
+    pub(crate) const DEFAULT_CONS_THRESHOLD: usize = 800_000;
+    /// Default value of `gc-cons-percentage`.
+    pub(crate) const DEFAULT_CONS_PERCENTAGE: f64 = 0.1;
+    /// Minimum size of the old generation that triggers a major collection.
+    const MIN_GC_BYTES: usize = 8 * 1024 * 1024;
     pub(crate) fn new(roots: &'rt RootSet) -> Self {
-        Self { block: Block::new_local(), root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Self::from_block_unchecked(Block::new_local(), roots)
     }
 
     pub(crate) fn from_block(block: Block<false>, roots: &'rt RootSet) -> Self {
         Block::assert_unique();
-        Context { block, root_set: roots, next_limit: Self::MIN_GC_BYTES }
+        Self::from_block_unchecked(block, roots)
+    }
+
+    fn from_block_unchecked(block: Block<false>, roots: &'rt RootSet) -> Self {
+        CONTEXT_ID.set(NEXT_CONTEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed));
+        Context {
+            block,
+            old: OldSpace::default(),
+            root_set: roots,
+            major_base: 0,
+            cons_threshold: Self::DEFAULT_CONS_THRESHOLD,
+            cons_percentage: Self::DEFAULT_CONS_PERCENTAGE,
+            // Unit tests collect every time they are given the chance
+            stress: cfg!(test),
+            stats: GcStats::default(),
+        }
+    }
+
+    /// Set the values of `gc-cons-threshold` and `gc-cons-percentage`. A minor
+    /// collection is run once `threshold` bytes have been allocated in the
+    /// nursery. A major collection is run once the old generation has grown
+    /// by the larger of `threshold` and `percentage` of its size after the
+    /// last major collection.
+    pub(crate) fn set_gc_tuning(&mut self, threshold: usize, percentage: f64) {
+        // Like Emacs, don't let the threshold get so small that we are
+        // collecting all the time.
+        self.cons_threshold = threshold.max(Self::DEFAULT_CONS_THRESHOLD / 10);
+        self.cons_percentage = percentage.max(0.0);
+    }
+
+    /// The id of the context that is active on this thread.
+    pub(crate) fn current_id() -> u64 {
+        CONTEXT_ID.get()
+    }
+
+    pub(crate) fn gc_stats(&self) -> &GcStats {
+        &self.stats
+    }
+
+    /// Bytes reserved by the heap, including free space.
+    pub(crate) fn heap_size(&self) -> usize {
+        self.old.size() + self.block.objects.allocated_bytes()
+    }
+
+    /// When enabled, a collection is run every time [`Context::garbage_collect`]
+    /// is called, no matter how much has been allocated, and the memory freed
+    /// from the nursery is overwritten before it is allocated again. This is
+    /// very slow, but will quickly find objects that are not rooted or not
+    /// updated when they move.
+    ///
+    /// Stress mode only covers those safe points. Collections can't be run
+    /// from the allocator itself, because allocating only borrows the context
+    /// and unrooted objects are expected to stay valid until the next call to
+    /// `garbage_collect`.
+    pub(crate) fn set_gc_stress(&mut self, stress: bool) {
+        self.stress = stress;
     }
 
     pub(crate) fn bind<T>(&'ob self, obj: T) -> <T as WithLifetime<'ob>>::Out
@@ -160,13 +264,35 @@ impl<'ob, 'rt> Context<'rt> {
         self.root_set
     }
 
+    /// Bytes allocated in the nursery since the last collection.
+    fn nursery_bytes(&self) -> usize {
+        let nursery = &self.block.objects;
+        nursery.allocated_bytes() - nursery.chunk_capacity()
+    }
+
+    /// Size the old generation can grow to before a major collection is run.
+    fn major_limit(&self) -> usize {
+        let growth = (self.major_base as f64 * self.cons_percentage) as usize;
+        let limit = self.major_base.saturating_add(growth.max(self.cons_threshold));
+        limit.max(Self::MIN_GC_BYTES)
+    }
+
+    /// Run a collection if the heap has grown enough. A minor collection is
+    /// run once the nursery is full, and a major collection once the old
+    /// generation has grown past its limit. If `force` is true a major
+    /// collection is always run.
     pub(crate) fn garbage_collect(&mut self, force: bool) {
-        let bytes = self.block.objects.allocated_bytes();
-        if cfg!(not(test)) && !force && bytes < self.next_limit {
+        let major = force || self.old.size() >= self.major_limit();
+        if !self.stress && !major && self.nursery_bytes() < self.cons_threshold {
             return;
         }
 
-        let mut state = GcState::new();
+        let start = Instant::now();
+        let mut state = GcState::new(std::mem::take(&mut self.old));
+        if major {
+            state.to_space.start_major();
+            clear_remembered_set();
+        }
         for x in self.root_set.roots.borrow().iter() {
             // SAFETY: The contract of root structs will ensure that it removes
             // itself from this list before it drops.
@@ -174,26 +300,63 @@ impl<'ob, 'rt> Context<'rt> {
                 (**x).trace(&mut state);
             }
         }
+        crate::core::error::trace_error_data(&mut state);
+        if !major {
+            // Old objects are not traced in a minor collection, so anything
+            // they point to is only found through the remembered set.
+            trace_remembered_set(&mut state);
+        }
 
         state.trace_stack();
 
-        self.next_limit = (state.to_space.allocated_bytes() * Self::GC_GROWTH_FACTOR) / 10;
         self.block.drop_stack.borrow_mut().clear();
-        // Find all hashtables that have not been moved (i.e. They are no longer
-        // accessible) and drop them. Otherwise, update the object pointer.
-        self.block.lisp_hashtables.borrow_mut().retain_mut(|ptr| {
-            let table = unsafe { &**ptr };
-            if let Some(fwd) = table.forwarding_ptr() {
-                *ptr = fwd.as_ptr().cast::<LispHashTable>();
+        // Find all hashtables, property trees and markers that are no longer
+        // accessible and drop them.
+        let major_epoch = state.to_space.major_epoch();
+        drop_unreachable(&self.block.lisp_hashtables, major_epoch, LispHashTable::allocation_state);
+        drop_unreachable(&self.block.string_props, major_epoch, LispString::allocation_state);
+        drop_unreachable(&self.block.lisp_markers, major_epoch, LispMarker::allocation_state);
+
+        self.old = state.to_space;
+        if major {
+            self.old.sweep();
+            self.major_base = self.old.size();
+            self.stats.live = state.live;
+        } else {
+            self.stats.live.add(&state.live);
+        }
+        if self.stress {
+            self.block.poison_nursery();
+        }
+        self.block.objects.reset();
+
+        self.stats.gcs_done += 1;
+        self.stats.elapsed += start.elapsed();
+    }
+}
+
+/// Drop the objects in `objects` that did not survive a collection, and update
+/// the pointers to the ones that were moved. A young object is live if it was
+/// moved, and an old one if it was marked in this collection.
+fn drop_unreachable<T>(
+    objects: &RefCell<Vec<*const T>>,
+    major_epoch: Option<u8>,
+    allocation_state: impl Fn(&T) -> AllocState,
+) {
+    objects
+        .borrow_mut()
+        .retain_mut(|ptr| match allocation_state(unsafe { &**ptr }) {
+            AllocState::Forwarded(fwd) => {
+                *ptr = fwd.as_ptr().cast::<T>();
                 true
-            } else {
-                unsafe { std::ptr::drop_in_place(*ptr as *mut LispHashTable) };
+            }
+            AllocState::Old(mark) if major_epoch.is_none_or(|epoch| epoch == mark) => true,
+            AllocState::Global => panic!("global allocation found in local heap"),
+            AllocState::Old(_) | AllocState::Unmoved => {
+                unsafe { std::ptr::drop_in_place(*ptr as *mut T) };
                 false
             }
         });
-
-        self.block.objects = state.to_space;
-    }
 }
 
 impl Deref for Context<'_> {
@@ -227,7 +390,8 @@ mod test {
 
     use crate::core::{
         cons::Cons,
-        object::{HashTable, ObjectType, Symbol},
+        gc::HeapKind,
+        object::{HashTable, HashTest, LispHashTable, LispVec, NIL, ObjectType, Symbol},
     };
 
     use super::*;
@@ -267,7 +431,7 @@ mod test {
         println!("sym: {:?}", symbol.into_raw());
         let mut table = HashTable::default();
         table.insert(symbol, string);
-        let _ = table.get(&symbol).unwrap();
+        let _ = table.get(symbol).unwrap();
         root!(symbol, cx);
         let table = cx.add(table);
         let vec = vec![cons, table];
@@ -288,4 +452,131 @@ mod test {
         assert_eq!(**float, 1.5);
         assert_eq!(int, 1);
     }
+
+    #[test]
+    fn test_rehash_equal_keys() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        cx.set_gc_stress(false);
+        let mut table = HashTable::new(HashTest::Equal);
+        let symbol = cx.add(Symbol::new_uninterned("sym", cx));
+        for i in 0..10 {
+            table.insert(list![symbol, i; cx], cx.add(i));
+        }
+        let table: &LispHashTable = cx.add_as(table).untag();
+        root!(table, cx);
+        root!(symbol, cx);
+        cx.garbage_collect(true);
+        // The symbol inside the key is moved after the table is traced
+        for i in 0..10 {
+            let key = list![symbol.bind(cx), i; cx];
+            assert_eq!(table.bind(cx).get(key), Some(i.into()));
+        }
+    }
+
+    #[test]
+    fn test_gc_stats() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let list = list![1.5, "foo", vec![NIL, NIL]; cx];
+        root!(list, cx);
+        _ = list![1, 2, 3; cx];
+        assert_eq!(cx.allocated.objects(HeapKind::Cons), 6);
+        assert_eq!(cx.allocated.objects(HeapKind::Float), 1);
+        assert_eq!(cx.allocated.data(HeapKind::String), 3);
+
+        cx.garbage_collect(true);
+        let stats = cx.gc_stats();
+        assert_eq!(stats.gcs_done, 1);
+        assert_eq!(stats.live.objects(HeapKind::Cons), 3);
+        assert_eq!(stats.live.objects(HeapKind::Float), 1);
+        assert_eq!(stats.live.objects(HeapKind::String), 1);
+        assert_eq!(stats.live.data(HeapKind::Vec), 2 * size_of::<Object>());
+
+        // A minor collection only adds what was promoted
+        let cons: Object = Cons::new(2, 3, cx).into();
+        root!(cons, cx);
+        cx.garbage_collect(false);
+        assert_eq!(cx.gc_stats().live.objects(HeapKind::Cons), 4);
+        assert_eq!(cx.gc_stats().gcs_done, 2);
+    }
+
+    #[test]
+    fn test_old_multibyte_strings() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        cx.set_gc_stress(false);
+        let small = "λ".repeat(200);
+        // More bytes than a large object, but fewer chars
+        let large = "λ".repeat(6000);
+        root!(strings, new(Vec), cx);
+        strings.push(cx.add(small.as_str()));
+        strings.push(cx.add(large.as_str()));
+        for i in 0..5 {
+            cx.garbage_collect(true);
+            // Promote new strings into any lines that were freed
+            for j in 0..100 {
+                strings.push(cx.add(format!("{i}{j}").repeat(50)));
+            }
+            cx.garbage_collect(false);
+            assert_eq!(strings[0].bind(cx), small.as_str());
+            assert_eq!(strings[1].bind(cx), large.as_str());
+            strings.truncate(2);
+        }
+    }
+
+    #[test]
+    fn test_old_vectors_stay_in_place() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        cx.set_gc_stress(false);
+        let vec: &LispVec = cx.add_as(vec![cx.add("a"), cx.add(1)]).untag();
+        root!(vec, cx);
+        cx.garbage_collect(true);
+        let slice = vec.bind(cx).as_ptr();
+        for i in 0..5 {
+            vec.bind(cx).try_mut().unwrap()[1].set(cx.add(format!("{i}")));
+            cx.garbage_collect(i % 2 == 0);
+            let vec = vec.bind(cx);
+            assert_eq!(vec.as_ptr(), slice);
+            assert_eq!(vec[0].get(), "a");
+            assert_eq!(vec[1].get(), format!("{i}").as_str());
+        }
+    }
+
+    #[test]
+    fn test_cross_generation_pointers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let cons = Cons::new(NIL, NIL, cx);
+        root!(cons, cx);
+        let vec: &LispVec = cx.add_as(vec![NIL, NIL]).untag();
+        root!(vec, cx);
+        let table: &LispHashTable = cx.add_as(HashTable::default()).untag();
+        root!(table, cx);
+        // promote everything to the old generation
+        cx.garbage_collect(false);
+        for i in 0..50 {
+            // store young objects in the old ones
+            cons.bind(cx).set_car(cx.add(format!("car {i}"))).unwrap();
+            cons.bind(cx).set_cdr(list![i, "cdr"; cx]).unwrap();
+            vec.bind(cx).try_mut().unwrap()[1].set(cx.add(format!("vec {i}")));
+            table.bind(cx).insert(cx.add(i), cx.add(format!("table {i}")));
+            cx.garbage_collect(i % 10 == 9);
+            // Make sure the nursery memory is reused
+            for _ in 0..20 {
+                cx.add("garbage");
+            }
+
+            let cons = cons.bind(cx);
+            assert_eq!(cons.car(), format!("car {i}").as_str());
+            assert_eq!(cons.cdr(), list![i, "cdr"; cx]);
+            assert_eq!(vec.bind(cx)[1].get(), format!("vec {i}").as_str());
+            let table = table.bind(cx);
+            assert_eq!(table.len(), i as usize + 1);
+            for j in 0..=i {
+                assert_eq!(table.get(j.into()).unwrap(), format!("table {j}").as_str());
+            }
+        }
+    }
 }
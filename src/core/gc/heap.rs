@@ -1,7 +1,7 @@
-use super::{GcState, Trace};
+use super::{GcState, OldSpace, Trace};
 use std::{
     alloc::Layout,
-    cell::{Cell, UnsafeCell},
+    cell::{Cell, RefCell, UnsafeCell},
     fmt,
     mem::ManuallyDrop,
     ops::{Deref, DerefMut},
@@ -52,12 +52,51 @@ impl fmt::Debug for GcHeader {
 struct HeaderData {
     is_present: u8,
     marked: Cell<bool>,
+    // The object has been promoted to the old generation
+    old: Cell<bool>,
+    // The epoch of the last major collection that found this object live. Only
+    // used for old objects.
+    mark: Cell<u8>,
+    // The object is in the remembered set
+    remembered: Cell<bool>,
 }
 
 impl HeaderData {
     const PRESENT: u8 = 1;
     const fn new(marked: bool) -> Self {
-        Self { is_present: Self::PRESENT, marked: Cell::new(marked) }
+        Self {
+            is_present: Self::PRESENT,
+            marked: Cell::new(marked),
+            old: Cell::new(false),
+            mark: Cell::new(0),
+            remembered: Cell::new(false),
+        }
+    }
+}
+
+thread_local! {
+    /// Old objects that have been mutated since the last collection. These may
+    /// point to young objects, so they need to be traced during a minor
+    /// collection.
+    static REMEMBERED_SET: RefCell<Vec<NonNull<GcHeap<dyn Trace>>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Trace every object in the remembered set and clear it.
+pub(in crate::core) fn trace_remembered_set(state: &mut GcState) {
+    for obj in REMEMBERED_SET.take() {
+        // SAFETY: Old objects are not freed between major collections, and the
+        // remembered set is cleared at the start of each major collection.
+        let obj = unsafe { obj.as_ref() };
+        obj.header_data().remembered.set(false);
+        obj.data.trace(state);
+    }
+}
+
+/// Clear the remembered set. A major collection traces all live objects, so it
+/// is not needed.
+pub(in crate::core) fn clear_remembered_set() {
+    for obj in REMEMBERED_SET.take() {
+        unsafe { obj.as_ref() }.header_data().remembered.set(false);
     }
 }
 
@@ -87,10 +126,6 @@ impl<T> GcHeap<T> {
         Self::new(data, true)
     }
 
-    fn header(&self) -> &GcHeader {
-        unsafe { &*self.header.get() }
-    }
-
     pub(in crate::core) fn forward(&self, fwd_ptr: NonNull<u8>) {
         let header = unsafe { &mut *self.header.get() };
         header.fwd_ptr = fwd_ptr;
@@ -101,6 +136,8 @@ impl<T> GcHeap<T> {
             Ok(header) => {
                 if header.marked.get() {
                     AllocState::Global
+                } else if header.old.get() {
+                    AllocState::Old(header.mark.get())
                 } else {
                     AllocState::Unmoved
                 }
@@ -110,13 +147,68 @@ impl<T> GcHeap<T> {
     }
 
     fn is_marked(&self) -> bool {
-        self.header().get_header().unwrap().marked.get()
+        self.header_data().marked.get()
+    }
+
+    /// Move a newly copied object into the old generation.
+    pub(in crate::core) fn promote(&self, to_space: &OldSpace) {
+        let header = self.header_data();
+        header.old.set(true);
+        header.mark.set(to_space.epoch());
+        header.remembered.set(false);
+    }
+
+    /// Mark an object in the old generation as live. Old objects don't move,
+    /// and are only traced during a major collection, so this returns `None`
+    /// during a minor collection. Otherwise returns true the first time the
+    /// object is marked.
+    pub(in crate::core) fn mark_old(&self, to_space: &OldSpace) -> Option<bool> {
+        let epoch = to_space.major_epoch()?;
+        let header = self.header_data();
+        if header.mark.get() == epoch {
+            return Some(false);
+        }
+        header.mark.set(epoch);
+        to_space.mark_lines(std::ptr::from_ref(self).cast(), Layout::for_value(self).size());
+        Some(true)
+    }
+}
+
+impl<T: Trace + 'static> GcHeap<T> {
+    /// The write barrier. This needs to be called before a pointer is stored
+    /// into this object. Returns false if the object is a constant, in which
+    /// case it must not be mutated. If the object is in the old generation it
+    /// is added to the remembered set so that any young objects it points to
+    /// will be found by the next minor collection.
+    pub(in crate::core) fn write_barrier(&self) -> bool {
+        let header = self.header_data();
+        if header.marked.get() {
+            return false;
+        }
+        if header.old.get() && !header.remembered.get() {
+            header.remembered.set(true);
+            let obj: &GcHeap<dyn Trace> = self;
+            REMEMBERED_SET.with_borrow_mut(|set| set.push(NonNull::from(obj)));
+        }
+        true
+    }
+}
+
+impl<T: ?Sized> GcHeap<T> {
+    fn header(&self) -> &GcHeader {
+        unsafe { &*self.header.get() }
+    }
+
+    fn header_data(&self) -> &HeaderData {
+        self.header().get_header().expect("object was already forwarded")
     }
 }
 
 pub(in crate::core) enum AllocState {
     Forwarded(NonNull<u8>),
     Global,
+    /// In the old generation, with the epoch the object was last marked in.
+    Old(u8),
     Unmoved,
 }
 
@@ -125,7 +217,7 @@ pub(in crate::core) enum AllocState {
 /// this trait on `GcHeap`, which will just copy the object.
 pub(in crate::core) trait GcMoveable {
     type Value;
-    fn move_value(&self, _to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, _to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         None
     }
 }
@@ -133,7 +225,7 @@ pub(in crate::core) trait GcMoveable {
 impl<'a, T: GcMoveable<Value = NonNull<T>>> GcMoveable for &'a T {
     type Value = &'a T;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         let val = (*self).move_value(to_space);
         val.map(|(ptr, moved)| (unsafe { ptr.as_ref() }, moved))
     }
@@ -145,7 +237,10 @@ macro_rules! derive_GcMoveable {
         impl $crate::core::gc::GcMoveable for $name {
             type Value = std::ptr::NonNull<Self>;
 
-            fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+            fn move_value(
+                &self,
+                to_space: &$crate::core::gc::OldSpace,
+            ) -> Option<(Self::Value, bool)> {
                 match self.0.move_value(to_space) {
                     Some((ptr, moved)) => Some((ptr.cast::<Self>(), moved)),
                     None => None,
@@ -158,28 +253,30 @@ macro_rules! derive_GcMoveable {
 impl<T> GcMoveable for GcHeap<T> {
     type Value = NonNull<Self>;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         use std::ptr;
-        match self.header().get_header() {
-            Ok(header) => {
-                if header.marked.get() {
-                    // The object is global and should not be moved
-                    return None;
-                }
+        match self.allocation_state() {
+            // The object is global and should not be moved
+            AllocState::Global => None,
+            AllocState::Old(_) => {
+                let marked = self.mark_old(to_space)?;
+                Some((NonNull::from(self), marked))
+            }
+            AllocState::Unmoved => {
                 // move to to_space
                 let layout = Layout::for_value(self);
-                let to_ptr = to_space.alloc_layout(layout);
+                let to_ptr = to_space.alloc_layout(layout).cast::<Self>();
                 unsafe {
                     let src = ptr::from_ref(self);
-                    let dst = to_ptr.cast::<Self>().as_ptr();
-                    ptr::copy_nonoverlapping(src, dst, 1);
+                    ptr::copy_nonoverlapping(src, to_ptr.as_ptr(), 1);
+                    to_ptr.as_ref().promote(to_space);
                 }
                 // write forwarding pointer
-                self.forward(to_ptr);
+                self.forward(to_ptr.cast());
                 // return new address
-                Some((to_ptr.cast::<Self>(), true))
+                Some((to_ptr, true))
             }
-            Err(fwd) => Some((fwd.cast::<Self>(), false)),
+            AllocState::Forwarded(fwd) => Some((fwd.cast::<Self>(), false)),
         }
     }
 }
@@ -0,0 +1,315 @@
+//! The old generation of the heap. Objects that survive a minor collection are
+//! promoted here. This is a mark-region heap based on [immix]. Memory is
+//! divided into blocks, which are further divided into lines. Objects are bump
+//! allocated into holes of free lines. A major collection marks every line that
+//! holds a live object in place, and lines that were not marked become holes
+//! that can be reused. Blocks that have no live lines are released.
+//!
+//! [immix]: https://www.cs.utexas.edu/users/speedway/DaCapo/papers/immix-pldi-2008.pdf
+use rune_core::hashmap::HashMap;
+use std::{
+    alloc::{self, Layout},
+    cell::{Cell, RefCell},
+    ptr::NonNull,
+};
+
+const BLOCK_SIZE: usize = 32 * 1024;
+const LINE_SIZE: usize = 128;
+const LINE_COUNT: usize = BLOCK_SIZE / LINE_SIZE;
+// The line marks are stored at the start of each block, so the first lines
+// can't be used for objects.
+const FIRST_LINE: usize = LINE_COUNT / LINE_SIZE;
+/// Objects larger than this get their own allocation instead of being placed
+/// in a block.
+const LARGE_OBJECT_SIZE: usize = 8 * 1024;
+/// Line mark of a line that can be allocated into.
+const FREE: u8 = 0;
+
+struct LargeObject {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    mark: Cell<u8>,
+}
+
+/// The old generation. During a collection this is the space that objects get
+/// copied into.
+pub(in crate::core) struct OldSpace {
+    blocks: RefCell<Vec<NonNull<u8>>>,
+    large_objects: RefCell<HashMap<usize, LargeObject>>,
+    // The hole we are currently allocating into
+    cursor: Cell<*mut u8>,
+    limit: Cell<*mut u8>,
+    // Where to start looking for the next hole
+    next_block: Cell<usize>,
+    next_line: Cell<usize>,
+    // Every line and object that was live after the last major collection is
+    // marked with the current epoch. It is incremented at the start of each
+    // major collection so that nothing is marked.
+    epoch: u8,
+    major: bool,
+}
+
+impl Default for OldSpace {
+    fn default() -> Self {
+        Self {
+            blocks: RefCell::default(),
+            large_objects: RefCell::default(),
+            cursor: Cell::new(std::ptr::null_mut()),
+            limit: Cell::new(std::ptr::null_mut()),
+            next_block: Cell::new(0),
+            next_line: Cell::new(FIRST_LINE),
+            epoch: 1,
+            major: false,
+        }
+    }
+}
+
+fn line_marks<'a>(block: NonNull<u8>) -> &'a [Cell<u8>; LINE_COUNT] {
+    // SAFETY: Every block starts with its line marks, and blocks are only
+    // freed when no objects in them are live.
+    unsafe { &*block.as_ptr().cast::<[Cell<u8>; LINE_COUNT]>() }
+}
+
+fn block_layout() -> Layout {
+    Layout::from_size_align(BLOCK_SIZE, BLOCK_SIZE).unwrap()
+}
+
+impl OldSpace {
+    /// The mark epoch used for objects that are marked or promoted in the
+    /// current collection.
+    pub(in crate::core) fn epoch(&self) -> u8 {
+        self.epoch
+    }
+
+    /// The epoch live objects need to be marked with, or `None` if this is a
+    /// minor collection. Old objects are only traced during a major
+    /// collection.
+    pub(in crate::core) fn major_epoch(&self) -> Option<u8> {
+        self.major.then_some(self.epoch)
+    }
+
+    /// Start a major collection. Nothing in the old space is considered live
+    /// until it has been marked again.
+    pub(in crate::core) fn start_major(&mut self) {
+        self.epoch = match self.epoch {
+            u8::MAX => 1,
+            x => x + 1,
+        };
+        self.major = true;
+    }
+
+    /// Finish a major collection by freeing every line and large object that
+    /// was not marked.
+    pub(in crate::core) fn sweep(&mut self) {
+        let epoch = self.epoch;
+        self.blocks.get_mut().retain(|block| {
+            let mut live = false;
+            for mark in &line_marks(*block)[FIRST_LINE..] {
+                if mark.get() == epoch {
+                    live = true;
+                } else {
+                    mark.set(FREE);
+                }
+            }
+            if !live {
+                unsafe { alloc::dealloc(block.as_ptr(), block_layout()) };
+            }
+            live
+        });
+        self.large_objects.get_mut().retain(|_, obj| {
+            let live = obj.mark.get() == epoch;
+            if !live {
+                unsafe { alloc::dealloc(obj.ptr.as_ptr(), obj.layout) };
+            }
+            live
+        });
+        self.major = false;
+        // Start allocating from the first hole again
+        self.cursor.set(std::ptr::null_mut());
+        self.limit.set(std::ptr::null_mut());
+        self.next_block.set(0);
+        self.next_line.set(FIRST_LINE);
+    }
+
+    /// The number of bytes the old space has reserved.
+    pub(in crate::core) fn size(&self) -> usize {
+        let large: usize = self.large_objects.borrow().values().map(|x| x.layout.size()).sum();
+        self.blocks.borrow().len() * BLOCK_SIZE + large
+    }
+
+    pub(in crate::core) fn is_empty(&self) -> bool {
+        self.blocks.borrow().is_empty() && self.large_objects.borrow().is_empty()
+    }
+
+    /// Mark the allocation of `size` bytes starting at `ptr` as live. `ptr`
+    /// must have been allocated in this space.
+    pub(in crate::core) fn mark_lines(&self, ptr: *const u8, size: usize) {
+        if size == 0 {
+            return;
+        }
+        if size > LARGE_OBJECT_SIZE {
+            let large_objects = self.large_objects.borrow();
+            let obj = large_objects.get(&(ptr as usize)).expect("large object not in old space");
+            obj.mark.set(self.epoch);
+            return;
+        }
+        let offset = ptr as usize % BLOCK_SIZE;
+        let block = NonNull::new(ptr.wrapping_sub(offset).cast_mut()).unwrap();
+        let marks = line_marks(block);
+        for mark in &marks[offset / LINE_SIZE..=(offset + size - 1) / LINE_SIZE] {
+            mark.set(self.epoch);
+        }
+    }
+
+    pub(in crate::core) fn alloc_layout(&self, layout: Layout) -> NonNull<u8> {
+        if layout.size() > LARGE_OBJECT_SIZE {
+            return self.alloc_large(layout);
+        }
+        loop {
+            if let Some(ptr) = self.bump(layout) {
+                return ptr;
+            }
+            self.next_hole();
+        }
+    }
+
+    #[expect(clippy::mut_from_ref)]
+    pub(in crate::core) fn alloc<T>(&self, value: T) -> &mut T {
+        let ptr = self.alloc_layout(Layout::new::<T>()).cast::<T>();
+        unsafe {
+            ptr.as_ptr().write(value);
+            &mut *ptr.as_ptr()
+        }
+    }
+
+    #[expect(clippy::mut_from_ref)]
+    pub(in crate::core) fn alloc_slice_copy<T: Copy>(&self, src: &[T]) -> &mut [T] {
+        let ptr = self.alloc_layout(Layout::for_value(src)).cast::<T>();
+        unsafe {
+            std::ptr::copy_nonoverlapping(src.as_ptr(), ptr.as_ptr(), src.len());
+            std::slice::from_raw_parts_mut(ptr.as_ptr(), src.len())
+        }
+    }
+
+    #[expect(clippy::mut_from_ref)]
+    pub(in crate::core) fn alloc_str(&self, src: &str) -> &mut str {
+        let bytes = self.alloc_slice_copy(src.as_bytes());
+        unsafe { std::str::from_utf8_unchecked_mut(bytes) }
+    }
+
+    fn bump(&self, layout: Layout) -> Option<NonNull<u8>> {
+        let cursor = self.cursor.get();
+        if cursor.is_null() {
+            return None;
+        }
+        let start = cursor.wrapping_add(cursor.align_offset(layout.align()));
+        let end = start.wrapping_add(layout.size());
+        if end > self.limit.get() {
+            return None;
+        }
+        self.cursor.set(end);
+        self.mark_lines(start, layout.size());
+        NonNull::new(start)
+    }
+
+    /// Move the cursor to the next run of free lines, adding a new block if
+    /// there are none left.
+    fn next_hole(&self) {
+        let mut blocks = self.blocks.borrow_mut();
+        let mut block_idx = self.next_block.get();
+        let mut line = self.next_line.get();
+        while let Some(block) = blocks.get(block_idx) {
+            let marks = line_marks(*block);
+            while line < LINE_COUNT && marks[line].get() != FREE {
+                line += 1;
+            }
+            let start = line;
+            while line < LINE_COUNT && marks[line].get() == FREE {
+                line += 1;
+            }
+            if start < line {
+                self.set_hole(*block, start, line);
+                self.next_block.set(block_idx);
+                self.next_line.set(line);
+                return;
+            }
+            block_idx += 1;
+            line = FIRST_LINE;
+        }
+        let layout = block_layout();
+        let Some(block) = NonNull::new(unsafe { alloc::alloc_zeroed(layout) }) else {
+            alloc::handle_alloc_error(layout)
+        };
+        blocks.push(block);
+        self.set_hole(block, FIRST_LINE, LINE_COUNT);
+        self.next_block.set(blocks.len() - 1);
+        self.next_line.set(LINE_COUNT);
+    }
+
+    fn set_hole(&self, block: NonNull<u8>, start: usize, end: usize) {
+        let block = block.as_ptr();
+        self.cursor.set(block.wrapping_add(start * LINE_SIZE));
+        self.limit.set(block.wrapping_add(end * LINE_SIZE));
+    }
+
+    fn alloc_large(&self, layout: Layout) -> NonNull<u8> {
+        let Some(ptr) = NonNull::new(unsafe { alloc::alloc(layout) }) else {
+            alloc::handle_alloc_error(layout)
+        };
+        let obj = LargeObject { ptr, layout, mark: Cell::new(self.epoch) };
+        self.large_objects.borrow_mut().insert(ptr.as_ptr() as usize, obj);
+        ptr
+    }
+}
+
+impl Drop for OldSpace {
+    fn drop(&mut self) {
+        for block in self.blocks.get_mut().drain(..) {
+            unsafe { alloc::dealloc(block.as_ptr(), block_layout()) };
+        }
+        for (_, obj) in self.large_objects.get_mut().drain() {
+            unsafe { alloc::dealloc(obj.ptr.as_ptr(), obj.layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line_reuse() {
+        let mut space = OldSpace::default();
+        let a = space.alloc([1u8; LINE_SIZE]).as_ptr();
+        let b = space.alloc([2u8; LINE_SIZE]).as_ptr();
+        assert_eq!(space.size(), BLOCK_SIZE);
+
+        // only `b` survives, so the line used by `a` can be reused
+        space.start_major();
+        space.mark_lines(b, LINE_SIZE);
+        space.sweep();
+        let c = space.alloc([3u8; LINE_SIZE]).as_ptr();
+        assert_eq!(c, a);
+        assert_eq!(unsafe { *b.add(LINE_SIZE - 1) }, 2);
+
+        // Nothing survives, so the block is freed
+        space.start_major();
+        space.sweep();
+        assert!(space.is_empty());
+    }
+
+    #[test]
+    fn test_large_objects() {
+        let mut space = OldSpace::default();
+        let big = space.alloc_slice_copy(&[7u64; 2048]).as_ptr().cast::<u8>();
+        assert!(space.blocks.borrow().is_empty());
+        assert_eq!(space.size(), 2048 * 8);
+        space.start_major();
+        space.mark_lines(big, 2048 * 8);
+        space.sweep();
+        assert_eq!(space.size(), 2048 * 8);
+        space.start_major();
+        space.sweep();
+        assert!(space.is_empty());
+    }
+}
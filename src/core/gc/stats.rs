@@ -0,0 +1,134 @@
+//! Statistics about what the garbage collector is managing. These are used to
+//! implement `garbage-collect`, `memory-use-counts` and friends.
+use crate::core::cons::Cons;
+use crate::core::object::{
+    ByteFn, ByteString, CharTable, LispBigInt, LispBuffer, LispFloat, LispHashTable, LispMarker,
+    LispOverlay, LispString, LispVec, Object, ObjectType, Record, SymbolCell,
+};
+use std::cell::Cell;
+use std::time::Duration;
+
+/// The kinds of objects that can be allocated on the heap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum HeapKind {
+    Cons,
+    Float,
+    BigInt,
+    Symbol,
+    String,
+    ByteString,
+    Vec,
+    Record,
+    HashTable,
+    ByteFn,
+    Buffer,
+    CharTable,
+    Marker,
+    Overlay,
+}
+
+impl HeapKind {
+    const COUNT: usize = HeapKind::Overlay as usize + 1;
+
+    /// The size of an object of this kind, not including any data it owns
+    /// outside of the object.
+    pub(crate) const fn size(self) -> usize {
+        match self {
+            HeapKind::Cons => size_of::<Cons>(),
+            HeapKind::Float => size_of::<LispFloat>(),
+            HeapKind::BigInt => size_of::<LispBigInt>(),
+            HeapKind::Symbol => size_of::<SymbolCell>(),
+            HeapKind::String => size_of::<LispString>(),
+            HeapKind::ByteString => size_of::<ByteString>(),
+            HeapKind::Vec => size_of::<LispVec>(),
+            HeapKind::Record => size_of::<Record>(),
+            HeapKind::HashTable => size_of::<LispHashTable>(),
+            HeapKind::ByteFn => size_of::<ByteFn>(),
+            HeapKind::Buffer => size_of::<LispBuffer>(),
+            HeapKind::CharTable => size_of::<CharTable>(),
+            HeapKind::Marker => size_of::<LispMarker>(),
+            HeapKind::Overlay => size_of::<LispOverlay>(),
+        }
+    }
+}
+
+/// Number of objects and bytes for each [`HeapKind`].
+#[derive(Default, Debug)]
+pub(crate) struct HeapCounts {
+    objects: [Cell<usize>; HeapKind::COUNT],
+    /// Bytes owned by the objects outside of the object itself, such as the
+    /// characters of a string or the slots of a vector.
+    data: [Cell<usize>; HeapKind::COUNT],
+}
+
+impl HeapCounts {
+    pub(crate) fn record(&self, kind: HeapKind, data: usize) {
+        let idx = kind as usize;
+        self.objects[idx].set(self.objects[idx].get() + 1);
+        self.data[idx].set(self.data[idx].get() + data);
+    }
+
+    /// Record a vector-like object with `len` slots.
+    pub(crate) fn record_slots(&self, kind: HeapKind, len: usize) {
+        self.record(kind, len * size_of::<Object>());
+    }
+
+    /// Record a heap object. Immediate values are ignored.
+    pub(crate) fn record_object(&self, obj: Object) {
+        match obj.untag() {
+            ObjectType::Int(_) | ObjectType::SubrFn(_) => {}
+            ObjectType::Float(_) => self.record(HeapKind::Float, 0),
+            ObjectType::BigInt(x) => self.record(HeapKind::BigInt, x.size()),
+            ObjectType::String(x) => self.record(HeapKind::String, x.len()),
+            ObjectType::ByteString(x) => self.record(HeapKind::ByteString, x.len()),
+            ObjectType::Vec(x) => self.record_slots(HeapKind::Vec, x.len()),
+            ObjectType::Record(x) => self.record_slots(HeapKind::Record, x.len()),
+            ObjectType::HashTable(_) => self.record(HeapKind::HashTable, 0),
+            ObjectType::Cons(_) => self.record(HeapKind::Cons, 0),
+            ObjectType::Symbol(_) => self.record(HeapKind::Symbol, 0),
+            ObjectType::ByteFn(_) => self.record(HeapKind::ByteFn, 0),
+            ObjectType::Buffer(_) => self.record(HeapKind::Buffer, 0),
+            ObjectType::CharTable(_) => self.record(HeapKind::CharTable, 0),
+            ObjectType::Marker(_) => self.record(HeapKind::Marker, 0),
+            ObjectType::Overlay(_) => self.record(HeapKind::Overlay, 0),
+        }
+    }
+
+    pub(crate) fn objects(&self, kind: HeapKind) -> usize {
+        self.objects[kind as usize].get()
+    }
+
+    pub(crate) fn data(&self, kind: HeapKind) -> usize {
+        self.data[kind as usize].get()
+    }
+
+    /// Total bytes used by objects of this kind.
+    pub(crate) fn bytes(&self, kind: HeapKind) -> usize {
+        self.objects(kind) * kind.size() + self.data(kind)
+    }
+
+    pub(super) fn add(&self, other: &HeapCounts) {
+        for (x, y) in self.objects.iter().zip(&other.objects) {
+            x.set(x.get() + y.get());
+        }
+        for (x, y) in self.data.iter().zip(&other.data) {
+            x.set(x.get() + y.get());
+        }
+    }
+}
+
+/// Statistics kept by the [`Context`](super::Context) across collections.
+#[derive(Default, Debug)]
+pub(crate) struct GcStats {
+    /// Number of collections that have been run.
+    pub(crate) gcs_done: usize,
+    /// Total time spent in collections.
+    pub(crate) elapsed: Duration,
+    /// Objects that were live in the old generation after the last
+    /// collection. Old objects are only freed by a major collection, so
+    /// after a minor collection this also includes anything that has died
+    /// since the last major one. Objects are counted when they are traced
+    /// through a tagged pointer, so an object that is only referenced from a
+    /// typed root (like `Rto<&Cons>`) is not included.
+    pub(crate) live: HeapCounts,
+}
@@ -1,6 +1,7 @@
 use std::cell::RefCell;
 
 use super::super::object::RawObj;
+use super::{HeapCounts, OldSpace};
 use crate::core::object::{Gc, Object};
 use rune_core::hashmap::{HashMap, HashSet};
 
@@ -21,12 +22,14 @@ pub(crate) trait TracePtr {
 
 pub(crate) struct GcState {
     stack: Vec<RawObj>,
-    pub(in crate::core) to_space: bumpalo::Bump,
+    pub(in crate::core) to_space: OldSpace,
+    /// Objects that were moved or marked during this collection.
+    pub(in crate::core) live: HeapCounts,
 }
 
 impl GcState {
-    pub fn new() -> Self {
-        GcState { stack: Vec::new(), to_space: bumpalo::Bump::new() }
+    pub(in crate::core) fn new(to_space: OldSpace) -> Self {
+        GcState { stack: Vec::new(), to_space, live: HeapCounts::default() }
     }
 
     pub fn push(&mut self, obj: Object) {
@@ -9,6 +9,7 @@
 //! aligned. All objects should be bound to a lifetime to ensure sound operation
 //! of the vm.
 
+mod bignum;
 mod buffer;
 mod cell;
 mod chartab;
@@ -16,11 +17,14 @@ mod convert;
 mod float;
 mod func;
 mod hashtable;
+mod marker;
+mod overlay;
 mod string;
 mod symbol;
 mod tagged;
 mod vector;
 
+pub(crate) use bignum::*;
 pub(crate) use buffer::*;
 pub(super) use cell::*;
 pub(crate) use chartab::*;
@@ -28,6 +32,8 @@ pub(crate) use convert::*;
 pub(crate) use float::*;
 pub(crate) use func::*;
 pub(crate) use hashtable::*;
+pub(crate) use marker::*;
+pub(crate) use overlay::*;
 pub(crate) use string::*;
 pub(crate) use symbol::*;
 pub(crate) use tagged::*;
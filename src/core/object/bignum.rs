@@ -0,0 +1,142 @@
+use super::{CloneIn, IntoObject};
+use crate::core::gc::{AllocState, Block, GcHeap, GcMoveable, GcState, OldSpace, Trace};
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+use std::fmt::{Debug, Display};
+use std::ptr::NonNull;
+
+/// An integer that is too large to fit in a fixnum. The digits are stored
+/// outside of the object (like a [`ByteString`](super::ByteString)) so that the
+/// GC can copy them, which means a [`BigInt`] has to be built to do any
+/// arithmetic. Arithmetic results are demoted back to fixnums when they fit, so
+/// a `LispBigInt` should never hold a value in the fixnum range.
+pub(crate) struct LispBigInt(GcHeap<BigIntInner>);
+
+struct BigIntInner {
+    sign: Sign,
+    digits: *const [u32],
+}
+
+impl GcMoveable for LispBigInt {
+    type Value = NonNull<LispBigInt>;
+
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
+        match self.0.allocation_state() {
+            AllocState::Forwarded(f) => Some((f.cast::<Self>(), false)),
+            AllocState::Global => None,
+            AllocState::Old(_) => {
+                let marked = self.0.mark_old(to_space)?;
+                if marked {
+                    to_space.mark_lines(self.digits().as_ptr().cast(), self.size());
+                }
+                Some((NonNull::from(self), marked))
+            }
+            AllocState::Unmoved => {
+                let ptr = {
+                    let digits = to_space.alloc_slice_copy(self.digits());
+                    let bigint = LispBigInt::new(self.0.sign, digits, false);
+                    let alloc = to_space.alloc(bigint);
+                    alloc.0.promote(to_space);
+                    NonNull::from(alloc)
+                };
+                self.0.forward(ptr.cast::<u8>());
+                Some((ptr, true))
+            }
+        }
+    }
+}
+
+impl Trace for BigIntInner {
+    fn trace(&self, _state: &mut GcState) {}
+}
+
+impl Trace for LispBigInt {
+    fn trace(&self, _state: &mut GcState) {}
+}
+
+impl LispBigInt {
+    pub(in crate::core) fn new(sign: Sign, digits: *const [u32], constant: bool) -> Self {
+        Self(GcHeap::new(BigIntInner { sign, digits }, constant))
+    }
+
+    fn digits(&self) -> &[u32] {
+        unsafe { &*self.0.digits }
+    }
+
+    /// Bytes used to store the digits.
+    pub(crate) fn size(&self) -> usize {
+        size_of_val(self.digits())
+    }
+
+    /// Build the value of this integer.
+    pub(crate) fn value(&self) -> BigInt {
+        BigInt::from_slice(self.0.sign, self.digits())
+    }
+
+    pub(crate) fn is_negative(&self) -> bool {
+        self.0.sign == Sign::Minus
+    }
+
+    /// The closest float to this integer. Integers too large for a float
+    /// become infinity.
+    pub(crate) fn to_f64(&self) -> f64 {
+        let inf = if self.is_negative() { f64::NEG_INFINITY } else { f64::INFINITY };
+        self.value().to_f64().unwrap_or(inf)
+    }
+
+    /// A hash of the value, so that equal integers hash the same no matter
+    /// where they are allocated.
+    pub(crate) fn hash_value<H: std::hash::Hasher>(&self, state: &mut H) {
+        use std::hash::Hash;
+        self.0.sign.hash(state);
+        self.digits().hash(state);
+    }
+}
+
+impl PartialEq for LispBigInt {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.sign == other.0.sign && self.digits() == other.digits()
+    }
+}
+
+impl Eq for LispBigInt {}
+
+impl<'new> CloneIn<'new, &'new Self> for LispBigInt {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> super::Gc<&'new Self> {
+        self.value().into_obj(bk)
+    }
+}
+
+impl Display for LispBigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.value())
+    }
+}
+
+impl Debug for LispBigInt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use crate::core::object::ObjectType;
+    use rune_core::macros::root;
+
+    #[test]
+    fn bigint_survives_gc() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let value = BigInt::from(u64::MAX) * -3i64;
+        let obj = cx.add(value.clone());
+        root!(obj, cx);
+        cx.garbage_collect(true);
+        let ObjectType::BigInt(x) = obj.bind(cx).untag() else { unreachable!() };
+        assert_eq!(x.value(), value);
+        assert!(x.is_negative());
+        assert_eq!(x.to_string(), "-55340232221128654845");
+    }
+}
@@ -1,8 +1,8 @@
-use super::{Gc, Object, ObjectType, TagType, WithLifetime};
+use super::{CharTable, Gc, Object, ObjectType, Overlays, Symbol, TagType, WithLifetime};
 use crate::{
     core::{
-        error::{Type, TypeError},
-        gc::{Block, Context, GcHeap, GcState, Trace},
+        error::{SignalError, Type, TypeError},
+        gc::{Block, Context, GcHeap, GcState, HeapKind, Slot, Trace},
     },
     derive_GcMoveable,
     intervals::IntervalTree,
@@ -14,7 +14,7 @@ use std::{
     ops::{Deref, DerefMut},
     sync::{Mutex, MutexGuard},
 };
-use text_buffer::Buffer as TextBuffer;
+use text_buffer::{Buffer as TextBuffer, Marker as TextMarker};
 
 /// A Handle to an open buffer. Only one thread can hold this at a time.
 #[derive(Debug)]
@@ -37,6 +37,9 @@ impl OpenBuffer<'_> {
     // TODO: we shouldn't leave it empty
     pub(crate) fn kill(&mut self) -> bool {
         let killed = self.data.is_some();
+        if let Some(data) = self.data.as_mut() {
+            data.text.clear_markers();
+        }
         *self.data = None;
         killed
     }
@@ -46,14 +49,31 @@ impl OpenBuffer<'_> {
     }
 
     pub(crate) fn insert(&mut self, arg: Object) -> Result<()> {
-        match arg.untag() {
+        self.insert_inner(arg, false)
+    }
+
+    /// Like [`insert`](Self::insert), but all markers at point end up after the
+    /// inserted text.
+    pub(crate) fn insert_before_markers(&mut self, arg: Object) -> Result<()> {
+        self.insert_inner(arg, true)
+    }
+
+    fn insert_inner(&mut self, arg: Object, before_markers: bool) -> Result<()> {
+        let text = &mut self.get_mut().text;
+        let buf = &mut [0; 4];
+        let slice: &str = match arg.untag() {
             ObjectType::Int(i) => {
                 let Ok(u_32) = i.try_into() else { bail!("{i} is an invalid char") };
                 let Some(chr) = char::from_u32(u_32) else { bail!("{i} is an Invalid char") };
-                self.get_mut().text.insert_char(chr);
+                chr.encode_utf8(buf)
             }
-            ObjectType::String(s) => self.get_mut().text.insert(s),
+            ObjectType::String(s) => s,
             x => bail!(TypeError::new(Type::String, x)),
+        };
+        if before_markers {
+            text.insert_before_markers(slice);
+        } else {
+            text.insert(slice);
         }
         Ok(())
     }
@@ -71,9 +91,12 @@ impl OpenBuffer<'_> {
         Ok(())
     }
 
+    /// Convert a 1-based position to a character index, checking that it is in
+    /// the accessible portion of the buffer.
     fn in_range(&self, pos: usize) -> Result<usize> {
-        if pos == 0 || pos > self.get().text.len_chars() + 1 {
-            bail!("Position {pos} out of range in {}", self.get().name);
+        let data = self.get();
+        if pos == 0 || pos - 1 < data.begv() || pos - 1 > data.zv() {
+            bail!(SignalError::args_out_of_range(&[pos.into()]));
         }
         Ok(pos - 1)
     }
@@ -107,24 +130,189 @@ impl DerefMut for OpenBuffer<'_> {
     }
 }
 
-/// The actual data of the buffer. Buffer local variables will be stored here
-/// eventually.
+/// The actual data of the buffer.
 #[derive(Debug)]
 pub(crate) struct BufferData {
     pub(crate) name: String,
     pub(crate) text: TextBuffer,
     pub(crate) textprops: IntervalTree<'static>,
+    /// The overlays in this buffer. Use [`overlays`](Self::overlays) to look
+    /// them up by position.
+    pub(crate) overlays: Overlays,
+    /// Markers for the start and end of the accessible portion of the buffer
+    /// (BEGV and ZV). `None` if the buffer is not narrowed.
+    restriction: Option<(TextMarker, TextMarker)>,
+    /// Buffer-local variable bindings, in the order they were created. A value
+    /// of `None` means the local binding is void.
+    locals: Vec<(Slot<Symbol<'static>>, Option<Slot<Object<'static>>>)>,
+    /// The syntax table of the buffer, or `None` if it uses the standard
+    /// syntax table.
+    syntax_table: Option<Slot<Object<'static>>>,
+    /// The case table of the buffer, or `None` if it uses the standard case
+    /// table.
+    case_table: Option<Slot<Object<'static>>>,
 }
 
 impl BufferData {
     pub fn textprops_with_lifetime<'new>(&mut self) -> &mut IntervalTree<'new> {
         unsafe { std::mem::transmute(&mut self.textprops) }
     }
+
+    /// The overlays in this buffer, with the index brought up to date with the
+    /// text.
+    pub(crate) fn overlays(&mut self) -> &Overlays {
+        self.overlays.sync(self.text.modified_tick());
+        &self.overlays
+    }
+
+    /// The character index of the start of the accessible portion of the
+    /// buffer.
+    pub(crate) fn begv(&self) -> usize {
+        match &self.restriction {
+            Some((beg, _)) => beg.position().unwrap(),
+            None => 0,
+        }
+    }
+
+    /// The character index of the end of the accessible portion of the buffer.
+    pub(crate) fn zv(&self) -> usize {
+        match &self.restriction {
+            Some((_, end)) => end.position().unwrap(),
+            None => self.text.len_chars(),
+        }
+    }
+
+    /// Restrict editing to the characters between `beg` and `end`. Point is
+    /// moved inside the new restriction.
+    pub(crate) fn narrow(&mut self, beg: usize, end: usize) {
+        self.widen();
+        let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+        let beg = self.text.add_marker(beg, false);
+        // Text inserted at the end of the restriction is inside of it
+        let end = self.text.add_marker(end, true);
+        self.restriction = Some((beg, end));
+        self.clamp_cursor();
+    }
+
+    /// Remove any restriction on the buffer.
+    pub(crate) fn widen(&mut self) {
+        if let Some((beg, end)) = self.restriction.take() {
+            self.text.remove_marker(&beg);
+            self.text.remove_marker(&end);
+        }
+    }
+
+    /// Whether any part of the buffer is inaccessible.
+    pub(crate) fn is_narrowed(&self) -> bool {
+        self.begv() != 0 || self.zv() != self.text.len_chars()
+    }
+
+    /// Save the current restriction so that it can be restored with
+    /// [`restore_restriction`](Self::restore_restriction). The saved bounds
+    /// are adjusted for edits made in the meantime.
+    pub(crate) fn save_restriction(&mut self) -> Option<(TextMarker, TextMarker)> {
+        self.restriction.as_ref()?;
+        let beg = self.text.add_marker(self.begv(), false);
+        let end = self.text.add_marker(self.zv(), true);
+        Some((beg, end))
+    }
+
+    pub(crate) fn restore_restriction(&mut self, saved: &Option<(TextMarker, TextMarker)>) {
+        match saved {
+            Some((beg, end)) => {
+                self.narrow(beg.position().unwrap(), end.position().unwrap());
+                self.text.remove_marker(beg);
+                self.text.remove_marker(end);
+            }
+            None => self.widen(),
+        }
+    }
+
+    /// The buffer-local value of `symbol`. Returns `None` if the variable is
+    /// not local to this buffer, and `Some(None)` if the local binding is void.
+    pub(crate) fn local<'ob>(
+        &self,
+        symbol: Symbol,
+        cx: &'ob Context,
+    ) -> Option<Option<Object<'ob>>> {
+        let (_, value) = self.locals.iter().find(|(sym, _)| **sym == symbol)?;
+        Some(value.as_ref().map(|x| cx.bind(**x)))
+    }
+
+    pub(crate) fn syntax_table<'ob>(&self, cx: &'ob Context) -> Option<&'ob CharTable> {
+        match cx.bind(**self.syntax_table.as_ref()?).untag() {
+            ObjectType::CharTable(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_syntax_table(&mut self, table: &CharTable) {
+        // SAFETY: every live buffer is traced as part of the environment
+        let table: Object = table.into();
+        self.syntax_table = Some(Slot::new(unsafe { table.with_lifetime() }));
+    }
+
+    pub(crate) fn case_table<'ob>(&self, cx: &'ob Context) -> Option<&'ob CharTable> {
+        match cx.bind(**self.case_table.as_ref()?).untag() {
+            ObjectType::CharTable(table) => Some(table),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn set_case_table(&mut self, table: &CharTable) {
+        // SAFETY: every live buffer is traced as part of the environment
+        let table: Object = table.into();
+        self.case_table = Some(Slot::new(unsafe { table.with_lifetime() }));
+    }
+
+    pub(crate) fn has_local(&self, symbol: Symbol) -> bool {
+        self.locals.iter().any(|(sym, _)| **sym == symbol)
+    }
+
+    /// Set the buffer-local value of `symbol`, creating the local binding if
+    /// needed.
+    pub(crate) fn set_local(&mut self, symbol: Symbol, value: Option<Object>) {
+        // SAFETY: every live buffer is traced as part of the environment
+        let value = value.map(|x| Slot::new(unsafe { x.with_lifetime() }));
+        match self.locals.iter_mut().find(|(sym, _)| **sym == symbol) {
+            Some((_, slot)) => *slot = value,
+            None => self.locals.push((Slot::new(unsafe { symbol.with_lifetime() }), value)),
+        }
+    }
+
+    /// Remove the buffer-local binding of `symbol`. Returns false if there was
+    /// none.
+    pub(crate) fn kill_local(&mut self, symbol: Symbol) -> bool {
+        match self.locals.iter().position(|(sym, _)| **sym == symbol) {
+            Some(idx) => {
+                self.locals.remove(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn locals<'ob>(
+        &self,
+        cx: &'ob Context,
+    ) -> impl Iterator<Item = (Symbol<'ob>, Option<Object<'ob>>)> {
+        self.locals
+            .iter()
+            .map(|(sym, val)| (cx.bind(**sym), val.as_ref().map(|x| cx.bind(**x))))
+    }
+
+    fn clamp_cursor(&mut self) {
+        let point = self.text.cursor().chars().clamp(self.begv(), self.zv());
+        self.text.set_cursor(point);
+    }
 }
 
 #[derive(Debug)]
 struct LispBufferInner {
     text_buffer: Mutex<Option<BufferData>>,
+    /// The id of the context that created the buffer. Objects stored in the
+    /// buffer come from the heap of this context.
+    owner: u64,
 }
 
 /// A lisp handle to a buffer. This is a just a reference type and does not give
@@ -134,16 +322,35 @@ pub(crate) struct LispBuffer(GcHeap<LispBufferInner>);
 
 derive_GcMoveable!(LispBuffer);
 
+/// A restriction saved by `save-restriction`, along with the buffer it belongs
+/// to.
+#[derive(Debug)]
+pub(crate) struct SavedRestriction {
+    pub(crate) buffer: &'static LispBuffer,
+    pub(crate) bounds: Option<(TextMarker, TextMarker)>,
+}
+
 impl LispBuffer {
     pub(crate) fn create(name: String, block: &Block<true>) -> &LispBuffer {
         let buffer = unsafe { Self::new(name, block) };
+        block.allocated.record(HeapKind::Buffer, 0);
         block.objects.alloc(buffer)
     }
 
     pub(crate) unsafe fn new(name: String, _: &Block<true>) -> LispBuffer {
         let textprops = IntervalTree::new();
         let new = LispBufferInner {
-            text_buffer: Mutex::new(Some(BufferData { name, text: TextBuffer::new(), textprops })),
+            text_buffer: Mutex::new(Some(BufferData {
+                name,
+                text: TextBuffer::new(),
+                textprops,
+                overlays: Overlays::default(),
+                restriction: None,
+                locals: Vec::new(),
+                syntax_table: None,
+                case_table: None,
+            })),
+            owner: Context::current_id(),
         };
         Self(GcHeap::new(new, true))
     }
@@ -157,6 +364,21 @@ impl LispBuffer {
     }
 }
 
+impl LispBuffer {
+    /// Trace the objects held by the buffer. Only buffers created by the
+    /// current context are traced, because objects in other buffers belong to
+    /// another heap, which may already be dropped. A buffer that is locked is
+    /// skipped, since whoever holds it traces it.
+    pub(crate) fn trace_contents(&self, state: &mut GcState) {
+        if self.0.owner != Context::current_id() {
+            return;
+        }
+        if let Ok(data) = self.0.text_buffer.try_lock() {
+            data.trace(state);
+        }
+    }
+}
+
 impl PartialEq for LispBufferInner {
     fn eq(&self, other: &Self) -> bool {
         std::ptr::eq(self, other)
@@ -177,14 +399,29 @@ impl PartialEq<LispBuffer> for OpenBuffer<'_> {
 
 impl Eq for LispBufferInner {}
 
-impl Display for LispBuffer {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let data = self.0.text_buffer.lock().unwrap();
+impl LispBuffer {
+    /// Write the name of the buffer without locking it.
+    pub(crate) fn fmt_name(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // The buffer is locked while it is current, so fall back to looking up
+        // the name in the buffer list rather than deadlocking.
+        let Ok(data) = self.0.text_buffer.try_lock() else {
+            let buffers = crate::buffer::BUFFERS.lock().unwrap();
+            let name = buffers.iter().find(|(_, b)| std::ptr::eq(**b, self)).map(|(name, _)| name);
+            return write!(f, "{}", name.map_or("buffer", |x| x));
+        };
         let name = match data.as_ref() {
             Some(buf) => &buf.name,
             None => "deleted buffer",
         };
-        write!(f, "#<{name}>")
+        write!(f, "{name}")
+    }
+}
+
+impl Display for LispBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#<")?;
+        self.fmt_name(f)?;
+        write!(f, ">")
     }
 }
 
@@ -196,14 +433,29 @@ impl std::fmt::Debug for LispBuffer {
 
 impl Trace for LispBufferInner {
     fn trace(&self, state: &mut GcState) {
-        // Implement once we hold gc data in the buffer
         let buf = self.text_buffer.lock().unwrap();
         if let Some(buf) = buf.as_ref() {
-            buf.textprops.trace(state);
+            buf.trace(state);
         }
     }
 }
 
+impl Trace for BufferData {
+    fn trace(&self, state: &mut GcState) {
+        self.textprops.trace(state);
+        self.overlays.trace(state);
+        self.locals.trace(state);
+        self.syntax_table.trace(state);
+        self.case_table.trace(state);
+    }
+}
+
+impl Trace for &LispBuffer {
+    fn trace(&self, _: &mut GcState) {
+        // Buffers are allocated in the global block and never move
+    }
+}
+
 impl<'new> LispBuffer {
     pub(in crate::core) fn clone_in<const C: bool>(
         &self,
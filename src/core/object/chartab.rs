@@ -7,19 +7,34 @@ use rune_core::hashmap::HashMap;
 use rune_macros::Trace;
 use std::{cell::RefCell, fmt};
 
+/// Values for inclusive ranges of characters.
+type Ranges<'ob> = Vec<((usize, usize), Slot<Object<'ob>>)>;
+
 #[derive(Debug, Eq, Trace)]
 pub struct CharTableInner<'ob> {
     parent: RefCell<Option<Slot<&'ob CharTable>>>,
+    /// The symbol that describes what the table is used for, like
+    /// `syntax-table`.
+    purpose: Slot<Object<'ob>>,
     data: RefCell<HashMap<usize, Slot<Object<'ob>>>>,
+    /// Values set for ranges of characters, in the order they were set. Values
+    /// set for single characters in `data` take precedence over these.
+    ranges: RefCell<Ranges<'ob>>,
     init: Slot<Object<'ob>>,
+    /// Slots for data that is not associated with a character, like the
+    /// uppercase table of a case table.
+    extras: RefCell<Vec<Slot<Object<'ob>>>>,
 }
 
 impl<'ob> CharTableInner<'ob> {
-    pub fn new(init: Option<Object<'ob>>) -> Self {
+    pub fn new(purpose: Object<'ob>, init: Option<Object<'ob>>, extra_slots: usize) -> Self {
         CharTableInner {
             parent: RefCell::new(None),
+            purpose: Slot::new(purpose),
             data: RefCell::new(HashMap::default()),
+            ranges: RefCell::new(Vec::new()),
             init: Slot::new(init.unwrap_or(NIL)),
+            extras: RefCell::new(vec![Slot::new(NIL); extra_slots]),
         }
     }
 }
@@ -47,8 +62,14 @@ impl<'new> CloneIn<'new, &'new Self> for CharTable {
             data.insert(*key, new_value);
         }
         let data = RefCell::new(data);
+        let ranges = self.0.ranges.borrow();
+        let ranges = ranges.iter().map(|(range, value)| (*range, Slot::new(value.clone_in(bk))));
+        let ranges = RefCell::new(ranges.collect());
+        let purpose = Slot::new(self.0.purpose.clone_in(bk));
         let init = Slot::new(self.0.init.clone_in(bk));
-        CharTableInner { parent, data, init }.into_obj(bk)
+        let extras = self.0.extras.borrow().iter().map(|x| Slot::new(x.clone_in(bk))).collect();
+        let extras = RefCell::new(extras);
+        CharTableInner { parent, purpose, data, ranges, init, extras }.into_obj(bk)
     }
 }
 
@@ -60,18 +81,84 @@ impl CharTable {
         Self(GcHeap::new(table, constant))
     }
 
+    /// The value for the character `idx`. If the table has no value for it,
+    /// the value in the parent table is used.
     pub fn get(&self, idx: usize) -> Object {
-        match self.0.data.borrow().get(&idx) {
-            Some(x) => **x,
+        let value = self.get_own(idx);
+        match self.parent() {
+            Some(parent) if value.is_nil() => parent.get(idx),
+            _ => value,
+        }
+    }
+
+    /// The value for the character `idx` in this table, ignoring the parent.
+    pub fn get_own(&self, idx: usize) -> Object {
+        if let Some(x) = self.0.data.borrow().get(&idx) {
+            return **x;
+        }
+        let ranges = self.0.ranges.borrow();
+        match ranges.iter().rev().find(|((beg, end), _)| (*beg..=*end).contains(&idx)) {
+            Some((_, value)) => **value,
             None => *self.0.init,
         }
     }
 
     pub fn set(&self, idx: usize, item: Object) {
+        self.0.write_barrier();
         unsafe { self.0.data.borrow_mut().insert(idx, Slot::new(item.with_lifetime())) };
     }
 
+    /// Set the value for the characters from `beg` to `end` inclusive.
+    pub fn set_range(&self, beg: usize, end: usize, item: Object) {
+        self.0.write_barrier();
+        self.0.data.borrow_mut().retain(|idx, _| !(beg..=end).contains(idx));
+        let item = unsafe { Slot::new(item.with_lifetime()) };
+        self.0.ranges.borrow_mut().push(((beg, end), item));
+    }
+
+    pub fn parent(&self) -> Option<&Self> {
+        self.0.parent.borrow().as_ref().map(|x| **x)
+    }
+
+    pub fn purpose(&self) -> Object {
+        *self.0.purpose
+    }
+
+    /// A copy of the table with the same purpose, parent and values.
+    pub fn copy(&self) -> CharTableInner<'_> {
+        let parent = self.0.parent.borrow().as_ref().map(|x| Slot::new(**x));
+        let data = self.0.data.borrow().iter().map(|(idx, x)| (*idx, Slot::new(**x))).collect();
+        let ranges = self.0.ranges.borrow();
+        let ranges = ranges.iter().map(|(range, x)| (*range, Slot::new(**x)));
+        CharTableInner {
+            parent: RefCell::new(parent),
+            purpose: Slot::new(*self.0.purpose),
+            data: RefCell::new(data),
+            ranges: RefCell::new(ranges.collect()),
+            init: Slot::new(*self.0.init),
+            extras: RefCell::new(self.0.extras.borrow().iter().map(|x| Slot::new(**x)).collect()),
+        }
+    }
+
+    /// The value of extra slot `n`, or `None` if the table has no such slot.
+    pub fn extra_slot(&self, n: usize) -> Option<Object> {
+        self.0.extras.borrow().get(n).map(|x| **x)
+    }
+
+    /// Set extra slot `n`. Returns false if the table has no such slot.
+    pub fn set_extra_slot(&self, n: usize, value: Object) -> bool {
+        self.0.write_barrier();
+        match self.0.extras.borrow_mut().get_mut(n) {
+            Some(slot) => {
+                *slot = unsafe { Slot::new(value.with_lifetime()) };
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn set_parent(&self, new: Option<&Self>) {
+        self.0.write_barrier();
         let new_ptr = new.map(|n| unsafe { Slot::new(n.with_lifetime()) });
         *self.0.parent.borrow_mut() = new_ptr;
     }
@@ -6,7 +6,8 @@ use crate::data::LispError;
 
 use super::{
     super::error::{Type, TypeError},
-    ByteString, CharTable, LispHashTable, LispString, LispVec, NIL, OptionalFlag, TRUE,
+    ByteString, CharTable, LispHashTable, LispMarker, LispOverlay, LispString, LispVec, NIL,
+    OptionalFlag, TRUE,
 };
 use super::{Gc, LispFloat, Object, ObjectType, Symbol};
 use anyhow::Context;
@@ -27,6 +28,7 @@ impl TryFrom<Object<'_>> for f64 {
         match obj.untag() {
             ObjectType::Int(x) => Ok(x as f64),
             ObjectType::Float(x) => Ok(**x),
+            ObjectType::BigInt(x) => Ok(x.to_f64()),
             x => Err(TypeError::new(Type::Number, x)),
         }
     }
@@ -126,6 +128,8 @@ define_unbox!(ByteString, String, &'ob ByteString);
 define_unbox!(Vec, &'ob LispVec);
 define_unbox!(Symbol, Symbol<'ob>);
 define_unbox!(CharTable, &'ob CharTable);
+define_unbox!(Marker, &'ob LispMarker);
+define_unbox!(Overlay, &'ob LispOverlay);
 
 impl<'ob, T> From<Option<T>> for Object<'ob>
 where
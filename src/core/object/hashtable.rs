@@ -3,17 +3,163 @@
 //! iterate and mutate at the same time. Third we need to be able to clean up
 //! the heap allocation when it is garbage collected.
 use super::{CloneIn, Gc, IntoObject, ObjCell, Object, WithLifetime};
-use crate::core::env::INTERNED_SYMBOLS;
-use crate::core::gc::{Block, GcHeap, GcState, Trace};
+use crate::core::env::{INTERNED_SYMBOLS, sym};
+use crate::core::gc::{AllocState, Block, GcHeap, GcState, Trace};
 use crate::derive_GcMoveable;
+use crate::fns::{eql, hash_eql, hash_equal};
 use rune_core::hashmap::{HashSet, IndexMap};
 use rune_macros::Trace;
 use std::cell::RefCell;
 use std::fmt::{self, Debug, Display, Write};
-use std::ptr::NonNull;
+use std::hash::{Hash, Hasher};
 use std::sync::Mutex;
 
-pub(crate) type HashTable<'ob> = IndexMap<Object<'ob>, Object<'ob>>;
+/// The function used to compare the keys of a hash table.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub(crate) enum HashTest {
+    Eq,
+    #[default]
+    Eql,
+    Equal,
+}
+
+impl HashTest {
+    /// The test named by `name`, if it is one of the builtin tests.
+    pub(crate) fn from_name(name: Object) -> Option<Self> {
+        match () {
+            () if name == sym::EQ => Some(Self::Eq),
+            () if name == sym::EQL => Some(Self::Eql),
+            () if name == sym::EQUAL => Some(Self::Equal),
+            () => None,
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Eql => "eql",
+            Self::Equal => "equal",
+        }
+    }
+}
+
+/// A key of a [`HashTable`]. Every key carries the test of its table so that
+/// it can be hashed and compared the same way the table does.
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct HashKey<'ob> {
+    obj: Object<'ob>,
+    test: HashTest,
+}
+
+impl Hash for HashKey<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self.test {
+            HashTest::Eq => self.obj.hash(state),
+            HashTest::Eql => hash_eql(self.obj, state),
+            HashTest::Equal => hash_equal(self.obj, 0, state),
+        }
+    }
+}
+
+impl PartialEq for HashKey<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match self.test {
+            HashTest::Eq => self.obj.ptr_eq(other.obj),
+            HashTest::Eql => eql(self.obj, other.obj),
+            HashTest::Equal => self.obj == other.obj,
+        }
+    }
+}
+
+impl Eq for HashKey<'_> {}
+
+/// The same layout as [`HashKey`], but the object can be updated in place when
+/// it is traced.
+#[repr(C)]
+struct HashKeyCell {
+    obj: ObjCell,
+    test: HashTest,
+}
+
+impl HashKeyCell {
+    fn key(&self) -> HashKey<'_> {
+        HashKey { obj: self.obj.get(), test: self.test }
+    }
+}
+
+impl Hash for HashKeyCell {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl PartialEq for HashKeyCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for HashKeyCell {}
+
+impl Trace for HashKeyCell {
+    fn trace(&self, state: &mut GcState) {
+        self.obj.trace(state);
+    }
+}
+
+/// The map of a lisp hash table, along with the test used to compare keys.
+#[derive(Default)]
+pub(crate) struct HashTable<'ob> {
+    test: HashTest,
+    map: IndexMap<HashKey<'ob>, Object<'ob>>,
+}
+
+impl<'ob> HashTable<'ob> {
+    pub(crate) fn new(test: HashTest) -> Self {
+        Self { test, map: IndexMap::default() }
+    }
+
+    pub(crate) fn with_capacity(test: HashTest, size: usize) -> Self {
+        Self { test, map: IndexMap::with_capacity_and_hasher(size, Default::default()) }
+    }
+
+    pub(crate) fn test(&self) -> HashTest {
+        self.test
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn key<'a>(&self, obj: Object<'a>) -> HashKey<'a> {
+        HashKey { obj, test: self.test }
+    }
+
+    pub(crate) fn get(&self, key: Object) -> Option<Object<'ob>> {
+        self.map.get(&self.key(key)).copied()
+    }
+
+    pub(crate) fn get_index(&self, index: usize) -> Option<(Object<'ob>, Object<'ob>)> {
+        self.map.get_index(index).map(|(k, v)| (k.obj, *v))
+    }
+
+    pub(crate) fn get_index_of(&self, key: Object) -> Option<usize> {
+        self.map.get_index_of(&self.key(key))
+    }
+
+    pub(crate) fn insert(&mut self, key: Object<'ob>, value: Object<'ob>) {
+        self.map.insert(self.key(key), value);
+    }
+
+    pub(crate) fn shift_remove(&mut self, key: Object<'ob>) {
+        self.map.shift_remove(&self.key(key));
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Object<'ob>, Object<'ob>)> + '_ {
+        self.map.iter().map(|(k, v)| (k.obj, *v))
+    }
+}
 
 #[derive(PartialEq, Trace)]
 pub(crate) struct LispHashTable(GcHeap<HashTableCore<'static>>);
@@ -39,13 +185,8 @@ impl LispHashTable {
         Self(GcHeap::new(HashTableCore::new(table, constant), constant))
     }
 
-    pub(in crate::core) fn forwarding_ptr(&self) -> Option<NonNull<u8>> {
-        use crate::core::gc::AllocState as A;
-        match self.0.allocation_state() {
-            A::Forwarded(f) => Some(f),
-            A::Global => panic!("global hashtable allocation found in local heap"),
-            A::Unmoved => None,
-        }
+    pub(in crate::core) fn allocation_state(&self) -> AllocState {
+        self.0.allocation_state()
     }
 }
 
@@ -67,25 +208,30 @@ struct HashTableInner<'ob> {
 }
 
 impl LispHashTable {
+    pub(crate) fn test(&self) -> HashTest {
+        self.0.with(|x| x.test())
+    }
+
     pub(crate) fn len(&self) -> usize {
         self.0.with(|x| x.len())
     }
 
     pub(crate) fn get(&self, key: Object) -> Option<Object<'_>> {
-        self.0.with(|x| x.get(&key).copied())
+        self.0.with(|x| x.get(key))
     }
 
     pub(crate) fn get_index(&self, index: usize) -> Option<(Object, Object)> {
-        self.0.with(|x| x.get_index(index).map(|(k, v)| (*k, *v)))
+        self.0.with(|x| x.get_index(index))
     }
 
     pub(crate) fn get_index_of(&self, key: Object) -> Option<usize> {
-        self.0.with(|x| x.get_index_of(&key))
+        self.0.with(|x| x.get_index_of(key))
     }
 
     pub(crate) fn insert(&self, key: Object, value: Object) {
         match &self.0.0 {
             HashTableType::Local(table) => {
+                self.0.write_barrier();
                 let key = unsafe { key.with_lifetime() };
                 let value = unsafe { value.with_lifetime() };
                 table.borrow_mut().inner.insert(key, value)
@@ -104,7 +250,7 @@ impl LispHashTable {
 
     pub(crate) fn shift_remove(&self, key: Object) {
         let key = unsafe { key.with_lifetime() };
-        self.0.with(|x| x.shift_remove(&key));
+        self.0.with(|x| x.shift_remove(key));
     }
 
     pub(crate) fn get_iter_index(&self) -> usize {
@@ -153,8 +299,8 @@ impl Trace for HashTableCore<'_> {
         // ObjCell are updated in place when traced, so casting to ObjCell will
         // allow all the objects to be updated.
         let table = unsafe {
-            std::mem::transmute::<&mut IndexMap<Object, Object>, &mut IndexMap<ObjCell, ObjCell>>(
-                table,
+            std::mem::transmute::<&mut IndexMap<HashKey, Object>, &mut IndexMap<HashKeyCell, ObjCell>>(
+                &mut table.map,
             )
         };
         table.rehash_keys(|key, val| {
@@ -172,9 +318,9 @@ impl PartialEq for HashTableCore<'_> {
 
 impl<'new> CloneIn<'new, &'new Self> for LispHashTable {
     fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
-        let mut table = HashTable::default();
+        let mut table = HashTable::new(self.test());
         self.0.with(|x| {
-            for (key, value) in x {
+            for (key, value) in x.iter() {
                 let new_key = key.clone_in(bk);
                 let new_value = value.clone_in(bk);
                 table.insert(new_key, new_value);
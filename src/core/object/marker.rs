@@ -0,0 +1,136 @@
+use super::{CloneIn, Gc, IntoObject, LispBuffer};
+use crate::{
+    core::gc::{AllocState, Block, GcHeap, GcState, Trace},
+    derive_GcMoveable,
+};
+use rune_macros::Trace;
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+};
+use text_buffer::Marker as TextMarker;
+
+/// The buffer a marker points into, along with the handle that the buffer uses
+/// to keep the position up to date.
+#[derive(Debug, Clone)]
+pub(crate) struct MarkerLoc {
+    pub(crate) buffer: &'static LispBuffer,
+    pub(crate) marker: TextMarker,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct MarkerInner {
+    loc: RefCell<Option<MarkerLoc>>,
+    insertion_type: Cell<bool>,
+}
+
+impl MarkerInner {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A lisp marker. The position is owned by the text buffer it points into,
+/// which adjusts it on every insertion and deletion.
+#[derive(PartialEq, Eq, Trace)]
+pub(crate) struct LispMarker(GcHeap<MarkerInner>);
+
+derive_GcMoveable!(LispMarker);
+
+impl LispMarker {
+    pub(in crate::core) unsafe fn new(inner: MarkerInner, constant: bool) -> Self {
+        Self(GcHeap::new(inner, constant))
+    }
+
+    pub(in crate::core) fn allocation_state(&self) -> AllocState {
+        self.0.allocation_state()
+    }
+
+    /// The buffer this marker points into, if any.
+    pub(crate) fn buffer(&self) -> Option<&LispBuffer> {
+        let loc = self.0.loc.borrow();
+        let loc = loc.as_ref()?;
+        loc.marker.position()?;
+        Some(loc.buffer)
+    }
+
+    /// The 1-based character position of the marker, or `None` if it does not
+    /// point anywhere.
+    pub(crate) fn position(&self) -> Option<usize> {
+        self.0.loc.borrow().as_ref()?.marker.position().map(|x| x + 1)
+    }
+
+    pub(crate) fn loc(&self) -> Option<MarkerLoc> {
+        self.0.loc.borrow().clone()
+    }
+
+    /// Point this marker at a new location. The caller is responsible for
+    /// removing the old text marker from its buffer.
+    pub(crate) fn set_loc(&self, loc: Option<MarkerLoc>) {
+        *self.0.loc.borrow_mut() = loc;
+    }
+
+    pub(crate) fn insertion_type(&self) -> bool {
+        self.0.insertion_type.get()
+    }
+
+    pub(crate) fn set_insertion_type(&self, advances: bool) {
+        self.0.insertion_type.set(advances);
+        if let Some(loc) = self.0.loc.borrow().as_ref() {
+            loc.marker.set_insertion_type(advances);
+        }
+    }
+}
+
+/// Markers are `equal` if they point to the same position in the same buffer,
+/// or if neither points anywhere.
+impl PartialEq for MarkerInner {
+    fn eq(&self, other: &Self) -> bool {
+        let get = |x: &Self| {
+            let loc = x.loc.borrow();
+            let loc = loc.as_ref()?;
+            Some((loc.buffer as *const LispBuffer, loc.marker.position()?))
+        };
+        get(self) == get(other)
+    }
+}
+
+impl Eq for MarkerInner {}
+
+impl Trace for MarkerInner {
+    fn trace(&self, _: &mut GcState) {
+        // Buffers are never collected, so there is nothing to trace
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispMarker {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        // TODO: The clone shares the text marker with the original, so moving
+        // one will move the other.
+        let inner = MarkerInner {
+            loc: RefCell::new(self.loc()),
+            insertion_type: Cell::new(self.insertion_type()),
+        };
+        inner.into_obj(bk)
+    }
+}
+
+impl fmt::Display for LispMarker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let loc = self.0.loc.borrow();
+        match loc.as_ref().and_then(|loc| Some((loc.buffer, loc.marker.position()?))) {
+            Some((buffer, pos)) => {
+                write!(f, "#<marker at {} in ", pos + 1)?;
+                buffer.fmt_name(f)?;
+                write!(f, ">")
+            }
+            None => write!(f, "#<marker in no buffer>"),
+        }
+    }
+}
+
+impl fmt::Debug for LispMarker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,285 @@
+use super::{CloneIn, Gc, IntoObject, LispBuffer, NIL, ObjCell, Object, ObjectType, WithLifetime};
+use crate::{
+    core::gc::{Block, Context, GcHeap, GcState, Slot, Trace},
+    derive_GcMoveable,
+};
+use anyhow::{Result, bail};
+use interval_tree::IntervalTree;
+use rune_macros::Trace;
+use std::{cell::RefCell, fmt};
+use text_buffer::Marker as TextMarker;
+
+/// The buffer an overlay is in, along with the handles that the buffer uses to
+/// keep its start and end up to date.
+#[derive(Debug, Clone)]
+pub(crate) struct OverlayLoc {
+    pub(crate) buffer: &'static LispBuffer,
+    pub(crate) start: TextMarker,
+    pub(crate) end: TextMarker,
+}
+
+pub(crate) struct OverlayInner {
+    loc: RefCell<Option<OverlayLoc>>,
+    plist: ObjCell,
+    /// Text inserted at the start of the overlay is placed before it.
+    front_advance: bool,
+    /// Text inserted at the end of the overlay is placed inside it.
+    rear_advance: bool,
+}
+
+impl OverlayInner {
+    pub(crate) fn new(front_advance: bool, rear_advance: bool) -> Self {
+        // SAFETY: nil is never collected
+        let plist = unsafe { ObjCell::new(NIL) };
+        Self { loc: RefCell::new(None), plist, front_advance, rear_advance }
+    }
+}
+
+/// A lisp overlay. Like a [marker](super::LispMarker), the start and end are
+/// owned by the text buffer, which adjusts them on every insertion and
+/// deletion. The buffer also keeps a list of its overlays so that they can be
+/// found by position.
+#[derive(PartialEq, Eq, Trace)]
+pub(crate) struct LispOverlay(GcHeap<OverlayInner>);
+
+derive_GcMoveable!(LispOverlay);
+
+impl LispOverlay {
+    pub(in crate::core) unsafe fn new(inner: OverlayInner, constant: bool) -> Self {
+        Self(GcHeap::new(inner, constant))
+    }
+
+    /// The buffer this overlay is in, if any.
+    pub(crate) fn buffer(&self) -> Option<&LispBuffer> {
+        let loc = self.0.loc.borrow();
+        let loc = loc.as_ref()?;
+        loc.start.position()?;
+        Some(loc.buffer)
+    }
+
+    /// The character indices of the start and end of the overlay. An empty
+    /// overlay whose start advances can end up with the start after the end,
+    /// in which case it stays empty at the end.
+    pub(crate) fn bounds(&self) -> Option<(usize, usize)> {
+        let loc = self.0.loc.borrow();
+        let loc = loc.as_ref()?;
+        let (start, end) = (loc.start.position()?, loc.end.position()?);
+        Some((start.min(end), end))
+    }
+
+    /// The 1-based position of the start of the overlay.
+    pub(crate) fn start(&self) -> Option<usize> {
+        self.bounds().map(|(start, _)| start + 1)
+    }
+
+    /// The 1-based position of the end of the overlay.
+    pub(crate) fn end(&self) -> Option<usize> {
+        self.bounds().map(|(_, end)| end + 1)
+    }
+
+    pub(crate) fn loc(&self) -> Option<OverlayLoc> {
+        self.0.loc.borrow().clone()
+    }
+
+    /// Place this overlay at a new location. The caller is responsible for
+    /// removing the old text markers and the overlay from its buffer.
+    pub(crate) fn set_loc(&self, loc: Option<OverlayLoc>) {
+        *self.0.loc.borrow_mut() = loc;
+    }
+
+    pub(crate) fn plist(&self) -> Object<'_> {
+        self.0.plist.get()
+    }
+
+    pub(crate) fn set_plist(&self, plist: Object) -> Result<()> {
+        if !self.0.write_barrier() {
+            bail!("Attempt to modify a constant overlay");
+        }
+        unsafe { self.0.plist.as_mut().set(plist) };
+        Ok(())
+    }
+
+    pub(crate) fn front_advance(&self) -> bool {
+        self.0.front_advance
+    }
+
+    pub(crate) fn rear_advance(&self) -> bool {
+        self.0.rear_advance
+    }
+}
+
+/// Overlays are only equal to themselves.
+impl PartialEq for OverlayInner {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for OverlayInner {}
+
+impl Trace for OverlayInner {
+    fn trace(&self, state: &mut GcState) {
+        // Buffers are never collected, so only the plist needs to be traced
+        self.plist.trace(state);
+    }
+}
+
+impl<'new> CloneIn<'new, &'new Self> for LispOverlay {
+    fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> Gc<&'new Self> {
+        // TODO: The clone shares the text markers with the original, and is
+        // not in the overlay list of its buffer.
+        let inner = OverlayInner::new(self.front_advance(), self.rear_advance());
+        *inner.loc.borrow_mut() = self.loc();
+        let plist = self.plist().clone_in(bk);
+        let new = inner.into_obj(bk);
+        unsafe { new.untag().0.plist.as_mut().set(plist) };
+        new
+    }
+}
+
+impl fmt::Display for LispOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.bounds().zip(self.buffer()) {
+            Some(((start, end), buffer)) => {
+                write!(f, "#<overlay from {} to {} in ", start + 1, end + 1)?;
+                buffer.fmt_name(f)?;
+                write!(f, ">")
+            }
+            None => write!(f, "#<overlay in no buffer>"),
+        }
+    }
+}
+
+impl fmt::Debug for LispOverlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// The overlays of a buffer. The overlays themselves track their position, and
+/// the tree is an index from positions to the overlays that cover them. The
+/// index is rebuilt the first time it is used after the text or the overlays
+/// change.
+#[derive(Debug, Default)]
+pub(crate) struct Overlays {
+    list: Vec<Slot<Object<'static>>>,
+    /// Each interval holds the indices in `list` of the overlays that cover it.
+    tree: IntervalTree<Vec<usize>>,
+    /// Empty overlays cannot be stored in the tree.
+    empty: Vec<usize>,
+    /// The modification tick of the text when the index was built, or `None`
+    /// if the overlays changed since then.
+    synced: Option<usize>,
+}
+
+impl Overlays {
+    pub(crate) fn add(&mut self, overlay: &LispOverlay) {
+        // SAFETY: the list is traced along with the buffer
+        let overlay: Object<'static> = unsafe { overlay.with_lifetime() }.into();
+        self.list.push(Slot::new(overlay));
+        self.synced = None;
+    }
+
+    pub(crate) fn remove(&mut self, overlay: &LispOverlay) {
+        if let Some(idx) = self.list.iter().position(|x| std::ptr::eq(Self::get(x), overlay)) {
+            self.list.remove(idx);
+            self.synced = None;
+        }
+    }
+
+    /// Rebuild the index if the text was modified at `tick`.
+    pub(crate) fn sync(&mut self, tick: usize) {
+        if self.synced == Some(tick) {
+            return;
+        }
+        self.tree = IntervalTree::new();
+        self.empty.clear();
+        for (idx, overlay) in self.list.iter().enumerate() {
+            let Some((start, end)) = Self::get(overlay).bounds() else { continue };
+            if start == end {
+                self.empty.push(idx);
+            } else {
+                self.tree.insert(start..end, vec![idx], |mut new, old| {
+                    new.extend(old);
+                    new
+                });
+            }
+        }
+        self.synced = Some(tick);
+    }
+
+    fn get<'a>(slot: &'a Slot<Object<'static>>) -> &'a LispOverlay {
+        let ObjectType::Overlay(overlay) = slot.untag() else { unreachable!() };
+        overlay
+    }
+
+    fn bind<'ob>(&self, idx: usize, cx: &'ob Context) -> &'ob LispOverlay {
+        cx.bind(Self::get(&self.list[idx]))
+    }
+
+    /// The overlays that contain the character at index `pos`.
+    pub(crate) fn at<'ob>(&self, pos: usize, cx: &'ob Context) -> Vec<&'ob LispOverlay> {
+        debug_assert!(self.synced.is_some(), "overlay index is out of date");
+        let Some(node) = self.tree.find(pos) else { return Vec::new() };
+        node.val.iter().map(|idx| self.bind(*idx, cx)).collect()
+    }
+
+    /// The overlays that overlap the characters from `beg` to `end`. Empty
+    /// overlays are included if they are at `beg` or inside the range, or at
+    /// `end` when `include_end` is true. If the range is empty, the overlays
+    /// that contain `beg` are included.
+    pub(crate) fn overlapping<'ob>(
+        &self,
+        beg: usize,
+        end: usize,
+        include_end: bool,
+        cx: &'ob Context,
+    ) -> Vec<&'ob LispOverlay> {
+        debug_assert!(self.synced.is_some(), "overlay index is out of date");
+        let mut found: Vec<usize> = if beg == end {
+            self.tree.find(beg).map(|node| node.val.clone()).unwrap_or_default()
+        } else {
+            self.tree
+                .find_intersects(beg..end)
+                .flat_map(|node| &node.val)
+                .copied()
+                .collect()
+        };
+        found.sort_unstable();
+        found.dedup();
+        for idx in &self.empty {
+            let (pos, _) = Self::get(&self.list[*idx]).bounds().unwrap();
+            if (beg..end).contains(&pos) || pos == beg || (include_end && pos == end) {
+                found.push(*idx);
+            }
+        }
+        found.into_iter().map(|idx| self.bind(idx, cx)).collect()
+    }
+
+    /// The first index after `pos` where an overlay starts or ends.
+    pub(crate) fn next_change(&self, pos: usize) -> Option<usize> {
+        debug_assert!(self.synced.is_some(), "overlay index is out of date");
+        let tree_change = self
+            .tree
+            .find_intersects(pos..usize::MAX)
+            .map(|node| if node.key.start > pos { node.key.start } else { node.key.end })
+            .next();
+        let empty_change = self
+            .empty
+            .iter()
+            .filter_map(|idx| Self::get(&self.list[*idx]).bounds())
+            .map(|(start, _)| start)
+            .filter(|start| *start > pos)
+            .min();
+        match (tree_change, empty_change) {
+            (Some(x), Some(y)) => Some(x.min(y)),
+            (x, y) => x.or(y),
+        }
+    }
+}
+
+impl Trace for Overlays {
+    fn trace(&self, state: &mut GcState) {
+        self.list.trace(state);
+    }
+}
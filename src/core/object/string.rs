@@ -1,6 +1,8 @@
-use super::{CloneIn, IntoObject};
-use crate::core::gc::{AllocState, Block, GcHeap, GcMoveable, GcState, Trace};
-use std::cell::Cell;
+use super::{CloneIn, IntoObject, WithLifetime};
+use crate::core::gc::{AllocState, Block, Context, GcHeap, GcMoveable, GcState, OldSpace, Trace};
+use crate::intervals::IntervalTree;
+use anyhow::{Result, bail};
+use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::fmt::{Debug, Display};
 use std::ops::Deref;
 use std::ptr::NonNull;
@@ -15,21 +17,35 @@ pub(crate) struct LispString(GcHeap<LispStringInner>);
 //
 // Case 2: The new char is a different size:
 // Need to allocate a new string and update the cell to point to that.
-struct LispStringInner(Cell<*mut str>);
+struct LispStringInner {
+    string: Cell<*mut str>,
+    // Text properties, indexed by character. Most strings never have any, so
+    // the tree is only allocated when the first property is added. The tree is
+    // owned by the string and is handed over to the new copy when it is moved.
+    props: RefCell<Option<Box<IntervalTree<'static>>>>,
+}
 
 impl GcMoveable for LispString {
     type Value = std::ptr::NonNull<LispString>;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         match self.0.allocation_state() {
             AllocState::Forwarded(f) => Some((f.cast::<Self>(), false)),
             AllocState::Global => None,
+            AllocState::Old(_) => {
+                let marked = self.0.mark_old(to_space)?;
+                if marked {
+                    to_space.mark_lines(self.as_ptr(), self.inner().len());
+                }
+                Some((NonNull::from(self), marked))
+            }
             AllocState::Unmoved => {
                 let ptr = {
-                    let mut new = GcString::from_str_in(self, to_space);
-                    let lisp_str = unsafe { LispString::new(new.as_mut_str(), false) };
-                    std::mem::forget(new);
+                    let new = to_space.alloc_str(self);
+                    let lisp_str = unsafe { LispString::new(new, false) };
+                    lisp_str.0.props.swap(&self.0.props);
                     let alloc = to_space.alloc(lisp_str);
+                    alloc.0.promote(to_space);
                     NonNull::from(alloc)
                 };
                 self.0.forward(ptr.cast::<u8>());
@@ -39,8 +55,18 @@ impl GcMoveable for LispString {
     }
 }
 
+impl Trace for LispStringInner {
+    fn trace(&self, state: &mut GcState) {
+        if let Some(props) = &*self.props.borrow() {
+            props.trace(state);
+        }
+    }
+}
+
 impl Trace for LispString {
-    fn trace(&self, _state: &mut GcState) {}
+    fn trace(&self, state: &mut GcState) {
+        self.0.trace(state);
+    }
 }
 
 impl Debug for LispString {
@@ -87,11 +113,44 @@ impl Deref for LispString {
 
 impl LispString {
     pub(in crate::core) unsafe fn new(string: *mut str, constant: bool) -> Self {
-        Self(GcHeap::new(LispStringInner(Cell::new(string)), constant))
+        let inner = LispStringInner { string: Cell::new(string), props: RefCell::new(None) };
+        Self(GcHeap::new(inner, constant))
     }
 
     pub(crate) fn inner(&self) -> &str {
-        unsafe { &*self.0.0.get() }
+        unsafe { &*self.0.string.get() }
+    }
+
+    pub(in crate::core) fn allocation_state(&self) -> AllocState {
+        self.0.allocation_state()
+    }
+
+    /// The text properties of this string, or `None` if it has never had any.
+    pub(crate) fn textprops(&self) -> Option<Ref<'_, IntervalTree<'_>>> {
+        let props = Ref::filter_map(self.0.props.borrow(), |x| x.as_deref()).ok()?;
+        // SAFETY: The objects in the tree live as long as the string.
+        Some(Ref::map(props, |x| unsafe { &*std::ptr::from_ref(x).cast::<IntervalTree>() }))
+    }
+
+    /// Mutable access to the text properties of this string. The tree is
+    /// created if the string does not have any properties yet.
+    pub(crate) fn textprops_mut<'a>(
+        &'a self,
+        cx: &'a Context,
+    ) -> Result<RefMut<'a, IntervalTree<'a>>> {
+        if !self.0.write_barrier() {
+            bail!("Attempt to modify text properties of constant string");
+        }
+        let mut props = self.0.props.borrow_mut();
+        if props.is_none() {
+            *props = Some(Box::new(IntervalTree::new()));
+            cx.string_props.borrow_mut().push(self);
+        }
+        let props = RefMut::map(props, |x| x.as_deref_mut().unwrap());
+        // SAFETY: The objects in the tree live as long as the string.
+        Ok(RefMut::map(props, |x| unsafe {
+            &mut *std::ptr::from_mut(x).cast::<IntervalTree>()
+        }))
     }
 }
 
@@ -101,7 +160,7 @@ impl LispString {
     }
 
     pub(crate) fn clear(&self) {
-        let inner_mut_str = unsafe { &mut *self.0.0.get() };
+        let inner_mut_str = unsafe { &mut *self.0.string.get() };
         for byte in unsafe { inner_mut_str.as_bytes_mut().iter_mut() } {
             *byte = b'\0';
         }
@@ -110,7 +169,19 @@ impl LispString {
 
 impl<'new> CloneIn<'new, &'new Self> for LispString {
     fn clone_in<const C: bool>(&self, bk: &'new Block<C>) -> super::Gc<&'new Self> {
-        GcString::from_str_in(self.inner(), &bk.objects).into_obj(bk)
+        let string = GcString::from_str_in(self.inner(), &bk.objects).into_obj(bk);
+        if let Some(props) = self.textprops() {
+            let mut tree = IntervalTree::new();
+            for (range, plist) in props.iter(0, self.len()) {
+                if !plist.is_nil() {
+                    tree.set_properties(range.start, range.end, plist.clone_in(bk));
+                }
+            }
+            let new = string.untag();
+            *new.0.props.borrow_mut() = Some(Box::new(unsafe { tree.with_lifetime() }));
+            bk.string_props.borrow_mut().push(new);
+        }
+        string
     }
 }
 
@@ -133,7 +204,6 @@ impl<'a> From<&'a LispString> for &'a [u8] {
 }
 
 pub(crate) struct ByteString(GcHeap<*mut [u8]>);
-type ByteVec<'a> = bumpalo::collections::Vec<'a, u8>;
 
 impl Deref for ByteString {
     type Target = [u8];
@@ -146,17 +216,23 @@ impl Deref for ByteString {
 impl GcMoveable for ByteString {
     type Value = std::ptr::NonNull<ByteString>;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         match self.0.allocation_state() {
             AllocState::Forwarded(f) => Some((f.cast::<Self>(), false)),
             AllocState::Global => None,
+            AllocState::Old(_) => {
+                let marked = self.0.mark_old(to_space)?;
+                if marked {
+                    to_space.mark_lines(self.as_ptr(), self.len());
+                }
+                Some((NonNull::from(self), marked))
+            }
             AllocState::Unmoved => {
                 let ptr = {
-                    let mut new = ByteVec::new_in(to_space);
-                    new.extend_from_slice(self.inner());
-                    let byte_string = ByteString::new(new.as_mut_slice(), false);
-                    std::mem::forget(new);
+                    let new = to_space.alloc_slice_copy(self.inner());
+                    let byte_string = ByteString::new(new, false);
                     let alloc = to_space.alloc(byte_string);
+                    alloc.0.promote(to_space);
                     NonNull::from(alloc)
                 };
                 self.0.forward(ptr.cast::<u8>());
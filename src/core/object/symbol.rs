@@ -1,5 +1,7 @@
 use crate::core::env::sym::BUILTIN_SYMBOLS;
-use crate::core::gc::{Block, Context, GcHeap, GcMoveable, GcState, Trace, TracePtr};
+use crate::core::gc::{
+    Block, Context, GcHeap, GcMoveable, GcState, HeapKind, OldSpace, Trace, TracePtr,
+};
 use crate::core::object::{CloneIn, Function, FunctionType, Gc, IntoObject, TagType, WithLifetime};
 use anyhow::{Result, bail};
 use std::cell::Cell;
@@ -23,6 +25,8 @@ struct SymbolCellData {
     // https://github.com/crossbeam-rs/crossbeam/issues/748
     func: Option<AtomicPtr<u8>>,
     special: AtomicBool,
+    /// If true, setting the variable makes it local to the current buffer.
+    buffer_local: AtomicBool,
 }
 
 #[derive(Debug)]
@@ -97,6 +101,14 @@ impl<'a> Symbol<'a> {
     pub(crate) fn is_special(self) -> bool {
         self.0.special.load(Ordering::Acquire)
     }
+
+    pub(crate) fn make_buffer_local(self) {
+        self.0.buffer_local.store(true, Ordering::Release);
+    }
+
+    pub(crate) fn is_buffer_local(self) -> bool {
+        self.0.buffer_local.load(Ordering::Acquire)
+    }
 }
 
 unsafe impl Send for Symbol<'_> {}
@@ -112,6 +124,7 @@ impl<'new> WithLifetime<'new> for Symbol<'_> {
 
 impl TracePtr for Symbol<'_> {
     fn trace_ptr(&self, state: &mut GcState) {
+        state.live.record(HeapKind::Symbol, 0);
         self.get().trace(state);
     }
 }
@@ -119,7 +132,7 @@ impl TracePtr for Symbol<'_> {
 impl<'a> GcMoveable for Symbol<'a> {
     type Value = Symbol<'a>;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         let val = self.get().0.move_value(to_space);
         val.map(|(ptr, moved)| {
             let symbol = unsafe {
@@ -218,7 +231,7 @@ impl SymbolCell {
 
     fn new_normal(name: &'static str, block: &Block<true>) -> Self {
         // We have to do this workaround because starts_with is not const
-        if name.as_bytes()[0] == b':' {
+        if let [b':', ..] = name.as_bytes() {
             Self::new_const(name, block)
         } else {
             Self(GcHeap::new(
@@ -226,6 +239,7 @@ impl SymbolCell {
                     name: SymbolName::Interned(name),
                     func: Some(Self::EMTPTY),
                     special: AtomicBool::new(false),
+                    buffer_local: AtomicBool::new(false),
                 },
                 true,
             ))
@@ -234,13 +248,14 @@ impl SymbolCell {
 
     pub(in crate::core) const fn new_static(name: &'static str) -> Self {
         // We have to do this workaround because starts_with is not const
-        if name.as_bytes()[0] == b':' {
+        if let [b':', ..] = name.as_bytes() {
             Self::new_static_const(name)
         } else {
             Self(GcHeap::new_pure(SymbolCellData {
                 name: SymbolName::Interned(name),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                buffer_local: AtomicBool::new(false),
             }))
         }
     }
@@ -250,6 +265,7 @@ impl SymbolCell {
             name: SymbolName::Interned(name),
             func: Some(Self::EMTPTY),
             special: AtomicBool::new(true),
+            buffer_local: AtomicBool::new(false),
         }))
     }
 
@@ -259,6 +275,7 @@ impl SymbolCell {
                 name: SymbolName::Interned(name),
                 func: None,
                 special: AtomicBool::new(true),
+                buffer_local: AtomicBool::new(false),
             },
             true,
         ))
@@ -269,6 +286,7 @@ impl SymbolCell {
             name: SymbolName::Interned(name),
             func: None,
             special: AtomicBool::new(true),
+            buffer_local: AtomicBool::new(false),
         }))
     }
 
@@ -281,6 +299,7 @@ impl SymbolCell {
                 name: SymbolName::Uninterned(Cell::new(name)),
                 func: Some(Self::EMTPTY),
                 special: AtomicBool::new(false),
+                buffer_local: AtomicBool::new(false),
             },
             C,
         ))
@@ -4,17 +4,19 @@ use super::{
         error::{Type, TypeError},
         gc::Block,
     },
-    ByteFnPrototype, ByteString, CharTableInner, GcString, LispBuffer,
+    ByteFnPrototype, ByteString, CharTableInner, GcString, LispBuffer, LispMarker, LispOverlay,
+    MarkerInner, OverlayInner,
 };
 use super::{
-    ByteFn, CharTable, HashTable, LispFloat, LispHashTable, LispString, LispVec, Record,
-    RecordBuilder, SubrFn, Symbol, SymbolCell,
+    ByteFn, CharTable, HashTable, LispBigInt, LispFloat, LispHashTable, LispString, LispVec,
+    Record, RecordBuilder, SubrFn, Symbol, SymbolCell,
 };
 use crate::core::{
     env::sym,
-    gc::{DropStackElem, GcMoveable, GcState, Trace, TracePtr},
+    gc::{DropStackElem, GcMoveable, GcState, HeapKind, OldSpace, Trace, TracePtr},
 };
 use bumpalo::collections::Vec as GcVec;
+use num_bigint::BigInt;
 use private::{Tag, TaggedPtr};
 use rune_core::hashmap::HashSet;
 use std::marker::PhantomData;
@@ -284,6 +286,7 @@ impl<T> GcPtr for Gc<T> {}
 impl GcPtr for Symbol<'_> {}
 
 object_trait_impls!(LispFloat);
+object_trait_impls!(LispBigInt);
 object_trait_impls!(Cons);
 object_trait_impls!(ByteFn);
 object_trait_impls!(LispString);
@@ -293,6 +296,8 @@ object_trait_impls!(Record);
 object_trait_impls!(LispHashTable);
 object_trait_impls!(LispBuffer);
 object_trait_impls!(CharTable);
+object_trait_impls!(LispMarker);
+object_trait_impls!(LispOverlay);
 
 /// Trait for types that can be managed by the GC. This trait is implemented for
 /// as many types as possible, even for types that are already Gc managed, Like
@@ -340,6 +345,7 @@ impl IntoObject for f64 {
     type Out<'ob> = &'ob LispFloat;
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        block.allocated.record(HeapKind::Float, 0);
         let ptr = block.objects.alloc(LispFloat::new(self, C));
         unsafe { Self::Out::tag_ptr(ptr) }
     }
@@ -369,10 +375,8 @@ impl IntoObject for Cons {
     type Out<'ob> = &'ob Cons;
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        block.allocated.record(HeapKind::Cons, 0);
         let ptr = block.objects.alloc(self);
-        if C {
-            ptr.mark_const();
-        }
         unsafe { Self::Out::tag_ptr(ptr) }
     }
 }
@@ -381,6 +385,7 @@ impl IntoObject for ByteFnPrototype {
     type Out<'ob> = &'ob ByteFn;
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        block.allocated.record(HeapKind::ByteFn, 0);
         let ptr = block.objects.alloc(ByteFn::new(self, C));
         unsafe { Self::Out::tag_ptr(ptr) }
     }
@@ -390,6 +395,7 @@ impl IntoObject for SymbolCell {
     type Out<'ob> = Symbol<'ob>;
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        block.allocated.record(HeapKind::Symbol, 0);
         let ptr = block.objects.alloc(self);
         let sym = unsafe { Symbol::from_ptr(ptr) };
         unsafe { Self::Out::tag_ptr(sym.get_ptr()) }
@@ -403,6 +409,7 @@ impl IntoObject for String {
         unsafe {
             let mut this = self;
             let ptr = this.as_mut_str();
+            block.allocated.record(HeapKind::String, ptr.len());
             let ptr = block.objects.alloc(LispString::new(ptr, C));
             block.drop_stack.borrow_mut().push(DropStackElem::String(this));
             Self::Out::tag_ptr(ptr)
@@ -416,6 +423,7 @@ impl IntoObject for GcString<'_> {
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
             let mut this = self;
+            block.allocated.record(HeapKind::String, this.len());
             let ptr = block.objects.alloc(LispString::new(this.as_mut_str(), C));
             std::mem::forget(this);
             Self::Out::tag_ptr(ptr)
@@ -437,18 +445,32 @@ impl IntoObject for Vec<u8> {
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         let mut this = self;
         let slice = this.as_mut_slice();
+        block.allocated.record(HeapKind::ByteString, slice.len());
         let ptr = block.objects.alloc(ByteString::new(slice, C));
         block.drop_stack.borrow_mut().push(DropStackElem::ByteString(this));
         unsafe { <&ByteString>::tag_ptr(ptr) }
     }
 }
 
+impl IntoObject for BigInt {
+    type Out<'ob> = &'ob LispBigInt;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        let (sign, digits) = self.to_u32_digits();
+        block.allocated.record(HeapKind::BigInt, size_of_val(digits.as_slice()));
+        let ptr = block.objects.alloc(LispBigInt::new(sign, digits.as_slice(), C));
+        block.drop_stack.borrow_mut().push(DropStackElem::BigInt(digits));
+        unsafe { <&LispBigInt>::tag_ptr(ptr) }
+    }
+}
+
 impl IntoObject for Vec<Object<'_>> {
     type Out<'ob> = &'ob LispVec;
 
     fn into_obj<const C: bool>(mut self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
             // having the reference implicity cast a ptr triggers UB
+            block.allocated.record_slots(HeapKind::Vec, self.len());
             let ptr = self.as_mut_slice() as *mut [Object];
             let ptr = block.objects.alloc(LispVec::new(ptr, C));
             block.drop_stack.borrow_mut().push(DropStackElem::Vec(self.with_lifetime()));
@@ -462,6 +484,7 @@ impl IntoObject for GcVec<'_, Object<'_>> {
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
+            block.allocated.record_slots(HeapKind::Vec, self.len());
             // having the reference implicity cast a ptr triggers UB
             let ptr = self.into_bump_slice_mut() as *mut [Object];
             let ptr = block.objects.alloc(LispVec::new(ptr, C));
@@ -485,6 +508,7 @@ impl IntoObject for RecordBuilder<'_> {
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
+            block.allocated.record_slots(HeapKind::Record, self.0.len());
             // record is the same layout as lispvec, just a different newtype wrapper
             let ptr = self.0.into_bump_slice_mut() as *mut [Object];
             let ptr = block.objects.alloc(LispVec::new(ptr, C));
@@ -498,6 +522,7 @@ impl IntoObject for HashTable<'_> {
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
+            block.allocated.record(HeapKind::HashTable, 0);
             let ptr = block.objects.alloc(LispHashTable::new(self, C));
             block.lisp_hashtables.borrow_mut().push(ptr);
             <&LispHashTable>::tag_ptr(ptr)
@@ -510,12 +535,38 @@ impl IntoObject for CharTableInner<'_> {
 
     fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
         unsafe {
+            block.allocated.record(HeapKind::CharTable, 0);
             let ptr = block.objects.alloc(CharTable::new(self, C));
             <Self::Out<'_>>::tag_ptr(ptr)
         }
     }
 }
 
+impl IntoObject for MarkerInner {
+    type Out<'ob> = &'ob LispMarker;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        unsafe {
+            block.allocated.record(HeapKind::Marker, 0);
+            let ptr = block.objects.alloc(LispMarker::new(self, C));
+            block.lisp_markers.borrow_mut().push(ptr);
+            <Self::Out<'_>>::tag_ptr(ptr)
+        }
+    }
+}
+
+impl IntoObject for OverlayInner {
+    type Out<'ob> = &'ob LispOverlay;
+
+    fn into_obj<const C: bool>(self, block: &Block<C>) -> Gc<Self::Out<'_>> {
+        unsafe {
+            block.allocated.record(HeapKind::Overlay, 0);
+            let ptr = block.objects.alloc(LispOverlay::new(self, C));
+            <Self::Out<'_>>::tag_ptr(ptr)
+        }
+    }
+}
+
 mod private {
     use super::{Gc, WithLifetime};
 
@@ -525,6 +576,7 @@ mod private {
         Symbol = 0,
         Int,
         Float,
+        BigInt,
         Cons,
         String,
         ByteString,
@@ -535,6 +587,8 @@ mod private {
         ByteFn,
         Buffer,
         CharTable,
+        Marker,
+        Overlay,
     }
 
     /// Trait for tagged pointers. Anything that can be stored and passed around
@@ -635,6 +689,7 @@ impl<'a> TaggedPtr for ObjectType<'a> {
                 Tag::ByteFn => ObjectType::ByteFn(<&ByteFn>::from_obj_ptr(ptr)),
                 Tag::Int => ObjectType::Int(i64::from_obj_ptr(ptr)),
                 Tag::Float => ObjectType::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::BigInt => ObjectType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
                 Tag::String => ObjectType::String(<&LispString>::from_obj_ptr(ptr)),
                 Tag::ByteString => ObjectType::ByteString(<&ByteString>::from_obj_ptr(ptr)),
                 Tag::Vec => ObjectType::Vec(<&LispVec>::from_obj_ptr(ptr)),
@@ -642,6 +697,8 @@ impl<'a> TaggedPtr for ObjectType<'a> {
                 Tag::HashTable => ObjectType::HashTable(<&LispHashTable>::from_obj_ptr(ptr)),
                 Tag::Buffer => ObjectType::Buffer(<&LispBuffer>::from_obj_ptr(ptr)),
                 Tag::CharTable => ObjectType::CharTable(<&CharTable>::from_obj_ptr(ptr)),
+                Tag::Marker => ObjectType::Marker(<&LispMarker>::from_obj_ptr(ptr)),
+                Tag::Overlay => ObjectType::Overlay(<&LispOverlay>::from_obj_ptr(ptr)),
             }
         }
     }
@@ -650,6 +707,7 @@ impl<'a> TaggedPtr for ObjectType<'a> {
         match self {
             ObjectType::Int(x) => TaggedPtr::tag(x).into(),
             ObjectType::Float(x) => TaggedPtr::tag(x).into(),
+            ObjectType::BigInt(x) => TaggedPtr::tag(x).into(),
             ObjectType::Symbol(x) => TaggedPtr::tag(x).into(),
             ObjectType::Cons(x) => TaggedPtr::tag(x).into(),
             ObjectType::Vec(x) => TaggedPtr::tag(x).into(),
@@ -661,6 +719,8 @@ impl<'a> TaggedPtr for ObjectType<'a> {
             ObjectType::SubrFn(x) => TaggedPtr::tag(x).into(),
             ObjectType::Buffer(x) => TaggedPtr::tag(x).into(),
             ObjectType::CharTable(x) => TaggedPtr::tag(x).into(),
+            ObjectType::Marker(x) => TaggedPtr::tag(x).into(),
+            ObjectType::Overlay(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
@@ -722,6 +782,66 @@ impl<'a> TaggedPtr for FunctionType<'a> {
     }
 }
 
+impl<'a> TaggedPtr for NumberOrMarkerType<'a> {
+    type Ptr = NumberOrMarkerType<'a>;
+    const TAG: Tag = Tag::Int;
+
+    unsafe fn tag_ptr(_: *const Self::Ptr) -> Gc<Self> {
+        unimplemented!()
+    }
+
+    fn untag(val: Gc<Self>) -> Self {
+        let (ptr, tag) = val.untag_ptr();
+        unsafe {
+            match tag {
+                Tag::Int => NumberOrMarkerType::Int(i64::from_obj_ptr(ptr)),
+                Tag::Float => NumberOrMarkerType::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::BigInt => NumberOrMarkerType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
+                Tag::Marker => NumberOrMarkerType::Marker(<&LispMarker>::from_obj_ptr(ptr)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn tag(self) -> Gc<Self> {
+        match self {
+            NumberOrMarkerType::Int(x) => TaggedPtr::tag(x).into(),
+            NumberOrMarkerType::Float(x) => TaggedPtr::tag(x).into(),
+            NumberOrMarkerType::BigInt(x) => TaggedPtr::tag(x).into(),
+            NumberOrMarkerType::Marker(x) => TaggedPtr::tag(x).into(),
+        }
+    }
+}
+
+impl<'a> TaggedPtr for IntOrMarkerType<'a> {
+    type Ptr = IntOrMarkerType<'a>;
+    const TAG: Tag = Tag::Int;
+
+    unsafe fn tag_ptr(_: *const Self::Ptr) -> Gc<Self> {
+        unimplemented!()
+    }
+
+    fn untag(val: Gc<Self>) -> Self {
+        let (ptr, tag) = val.untag_ptr();
+        unsafe {
+            match tag {
+                Tag::Int => IntOrMarkerType::Int(i64::from_obj_ptr(ptr)),
+                Tag::BigInt => IntOrMarkerType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
+                Tag::Marker => IntOrMarkerType::Marker(<&LispMarker>::from_obj_ptr(ptr)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn tag(self) -> Gc<Self> {
+        match self {
+            IntOrMarkerType::Int(x) => TaggedPtr::tag(x).into(),
+            IntOrMarkerType::BigInt(x) => TaggedPtr::tag(x).into(),
+            IntOrMarkerType::Marker(x) => TaggedPtr::tag(x).into(),
+        }
+    }
+}
+
 impl<'a> TaggedPtr for NumberType<'a> {
     type Ptr = NumberType<'a>;
     const TAG: Tag = Tag::Int;
@@ -736,6 +856,7 @@ impl<'a> TaggedPtr for NumberType<'a> {
             match tag {
                 Tag::Int => NumberType::Int(i64::from_obj_ptr(ptr)),
                 Tag::Float => NumberType::Float(<&LispFloat>::from_obj_ptr(ptr)),
+                Tag::BigInt => NumberType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
                 _ => unreachable!(),
             }
         }
@@ -745,12 +866,42 @@ impl<'a> TaggedPtr for NumberType<'a> {
         match self {
             NumberType::Int(x) => TaggedPtr::tag(x).into(),
             NumberType::Float(x) => TaggedPtr::tag(x).into(),
+            NumberType::BigInt(x) => TaggedPtr::tag(x).into(),
+        }
+    }
+}
+
+impl<'a> TaggedPtr for IntegerType<'a> {
+    type Ptr = IntegerType<'a>;
+    const TAG: Tag = Tag::Int;
+
+    unsafe fn tag_ptr(_: *const Self::Ptr) -> Gc<Self> {
+        unimplemented!()
+    }
+
+    fn untag(val: Gc<Self>) -> Self {
+        let (ptr, tag) = val.untag_ptr();
+        unsafe {
+            match tag {
+                Tag::Int => IntegerType::Int(i64::from_obj_ptr(ptr)),
+                Tag::BigInt => IntegerType::BigInt(<&LispBigInt>::from_obj_ptr(ptr)),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    fn tag(self) -> Gc<Self> {
+        match self {
+            IntegerType::Int(x) => TaggedPtr::tag(x).into(),
+            IntegerType::BigInt(x) => TaggedPtr::tag(x).into(),
         }
     }
 }
 
-const MAX_FIXNUM: i64 = i64::MAX >> 8;
-const MIN_FIXNUM: i64 = i64::MIN >> 8;
+/// The largest integer that can be stored without allocating a bignum.
+pub(crate) const MAX_FIXNUM: i64 = i64::MAX >> 8;
+/// The smallest integer that can be stored without allocating a bignum.
+pub(crate) const MIN_FIXNUM: i64 = i64::MIN >> 8;
 
 impl TaggedPtr for i64 {
     type Ptr = i64;
@@ -790,6 +941,18 @@ impl TaggedPtr for &LispFloat {
     }
 }
 
+impl TaggedPtr for &LispBigInt {
+    type Ptr = LispBigInt;
+    const TAG: Tag = Tag::BigInt;
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 impl TaggedPtr for &Cons {
     type Ptr = Cons;
     const TAG: Tag = Tag::Cons;
@@ -928,11 +1091,39 @@ impl TaggedPtr for &CharTable {
     }
 }
 
+impl TaggedPtr for &LispMarker {
+    type Ptr = LispMarker;
+    const TAG: Tag = Tag::Marker;
+
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
+impl TaggedPtr for &LispOverlay {
+    type Ptr = LispOverlay;
+    const TAG: Tag = Tag::Overlay;
+
+    unsafe fn from_obj_ptr(ptr: *const u8) -> Self {
+        &*ptr.cast::<Self::Ptr>()
+    }
+
+    fn get_ptr(self) -> *const Self::Ptr {
+        self as *const Self::Ptr
+    }
+}
+
 impl<T> TracePtr for Gc<T> {
     fn trace_ptr(&self, state: &mut GcState) {
+        state.live.record_object(self.as_obj());
         match self.as_obj().untag() {
             ObjectType::Int(_) | ObjectType::SubrFn(_) => {}
             ObjectType::Float(x) => x.trace(state),
+            ObjectType::BigInt(x) => x.trace(state),
             ObjectType::String(x) => x.trace(state),
             ObjectType::ByteString(x) => x.trace(state),
             ObjectType::Vec(vec) => vec.trace(state),
@@ -943,6 +1134,8 @@ impl<T> TracePtr for Gc<T> {
             ObjectType::ByteFn(x) => x.trace(state),
             ObjectType::Buffer(x) => x.trace(state),
             ObjectType::CharTable(x) => x.trace(state),
+            ObjectType::Marker(x) => x.trace(state),
+            ObjectType::Overlay(x) => x.trace(state),
         }
     }
 }
@@ -976,8 +1169,9 @@ macro_rules! cast_gc {
 pub(crate) enum NumberType<'ob> {
     Int(i64) = Tag::Int as u8,
     Float(&'ob LispFloat) = Tag::Float as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
 }
-cast_gc!(NumberType<'ob> => i64, &LispFloat);
+cast_gc!(NumberType<'ob> => i64, &LispFloat, &LispBigInt);
 
 /// Represents a tagged pointer to a number value
 pub(crate) type Number<'ob> = Gc<NumberType<'ob>>;
@@ -990,6 +1184,74 @@ impl<'old, 'new> WithLifetime<'new> for NumberType<'old> {
     }
 }
 
+// Number or marker
+#[derive(Copy, Clone)]
+#[repr(u8)]
+/// The enum form of [NumberOrMarker] to take advantage of ergonomics of enums in Rust.
+pub(crate) enum NumberOrMarkerType<'ob> {
+    Int(i64) = Tag::Int as u8,
+    Float(&'ob LispFloat) = Tag::Float as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
+    Marker(&'ob LispMarker) = Tag::Marker as u8,
+}
+cast_gc!(NumberOrMarkerType<'ob> => i64, &LispFloat, &LispBigInt, &LispMarker);
+
+/// Represents a tagged pointer to a number or a marker. Markers that point
+/// into a buffer are treated as their position.
+pub(crate) type NumberOrMarker<'ob> = Gc<NumberOrMarkerType<'ob>>;
+
+impl<'old, 'new> WithLifetime<'new> for NumberOrMarkerType<'old> {
+    type Out = NumberOrMarkerType<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<NumberOrMarkerType<'old>, NumberOrMarkerType<'new>>(self)
+    }
+}
+
+// Integer
+#[derive(Copy, Clone)]
+#[repr(u8)]
+/// The enum form of [Integer] to take advantage of ergonomics of enums in Rust.
+pub(crate) enum IntegerType<'ob> {
+    Int(i64) = Tag::Int as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
+}
+cast_gc!(IntegerType<'ob> => i64, &LispBigInt);
+
+/// Represents a tagged pointer to an integer, which is either a fixnum or a
+/// bignum.
+pub(crate) type Integer<'ob> = Gc<IntegerType<'ob>>;
+
+impl<'old, 'new> WithLifetime<'new> for IntegerType<'old> {
+    type Out = IntegerType<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<IntegerType<'old>, IntegerType<'new>>(self)
+    }
+}
+
+// Integer or marker
+#[derive(Copy, Clone)]
+#[repr(u8)]
+/// The enum form of [IntOrMarker] to take advantage of ergonomics of enums in Rust.
+pub(crate) enum IntOrMarkerType<'ob> {
+    Int(i64) = Tag::Int as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
+    Marker(&'ob LispMarker) = Tag::Marker as u8,
+}
+cast_gc!(IntOrMarkerType<'ob> => i64, &LispBigInt, &LispMarker);
+
+/// Represents a tagged pointer to an integer or a marker.
+pub(crate) type IntOrMarker<'ob> = Gc<IntOrMarkerType<'ob>>;
+
+impl<'old, 'new> WithLifetime<'new> for IntOrMarkerType<'old> {
+    type Out = IntOrMarkerType<'new>;
+
+    unsafe fn with_lifetime(self) -> Self::Out {
+        std::mem::transmute::<IntOrMarkerType<'old>, IntOrMarkerType<'new>>(self)
+    }
+}
+
 // List
 #[derive(Copy, Clone)]
 #[repr(u8)]
@@ -1049,6 +1311,7 @@ impl<'old, 'new> WithLifetime<'new> for FunctionType<'old> {
 pub(crate) enum ObjectType<'ob> {
     Int(i64) = Tag::Int as u8,
     Float(&'ob LispFloat) = Tag::Float as u8,
+    BigInt(&'ob LispBigInt) = Tag::BigInt as u8,
     Symbol(Symbol<'ob>) = Tag::Symbol as u8,
     Cons(&'ob Cons) = Tag::Cons as u8,
     Vec(&'ob LispVec) = Tag::Vec as u8,
@@ -1060,6 +1323,8 @@ pub(crate) enum ObjectType<'ob> {
     SubrFn(&'static SubrFn) = Tag::SubrFn as u8,
     Buffer(&'static LispBuffer) = Tag::Buffer as u8,
     CharTable(&'static CharTable) = Tag::CharTable as u8,
+    Marker(&'ob LispMarker) = Tag::Marker as u8,
+    Overlay(&'ob LispOverlay) = Tag::Overlay as u8,
 }
 
 /// The Object defintion that contains all other possible lisp objects. This
@@ -1067,11 +1332,15 @@ pub(crate) enum ObjectType<'ob> {
 pub(crate) type Object<'ob> = Gc<ObjectType<'ob>>;
 
 cast_gc!(ObjectType<'ob> => NumberType<'ob>,
+         NumberOrMarkerType<'ob>,
+         IntegerType<'ob>,
+         IntOrMarkerType<'ob>,
          ListType<'ob>,
          FunctionType<'ob>,
          i64,
          Symbol<'_>,
          &'ob LispFloat,
+         &'ob LispBigInt,
          &'ob Cons,
          &'ob LispVec,
          &'ob Record,
@@ -1081,7 +1350,9 @@ cast_gc!(ObjectType<'ob> => NumberType<'ob>,
          &'ob ByteFn,
          &'ob SubrFn,
          &'ob LispBuffer,
-         &'ob CharTable
+         &'ob CharTable,
+         &'ob LispMarker,
+         &'ob LispOverlay
 );
 
 impl ObjectType<'_> {
@@ -1090,7 +1361,7 @@ impl ObjectType<'_> {
     /// Return the type of an object
     pub(crate) fn get_type(self) -> Type {
         match self {
-            ObjectType::Int(_) => Type::Int,
+            ObjectType::Int(_) | ObjectType::BigInt(_) => Type::Int,
             ObjectType::Float(_) => Type::Float,
             ObjectType::Symbol(_) => Type::Symbol,
             ObjectType::Cons(_) => Type::Cons,
@@ -1102,6 +1373,8 @@ impl ObjectType<'_> {
             ObjectType::ByteFn(_) | ObjectType::SubrFn(_) => Type::Func,
             ObjectType::Buffer(_) => Type::Buffer,
             ObjectType::CharTable(_) => Type::CharTable,
+            ObjectType::Marker(_) => Type::Marker,
+            ObjectType::Overlay(_) => Type::Overlay,
         }
     }
 }
@@ -1190,12 +1463,67 @@ impl<'ob> TryFrom<Object<'ob>> for Number<'ob> {
 
     fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
         match value.get_tag() {
-            Tag::Int | Tag::Float => unsafe { Ok(cast_gc(value)) },
+            Tag::Int | Tag::Float | Tag::BigInt => unsafe { Ok(cast_gc(value)) },
             _ => Err(TypeError::new(Type::Number, value)),
         }
     }
 }
 
+impl<'ob> TryFrom<Object<'ob>> for Integer<'ob> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::Int | Tag::BigInt => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::Int, value)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for NumberOrMarker<'ob> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.untag() {
+            ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::BigInt(_) => unsafe {
+                Ok(cast_gc(value))
+            },
+            ObjectType::Marker(m) if m.position().is_some() => unsafe { Ok(cast_gc(value)) },
+            ObjectType::Marker(_) => Err(anyhow::anyhow!("Marker does not point anywhere")),
+            _ => Err(TypeError::new(Type::NumberOrMarker, value).into()),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Option<NumberOrMarker<'ob>> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        if value.is_nil() { Ok(None) } else { value.try_into().map(Some) }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for IntOrMarker<'ob> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.untag() {
+            ObjectType::Int(_) | ObjectType::BigInt(_) => unsafe { Ok(cast_gc(value)) },
+            ObjectType::Marker(m) if m.position().is_some() => unsafe { Ok(cast_gc(value)) },
+            ObjectType::Marker(_) => Err(anyhow::anyhow!("Marker does not point anywhere")),
+            _ => Err(TypeError::new(Type::IntOrMarker, value).into()),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Option<IntOrMarker<'ob>> {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        if value.is_nil() { Ok(None) } else { value.try_into().map(Some) }
+    }
+}
+
 impl<'ob> TryFrom<Object<'ob>> for Option<Number<'ob>> {
     type Error = TypeError;
 
@@ -1343,6 +1671,28 @@ impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispBuffer> {
     }
 }
 
+impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispMarker> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::Marker => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::Marker, value)),
+        }
+    }
+}
+
+impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob LispOverlay> {
+    type Error = TypeError;
+
+    fn try_from(value: Object<'ob>) -> Result<Self, Self::Error> {
+        match value.get_tag() {
+            Tag::Overlay => unsafe { Ok(cast_gc(value)) },
+            _ => Err(TypeError::new(Type::Overlay, value)),
+        }
+    }
+}
+
 impl<'ob> TryFrom<Object<'ob>> for Gc<&'ob CharTable> {
     type Error = TypeError;
 
@@ -1385,11 +1735,14 @@ where
             ObjectType::ByteFn(x) => x.clone_in(bk).into(),
             ObjectType::SubrFn(x) => x.into(),
             ObjectType::Float(x) => x.clone_in(bk).into(),
+            ObjectType::BigInt(x) => x.clone_in(bk).into(),
             ObjectType::Vec(x) => x.clone_in(bk).into(),
             ObjectType::Record(x) => x.clone_in(bk).into(),
             ObjectType::HashTable(x) => x.clone_in(bk).into(),
             ObjectType::Buffer(x) => x.clone_in(bk).into(),
             ObjectType::CharTable(x) => x.clone_in(bk).into(),
+            ObjectType::Marker(x) => x.clone_in(bk).into(),
+            ObjectType::Overlay(x) => x.clone_in(bk).into(),
         };
         let Ok(x) = Gc::<U>::try_from(obj) else { unreachable!() };
         x
@@ -1403,7 +1756,7 @@ where
 {
     type Value = Self;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         self.untag().move_value(to_space).map(|(x, moved)| (x.tag(), moved))
     }
 }
@@ -1411,10 +1764,11 @@ where
 impl GcMoveable for Object<'_> {
     type Value = Self;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             ObjectType::Int(_) | ObjectType::SubrFn(_) | ObjectType::NIL => return None,
             ObjectType::Float(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::BigInt(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Cons(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Vec(x) => cast_pair(x.move_value(to_space)?),
             ObjectType::Record(x) => cast_pair(x.move_value(to_space)?),
@@ -1430,6 +1784,8 @@ impl GcMoveable for Object<'_> {
                 (sym.as_ptr(), moved)
             }
             ObjectType::CharTable(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::Marker(x) => cast_pair(x.move_value(to_space)?),
+            ObjectType::Overlay(x) => cast_pair(x.move_value(to_space)?),
         };
 
         let tag = self.get_tag();
@@ -1440,7 +1796,7 @@ impl GcMoveable for Object<'_> {
 impl GcMoveable for Function<'_> {
     type Value = Self;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             FunctionType::SubrFn(_) => return None,
             FunctionType::Cons(x) => cast_pair(x.move_value(to_space)?),
@@ -1459,7 +1815,7 @@ impl GcMoveable for Function<'_> {
 impl GcMoveable for List<'_> {
     type Value = Self;
 
-    fn move_value(&self, to_space: &bumpalo::Bump) -> Option<(Self::Value, bool)> {
+    fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
         let data = match self.untag() {
             ListType::Cons(x) => cast_pair(x.move_value(to_space)?),
             ListType::Nil => return None,
@@ -1596,7 +1952,11 @@ impl<T> Eq for Gc<T> {}
 use std::hash::{Hash, Hasher};
 impl<T> Hash for Gc<T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.ptr.hash(state);
+        // Bignums are compared by value, so they need to be hashed by value
+        match self.as_obj().untag() {
+            ObjectType::BigInt(x) => x.hash_value(state),
+            _ => self.ptr.hash(state),
+        }
     }
 }
 
@@ -1631,8 +1991,11 @@ impl ObjectType<'_> {
             ObjectType::ByteFn(x) => D::fmt(x, f),
             ObjectType::SubrFn(x) => D::fmt(x, f),
             ObjectType::Float(x) => D::fmt(x, f),
+            ObjectType::BigInt(x) => D::fmt(x, f),
             ObjectType::Buffer(x) => D::fmt(x, f),
             ObjectType::CharTable(x) => D::fmt(x, f),
+            ObjectType::Marker(x) => D::fmt(x, f),
+            ObjectType::Overlay(x) => D::fmt(x, f),
         }
     }
 }
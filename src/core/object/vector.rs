@@ -1,8 +1,5 @@
 use super::{CloneIn, Gc, IntoObject, MutObjCell, ObjCell, Object};
-use crate::{
-    core::gc::{Block, GcHeap, GcState, Trace},
-    derive_GcMoveable,
-};
+use crate::core::gc::{AllocState, Block, GcHeap, GcMoveable, GcState, OldSpace, Trace};
 use anyhow::{Result, anyhow};
 use bumpalo::collections::Vec as GcVec;
 use rune_core::hashmap::HashSet;
@@ -11,7 +8,7 @@ use std::{
     cell::Cell,
     fmt::{self, Write},
     ops::Deref,
-    ptr::addr_of,
+    ptr::{NonNull, addr_of},
 };
 
 struct LispVecInner {
@@ -25,7 +22,45 @@ struct LispVecInner {
 #[derive(PartialEq, Eq, Trace)]
 pub(crate) struct LispVec(GcHeap<LispVecInner>);
 
-derive_GcMoveable!(LispVec);
+/// Vectors and records own their slice of elements, so it is copied along with
+/// the object when it leaves the nursery. Old objects never move, so the lines
+/// of their slice only need to be marked.
+macro_rules! vec_moveable {
+    ($name:ident) => {
+        impl GcMoveable for $name {
+            type Value = NonNull<Self>;
+
+            fn move_value(&self, to_space: &OldSpace) -> Option<(Self::Value, bool)> {
+                match self.0.allocation_state() {
+                    AllocState::Forwarded(f) => Some((f.cast::<Self>(), false)),
+                    AllocState::Global => None,
+                    AllocState::Old(_) => {
+                        let marked = self.0.mark_old(to_space)?;
+                        if marked {
+                            let slice = self.0.get_slice();
+                            to_space.mark_lines(slice.as_ptr().cast(), size_of_val(slice));
+                        }
+                        Some((NonNull::from(self), marked))
+                    }
+                    AllocState::Unmoved => {
+                        let ptr = {
+                            let slice = unsafe { &*(self.0.inner.get() as *const [Object]) };
+                            let new = to_space.alloc_slice_copy(slice);
+                            let inner = unsafe { LispVecInner::new(new, false) };
+                            let alloc = to_space.alloc($name(GcHeap::new(inner, false)));
+                            alloc.0.promote(to_space);
+                            NonNull::from(alloc)
+                        };
+                        self.0.forward(ptr.cast::<u8>());
+                        Some((ptr, true))
+                    }
+                }
+            }
+        }
+    };
+}
+
+vec_moveable!(LispVec);
 
 impl Deref for LispVec {
     type Target = [ObjCell];
@@ -65,11 +100,11 @@ impl LispVecInner {
 
 impl LispVec {
     pub(crate) fn try_mut(&self) -> Result<&[MutObjCell]> {
-        if self.0.is_const {
-            Err(anyhow!("Attempt to mutate constant Vector"))
-        } else {
+        if self.0.write_barrier() {
             // SAFETY: ObjCell and MutObjCell have the same representation.
             unsafe { Ok(&*(self.0.inner.get() as *const [MutObjCell])) }
+        } else {
+            Err(anyhow!("Attempt to mutate constant Vector"))
         }
     }
 }
@@ -85,18 +120,11 @@ impl<'new> CloneIn<'new, &'new Self> for LispVec {
 impl Trace for LispVecInner {
     fn trace(&self, state: &mut GcState) {
         assert!(!self.is_const, "Attempt to trace mutable vector");
-        // Update the object pointers in the vector
-        // move the vector to the to-space.
-        //
-        // TODO: can we update and move in one step? should be able to use
-        // `alloc_slice_fill_iter`
-        let slice = unsafe { &*(self.inner.get() as *mut [Object]) };
-        let new = state.to_space.alloc_slice_copy(slice);
-        let new = unsafe { std::mem::transmute::<&mut [Object], &mut [ObjCell]>(new) };
-        for x in &mut *new {
+        // The slice was already copied out of the nursery when the vector was
+        // moved, so the elements can be updated in place.
+        for x in self.get_slice() {
             x.trace(state);
         }
-        self.inner.set(new);
     }
 }
 
@@ -148,7 +176,7 @@ pub(crate) struct RecordBuilder<'ob>(pub(crate) GcVec<'ob, Object<'ob>>);
 #[derive(PartialEq, Eq, Trace)]
 pub(crate) struct Record(GcHeap<LispVecInner>);
 
-derive_GcMoveable!(Record);
+vec_moveable!(Record);
 
 impl Deref for Record {
     type Target = [ObjCell];
@@ -174,11 +202,11 @@ impl fmt::Display for Record {
 
 impl Record {
     pub(crate) fn try_mut(&self) -> Result<&[MutObjCell]> {
-        if self.0.is_const {
-            Err(anyhow!("Attempt to mutate constant Vector"))
-        } else {
+        if self.0.write_barrier() {
             // SAFETY: ObjCell and MutObjCell have the same representation.
             unsafe { Ok(&*(self.0.inner.get() as *const [MutObjCell])) }
+        } else {
+            Err(anyhow!("Attempt to mutate constant Vector"))
         }
     }
 
@@ -1,14 +1,17 @@
 //! Utilities for variables and values.
+use crate::arith::{NumberValue, parse_number_prefix};
 use crate::core::{
     cons::Cons,
     env::{Env, INTERNED_SYMBOLS, sym},
-    error::{Type, TypeError},
+    error::{ErrorData, SignalError, Type, TypeError},
     gc::{Context, Rt},
     object::{
-        IntoObject, List, ListType, NIL, Number, Object, ObjectType, SubrFn, Symbol, WithLifetime,
+        Gc, Integer, IntegerType, IntoObject, LispBuffer, List, ListType, NIL, Number, NumberType,
+        Object, ObjectType, SubrFn, Symbol, WithLifetime,
     },
 };
-use anyhow::{Result, anyhow};
+use crate::print::float_to_string;
+use anyhow::{Result, anyhow, bail};
 use rune_core::{hashmap::HashSet, macros::list};
 use rune_macros::defun;
 use std::sync::LazyLock;
@@ -38,7 +41,9 @@ pub(crate) fn defalias<'ob>(
     definition: Object,
     _docstring: Option<&str>,
 ) -> Result<Symbol<'ob>> {
-    fset(symbol, definition)
+    fset(symbol, definition)?;
+    crate::debug::record_definition(symbol.name());
+    Ok(symbol)
 }
 
 #[defun]
@@ -79,9 +84,61 @@ pub(crate) fn get<'ob>(
 }
 
 #[defun]
-pub(crate) fn local_variable_if_set_p(_sym: Symbol) -> bool {
-    // TODO: Implement buffer locals
-    false
+pub(crate) fn local_variable_p(
+    variable: Symbol,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+) -> Result<bool> {
+    match buffer {
+        Some(buffer) => env.with_buffer(buffer.untag(), |b| b.has_local(variable)),
+        None => Ok(env.current_buffer.get().has_local(variable)),
+    }
+}
+
+#[defun]
+pub(crate) fn local_variable_if_set_p(
+    variable: Symbol,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &Rt<Env>,
+) -> Result<bool> {
+    Ok(variable.is_buffer_local() || local_variable_p(variable, buffer, env)?)
+}
+
+#[defun]
+pub(crate) fn make_local_variable<'ob>(
+    variable: Symbol<'ob>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<Symbol<'ob>> {
+    if variable.is_const() {
+        bail!("Symbol {variable} may not be buffer-local");
+    }
+    let default = env.vars.get(variable).map(|x| x.bind(cx));
+    let buffer = env.current_buffer.get_mut();
+    if !buffer.has_local(variable) {
+        buffer.set_local(variable, default);
+    }
+    Ok(variable)
+}
+
+#[defun]
+pub(crate) fn kill_local_variable<'ob>(variable: Symbol<'ob>, env: &mut Rt<Env>) -> Symbol<'ob> {
+    env.current_buffer.get_mut().kill_local(variable);
+    variable
+}
+
+#[defun]
+pub(crate) fn buffer_local_value<'ob>(
+    variable: Symbol,
+    buffer: Gc<&LispBuffer>,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let value = match env.with_buffer(buffer.untag(), |b| b.local(variable, cx))? {
+        Some(local) => local,
+        None => env.vars.get(variable).map(|x| x.bind(cx)),
+    };
+    value.ok_or_else(|| SignalError::void_variable(variable).into())
 }
 
 #[defun]
@@ -90,8 +147,10 @@ pub(crate) fn default_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    // TODO: Implement buffer locals
-    symbol_value(symbol, env, cx).ok_or_else(|| anyhow!("Void variable: {symbol}"))
+    match env.vars.get(symbol) {
+        Some(value) => Ok(value.bind(cx)),
+        None => Err(SignalError::void_variable(symbol).into()),
+    }
 }
 
 #[defun]
@@ -108,7 +167,7 @@ pub(crate) fn symbol_value<'ob>(
     env: &Rt<Env>,
     cx: &'ob Context,
 ) -> Option<Object<'ob>> {
-    env.vars.get(symbol).map(|x| x.bind(cx))
+    env.var(symbol, cx)
 }
 
 #[defun]
@@ -133,13 +192,18 @@ pub(crate) fn fmakunbound(symbol: Symbol) -> Symbol {
 }
 
 #[defun]
-pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>) -> bool {
-    env.vars.get(symbol).is_some()
+pub(crate) fn boundp(symbol: Symbol, env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(symbol, cx).is_some()
 }
 
 #[defun]
 pub(crate) fn makunbound<'ob>(symbol: Symbol<'ob>, env: &mut Rt<Env>) -> Symbol<'ob> {
-    env.vars.remove(symbol);
+    let buffer = env.current_buffer.get_mut();
+    if buffer.has_local(symbol) {
+        buffer.set_local(symbol, None);
+    } else {
+        env.vars.remove(symbol);
+    }
     symbol
 }
 
@@ -185,13 +249,20 @@ pub(crate) fn stringp(object: Object) -> bool {
 
 #[defun]
 pub(crate) fn numberp(object: Object) -> bool {
-    matches!(object.untag(), ObjectType::Int(_) | ObjectType::Float(_))
+    matches!(
+        object.untag(),
+        ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::BigInt(_)
+    )
 }
 
 #[defun]
-pub(crate) fn markerp(_: Object) -> bool {
-    // TODO: implement
-    false
+pub(crate) fn markerp(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Marker(_))
+}
+
+#[defun]
+pub(crate) fn overlayp(object: Object) -> bool {
+    matches!(object.untag(), ObjectType::Overlay(_))
 }
 
 #[defun]
@@ -219,7 +290,7 @@ pub(crate) fn keywordp(object: Object) -> bool {
 
 #[defun]
 pub(crate) fn integerp(object: Object) -> bool {
-    matches!(object.untag(), ObjectType::Int(_))
+    matches!(object.untag(), ObjectType::Int(_) | ObjectType::BigInt(_))
 }
 
 #[defun]
@@ -253,17 +324,27 @@ pub(crate) fn multibyte_string_p(object: Object) -> bool {
     matches!(object.untag(), ObjectType::String(_))
 }
 
+/// Parse a number at the start of `string`, ignoring leading spaces and tabs
+/// and anything after the number. Floats are only parsed in base 10.
 #[defun]
-fn string_to_number<'ob>(string: &str, base: Option<i64>, cx: &'ob Context) -> Number<'ob> {
-    // TODO: Handle trailing characters, which should be ignored
+fn string_to_number(string: &str, base: Option<i64>) -> Result<NumberValue> {
     let base = base.unwrap_or(10);
-    let string = string.trim();
-    match i64::from_str_radix(string, base as u32) {
-        Ok(x) => x.into(),
-        Err(_) => match string.parse::<f64>() {
-            Ok(x) => cx.add_as(x),
-            Err(_) => 0.into(),
-        },
+    if !(2..=16).contains(&base) {
+        bail!(SignalError::args_out_of_range(&[base.into()]));
+    }
+    let string = string.trim_start_matches([' ', '\t']);
+    match parse_number_prefix(string, base as u32) {
+        Some((num, _)) => Ok(num),
+        None => Ok(NumberValue::Int(0)),
+    }
+}
+
+#[defun]
+fn number_to_string(number: Number, env: &Rt<Env>, cx: &Context) -> String {
+    match number.untag() {
+        NumberType::Int(x) => x.to_string(),
+        NumberType::BigInt(x) => x.to_string(),
+        NumberType::Float(x) => float_to_string(**x, env, cx),
     }
 }
 
@@ -275,13 +356,23 @@ pub(crate) fn defvar<'ob>(
     env: &mut Rt<Env>,
 ) -> Result<Object<'ob>> {
     let value = initvalue.unwrap_or_default();
-    set(symbol, value, env)
+    env.set_default(symbol, value)?;
+    Ok(value)
 }
 
 #[defun]
-pub(crate) fn make_variable_buffer_local(variable: Symbol) -> Symbol {
-    // TODO: Implement
-    variable
+pub(crate) fn make_variable_buffer_local<'ob>(
+    variable: Symbol<'ob>,
+    env: &mut Rt<Env>,
+) -> Result<Symbol<'ob>> {
+    if variable.is_const() {
+        bail!("Symbol {variable} may not be buffer-local");
+    }
+    variable.make_buffer_local();
+    if env.vars.get(variable).is_none() {
+        env.vars.insert(variable, NIL);
+    }
+    Ok(variable)
 }
 
 #[defun]
@@ -298,10 +389,26 @@ fn subr_arity<'ob>(subr: &SubrFn, cx: &'ob Context) -> Object<'ob> {
 }
 
 #[defun]
-fn ash(value: i64, count: i64) -> i64 {
-    let shift = if count >= 0 { std::ops::Shl::shl } else { std::ops::Shr::shr };
-    let result = shift(value.abs(), count.abs());
-    if value >= 0 { result } else { -result }
+fn ash(value: Integer, count: i64) -> NumberValue {
+    if let IntegerType::Int(x) = value.untag() {
+        // Only use a bignum if the result doesn't fit
+        if count <= 0 {
+            return NumberValue::Int(x >> count.unsigned_abs().min(63));
+        }
+        if count < 64 {
+            let shifted = x << count;
+            if shifted >> count == x {
+                return NumberValue::Int(shifted);
+            }
+        }
+    }
+    let value = value.big();
+    let shifted = if count >= 0 {
+        value << count.unsigned_abs()
+    } else {
+        value >> count.unsigned_abs()
+    };
+    shifted.into()
 }
 
 #[defun]
@@ -382,7 +489,7 @@ pub(crate) fn aref<'ob>(array: Object<'ob>, idx: usize, cx: &'ob Context) -> Res
 #[defun]
 fn type_of(object: Object) -> Object {
     match object.untag() {
-        ObjectType::Int(_) => sym::INTEGER.into(),
+        ObjectType::Int(_) | ObjectType::BigInt(_) => sym::INTEGER.into(),
         ObjectType::Float(_) => sym::FLOAT.into(),
         ObjectType::Symbol(_) => sym::SYMBOL.into(),
         ObjectType::Cons(_) => sym::CONS.into(),
@@ -394,6 +501,8 @@ fn type_of(object: Object) -> Object {
         ObjectType::SubrFn(_) => sym::SUBR.into(),
         ObjectType::Buffer(_) => sym::BUFFER.into(),
         ObjectType::CharTable(_) => sym::CHAR_TABLE.into(),
+        ObjectType::Marker(_) => sym::MARKER.into(),
+        ObjectType::Overlay(_) => sym::OVERLAY.into(),
     }
 }
 
@@ -481,7 +590,7 @@ fn symbol_with_pos_p(_sym: Object) -> bool {
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct LispError {
-    message: &'static Cons,
+    message: ErrorData,
 }
 
 impl std::error::Error for LispError {}
@@ -493,14 +602,44 @@ impl std::fmt::Display for LispError {
     }
 }
 
+defsym!(ERROR_CONDITIONS);
+defsym!(ERROR_MESSAGE);
+defsym!(QUIT);
+defsym!(USER_ERROR);
+defsym!(WRONG_LENGTH_ARGUMENT);
+defsym!(WRONG_TYPE_ARGUMENT);
+defsym!(ARGS_OUT_OF_RANGE);
+defsym!(VOID_FUNCTION);
+defsym!(CYCLIC_FUNCTION_INDIRECTION);
+defsym!(CYCLIC_VARIABLE_INDIRECTION);
+defsym!(CIRCULAR_LIST);
+defsym!(SETTING_CONSTANT);
+defsym!(INVALID_READ_SYNTAX);
+defsym!(INVALID_FUNCTION);
 defsym!(WRONG_NUMBER_OF_ARGUMENTS);
+defsym!(NO_CATCH);
+defsym!(END_OF_FILE);
+defsym!(ARITH_ERROR);
+defsym!(DOMAIN_ERROR);
+defsym!(RANGE_ERROR);
+defsym!(SINGULARITY_ERROR);
+defsym!(OVERFLOW_ERROR);
+defsym!(UNDERFLOW_ERROR);
+defsym!(BEGINNING_OF_BUFFER);
+defsym!(END_OF_BUFFER);
+defsym!(BUFFER_READ_ONLY);
+defsym!(TEXT_READ_ONLY);
+defsym!(SEARCH_FAILED);
+defsym!(INVALID_REGEXP);
+defsym!(MARK_INACTIVE);
+defsym!(SCAN_ERROR);
 impl LispError {
     pub(crate) fn new(message: &Cons) -> Self {
-        Self { message: unsafe { message.with_lifetime() } }
+        Self { message: ErrorData::new(&[message.into()]) }
     }
 
     pub(crate) fn bind<'ob>(&self, cx: &'ob Context) -> &'ob Cons {
-        cx.bind(self.message)
+        self.message.bind(cx)[0].try_into().unwrap()
     }
 
     pub(crate) fn arg_cnt<'ob, T>(
@@ -515,8 +654,143 @@ impl LispError {
     }
 }
 
-unsafe impl Send for LispError {}
-unsafe impl Sync for LispError {}
+/// The errors that are predefined by the runtime. Each entry holds the error
+/// symbol, its full `error-conditions` list, and its `error-message`.
+const STANDARD_ERRORS: &[(Symbol<'static>, &[Symbol<'static>], &str)] = &[
+    (sym::ERROR, &[sym::ERROR], "error"),
+    (sym::QUIT, &[sym::QUIT], "Quit"),
+    (sym::USER_ERROR, &[sym::USER_ERROR, sym::ERROR], ""),
+    (
+        sym::WRONG_LENGTH_ARGUMENT,
+        &[sym::WRONG_LENGTH_ARGUMENT, sym::ERROR],
+        "Wrong length argument",
+    ),
+    (
+        sym::WRONG_TYPE_ARGUMENT,
+        &[sym::WRONG_TYPE_ARGUMENT, sym::ERROR],
+        "Wrong type argument",
+    ),
+    (
+        sym::ARGS_OUT_OF_RANGE,
+        &[sym::ARGS_OUT_OF_RANGE, sym::ERROR],
+        "Args out of range",
+    ),
+    (
+        sym::VOID_FUNCTION,
+        &[sym::VOID_FUNCTION, sym::ERROR],
+        "Symbol's function definition is void",
+    ),
+    (
+        sym::CYCLIC_FUNCTION_INDIRECTION,
+        &[sym::CYCLIC_FUNCTION_INDIRECTION, sym::ERROR],
+        "Symbol's chain of function indirections contains a loop",
+    ),
+    (
+        sym::CYCLIC_VARIABLE_INDIRECTION,
+        &[sym::CYCLIC_VARIABLE_INDIRECTION, sym::ERROR],
+        "Symbol's chain of variable indirections contains a loop",
+    ),
+    (sym::CIRCULAR_LIST, &[sym::CIRCULAR_LIST, sym::ERROR], "List contains a loop"),
+    (
+        sym::VOID_VARIABLE,
+        &[sym::VOID_VARIABLE, sym::ERROR],
+        "Symbol's value as variable is void",
+    ),
+    (
+        sym::SETTING_CONSTANT,
+        &[sym::SETTING_CONSTANT, sym::ERROR],
+        "Attempt to set a constant symbol",
+    ),
+    (
+        sym::INVALID_READ_SYNTAX,
+        &[sym::INVALID_READ_SYNTAX, sym::ERROR],
+        "Invalid read syntax",
+    ),
+    (sym::INVALID_FUNCTION, &[sym::INVALID_FUNCTION, sym::ERROR], "Invalid function"),
+    (
+        sym::WRONG_NUMBER_OF_ARGUMENTS,
+        &[sym::WRONG_NUMBER_OF_ARGUMENTS, sym::ERROR],
+        "Wrong number of arguments",
+    ),
+    (sym::NO_CATCH, &[sym::NO_CATCH, sym::ERROR], "No catch for tag"),
+    (sym::END_OF_FILE, &[sym::END_OF_FILE, sym::ERROR], "End of file during parsing"),
+    (sym::ARITH_ERROR, &[sym::ARITH_ERROR, sym::ERROR], "Arithmetic error"),
+    (
+        sym::DOMAIN_ERROR,
+        &[sym::DOMAIN_ERROR, sym::ARITH_ERROR, sym::ERROR],
+        "Arithmetic domain error",
+    ),
+    (
+        sym::RANGE_ERROR,
+        &[sym::RANGE_ERROR, sym::ARITH_ERROR, sym::ERROR],
+        "Arithmetic range error",
+    ),
+    (
+        sym::SINGULARITY_ERROR,
+        &[sym::SINGULARITY_ERROR, sym::DOMAIN_ERROR, sym::ARITH_ERROR, sym::ERROR],
+        "Arithmetic singularity error",
+    ),
+    (
+        sym::OVERFLOW_ERROR,
+        &[sym::OVERFLOW_ERROR, sym::RANGE_ERROR, sym::ARITH_ERROR, sym::ERROR],
+        "Arithmetic overflow error",
+    ),
+    (
+        sym::UNDERFLOW_ERROR,
+        &[sym::UNDERFLOW_ERROR, sym::RANGE_ERROR, sym::ARITH_ERROR, sym::ERROR],
+        "Arithmetic underflow error",
+    ),
+    (
+        sym::BEGINNING_OF_BUFFER,
+        &[sym::BEGINNING_OF_BUFFER, sym::ERROR],
+        "Beginning of buffer",
+    ),
+    (sym::END_OF_BUFFER, &[sym::END_OF_BUFFER, sym::ERROR], "End of buffer"),
+    (
+        sym::BUFFER_READ_ONLY,
+        &[sym::BUFFER_READ_ONLY, sym::ERROR],
+        "Buffer is read-only",
+    ),
+    (
+        sym::TEXT_READ_ONLY,
+        &[sym::TEXT_READ_ONLY, sym::BUFFER_READ_ONLY, sym::ERROR],
+        "Text is read-only",
+    ),
+    (sym::SEARCH_FAILED, &[sym::SEARCH_FAILED, sym::ERROR], "Search failed"),
+    (sym::INVALID_REGEXP, &[sym::INVALID_REGEXP, sym::ERROR], "Invalid regexp"),
+    (
+        sym::MARK_INACTIVE,
+        &[sym::MARK_INACTIVE, sym::ERROR],
+        "The mark is not active now",
+    ),
+    (sym::SCAN_ERROR, &[sym::SCAN_ERROR, sym::ERROR], "Scan error"),
+];
+
+/// Set the `error-conditions` and `error-message` properties of the standard
+/// errors.
+pub(crate) fn init_errors(env: &mut Rt<Env>, cx: &Context) {
+    for (error, conditions, message) in STANDARD_ERRORS {
+        let conditions: Vec<Object> = conditions.iter().map(|&x| x.into()).collect();
+        let conditions = crate::fns::slice_into_list(&conditions, None, cx);
+        env.set_prop(*error, sym::ERROR_CONDITIONS, conditions);
+        env.set_prop(*error, sym::ERROR_MESSAGE, cx.add(*message));
+    }
+}
+
+/// Return true if `condition` is one of the conditions of `error`. The
+/// conditions come from the `error-conditions` property, falling back to the
+/// standard errors if the property was never set.
+pub(crate) fn has_condition(error: Symbol, condition: Symbol, env: &Rt<Env>, cx: &Context) -> bool {
+    match get(error, sym::ERROR_CONDITIONS, env, cx).untag() {
+        ObjectType::Cons(conditions) => {
+            conditions.elements().any(|x| x.is_ok_and(|x| x == condition))
+        }
+        _ => STANDARD_ERRORS
+            .iter()
+            .find(|x| x.0 == error)
+            .is_some_and(|(_, conditions, _)| conditions.contains(&condition)),
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -525,19 +799,241 @@ mod test {
 
     #[test]
     fn test_ash() {
-        assert_eq!(ash(4, 1), 8);
-        assert_eq!(ash(4, -1), 2);
-        assert_eq!(ash(-8, -1), -4);
-        assert_eq!(ash(256, -8), 1);
-        assert_eq!(ash(-8, 1), -16);
+        assert_eq!(ash(4.into(), 1), NumberValue::Int(8));
+        assert_eq!(ash(4.into(), -1), NumberValue::Int(2));
+        assert_eq!(ash((-8).into(), -1), NumberValue::Int(-4));
+        assert_eq!(ash((-7).into(), -1), NumberValue::Int(-4));
+        assert_eq!(ash(256.into(), -8), NumberValue::Int(1));
+        assert_eq!(ash((-8).into(), 1), NumberValue::Int(-16));
+        assert_eq!(ash(1.into(), 64), NumberValue::Big(num_bigint::BigInt::from(1) << 64));
+        assert_eq!(ash((-1).into(), -100), NumberValue::Int(-1));
+    }
+
+    #[test]
+    fn test_string_to_number() {
+        let cases = [
+            ("1", None, NumberValue::Int(1)),
+            ("  \t-12", None, NumberValue::Int(-12)),
+            ("+7", None, NumberValue::Int(7)),
+            ("1.", None, NumberValue::Int(1)),
+            ("12abc", None, NumberValue::Int(12)),
+            ("abc", None, NumberValue::Int(0)),
+            ("", None, NumberValue::Int(0)),
+            ("-", None, NumberValue::Int(0)),
+            (".", None, NumberValue::Int(0)),
+            ("1.5", None, NumberValue::Float(1.5)),
+            (".5", None, NumberValue::Float(0.5)),
+            ("-.5e1", None, NumberValue::Float(-5.0)),
+            ("1e3", None, NumberValue::Float(1000.0)),
+            ("1.e3", None, NumberValue::Float(1000.0)),
+            ("1.5E-2", None, NumberValue::Float(0.015)),
+            ("1.5e", None, NumberValue::Float(1.5)),
+            ("1e+", None, NumberValue::Int(1)),
+            ("1e500", None, NumberValue::Float(f64::INFINITY)),
+            ("1.0e+INF", None, NumberValue::Float(f64::INFINITY)),
+            ("-1.0e+INFINITY", None, NumberValue::Float(f64::NEG_INFINITY)),
+            ("1.0e-INF", None, NumberValue::Float(1.0)),
+            ("ff", Some(16), NumberValue::Int(255)),
+            ("-1e5", Some(16), NumberValue::Int(-0x1e5)),
+            ("101.1", Some(2), NumberValue::Int(5)),
+            ("129", Some(8), NumberValue::Int(0o12)),
+            (
+                "18446744073709551616",
+                None,
+                NumberValue::Big(num_bigint::BigInt::from(1) << 64),
+            ),
+        ];
+        for (string, base, expect) in cases {
+            assert_eq!(string_to_number(string, base).unwrap(), expect, "{string:?}");
+        }
+        assert!(string_to_number("1", Some(17)).is_err());
+        assert!(string_to_number("1", Some(1)).is_err());
+
+        let nan = |string| match string_to_number(string, None).unwrap() {
+            NumberValue::Float(x) if x.is_nan() => x.to_bits(),
+            x => panic!("{string} parsed as {x:?}"),
+        };
+        assert_eq!(nan("0.0e+NaN"), f64::NAN.to_bits());
+        assert_eq!(nan("-0.0e+NaN"), (-f64::NAN).to_bits());
+        assert_eq!(nan("5.0e+NaN"), f64::NAN.to_bits() | 5);
+    }
+
+    #[test]
+    fn test_number_to_string() {
+        let cases = [
+            ("1", "1"),
+            ("-5", "-5"),
+            ("18446744073709551616", "18446744073709551616"),
+            ("1.0", "1.0"),
+            ("-0.0", "-0.0"),
+            ("0.1", "0.1"),
+            ("1.5", "1.5"),
+            ("100.0", "100.0"),
+            ("1e14", "100000000000000.0"),
+            ("1e15", "1e+15"),
+            ("-1.5e20", "-1.5e+20"),
+            ("1e100", "1e+100"),
+            ("0.0001", "0.0001"),
+            ("0.00001", "1e-05"),
+            ("1.5e-300", "1.5e-300"),
+            ("123456789.123", "123456789.123"),
+            ("0.30000000000000004", "0.30000000000000004"),
+            ("1.7976931348623157e308", "1.7976931348623157e+308"),
+            ("5e-324", "5e-324"),
+            ("1.0e+INF", "1.0e+INF"),
+            ("-1.0e+INF", "-1.0e+INF"),
+            ("0.0e+NaN", "0.0e+NaN"),
+            ("-0.0e+NaN", "-0.0e+NaN"),
+            ("42.0e+NaN", "42.0e+NaN"),
+        ];
+        for (number, expect) in cases {
+            assert_lisp(&format!("(number-to-string {number})"), &format!("\"{expect}\""));
+        }
+        let formatted = [
+            ("%.3f", "1.0", "1.000"),
+            ("%.0f", "2.5", "2"),
+            ("%.2e", "1234.5", "1.23e+03"),
+            ("%g", "0.0000125", "1.25e-05"),
+            ("%g", "2.0", "2.0"),
+        ];
+        for (format, number, expect) in formatted {
+            assert_lisp(
+                &format!("(let ((float-output-format \"{format}\")) (number-to-string {number}))"),
+                &format!("\"{expect}\""),
+            );
+        }
     }
 
     #[test]
     fn test_functionp() {
         assert_lisp("(functionp '(lambda nil))", "t");
     }
+
+    #[test]
+    fn test_buffer_local_variables() {
+        assert_lisp(
+            "(progn (defvar bl-test-1 1)
+               (let ((orig (current-buffer)) (other (get-buffer-create \"bl-test-1\")))
+                 (make-local-variable 'bl-test-1)
+                 (setq bl-test-1 (list 2))
+                 (garbage-collect)
+                 (list bl-test-1 (default-value 'bl-test-1) (local-variable-p 'bl-test-1)
+                       (buffer-local-value 'bl-test-1 other)
+                       (progn (set-buffer other) bl-test-1)
+                       (progn (set-buffer orig) (set-default 'bl-test-1 3) bl-test-1)
+                       (progn (kill-local-variable 'bl-test-1) bl-test-1))))",
+            "((2) 1 t 1 1 (2) 3)",
+        );
+        assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"bl-test-2\"))
+               (set (make-local-variable 'bl-test-2) '(a b))
+               (make-local-variable 'bl-void-2)
+               (list (buffer-local-variables) (boundp 'bl-void-2)))",
+            "(((bl-test-2 a b) bl-void-2) nil)",
+        );
+    }
+
+    #[test]
+    fn test_background_buffer_local_gc() {
+        use crate::buffer::{get_buffer_create, set_buffer};
+        use crate::core::gc::RootSet;
+        use rune_core::macros::{list, root};
+
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let var = crate::core::env::intern("bl-gc-test", cx);
+        let a = get_buffer_create(cx.add("bl-gc-a"), None, cx).unwrap();
+        set_buffer(a, env, cx).unwrap();
+        make_local_variable(var, env, cx).unwrap();
+        let value = list!["foo", 1.5; cx];
+        env.current_buffer.get_mut().set_local(var, Some(value));
+        let b = get_buffer_create(cx.add("bl-gc-b"), None, cx).unwrap();
+        set_buffer(b, env, cx).unwrap();
+
+        // The value is only reachable through a buffer that is not current
+        cx.set_gc_stress(true);
+        cx.garbage_collect(false);
+        cx.set_gc_stress(false);
+        // Reuse the nursery memory the value was in
+        for _ in 0..100 {
+            _ = list!["bar", 2; cx];
+        }
+        cx.garbage_collect(true);
+
+        let a = get_buffer_create(cx.add("bl-gc-a"), None, cx).unwrap();
+        set_buffer(a, env, cx).unwrap();
+        let var = crate::core::env::intern("bl-gc-test", cx);
+        let value = env.current_buffer.get().local(var, cx).flatten().unwrap();
+        assert_eq!(value, list!["foo", 1.5; cx]);
+    }
+
+    #[test]
+    fn test_buffer_from_dropped_context() {
+        // The buffer holds objects of the first context, which is dropped
+        // before the second one collects.
+        assert_lisp(
+            "(let ((orig (current-buffer)))
+               (set-buffer (get-buffer-create \"bl-dropped\"))
+               (set (make-local-variable 'bl-dropped) (list 1 2))
+               (insert \"abc\")
+               (put-text-property 1 3 'face (list 'bold) nil)
+               (set-buffer orig)
+               nil)",
+            "nil",
+        );
+        assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"bl-dropped-2\"))
+                    (insert \"abc\")
+                    (put-text-property 1 3 'face (list 'italic) nil)
+                    (put-text-property 2 3 'mouse (list 'italic) nil)
+                    (insert \"abc\")
+                    (garbage-collect)
+                    nil)",
+            "nil",
+        );
+    }
+
+    #[test]
+    fn test_make_variable_buffer_local() {
+        assert_lisp(
+            "(progn (defvar bl-test-3 1)
+               (make-variable-buffer-local 'bl-test-3)
+               (let ((orig (current-buffer)))
+                 (setq bl-test-3 2)
+                 (set-buffer (get-buffer-create \"bl-test-3\"))
+                 (list bl-test-3 (local-variable-if-set-p 'bl-test-3)
+                       (local-variable-p 'bl-test-3) (buffer-local-value 'bl-test-3 orig))))",
+            "(1 t nil 2)",
+        );
+        // setting a let-bound default value does not create a local binding
+        assert_lisp(
+            "(progn (make-variable-buffer-local 'bl-test-4)
+               (list (let ((bl-test-4 2)) (setq bl-test-4 3) (local-variable-p 'bl-test-4))
+                     bl-test-4 (default-value 'bl-test-4)))",
+            "(nil nil nil)",
+        );
+    }
+
+    #[test]
+    fn test_let_buffer_local() {
+        // The local binding is restored in the buffer it was made in, even if
+        // another buffer is current when the let exits.
+        assert_lisp(
+            "(progn (defvar bl-test-5 1)
+               (let ((orig (current-buffer)) (other (get-buffer-create \"bl-test-5\")))
+                 (set (make-local-variable 'bl-test-5) 2)
+                 (list (let ((bl-test-5 3))
+                         (set-buffer other)
+                         (list bl-test-5 (buffer-local-value 'bl-test-5 orig)))
+                       (buffer-local-value 'bl-test-5 orig) bl-test-5)))",
+            "((1 3) 2 1)",
+        );
+    }
 }
 
+defvar!(MOST_POSITIVE_FIXNUM, crate::core::object::MAX_FIXNUM);
+defvar!(MOST_NEGATIVE_FIXNUM, crate::core::object::MIN_FIXNUM);
 defsym!(MANY);
 defsym!(INTEGER);
 defsym!(SYMBOL);
@@ -546,3 +1042,11 @@ defsym!(HASH_TABLE);
 defsym!(BUFFER);
 defsym!(SUBR);
 defsym!(CHAR_TABLE);
+defsym!(MARKER);
+defsym!(OVERLAY);
+defsym!(SEQUENCEP);
+defsym!(CHAR_OR_STRING_P);
+defsym!(CHAR_TABLE_P);
+defsym!(BUFFER_OR_STRING_P);
+defsym!(NUMBER_OR_MARKER_P);
+defsym!(INTEGER_OR_MARKER_P);
@@ -1,4 +1,6 @@
 //! Debugging utilities.
+use rune_core::hashmap::HashMap;
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 static FLAG: AtomicBool = AtomicBool::new(false);
@@ -15,6 +17,48 @@ pub(crate) fn disable_debug() {
     FLAG.store(false, Ordering::Release);
 }
 
+static SOURCE_TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Whether backtraces of uncaught errors should include the definitions of
+/// interpreted functions.
+pub(crate) fn source_trace_enabled() -> bool {
+    SOURCE_TRACE.load(Ordering::Acquire)
+}
+
+pub(crate) fn enable_source_trace() {
+    SOURCE_TRACE.store(true, Ordering::Release);
+}
+
+thread_local! {
+    /// The `file:line` of the top level form that is being loaded.
+    static LOAD_LOCATION: RefCell<Option<Box<str>>> = const { RefCell::new(None) };
+    /// The `file:line` where each function was defined, by name. This is only
+    /// recorded when source traces are enabled.
+    static DEFINITIONS: RefCell<HashMap<Box<str>, Box<str>>> = RefCell::default();
+}
+
+/// Set the location of the form being loaded, returning the previous one.
+pub(crate) fn set_load_location(location: Option<Box<str>>) -> Option<Box<str>> {
+    LOAD_LOCATION.replace(location)
+}
+
+/// Record that function `name` was defined by the form being loaded.
+pub(crate) fn record_definition(name: &str) {
+    if !source_trace_enabled() {
+        return;
+    }
+    LOAD_LOCATION.with_borrow(|location| {
+        if let Some(location) = location {
+            DEFINITIONS.with_borrow_mut(|defs| defs.insert(name.into(), location.clone()));
+        }
+    });
+}
+
+/// Where function `name` was defined, if it was loaded from a file.
+pub(crate) fn definition_location(name: &str) -> Option<Box<str>> {
+    DEFINITIONS.with_borrow(|defs| defs.get(name).cloned())
+}
+
 macro_rules! debug {
     ($($arg:tt)*) => {{
         if crate::debug::debug_enabled() {
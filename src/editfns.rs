@@ -1,77 +1,391 @@
 //! Buffer editing utilities.
 use crate::core::{
-    env::{ArgSlice, Env},
+    env::{ArgSlice, Env, sym},
+    error::SignalError,
     gc::{Context, Rt},
-    object::{Object, ObjectType},
+    object::{
+        Gc, IntOrMarker, IntoObject, LispMarker, LispString, NIL, Number, Object, ObjectType,
+        OpenBuffer,
+    },
 };
-use anyhow::{Result, bail, ensure};
+use crate::fns::copy_sequence;
+use crate::print::{format_float_with, print_to_string};
+use crate::textprops::{
+    PropertySetType, add_properties, copy_textprops, insert_textprops, verify_modification,
+};
+use anyhow::{Result, anyhow, bail};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed, Zero};
 use rune_macros::defun;
-use std::{fmt::Write as _, io::Write};
+use std::io::Write;
+use std::ops::Range;
 
+/// Display a message. There is no echo area, so like Emacs in batch mode the
+/// message is written to stderr.
 #[defun]
-fn message(format_string: &str, args: &[Object]) -> Result<String> {
-    let message = format(format_string, args)?;
-    println!("MESSAGE: {message}");
-    std::io::stdout().flush()?;
+fn message(format_string: &str, args: &[Object], env: &Rt<Env>, cx: &Context) -> Result<String> {
+    let (message, _) = format_with(format_string, args, Some(QuotingStyle::new(env, cx)), env, cx)?;
+    writeln!(std::io::stderr(), "{message}")?;
     Ok(message)
 }
 
 defvar!(MESSAGE_NAME);
 defvar!(MESSAGE_TYPE, "new message");
 
-#[defun]
-fn format(string: &str, objects: &[Object]) -> Result<String> {
-    let mut result = String::new();
-    let mut arguments = objects.iter();
-    let mut remaining = string;
+/// A `%` directive in a format string, which has the form
+/// `%[FIELD$][FLAGS][WIDTH][.PRECISION]CONVERSION`.
+#[derive(Debug, Default, PartialEq)]
+struct FormatSpec {
+    /// The 1-based argument to use, from `%N$`.
+    field: Option<usize>,
+    /// `-`: pad on the right instead of the left.
+    left_align: bool,
+    /// `+`: always include a sign for numbers.
+    plus: bool,
+    /// ` `: use a space as the sign of non-negative numbers.
+    space: bool,
+    /// `#`: use the alternate form of the conversion.
+    alternate: bool,
+    /// `0`: pad numbers with zeros instead of spaces.
+    zero_pad: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
 
-    let mut escaped = false;
-    let mut is_format_char = |c: char| {
-        if escaped {
-            escaped = false;
-            false
-        } else if c == '\\' {
-            escaped = true;
-            false
+impl FormatSpec {
+    /// Parse a directive from the text after a `%`, returning the directive
+    /// and the number of bytes it used.
+    fn parse(string: &str) -> Result<(Self, usize)> {
+        let bytes = string.as_bytes();
+        let digits_end =
+            |start: usize| start + bytes[start..].iter().take_while(|x| x.is_ascii_digit()).count();
+        let number = |start, end| string[start..end].parse().unwrap_or(usize::MAX);
+        let mut spec = Self::default();
+        let mut idx = digits_end(0);
+        if idx > 0 && bytes.get(idx) == Some(&b'$') {
+            spec.field = Some(number(0, idx));
+            idx += 1;
         } else {
-            c == '%'
+            idx = 0;
         }
-    };
-    while let Some(start) = remaining.find(&mut is_format_char) {
-        result += &remaining[..start];
-        let Some(specifier) = remaining.as_bytes().get(start + 1) else {
+        while let Some(flag) = bytes.get(idx) {
+            match flag {
+                b'-' => spec.left_align = true,
+                b'+' => spec.plus = true,
+                b' ' => spec.space = true,
+                b'#' => spec.alternate = true,
+                b'0' => spec.zero_pad = true,
+                _ => break,
+            }
+            idx += 1;
+        }
+        let width_end = digits_end(idx);
+        if width_end > idx {
+            spec.width = number(idx, width_end);
+        }
+        idx = width_end;
+        if bytes.get(idx) == Some(&b'.') {
+            let precision_end = digits_end(idx + 1);
+            spec.precision =
+                Some(if precision_end > idx + 1 { number(idx + 1, precision_end) } else { 0 });
+            idx = precision_end;
+        }
+        let Some(conversion) = string[idx..].chars().next() else {
             bail!("Format string ends in middle of format specifier")
         };
+        spec.conversion = conversion;
+        Ok((spec, idx + conversion.len_utf8()))
+    }
+
+    /// Pad `body` to the field width. `sign` is the sign and base prefix of a
+    /// number, which goes before any zero padding.
+    fn pad(&self, sign: &str, body: &str, zero_pad: bool) -> String {
+        let len = sign.chars().count() + body.chars().count();
+        let fill = self.width.saturating_sub(len);
+        if self.left_align {
+            format!("{sign}{body}{:fill$}", "")
+        } else if zero_pad && self.zero_pad {
+            format!("{sign}{}{body}", "0".repeat(fill))
+        } else {
+            format!("{:fill$}{sign}{body}", "")
+        }
+    }
+
+    /// The sign to print before a number.
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    fn format_integer(&self, value: &BigInt) -> String {
+        let radix = match self.conversion {
+            'o' => 8,
+            'x' | 'X' => 16,
+            _ => 10,
+        };
+        let mut digits = value.magnitude().to_str_radix(radix);
+        if self.conversion == 'X' {
+            digits.make_ascii_uppercase();
+        }
+        // Like C, a precision is the minimum number of digits
+        if let Some(precision) = self.precision {
+            if precision == 0 && value.is_zero() {
+                digits.clear();
+            } else if digits.len() < precision {
+                digits.insert_str(0, &"0".repeat(precision - digits.len()));
+            }
+        }
+        let mut sign = self.sign(value.is_negative()).to_owned();
+        if self.alternate {
+            match self.conversion {
+                'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                'x' if !value.is_zero() => sign.push_str("0x"),
+                'X' if !value.is_zero() => sign.push_str("0X"),
+                _ => {}
+            }
+        }
+        self.pad(&sign, &digits, self.precision.is_none())
+    }
+
+    fn format_float(&self, value: f64) -> String {
+        let sign = self.sign(value.is_sign_negative());
+        if !value.is_finite() {
+            let body = if value.is_nan() { "nan" } else { "inf" };
+            return self.pad(sign, body, false);
+        }
+        let body = format_float_with(value.abs(), self.precision, self.conversion, self.alternate);
+        self.pad(sign, &body, true)
+    }
+
+    /// Format `arg` according to this directive.
+    fn format_arg(&self, arg: Object, env: &Rt<Env>, cx: &Context) -> Result<String> {
+        let mismatch = || anyhow!("Format specifier doesn't match argument type");
+        match self.conversion {
+            's' | 'S' => {
+                let string = match arg.untag() {
+                    ObjectType::String(x) if self.conversion == 's' => x.inner().to_owned(),
+                    _ => print_to_string(arg, self.conversion == 'S', env, cx),
+                };
+                let string = match self.precision {
+                    Some(precision) => string.chars().take(precision).collect(),
+                    None => string,
+                };
+                Ok(self.pad("", &string, false))
+            }
+            'c' => {
+                let chr = match arg.untag() {
+                    ObjectType::Int(x) => u32::try_from(x).ok().and_then(char::from_u32),
+                    _ => None,
+                };
+                let chr = chr.ok_or_else(mismatch)?;
+                Ok(self.pad("", chr.encode_utf8(&mut [0; 4]), false))
+            }
+            'd' | 'o' | 'x' | 'X' => {
+                let value = match arg.untag() {
+                    ObjectType::Int(x) => BigInt::from(x),
+                    ObjectType::BigInt(x) => x.value(),
+                    ObjectType::Float(x) => {
+                        let Some(x) = BigInt::from_f64(x.trunc()) else {
+                            bail!(SignalError::Overflow)
+                        };
+                        x
+                    }
+                    _ => return Err(mismatch()),
+                };
+                Ok(self.format_integer(&value))
+            }
+            'e' | 'f' | 'g' => {
+                let number: Number = arg.try_into().map_err(|_| mismatch())?;
+                Ok(self.format_float(number.val().to_f64()))
+            }
+            c => bail!("Invalid format operation %{c}"),
+        }
+    }
+}
+
+/// How `format-message` translates grave accents and apostrophes, from
+/// `text-quoting-style`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum QuotingStyle {
+    /// Use curved quotes like ‘this’.
+    Curve,
+    /// Use apostrophes like 'this'.
+    Straight,
+    /// Leave the quotes as they are.
+    Grave,
+}
+
+impl QuotingStyle {
+    fn new(env: &Rt<Env>, cx: &Context) -> Self {
+        match env.var(sym::TEXT_QUOTING_STYLE, cx) {
+            Some(style) if style == sym::STRAIGHT => QuotingStyle::Straight,
+            Some(style) if style == sym::GRAVE => QuotingStyle::Grave,
+            _ => QuotingStyle::Curve,
+        }
+    }
+
+    fn translate(self, chr: char) -> char {
+        match (self, chr) {
+            (QuotingStyle::Curve, '`') => '‘',
+            (QuotingStyle::Curve, '\'') => '’',
+            (QuotingStyle::Straight, '`') => '\'',
+            _ => chr,
+        }
+    }
+}
+
+/// The output of a `%` directive in `format`, which is used to copy text
+/// properties onto the result. Positions are in characters.
+struct FormatSpan<'ob> {
+    /// The directive in the format string.
+    format: Range<usize>,
+    /// The text it produced.
+    output: Range<usize>,
+    /// A string inserted by `%s`, and where its text is in the output.
+    arg: Option<(&'ob LispString, Range<usize>)>,
+}
+
+/// Build a string from a format string and its arguments. If `quoting` is set,
+/// quotes in the format string (but not the arguments) are translated. Along
+/// with the string, this returns the spans of the output that came from each
+/// directive.
+fn format_with<'ob>(
+    string: &str,
+    objects: &[Object<'ob>],
+    quoting: Option<QuotingStyle>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<(String, Vec<FormatSpan<'ob>>)> {
+    let mut result = String::with_capacity(string.len());
+    let mut spans = Vec::new();
+    let (mut format_pos, mut output_pos) = (0, 0);
+    let mut next_arg = 0;
+    let mut remaining = string;
+    while let Some(chr) = remaining.chars().next() {
+        remaining = &remaining[chr.len_utf8()..];
+        if chr != '%' {
+            result.push(quoting.map_or(chr, |style| style.translate(chr)));
+            format_pos += 1;
+            output_pos += 1;
+            continue;
+        }
+        let (spec, len) = FormatSpec::parse(remaining)?;
+        let format = format_pos..format_pos + 1 + remaining[..len].chars().count();
+        remaining = &remaining[len..];
+        format_pos = format.end;
         // "%%" inserts a single "%" in the output
-        if *specifier == b'%' {
+        if spec.conversion == '%' {
             result.push('%');
-        } else {
-            // TODO: currently handles all format types the same. Need to check the modifier characters.
-            let Some(val) = arguments.next() else {
-                bail!("Not enough arguments for format string")
-            };
-            match val.untag() {
-                ObjectType::String(string) => write!(result, "{string}")?,
-                obj => write!(result, "{obj}")?,
+            spans.push(FormatSpan { format, output: output_pos..output_pos + 1, arg: None });
+            output_pos += 1;
+            continue;
+        }
+        if let Some(field) = spec.field {
+            next_arg = field.saturating_sub(1);
+        }
+        let Some(arg) = objects.get(next_arg) else {
+            bail!("Not enough arguments for format string")
+        };
+        next_arg += 1;
+        let text = spec.format_arg(*arg, env, cx)?;
+        let output = output_pos..output_pos + text.chars().count();
+        let arg = match arg.untag() {
+            ObjectType::String(string) if spec.conversion == 's' => {
+                let len = spec.precision.map_or(string.len(), |x| x.min(string.len()));
+                let padding = if spec.left_align { 0 } else { spec.width.saturating_sub(len) };
+                let start = output_pos + padding;
+                Some((string, start..start + len))
+            }
+            _ => None,
+        };
+        result.push_str(&text);
+        output_pos = output.end;
+        spans.push(FormatSpan { format, output, arg });
+    }
+    Ok((result, spans))
+}
+
+/// Make the result of `format` into a string, and copy the text properties of
+/// the format string and the string arguments onto it. The properties of a
+/// directive cover all of the text that it produced.
+fn propertize_format<'ob>(
+    format: &'ob LispString,
+    (string, spans): (String, Vec<FormatSpan<'ob>>),
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let result: Gc<&LispString> = string.into_obj(cx);
+    let result = result.untag();
+    if let Some(props) = format.textprops() {
+        let tree = &mut *result.textprops_mut(cx)?;
+        let (mut format_pos, mut output_pos) = (0, 0);
+        for span in &spans {
+            // Text between the directives is copied unchanged
+            props.copy_into(format_pos, span.format.start, tree, output_pos, cx)?;
+            if let Some(node) = props.find(span.format.start) {
+                let plist = copy_sequence(*node.val, cx)?;
+                tree.set_properties(span.output.start, span.output.end, plist);
+            }
+            (format_pos, output_pos) = (span.format.end, span.output.end);
+        }
+        props.copy_into(format_pos, format.len(), tree, output_pos, cx)?;
+    }
+    // The properties of the arguments are added on top of the ones from the
+    // format string
+    for span in spans {
+        let Some((arg, range)) = span.arg else { continue };
+        let Some(arg_props) = arg.textprops() else { continue };
+        let tree = &mut *result.textprops_mut(cx)?;
+        for (arg_range, plist) in arg_props.iter(0, range.len()) {
+            if plist.is_nil() {
+                continue;
+            }
+            let (start, end) = (arg_range.start + range.start, arg_range.end + range.start);
+            let existing: Vec<_> = tree.iter(start, end).collect();
+            for (piece, old) in existing {
+                let old = copy_sequence(old, cx)?;
+                let merged = add_properties(plist, old, PropertySetType::Replace, false, cx)?;
+                tree.set_properties(piece.start, piece.end, merged);
             }
         }
-        remaining = &remaining[start + 2..];
     }
-    result += remaining;
-    ensure!(arguments.next().is_none(), "Too many arguments for format string");
-    Ok(result)
+    Ok(result.into())
+}
+
+#[defun]
+pub(crate) fn format<'ob>(
+    string: &'ob LispString,
+    objects: &[Object<'ob>],
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let output = format_with(string, objects, None, env, cx)?;
+    propertize_format(string, output, cx)
 }
 
 #[defun]
-fn format_message(string: &str, objects: &[Object]) -> Result<String> {
-    let formatted = format(string, objects)?;
-    // TODO: implement support for `text-quoting-style`.
-    Ok(formatted
-        .chars()
-        .map(|c| if matches!(c, '`' | '\'') { '"' } else { c })
-        .collect())
+pub(crate) fn format_message<'ob>(
+    string: &'ob LispString,
+    objects: &[Object<'ob>],
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let output = format_with(string, objects, Some(QuotingStyle::new(env, cx)), env, cx)?;
+    propertize_format(string, output, cx)
 }
 
+defvar!(TEXT_QUOTING_STYLE);
+defsym!(CURVE);
+defsym!(STRAIGHT);
+defsym!(GRAVE);
+
 #[defun]
 fn string_to_char(string: &str) -> char {
     string.chars().next().unwrap_or('\0')
@@ -82,60 +396,243 @@ fn char_to_string(chr: char) -> String {
     format!("{chr}")
 }
 
-#[defun]
-pub(crate) fn insert(args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+/// Insert `args` at point. If `before_markers` is true, markers at point end up
+/// after the inserted text. If `inherit` is true, the text inherits the sticky
+/// text properties around it.
+fn general_insert(
+    args: ArgSlice,
+    before_markers: bool,
+    inherit: bool,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+    let inherit = inherit.then(|| env.var(sym::TEXT_PROPERTY_DEFAULT_NONSTICKY, cx).unwrap_or(NIL));
     let env = &mut **env; // Deref into rooted type so we can split the borrow
     let buffer = env.current_buffer.get_mut();
     let args = Rt::bind_slice(env.stack.arg_slice(args), cx);
     for arg in args {
-        buffer.insert(*arg)?;
+        insert_at_point(buffer, *arg, before_markers, inhibit_read_only, inherit, cx)?;
     }
     Ok(())
 }
 
-// TODO: this should not throw and error. Buffer will always be present.
+/// Insert the string or character `arg` at point in `buffer`. Read-only text is
+/// checked against `inhibit_read_only`, and the text properties of the buffer
+/// are moved to make room for the new text. `inherit` is the same as in
+/// [`insert_textprops`].
+pub(crate) fn insert_at_point<'ob>(
+    buffer: &mut OpenBuffer,
+    arg: Object<'ob>,
+    before_markers: bool,
+    inhibit_read_only: Object,
+    inherit: Option<Object<'ob>>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let start = buffer.get().text.cursor().chars() + 1;
+    verify_modification(buffer.get_mut(), start, start, inhibit_read_only)?;
+    if before_markers {
+        buffer.insert_before_markers(arg)?;
+    } else {
+        buffer.insert(arg)?;
+    }
+    let end = buffer.get().text.cursor().chars() + 1;
+    insert_textprops(buffer.get_mut(), start, end, arg, inherit, cx)
+}
+
 #[defun]
-pub(crate) fn goto_char(position: usize, env: &mut Rt<Env>) -> Result<()> {
-    let buffer = env.current_buffer.get_mut();
-    buffer.text.set_cursor(position);
-    Ok(())
+pub(crate) fn insert(args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    general_insert(args, false, false, env, cx)
 }
 
-// TODO: this should not throw and error. Buffer will always be present.
 #[defun]
-pub(crate) fn point_max(env: &mut Rt<Env>) -> Result<usize> {
-    let buffer = env.current_buffer.get_mut();
-    // TODO: Handle narrowing
-    Ok(buffer.text.len_chars() + 1)
+fn insert_and_inherit(args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    general_insert(args, false, true, env, cx)
 }
 
 #[defun]
-pub(crate) fn point_min() -> usize {
-    // TODO: Handle narrowing
-    1
+fn insert_before_markers(args: ArgSlice, env: &mut Rt<Env>, cx: &Context) -> Result<()> {
+    general_insert(args, true, false, env, cx)
 }
 
 #[defun]
-pub(crate) fn point_marker(env: &mut Rt<Env>) -> usize {
-    // TODO: Implement marker objects
-    env.current_buffer.get_mut().text.cursor().chars()
+fn insert_before_markers_and_inherit(
+    args: ArgSlice,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    general_insert(args, true, true, env, cx)
+}
+
+/// Convert a lisp position to a `usize`. Negative positions are never valid.
+fn position(pos: IntOrMarker) -> Result<usize> {
+    let pos = pos.int();
+    match usize::try_from(pos) {
+        Ok(pos) => Ok(pos),
+        Err(_) => bail!(SignalError::args_out_of_range(&[pos.into()])),
+    }
 }
 
 #[defun]
-fn delete_region(start: usize, end: usize, env: &mut Rt<Env>) -> Result<()> {
+pub(crate) fn goto_char<'ob>(position: IntOrMarker<'ob>, env: &mut Rt<Env>) -> IntOrMarker<'ob> {
+    let data = env.current_buffer.get_mut();
+    let pos = position.int().clamp(data.begv() as i64 + 1, data.zv() as i64 + 1) as usize;
+    data.text.set_cursor(pos - 1);
+    position
+}
+
+#[defun]
+pub(crate) fn point_max(env: &Rt<Env>) -> usize {
+    env.current_buffer.get().zv() + 1
+}
+
+#[defun]
+pub(crate) fn point_min(env: &Rt<Env>) -> usize {
+    env.current_buffer.get().begv() + 1
+}
+
+#[defun]
+pub(crate) fn point_marker<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispMarker> {
+    let point = point(env) as i64;
+    crate::marker::copy_marker(Some(point.into()), None, env, cx)
+}
+
+#[defun]
+fn point_min_marker<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispMarker> {
+    let min = point_min(env) as i64;
+    crate::marker::copy_marker(Some(min.into()), None, env, cx)
+}
+
+#[defun]
+fn point_max_marker<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> Result<&'ob LispMarker> {
+    let max = point_max(env) as i64;
+    crate::marker::copy_marker(Some(max.into()), None, env, cx)
+}
+
+#[defun]
+pub(crate) fn delete_region(
+    start: IntOrMarker,
+    end: IntOrMarker,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let (start, end) = (position(start)?, position(end)?);
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+    verify_modification(env.current_buffer.get_mut(), start, end, inhibit_read_only)?;
     env.current_buffer.get_mut().delete(start, end)
 }
 
 #[defun]
-fn bolp(env: &Rt<Env>) -> bool {
+pub(crate) fn buffer_substring<'ob>(
+    start: IntOrMarker,
+    end: IntOrMarker,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let (start, end) = (position(start)?, position(end)?);
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let (a, b) = env.current_buffer.get().slice_with_gap(start, end)?;
+    let string: Gc<&LispString> = [a, b].concat().into_obj(cx);
+    let props = env.current_buffer.get_mut().textprops_with_lifetime();
+    copy_textprops(props, start, end, string.untag(), 0, cx)?;
+    Ok(string.into())
+}
+
+/// Restrict editing in the current buffer to the text between `start` and
+/// `end`. The region may extend outside of the current restriction.
+#[defun]
+pub(crate) fn narrow_to_region(
+    start: IntOrMarker,
+    end: IntOrMarker,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    let (start, end) = (position(start)?, position(end)?);
+    let (start, end) = if start <= end { (start, end) } else { (end, start) };
+    let data = env.current_buffer.get_mut();
+    let max = data.text.len_chars() + 1;
+    if start == 0 || end > max {
+        bail!(SignalError::args_out_of_range(&[start.into(), end.into()]));
+    }
+    data.narrow(start - 1, end - 1);
+    Ok(())
+}
+
+#[defun]
+pub(crate) fn widen(env: &mut Rt<Env>) {
+    env.current_buffer.get_mut().widen();
+}
+
+#[defun]
+pub(crate) fn buffer_narrowed_p(env: &Rt<Env>) -> bool {
+    env.current_buffer.get().is_narrowed()
+}
+
+#[defun]
+pub(crate) fn char_after(pos: Option<IntOrMarker>, env: &Rt<Env>) -> Option<char> {
+    let data = env.current_buffer.get();
+    let pos = match pos {
+        Some(pos) => usize::try_from(pos.int() - 1).ok()?,
+        None => data.text.cursor().chars(),
+    };
+    if (data.begv()..data.zv()).contains(&pos) {
+        data.text.char_at(pos)
+    } else {
+        None
+    }
+}
+
+#[defun]
+pub(crate) fn char_before(pos: Option<IntOrMarker>, env: &Rt<Env>) -> Option<char> {
+    let data = env.current_buffer.get();
+    let pos = match pos {
+        Some(pos) => usize::try_from(pos.int() - 2).ok()?,
+        None => data.text.cursor().chars().checked_sub(1)?,
+    };
+    if (data.begv()..data.zv()).contains(&pos) {
+        data.text.char_at(pos)
+    } else {
+        None
+    }
+}
+
+#[defun]
+pub(crate) fn following_char(env: &Rt<Env>) -> char {
+    char_after(None, env).unwrap_or('\0')
+}
+
+#[defun]
+pub(crate) fn preceding_char(env: &Rt<Env>) -> char {
+    char_before(None, env).unwrap_or('\0')
+}
+
+#[defun]
+pub(crate) fn bolp(env: &Rt<Env>) -> bool {
     let buf = env.current_buffer.get();
     let chars = buf.text.cursor().chars();
-    chars == 0 || buf.text.char_at(chars - 1).unwrap() == '\n'
+    chars == buf.begv() || buf.text.char_at(chars - 1).unwrap() == '\n'
 }
 
 #[defun]
-fn point(env: &Rt<Env>) -> usize {
-    env.current_buffer.get().text.cursor().chars()
+pub(crate) fn eolp(env: &Rt<Env>) -> bool {
+    matches!(char_after(None, env), None | Some('\n'))
+}
+
+#[defun]
+pub(crate) fn bobp(env: &Rt<Env>) -> bool {
+    let buf = env.current_buffer.get();
+    buf.text.cursor().chars() == buf.begv()
+}
+
+#[defun]
+pub(crate) fn eobp(env: &Rt<Env>) -> bool {
+    let buf = env.current_buffer.get();
+    buf.text.cursor().chars() == buf.zv()
+}
+
+#[defun]
+pub(crate) fn point(env: &Rt<Env>) -> usize {
+    env.current_buffer.get().text.cursor().chars() + 1
 }
 
 #[defun]
@@ -157,22 +654,125 @@ mod test {
 
     use super::*;
 
+    fn lisp_string<'ob>(string: &str, cx: &'ob Context) -> &'ob LispString {
+        string.into_obj(cx).untag()
+    }
+
     #[test]
     fn test_format() {
-        assert_eq!(&format("%s", &[1.into()]).unwrap(), "1");
-        assert_eq!(&format("foo-%s", &[2.into()]).unwrap(), "foo-2");
-        assert_eq!(&format("%%", &[]).unwrap(), "%");
-        assert_eq!(&format("_%%_", &[]).unwrap(), "_%_");
-        assert_eq!(&format("foo-%s %s", &[3.into(), 4.into()]).unwrap(), "foo-3 4");
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let format = |string: &str, args: &[Object]| -> Result<String> {
+            let result: &LispString = format(lisp_string(string, cx), args, env, cx)?.try_into()?;
+            Ok(result.inner().to_owned())
+        };
+        assert_eq!(format("%s", &[1.into()]).unwrap(), "1");
+        assert_eq!(format("foo-%s", &[2.into()]).unwrap(), "foo-2");
+        assert_eq!(format("%%", &[]).unwrap(), "%");
+        assert_eq!(format("_%%_", &[]).unwrap(), "_%_");
+        assert_eq!(format("foo-%s %s", &[3.into(), 4.into()]).unwrap(), "foo-3 4");
         let sym = crate::core::env::sym::FUNCTION.into();
-        assert_eq!(&format("%s", &[sym]).unwrap(), "function");
+        assert_eq!(format("%s", &[sym]).unwrap(), "function");
 
-        assert!(&format("%s", &[]).is_err());
-        assert!(&format("%s", &[1.into(), 2.into()]).is_err());
+        assert!(format("%s", &[]).is_err());
+        // Extra arguments are ignored, like Emacs
+        assert_eq!(format("%s", &[1.into(), 2.into()]).unwrap(), "1");
 
         assert!(format("`%s' %s%s%s", &[0.into(), 1.into(), 2.into(), 3.into()]).is_ok());
     }
 
+    #[test]
+    fn test_format_directives() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let big = cx.add(num_bigint::BigInt::from(1) << 64);
+        let string = cx.add("a\"b");
+        let float = |x: f64| cx.add(x);
+        let cases: &[(&str, &[Object], &str)] = &[
+            ("%s", &[string], "a\"b"),
+            ("%S", &[string], "\"a\\\"b\""),
+            ("%5s|", &[string], "  a\"b|"),
+            ("%-5s|", &[string], "a\"b  |"),
+            ("%05s", &[string], "  a\"b"),
+            ("%.2s", &[string], "a\""),
+            ("%s", &[float(1.5)], "1.5"),
+            ("%c", &[97.into()], "a"),
+            ("%3c", &[955.into()], "  λ"),
+            ("%d", &[42.into()], "42"),
+            ("%d", &[(-42).into()], "-42"),
+            ("%d", &[float(2.9)], "2"),
+            ("%d", &[float(-2.9)], "-2"),
+            ("%5d|", &[42.into()], "   42|"),
+            ("%-5d|", &[42.into()], "42   |"),
+            ("%05d", &[(-42).into()], "-0042"),
+            ("%+d", &[42.into()], "+42"),
+            ("% d", &[42.into()], " 42"),
+            ("%+ d", &[42.into()], "+42"),
+            ("%.4d", &[42.into()], "0042"),
+            ("%06.3d", &[7.into()], "   007"),
+            ("%d", &[big], "18446744073709551616"),
+            ("%x", &[big], "10000000000000000"),
+            ("%o", &[8.into()], "10"),
+            ("%#o", &[8.into()], "010"),
+            ("%x", &[255.into()], "ff"),
+            ("%X", &[255.into()], "FF"),
+            ("%#x", &[255.into()], "0xff"),
+            ("%#X", &[255.into()], "0XFF"),
+            ("%#x", &[0.into()], "0"),
+            ("%x", &[(-255).into()], "-ff"),
+            ("%#08x", &[255.into()], "0x0000ff"),
+            ("%f", &[1.into()], "1.000000"),
+            ("%.2f", &[float(1.23456)], "1.23"),
+            ("%.0f", &[float(2.5)], "2"),
+            ("%#.0f", &[float(3.0)], "3."),
+            ("%e", &[float(1234.5)], "1.234500e+03"),
+            ("%.1e", &[float(0.000_12)], "1.2e-04"),
+            ("%g", &[float(0.000_12)], "0.00012"),
+            ("%g", &[float(1e20)], "1e+20"),
+            ("%g", &[float(100.0)], "100"),
+            ("%#g", &[float(100.0)], "100.000"),
+            ("%010.3f", &[float(-1.23456)], "-00001.235"),
+            ("%+.1f", &[float(2.0)], "+2.0"),
+            ("%f", &[float(f64::INFINITY)], "inf"),
+            ("%05f", &[float(f64::NEG_INFINITY)], " -inf"),
+            ("%f", &[float(f64::NAN)], "nan"),
+            ("%2$s %1$s", &[1.into(), 2.into()], "2 1"),
+            ("%2$s %s", &[1.into(), 2.into(), 3.into()], "2 3"),
+            ("%1$s %1$s", &[1.into()], "1 1"),
+            ("%1$-3d|", &[1.into()], "1  |"),
+            ("100%%", &[], "100%"),
+            ("`%s'", &[string], "`a\"b'"),
+        ];
+        for (string, args, expect) in cases {
+            let string = lisp_string(string, cx);
+            assert_eq!(format(string, args, env, cx).unwrap(), *expect, "{string}");
+        }
+        assert!(format(lisp_string("%d", cx), &[string], env, cx).is_err());
+        assert!(format(lisp_string("%c", cx), &[string], env, cx).is_err());
+        assert!(format(lisp_string("%f", cx), &[string], env, cx).is_err());
+        assert!(format(lisp_string("%q", cx), &[1.into()], env, cx).is_err());
+        assert!(format(lisp_string("%5", cx), &[1.into()], env, cx).is_err());
+        assert!(format(lisp_string("%3$s", cx), &[1.into()], env, cx).is_err());
+    }
+
+    #[test]
+    fn test_format_message() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let arg = cx.add("`x'");
+        let message = format_message(lisp_string("`%s' can't", cx), &[arg], env, cx).unwrap();
+        assert_eq!(message, "‘`x'’ can’t");
+        env.set_var(sym::TEXT_QUOTING_STYLE, sym::STRAIGHT.into()).unwrap();
+        let message = format_message(lisp_string("`%s' can't", cx), &[arg], env, cx).unwrap();
+        assert_eq!(message, "'`x'' can't");
+        env.set_var(sym::TEXT_QUOTING_STYLE, sym::GRAVE.into()).unwrap();
+        let message = format_message(lisp_string("`%s' can't", cx), &[arg], env, cx).unwrap();
+        assert_eq!(message, "``x'' can't");
+    }
+
     #[test]
     fn test_insert() {
         let roots = &RootSet::default();
@@ -203,7 +803,195 @@ mod test {
         insert(ArgSlice::new(2), env, cx).unwrap();
 
         assert_eq!(env.current_buffer.get(), "hello world");
-        delete_region(2, 4, env).unwrap();
+        delete_region(2.into(), 4.into(), env, cx).unwrap();
         assert_eq!(env.current_buffer.get(), "hlo world");
     }
+
+    #[test]
+    fn test_narrowing() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer = get_buffer_create(cx.add("test_narrowing"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.current_buffer.get_mut().text.insert("hello world");
+        assert!(!buffer_narrowed_p(env));
+
+        narrow_to_region(8.into(), 3.into(), env).unwrap();
+        assert!(buffer_narrowed_p(env));
+        assert_eq!((point_min(env), point_max(env)), (3, 8));
+        // point is moved into the accessible region
+        assert_eq!(point(env), 8);
+        goto_char(1.into(), env);
+        assert_eq!(point(env), 3);
+        assert!(bobp(env));
+        assert_eq!(char_before(None, env), None);
+        goto_char(20.into(), env);
+        assert_eq!(point(env), 8);
+        assert!(eobp(env));
+        assert_eq!(char_after(None, env), None);
+
+        assert!(delete_region(1.into(), 4.into(), env, cx).is_err());
+        assert!(buffer_substring(7.into(), 9.into(), env, cx).is_err());
+        delete_region(3.into(), 5.into(), env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "heo world");
+        assert_eq!(point_max(env), 6);
+
+        // text inserted at either end of the region is inside it
+        env.stack.push(cx.add("X"));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        goto_char(1.into(), env);
+        env.stack.push(cx.add("Y"));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        let (min, max) = (point_min(env) as i64, point_max(env) as i64);
+        assert_eq!(buffer_substring(min.into(), max.into(), env, cx).unwrap(), "Yo wX");
+
+        assert!(narrow_to_region(1.into(), 20.into(), env).is_err());
+        widen(env);
+        assert!(!buffer_narrowed_p(env));
+        assert_eq!((point_min(env), point_max(env)), (1, 12));
+        assert_eq!(env.current_buffer.get(), "heYo wXorld");
+    }
+    #[test]
+    fn test_text_properties() {
+        use crate::fns::equal_including_properties;
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let read = |s: &str| crate::reader::read(s, cx).unwrap().0;
+        let buffer = get_buffer_create(cx.add("test_text_properties"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.stack.push(read(r#"#("hello" 1 3 (a 1))"#));
+        env.stack.push(cx.add(" "));
+        env.stack.push(read(r#"#("world" 0 5 (b 2))"#));
+        insert(ArgSlice::new(3), env, cx).unwrap();
+        let substring = buffer_substring(2.into(), 10.into(), env, cx).unwrap();
+        let expect = read(r#"#("ello wor" 0 2 (a 1) 5 8 (b 2))"#);
+        assert!(equal_including_properties(substring, expect).unwrap());
+
+        let string = read(r#"#("<%s>" 0 4 (c 3))"#);
+        let arg = read(r#"#("xy" 1 2 (d 4))"#);
+        let string = string.try_into().unwrap();
+        let result = format(string, &[arg], env, cx).unwrap();
+        let expect = read(r#"#("<xy>" 0 2 (c 3) 2 3 (d 4 c 3) 3 4 (c 3))"#);
+        assert!(equal_including_properties(result, expect).unwrap());
+    }
+
+    #[test]
+    fn test_insert_and_inherit() {
+        use crate::core::env::intern;
+        use crate::textprops::{get_text_property, text_properties_at};
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let read = |s: &str| crate::reader::read(s, cx).unwrap().0;
+        let prop = |pos: usize, name: &str, env: &mut Rt<Env>| {
+            get_text_property(pos, cx.add(intern(name, cx)), NIL, env, cx).unwrap()
+        };
+        let buffer = get_buffer_create(cx.add("test_insert_and_inherit"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.stack
+            .push(read(r#"#("ab" 0 1 (a 1 b 2 rear-nonsticky (b)) 1 2 (c 3 front-sticky t))"#));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+
+        // Rear-sticky properties come from before and front-sticky ones from
+        // after, and take precedence over the text's own properties
+        goto_char(2.into(), env);
+        env.stack.push(read(r#"#("yy" 0 2 (a 5 d 4))"#));
+        insert_and_inherit(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "ayyb");
+        assert_eq!(prop(2, "a", env), 1);
+        assert_eq!(prop(2, "b", env), NIL);
+        assert_eq!(prop(2, "c", env), 3);
+        assert_eq!(prop(3, "d", env), 4);
+        assert_eq!(prop(4, "c", env), 3);
+
+        // Text inserted inside of an interval inherits all of its properties
+        goto_char(3.into(), env);
+        env.stack.push(cx.add("z"));
+        insert_and_inherit(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(prop(3, "a", env), 1);
+        assert_eq!(prop(3, "d", env), 4);
+
+        // Plain insertion does not inherit anything
+        goto_char(2.into(), env);
+        env.stack.push(cx.add("x"));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "axyzyb");
+        assert_eq!(text_properties_at(2, NIL, env, cx).unwrap(), NIL);
+        assert_eq!(prop(5, "d", env), 4);
+        assert_eq!(prop(6, "c", env), 3);
+
+        // Properties in `text-property-default-nonsticky` are not inherited
+        crate::core::env::init_variables(cx, env);
+        let buffer = get_buffer_create(cx.add("test_default_nonsticky"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.stack.push(read(r#"#("a" 0 1 (display 1 e 2))"#));
+        env.stack.push(cx.add("b"));
+        insert_and_inherit(ArgSlice::new(2), env, cx).unwrap();
+        assert_eq!(prop(2, "display", env), NIL);
+        assert_eq!(prop(2, "e", env), 2);
+    }
+
+    #[test]
+    fn test_insert_before_markers() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let buffer =
+            get_buffer_create(cx.add("test_insert_before_markers"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.stack.push(cx.add("ab"));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        goto_char(2.into(), env);
+        let marker = point_marker(env, cx).unwrap();
+        env.stack.push(cx.add("x"));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(marker.position(), Some(2));
+        env.stack.push(cx.add("y"));
+        insert_before_markers(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "axyb");
+        assert_eq!(marker.position(), Some(2));
+        goto_char(2.into(), env);
+        env.stack.push(cx.add("z"));
+        insert_before_markers(ArgSlice::new(1), env, cx).unwrap();
+        assert_eq!(marker.position(), Some(3));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let read = |s: &str| crate::reader::read(s, cx).unwrap().0;
+        let buffer = get_buffer_create(cx.add("test_read_only"), Some(NIL), cx).unwrap();
+        set_buffer(buffer, env, cx).unwrap();
+        env.stack
+            .push(read(r#"#("abcde" 1 3 (read-only locked) 4 5 (read-only "msg"))"#));
+        insert(ArgSlice::new(1), env, cx).unwrap();
+        let insert_at = |pos: i64, env: &mut Rt<Env>| {
+            goto_char(pos.into(), env);
+            env.stack.push(cx.add("x"));
+            insert(ArgSlice::new(1), env, cx)
+        };
+        // Read-only text is rear-sticky, but not front-sticky
+        assert!(insert_at(2, env).is_ok());
+        assert!(insert_at(4, env).is_err());
+        assert!(insert_at(5, env).is_err());
+        let err = delete_region(6.into(), 7.into(), env, cx).unwrap_err();
+        let err = err.downcast::<SignalError>().unwrap();
+        assert_eq!(err.symbol(), sym::TEXT_READ_ONLY);
+        assert_eq!(err.data(cx)[0], "msg");
+
+        // `inhibit-read-only` can allow all read-only text, or only text with
+        // certain values
+        env.set_var(sym::INHIBIT_READ_ONLY, read("(other)")).unwrap();
+        assert!(insert_at(4, env).is_err());
+        env.set_var(sym::INHIBIT_READ_ONLY, read("(locked)")).unwrap();
+        assert!(insert_at(4, env).is_ok());
+        assert!(delete_region(7.into(), 8.into(), env, cx).is_err());
+        env.set_var(sym::INHIBIT_READ_ONLY, sym::TRUE.into()).unwrap();
+        assert!(delete_region(7.into(), 8.into(), env, cx).is_ok());
+        assert_eq!(env.current_buffer.get(), "axbxcd");
+    }
 }
@@ -1,11 +1,9 @@
 //! Lisp evaluation primitives.
 use crate::core::cons::{Cons, ConsError};
 use crate::core::env::{ArgSlice, CallFrame, Env, sym};
-use crate::core::error::{Type, TypeError};
+use crate::core::error::{SignalError, Type, TypeError};
 use crate::core::gc::{Rt, Rto};
-use crate::core::object::{
-    FnArgs, Function, LispString, NIL, ObjectType, Symbol, TagType, display_slice,
-};
+use crate::core::object::{FnArgs, Function, LispString, NIL, ObjectType, Symbol, TagType};
 use crate::core::{
     gc::Context,
     object::{FunctionType, Gc, Object},
@@ -16,14 +14,30 @@ use crate::rooted_iter;
 use anyhow::{Result, anyhow, bail, ensure};
 use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
-use rune_core::macros::{bail_err, call, list, root};
+use rune_core::macros::{bail_err, call, list, rebind, root};
 use rune_macros::defun;
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug)]
 pub(crate) struct EvalError {
-    backtrace: Vec<Box<str>>,
+    /// The lisp backtrace when the error was signaled, innermost first. This
+    /// is only recorded for errors that are not handled.
+    backtrace: Vec<TraceFrame>,
     pub(crate) error: ErrorType,
+    /// Whether the debugger has already been considered for this error.
+    debugged: bool,
+}
+
+/// A frame of a recorded backtrace.
+#[derive(Debug)]
+struct TraceFrame {
+    /// The call, shown as `func(args...)`
+    call: Box<str>,
+    /// The `file:line` where the function was defined, if it was loaded from
+    /// a file
+    location: Option<Box<str>>,
+    /// The definition of the function, if it is interpreted
+    source: Option<Box<str>>,
 }
 
 #[derive(Debug)]
@@ -48,40 +62,93 @@ impl Display for EvalError {
 
 impl EvalError {
     pub(crate) fn new_error(error: anyhow::Error) -> Self {
-        Self { backtrace: Vec::new(), error: ErrorType::Err(error) }
+        Self { backtrace: Vec::new(), error: ErrorType::Err(error), debugged: false }
     }
 
     pub(crate) fn signal(error_symbol: Object, data: Object, env: &mut Rt<Env>) -> Self {
         Self {
             backtrace: Vec::new(),
             error: ErrorType::Signal(env.set_exception(error_symbol, data)),
+            debugged: false,
         }
     }
 
     pub(crate) fn throw(tag: Object, data: Object, env: &mut Rt<Env>) -> Self {
-        Self { backtrace: Vec::new(), error: ErrorType::Throw(env.set_exception(tag, data)) }
+        Self {
+            backtrace: Vec::new(),
+            error: ErrorType::Throw(env.set_exception(tag, data)),
+            debugged: false,
+        }
     }
 
     pub(crate) fn new(error: impl Into<Self>) -> Self {
         error.into()
     }
 
-    pub(crate) fn with_trace(error: anyhow::Error, name: &str, args: &[Rto<Object>]) -> Self {
-        let display = display_slice(args);
-        let trace = format!("{name} {display}").into_boxed_str();
-        Self { backtrace: vec![trace], error: ErrorType::Err(error) }
+    /// Record the frames that are currently on the lisp stack.
+    fn record_backtrace(&mut self, env: &Rt<Env>, cx: &Context) {
+        self.backtrace = env
+            .stack
+            .backtrace()
+            .map(|frame| {
+                let location = match frame.func.bind(cx).untag() {
+                    ObjectType::Symbol(name) => crate::debug::definition_location(name.name()),
+                    _ => None,
+                };
+                let func = crate::data::indirect_function(frame.func.bind(cx), cx);
+                let source = match func.untag() {
+                    ObjectType::Cons(def) => Some(def.to_string().into_boxed_str()),
+                    _ => None,
+                };
+                TraceFrame { call: frame.to_string().into_boxed_str(), location, source }
+            })
+            .collect();
     }
 
-    pub(crate) fn add_trace(mut self, name: &str, args: &[Rto<Object>]) -> Self {
-        let display = display_slice(args);
-        self.backtrace.push(format!("{name} {display}").into_boxed_str());
-        self
+    /// The `(error-symbol . data)` cons that a lisp handler receives for this
+    /// error. Errors raised from Rust are converted to the matching standard
+    /// error, and any other error becomes a plain `error` with its message.
+    pub(crate) fn error_object<'ob>(&self, env: &Rt<Env>, cx: &'ob Context) -> &'ob Cons {
+        match &self.error {
+            ErrorType::Signal(id) => {
+                let Some((sym, data)) = env.get_exception(*id) else {
+                    unreachable!("Exception not found")
+                };
+                Cons::new(sym, data, cx)
+            }
+            ErrorType::Err(err) => {
+                if let Some(lisp_error) = err.downcast_ref::<LispError>() {
+                    lisp_error.bind(cx)
+                } else if let Some(type_error) = err.downcast_ref::<TypeError>() {
+                    let (predicate, value) = type_error.data(cx);
+                    Cons::new(sym::WRONG_TYPE_ARGUMENT, list![predicate, value; cx], cx)
+                } else if let Some(signal) = err.downcast_ref::<SignalError>() {
+                    let data = crate::fns::slice_into_list(&signal.data(cx), None, cx);
+                    Cons::new(signal.symbol(), data, cx)
+                } else {
+                    Cons::new(sym::ERROR, list![format!("{err}"); cx], cx)
+                }
+            }
+            ErrorType::Throw(_) => unreachable!("Error type throw was not handled"),
+        }
     }
 
+    /// Print the recorded backtrace. If source traces are enabled, each frame
+    /// also shows where its function was defined, or the definition itself for
+    /// interpreted functions that were not loaded from a file.
     pub(crate) fn print_backtrace(&self) {
+        let source = crate::debug::source_trace_enabled();
         println!("BEGIN_BACKTRACE");
-        for (i, x) in self.backtrace.iter().enumerate() {
-            println!("{i}: {x}");
+        for (i, frame) in self.backtrace.iter().enumerate() {
+            println!("{i}: {}", frame.call);
+            if !source {
+                continue;
+            }
+            if let Some(location) = &frame.location {
+                println!("    at {location}");
+            } else if let Some(def) = &frame.source {
+                println!("    {def}");
+            }
         }
         println!("END_BACKTRACE");
     }
@@ -89,7 +156,10 @@ impl EvalError {
 
 impl From<anyhow::Error> for EvalError {
     fn from(e: anyhow::Error) -> Self {
-        Self::new_error(e)
+        match e.downcast::<EvalError>() {
+            Ok(err) => err,
+            Err(e) => Self::new_error(e),
+        }
     }
 }
 
@@ -117,6 +187,12 @@ impl From<TypeError> for EvalError {
     }
 }
 
+impl From<SignalError> for EvalError {
+    fn from(e: SignalError) -> Self {
+        Self::new_error(e.into())
+    }
+}
+
 impl From<LispError> for EvalError {
     fn from(e: LispError) -> Self {
         Self::new_error(e.into())
@@ -131,6 +207,115 @@ impl From<std::convert::Infallible> for EvalError {
 
 pub(crate) type EvalResult<'ob> = Result<Object<'ob>, EvalError>;
 
+/// Return true if a handler for `conditions` catches `error`, which is an
+/// `(error-symbol . data)` cons. `conditions` is either a single condition or
+/// a list of them, and `t` catches every error.
+pub(crate) fn handles_error(
+    conditions: Object,
+    error: &Cons,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let matches = |condition: Object| match (condition.untag(), error.car().untag()) {
+        (ObjectType::Symbol(sym::TRUE), _) => true,
+        (ObjectType::Symbol(condition), ObjectType::Symbol(error)) => {
+            crate::data::has_condition(error, condition, env, cx)
+        }
+        _ => false,
+    };
+    match conditions.untag() {
+        ObjectType::Symbol(_) => Ok(matches(conditions)),
+        ObjectType::Cons(list) => {
+            for condition in list {
+                if matches(condition?) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        _ => bail!("Invalid condition handler: {conditions}"),
+    }
+}
+
+/// Called the first time an error is seen, while the stack is still intact.
+/// The backtrace is recorded if no handler will catch the error, and the
+/// function in `debugger` is called if `debug-on-error` or `debug-on-signal`
+/// ask for it.
+pub(crate) fn before_unwind(mut err: EvalError, env: &mut Rt<Env>, cx: &mut Context) -> EvalError {
+    if err.debugged || matches!(err.error, ErrorType::Throw(_)) {
+        return err;
+    }
+    err.debugged = true;
+    let error = err.error_object(env, cx);
+    let handler = find_handler(error, env, cx);
+    if handler.is_none() {
+        err.record_backtrace(env, cx);
+    }
+    if !wants_debugger(error, handler, env, cx) {
+        return err;
+    }
+    root!(error, cx);
+    if let Err(e) = call_debugger(error, env, cx) {
+        let mut err = EvalError::from(e);
+        err.debugged = true;
+        return err;
+    }
+    // The debugger may have signaled errors of its own, which replace the
+    // current exception.
+    if let ErrorType::Signal(_) = err.error {
+        let error = error.bind(cx);
+        err.error = ErrorType::Signal(env.set_exception(error.car(), error.cdr()));
+    }
+    err
+}
+
+/// The conditions of the innermost active `condition-case` handler for
+/// `error`.
+fn find_handler<'ob>(error: &Cons, env: &Rt<Env>, cx: &'ob Context) -> Option<Object<'ob>> {
+    env.condition_stack
+        .iter()
+        .rev()
+        .map(|conditions| conditions.bind(cx))
+        .find(|conditions| handles_error(*conditions, error, env, cx).unwrap_or(false))
+}
+
+fn wants_debugger(error: &Cons, handler: Option<Object>, env: &Rt<Env>, cx: &Context) -> bool {
+    let debug_on_error = env.var(sym::DEBUG_ON_ERROR, cx).unwrap_or_default();
+    let enabled = match debug_on_error.untag() {
+        ObjectType::NIL => false,
+        ObjectType::Cons(_) => handles_error(debug_on_error, error, env, cx).unwrap_or(false),
+        _ => true,
+    };
+    if !enabled {
+        return false;
+    }
+    if env.var(sym::DEBUG_ON_SIGNAL, cx).is_some_and(|x| !x.is_nil()) {
+        return true;
+    }
+    // Errors that will be caught by a handler only enter the debugger if the
+    // handler lists `debug` as one of its conditions.
+    match handler.map(|x| x.untag()) {
+        None => true,
+        Some(ObjectType::Cons(list)) => list.elements().any(|x| x.is_ok_and(|x| x == sym::DEBUG)),
+        Some(_) => false,
+    }
+}
+
+fn call_debugger(error: &Rto<&Cons>, env: &mut Rt<Env>, cx: &mut Context) -> Result<()> {
+    let Some(debugger) = env.var(sym::DEBUGGER, cx) else {
+        bail!(SignalError::void_variable(sym::DEBUGGER))
+    };
+    let debugger: Function = debugger.try_into()?;
+    root!(debugger, cx);
+    // Errors inside the debugger itself should not recursively enter it.
+    env.varbind(sym::DEBUG_ON_ERROR, NIL, cx);
+    env.varbind(sym::DEBUG_ON_SIGNAL, NIL, cx);
+    let error: Object = error.bind(cx).into();
+    let result = call!(debugger, Object::from(sym::ERROR), error; env, cx).map(|_| ());
+    env.unbind(2, cx);
+    Ok(result?)
+}
+
 #[defun]
 pub(crate) fn apply<'ob>(
     function: &Rto<Function>,
@@ -177,8 +362,7 @@ fn run_hooks<'ob>(hooks: ArgSlice, env: &mut Rt<Env>, cx: &'ob mut Context) -> R
         let hook = env.stack[hook_count - i - 1].bind(cx);
         match hook.untag() {
             ObjectType::Symbol(sym) => {
-                if let Some(val) = env.vars.get(sym) {
-                    let val = val.bind(cx);
+                if let Some(val) = env.var(sym, cx) {
                     match val.untag() {
                         ObjectType::Cons(hook_list) => {
                             rooted_iter!(hooks, hook_list, cx);
@@ -211,8 +395,7 @@ fn run_hook_with_args<'ob>(
 ) -> Result<Object<'ob>> {
     match hook.untag(cx) {
         ObjectType::Symbol(sym) => {
-            if let Some(val) = env.vars.get(sym) {
-                let val = val.bind(cx);
+            if let Some(val) = env.var(sym, cx) {
                 match val.untag() {
                     ObjectType::Cons(hook_list) => {
                         rooted_iter!(hooks, hook_list, cx);
@@ -366,7 +549,9 @@ fn func_arity<'ob>(function: Function, cx: &'ob Context) -> Result<&'ob Cons> {
             Ok(from_args(args))
         }
         FunctionType::Symbol(sym) => {
-            let Some(func) = sym.follow_indirect(cx) else { bail!("Void Function: {sym}") };
+            let Some(func) = sym.follow_indirect(cx) else {
+                bail!(SignalError::void_function(sym))
+            };
             func_arity(func, cx)
         }
     }
@@ -395,6 +580,16 @@ fn special_variable_p(symbol: Symbol) -> bool {
     symbol.is_special()
 }
 
+#[defun]
+fn default_toplevel_value<'ob>(
+    symbol: Symbol,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    // TODO: this should skip over let bindings of the variable
+    crate::data::default_value(symbol, env, cx)
+}
+
 #[defun]
 fn set_default_toplevel_value<'ob>(
     symbol: Symbol,
@@ -411,11 +606,140 @@ fn set_default<'ob>(
     value: Object<'ob>,
     env: &'ob mut Rt<Env>,
 ) -> Result<Object<'ob>> {
-    // TODO: implement buffer local variables
-    env.set_var(symbol, value)?;
+    env.set_default(symbol, value)?;
     Ok(value)
 }
 
+/// A function call on the lisp stack, as seen by the backtrace functions.
+#[derive(Clone, Copy)]
+struct StackFrame<'ob> {
+    /// The `(function . args)` of the call
+    call: &'ob Cons,
+    evaluated: bool,
+    /// The depth of the binding stack when the function was called
+    depth: usize,
+    /// The depth of the binding stack when the next frame was called, so the
+    /// bindings made by this frame are between `depth` and `end`.
+    end: usize,
+}
+
+impl StackFrame<'_> {
+    fn evaluated(&self) -> Object<'static> {
+        self.evaluated.into()
+    }
+}
+
+/// The active frames, innermost first. If `base` is non-nil, the frames start
+/// at its innermost call.
+fn backtrace_from<'ob>(base: Object, env: &Rt<Env>, cx: &'ob Context) -> Vec<StackFrame<'ob>> {
+    let indirect = |func| crate::data::indirect_function(func, cx);
+    let base = (!base.is_nil()).then(|| indirect(base));
+    let mut end = env.binding_depth();
+    env.stack
+        .backtrace()
+        .map(|frame| {
+            let args = crate::fns::slice_into_list(Rt::bind_slice(frame.args, cx), None, cx);
+            let call = Cons::new(frame.func.bind(cx), args, cx);
+            let depth = frame.binding_depth;
+            let frame = StackFrame { call, evaluated: frame.evaluated, depth, end };
+            end = depth;
+            frame
+        })
+        .skip_while(|frame| base.is_some_and(|base| !eq(base, indirect(frame.call.car()))))
+        .collect()
+}
+
+#[defun]
+fn mapbacktrace<'ob>(
+    function: &Rto<Function>,
+    base: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let base = base.map_or(NIL, |x| x.bind(cx));
+    let frames = backtrace_from(base, env, cx);
+    let evaluated: Vec<_> = frames.iter().map(StackFrame::evaluated).collect();
+    let calls: Vec<_> = frames.iter().map(|frame| Object::from(frame.call)).collect();
+    root!(calls, cx);
+    for (call, evaluated) in calls.iter().zip(evaluated) {
+        let call: &Cons = call.bind(cx).try_into()?;
+        call!(function, evaluated, call.car(), call.cdr(), NIL; env, cx)?;
+    }
+    Ok(NIL)
+}
+
+#[defun]
+#[expect(non_snake_case)]
+fn backtrace_frame__internal<'ob>(
+    function: &Rto<Function>,
+    nframes: usize,
+    base: &Rto<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let Some(&frame) = backtrace_from(base.bind(cx), env, cx).get(nframes) else {
+        return Ok(NIL);
+    };
+    let call = frame.call;
+    call!(function, frame.evaluated(), call.car(), call.cdr(), NIL; env, cx).map_err(Into::into)
+}
+
+#[defun]
+#[expect(non_snake_case)]
+fn backtrace__locals<'ob>(
+    nframes: usize,
+    base: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let base = base.map_or(NIL, |x| x.bind(cx));
+    let Some(&frame) = backtrace_from(base, env, cx).get(nframes) else {
+        bail!(SignalError::args_out_of_range(&[cx.add(nframes)]))
+    };
+    // Look at the variables with the values they had in the frame, before any
+    // inner frames shadowed them.
+    env.swap_bindings(frame.end, true, cx);
+    let locals: Vec<Object> = env
+        .bound_vars(frame.depth..frame.end, cx)
+        .into_iter()
+        .map(|var| match env.var(var, cx) {
+            Some(value) => Cons::new(var, value, cx).into(),
+            None => var.into(),
+        })
+        .collect();
+    env.swap_bindings(frame.end, false, cx);
+    Ok(crate::fns::slice_into_list(&locals, None, cx))
+}
+
+#[defun]
+fn backtrace(env: &mut Rt<Env>, cx: &mut Context) -> Result<bool> {
+    let text: String = env.stack.backtrace().map(|frame| format!("  {frame}\n")).collect();
+    crate::print::write_to_stream(&text, None, env, cx)?;
+    Ok(false)
+}
+
+#[defun]
+fn backtrace_eval<'ob>(
+    exp: &Rto<Object>,
+    nframes: usize,
+    base: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let base = base.map_or(NIL, |x| x.bind(cx));
+    let Some(&StackFrame { depth, .. }) = backtrace_from(base, env, cx).get(nframes) else {
+        bail!(SignalError::args_out_of_range(&[cx.add(nframes)]))
+    };
+    // Evaluate with the dynamic bindings that were active in the frame.
+    env.swap_bindings(depth, true, cx);
+    let result = match crate::interpreter::eval(exp, None, env, cx) {
+        Ok(x) => Ok(rebind!(x, cx)),
+        Err(e) => Err(e),
+    };
+    env.swap_bindings(depth, false, cx);
+    result
+}
+
 impl Rto<Function<'_>> {
     pub(crate) fn call<'ob>(
         &self,
@@ -424,30 +748,41 @@ impl Rto<Function<'_>> {
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
         debug!("calling: {self}");
+        let binding_depth = frame.binding_depth();
+        frame.stack.set_frame_function(self.bind(cx).into(), true, binding_depth);
+        match self.call_function(frame, name, cx) {
+            Ok(x) => Ok(rebind!(x, cx)),
+            Err(e) => Err(before_unwind(e, frame, cx)),
+        }
+    }
+
+    fn call_function<'ob>(
+        &self,
+        frame: &mut CallFrame<'_, '_>,
+        name: Option<&str>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
         let name = name.unwrap_or("lambda");
         frame.finalize_arguments();
         let arg_cnt = frame.arg_count();
-        cx.garbage_collect(false);
+        crate::alloc::maybe_garbage_collect(frame, cx);
         match self.untag(cx) {
             FunctionType::ByteFn(f) => {
                 root!(f, cx);
                 crate::bytecode::call(f, arg_cnt, name, frame, cx)
-                    .map_err(|e| e.add_trace(name, frame.arg_slice()))
-            }
-            FunctionType::SubrFn(f) => {
-                (*f).call(arg_cnt, frame, cx).map_err(|e| add_trace(e, name, frame.arg_slice()))
             }
+            FunctionType::SubrFn(f) => (*f).call(arg_cnt, frame, cx).map_err(Into::into),
             FunctionType::Cons(_) => {
                 crate::interpreter::call_closure(self.try_as().unwrap(), arg_cnt, name, frame, cx)
-                    .map_err(|e| e.add_trace(name, frame.arg_slice()))
             }
             FunctionType::Symbol(sym) => {
-                let Some(func) = sym.follow_indirect(cx) else { bail_err!("Void Function: {sym}") };
+                let Some(func) = sym.follow_indirect(cx) else {
+                    bail_err!(SignalError::void_function(sym))
+                };
                 if let Ok((sym::AUTOLOAD, _)) = func.as_cons_pair() {
                     // TODO: inifinite loop if autoload does not resolve
                     root!(sym, cx);
-                    crate::eval::autoload_do_load(self.cast(), None, None, frame, cx)
-                        .map_err(|e| add_trace(e, name, frame.arg_slice()))?;
+                    crate::eval::autoload_do_load(self.cast(), None, None, frame, cx)?;
                     let Some(func) = sym.bind(cx).follow_indirect(cx) else {
                         bail_err!("autoload for {sym} failed to define function")
                     };
@@ -464,13 +799,6 @@ impl Rto<Function<'_>> {
     }
 }
 
-pub(crate) fn add_trace(err: anyhow::Error, name: &str, args: &[Rto<Object>]) -> EvalError {
-    match err.downcast::<EvalError>() {
-        Ok(err) => err.add_trace(name, args),
-        Err(e) => EvalError::with_trace(e, name, args),
-    }
-}
-
 defsym!(FUNCTION);
 defsym!(QUOTE);
 defsym!(MACRO);
@@ -484,6 +812,7 @@ defsym!(CLOSURE);
 defsym!(CONDITION_CASE);
 defsym!(UNWIND_PROTECT);
 defsym!(SAVE_EXCURSION);
+defsym!(SAVE_RESTRICTION);
 defsym!(SAVE_CURRENT_BUFFER);
 defsym!(WHILE);
 defsym!(INLINE);
@@ -505,5 +834,9 @@ defsym!(ERROR);
 defsym!(DEBUG);
 defsym!(VOID_VARIABLE);
 
+defsym!(DEBUG_EARLY);
+
 defvar!(DEBUG_ON_ERROR, false);
+defvar!(DEBUG_ON_SIGNAL, false);
+defvar!(DEBUGGER, sym::DEBUG_EARLY);
 defvar!(INTERNAL_MAKE_INTERPRETED_CLOSURE_FUNCTION);
@@ -3,11 +3,14 @@ use crate::{
     arith::NumberValue,
     core::{
         cons::Cons,
+        error::SignalError,
         gc::Context,
         object::{Number, NumberType, Object},
     },
 };
-
+use anyhow::{Result, bail};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Signed};
 use rune_macros::defun;
 
 #[inline(always)]
@@ -15,57 +18,56 @@ fn coerce(arg: Number) -> f64 {
     match arg.untag() {
         NumberType::Int(i) => i as f64,
         NumberType::Float(f) => **f,
+        NumberType::BigInt(b) => b.to_f64(),
+    }
+}
+
+/// Round a number to an integer with `round_fn`. Integers are returned as is,
+/// and floats that are too large for a fixnum become bignums.
+fn round_to_int(num: NumberValue, round_fn: fn(f64) -> f64) -> Result<NumberValue> {
+    match num {
+        NumberValue::Float(f) => match BigInt::from_f64(round_fn(f)) {
+            Some(x) => Ok(x.into()),
+            None => bail!(SignalError::Overflow),
+        },
+        int => Ok(int),
     }
 }
 
 #[defun]
-fn floor(arg: Number, divisor: Option<Number>) -> i64 {
+fn floor(arg: Number, divisor: Option<Number>) -> Result<NumberValue> {
     let num = match divisor {
         Some(div) => arg.val() / div.val(),
         None => arg.val(),
     };
-    match num {
-        NumberValue::Int(i) => i,
-        NumberValue::Float(f) => f.floor() as i64,
-    }
+    round_to_int(num, f64::floor)
 }
 
 #[defun]
-fn ceiling(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.ceil() as i64,
-    }
+fn ceiling(arg: Number) -> Result<NumberValue> {
+    round_to_int(arg.val(), f64::ceil)
 }
 
 #[defun]
 fn fceiling(arg: Number) -> f64 {
-    match arg.untag() {
-        NumberType::Int(i) => i as f64,
-        NumberType::Float(f) => f.ceil(),
-    }
+    coerce(arg).ceil()
 }
 
 #[defun]
-fn round(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.round() as i64,
-    }
+fn round(arg: Number) -> Result<NumberValue> {
+    round_to_int(arg.val(), f64::round)
 }
 
 #[defun]
-fn truncate(arg: Number) -> i64 {
-    match arg.untag() {
-        NumberType::Int(i) => i,
-        NumberType::Float(f) => f.trunc() as i64,
-    }
+fn truncate(arg: Number) -> Result<NumberValue> {
+    round_to_int(arg.val(), f64::trunc)
 }
 
 #[defun]
 fn float<'ob>(arg: Number<'ob>, cx: &'ob Context) -> Number<'ob> {
     match arg.untag() {
         NumberType::Int(i) => cx.add_as(i as f64),
+        NumberType::BigInt(b) => cx.add_as(b.to_f64()),
         NumberType::Float(_) => arg,
     }
 }
@@ -103,7 +105,7 @@ fn tan(arg: Number) -> f64 {
 #[defun]
 fn isnan(arg: Number) -> bool {
     match arg.untag() {
-        NumberType::Int(_) => false,
+        NumberType::Int(_) | NumberType::BigInt(_) => false,
         NumberType::Float(f) => f.is_nan(),
     }
 }
@@ -119,15 +121,20 @@ fn exp(arg: Number) -> f64 {
 }
 
 #[defun]
-fn expt(x: Number, y: Number) -> NumberValue {
-    // If either is a float, we use the float version
-    match (x.untag(), y.untag()) {
-        (NumberType::Int(x), NumberType::Int(y)) => NumberValue::Int(x.pow(y as u32)),
-        _ => {
-            let x = coerce(x);
-            let y = coerce(y);
-            NumberValue::Float(x.powf(y))
+fn expt(x: Number, y: Number) -> Result<NumberValue> {
+    // The result is only an integer if both are integers and the power is
+    // not negative. Otherwise we use the float version.
+    match (x.val(), y.val()) {
+        (x @ (NumberValue::Int(_) | NumberValue::Big(_)), NumberValue::Int(y)) if y >= 0 => {
+            let Ok(y) = u32::try_from(y) else { bail!(SignalError::Overflow) };
+            if let NumberValue::Int(x) = x {
+                if let Some(result) = x.checked_pow(y) {
+                    return Ok(NumberValue::Int(result));
+                }
+            }
+            Ok(x.into_big().pow(y).into())
         }
+        (x, y) => Ok(NumberValue::Float(x.to_f64().powf(y.to_f64()))),
     }
 }
 
@@ -147,9 +154,13 @@ fn sqrt(arg: Number) -> f64 {
 
 #[defun]
 fn abs(arg: Number) -> NumberValue {
-    match arg.untag() {
-        NumberType::Int(i) => NumberValue::Int(i.abs()),
-        NumberType::Float(f) => NumberValue::Float(f.abs()),
+    match arg.val() {
+        NumberValue::Int(i) => match i.checked_abs() {
+            Some(i) => NumberValue::Int(i),
+            None => BigInt::from(i).abs().into(),
+        },
+        NumberValue::Float(f) => NumberValue::Float(f.abs()),
+        NumberValue::Big(b) => NumberValue::Big(b.abs()),
     }
 }
 
@@ -3,16 +3,18 @@ use crate::{
     core::{
         cons::Cons,
         env::{Env, sym},
-        error::{Type, TypeError},
+        error::{SignalError, Type, TypeError},
         gc::{Context, Rt, Rto},
         object::{
-            Function, Gc, HashTable, IntoObject, LispHashTable, LispString, LispVec, List,
-            ListType, NIL, Object, ObjectType, OptionalFlag, Symbol, WithLifetime,
+            Function, Gc, HashTable, HashTest, IntoObject, LispHashTable, LispString, LispVec,
+            List, ListType, MAX_FIXNUM, NIL, Object, ObjectType, OptionalFlag, Symbol,
+            WithLifetime,
         },
     },
     data::aref,
     library::filevercmp::filevercmp,
     rooted_iter,
+    textprops::{copy_string_textprops, string_textprops_equal},
 };
 use anyhow::{Result, anyhow, bail, ensure};
 use base64::Engine;
@@ -20,6 +22,7 @@ use fallible_iterator::FallibleIterator;
 use fallible_streaming_iterator::FallibleStreamingIterator;
 use rune_core::macros::{call, list, rebind, root};
 use rune_macros::{defun, elprop};
+use std::hash::{DefaultHasher, Hash, Hasher};
 
 #[defun]
 fn identity(arg: Object) -> Object {
@@ -64,14 +67,102 @@ pub(crate) fn equal<'ob>(obj1: Object<'ob>, obj2: Object<'ob>) -> bool {
 pub(crate) fn eql<'ob>(obj1: Object<'ob>, obj2: Object<'ob>) -> bool {
     match (obj1.untag(), obj2.untag()) {
         (ObjectType::Float(f1), ObjectType::Float(f2)) => f1.to_bits() == f2.to_bits(),
+        (ObjectType::BigInt(b1), ObjectType::BigInt(b2)) => b1 == b2,
         _ => obj1.ptr_eq(obj2),
     }
 }
 
 #[defun]
-fn equal_including_properties<'ob>(o1: Object<'ob>, o2: Object<'ob>) -> bool {
-    // TODO: implement text properties
-    equal(o1, o2)
+pub(crate) fn equal_including_properties<'ob>(o1: Object<'ob>, o2: Object<'ob>) -> Result<bool> {
+    if !equal(o1, o2) {
+        return Ok(false);
+    }
+    match (o1.untag(), o2.untag()) {
+        (ObjectType::String(s1), ObjectType::String(s2)) => string_textprops_equal(s1, s2),
+        _ => Ok(true),
+    }
+}
+
+/// How deep `sxhash-equal' looks into nested lists and vectors.
+const SXHASH_MAX_DEPTH: usize = 3;
+/// How many elements of a list or vector `sxhash-equal' looks at.
+const SXHASH_MAX_LEN: usize = 7;
+
+fn hash_to_fixnum(hasher: &DefaultHasher) -> i64 {
+    (hasher.finish() & MAX_FIXNUM as u64) as i64
+}
+
+/// Hash an object so that objects that are `eql' hash the same.
+pub(crate) fn hash_eql<H: Hasher>(obj: Object, state: &mut H) {
+    match obj.untag() {
+        ObjectType::Float(x) => x.to_bits().hash(state),
+        _ => obj.hash(state),
+    }
+}
+
+/// Hash an object so that objects that are `equal' hash the same.
+pub(crate) fn hash_equal<H: Hasher>(obj: Object, depth: usize, state: &mut H) {
+    if depth > SXHASH_MAX_DEPTH {
+        return;
+    }
+    match obj.untag() {
+        ObjectType::Int(_) | ObjectType::Float(_) | ObjectType::BigInt(_) => hash_eql(obj, state),
+        ObjectType::Symbol(x) => x.name().hash(state),
+        ObjectType::String(x) => x.hash(state),
+        ObjectType::ByteString(x) => x.hash(state),
+        ObjectType::Cons(x) => {
+            let mut tail = Some(x);
+            for _ in 0..SXHASH_MAX_LEN {
+                let Some(cons) = tail else { break };
+                hash_equal(cons.car(), depth + 1, state);
+                tail = match cons.cdr().untag() {
+                    ObjectType::Cons(next) => Some(next),
+                    ObjectType::NIL => None,
+                    _ => {
+                        hash_equal(cons.cdr(), depth + 1, state);
+                        None
+                    }
+                };
+            }
+        }
+        ObjectType::Vec(x) => {
+            x.len().hash(state);
+            for elem in x.iter().take(SXHASH_MAX_LEN) {
+                hash_equal(elem.get(), depth + 1, state);
+            }
+        }
+        ObjectType::Record(x) => {
+            x.len().hash(state);
+            for elem in x.iter().take(SXHASH_MAX_LEN) {
+                hash_equal(elem.get(), depth + 1, state);
+            }
+        }
+        // Hash tables only rehash their keys when the keys themselves move, so
+        // the address of an object nested inside a key can't be used.
+        x if depth > 0 => std::mem::discriminant(&x).hash(state),
+        _ => obj.hash(state),
+    }
+}
+
+#[defun]
+fn sxhash_eq(obj: Object) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    obj.hash(&mut hasher);
+    hash_to_fixnum(&hasher)
+}
+
+#[defun]
+fn sxhash_eql(obj: Object) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    hash_eql(obj, &mut hasher);
+    hash_to_fixnum(&hasher)
+}
+
+#[defun]
+fn sxhash_equal(obj: Object) -> i64 {
+    let mut hasher = DefaultHasher::new();
+    hash_equal(obj, 0, &mut hasher);
+    hash_to_fixnum(&hasher)
 }
 
 #[defun]
@@ -89,7 +180,7 @@ pub fn plist_get<'ob>(plist: Object<'ob>, prop: Object<'ob>) -> Result<Object<'o
 }
 
 #[defun]
-fn plist_member<'ob>(
+pub(crate) fn plist_member<'ob>(
     plist: Object<'ob>,
     prop: Object<'ob>,
     predicate: Option<Object>,
@@ -108,11 +199,6 @@ fn plist_member<'ob>(
     Ok(NIL)
 }
 
-#[defun]
-pub(crate) fn prin1_to_string(object: Object, _noescape: Option<Object>) -> String {
-    format!("{object}")
-}
-
 #[defun]
 fn string_to_multibyte(string: &LispString) -> &LispString {
     // TODO: Handle the unibyte case
@@ -523,7 +609,7 @@ pub(crate) fn require<'ob>(
 }
 
 #[defun]
-pub(crate) fn concat(sequences: &[Object]) -> Result<String> {
+pub(crate) fn concat<'ob>(sequences: &[Object<'ob>], cx: &'ob Context) -> Result<Object<'ob>> {
     let mut concat = String::new();
     for elt in sequences {
         match elt.untag() {
@@ -532,7 +618,17 @@ pub(crate) fn concat(sequences: &[Object]) -> Result<String> {
             _ => bail!("Currently only concatenating strings are supported"),
         }
     }
-    Ok(concat)
+    let result: Gc<&LispString> = concat.into_obj(cx);
+    let result = result.untag();
+    let mut offset = 0;
+    for elt in sequences {
+        if let ObjectType::String(string) = elt.untag() {
+            let len = string.len();
+            copy_string_textprops(string, 0, len, result, offset, cx)?;
+            offset += len;
+        }
+    }
+    Ok(result.into())
 }
 
 #[defun]
@@ -834,6 +930,7 @@ pub(crate) fn clear_string(string: &LispString) -> Result<Object> {
 ///////////////
 
 defsym!(KW_TEST);
+defsym!(KW_SIZE);
 defsym!(KW_DOCUMENTATION);
 
 #[defun]
@@ -841,19 +938,20 @@ pub(crate) fn make_hash_table<'ob>(
     keyword_args: &[Object<'ob>],
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    let kw_test_pos = keyword_args.iter().step_by(2).position(|&x| x == sym::KW_TEST);
-    if let Some(i) = kw_test_pos {
-        let Some(val) = keyword_args.get((i * 2) + 1) else {
-            bail!("Missing keyword value for :test")
-        };
-        if *val != sym::EQ && *val != sym::EQUAL && *val != sym::EQL {
-            // TODO: we are currently only using `equal', but eq should be okay
-            bail!("only `eq' and `equal' keywords support for make-hash-table :test. Found {val}");
+    let mut test = HashTest::default();
+    let mut size = 0;
+    for pair in keyword_args.chunks(2) {
+        let [key, val] = *pair else { bail!("Missing keyword value for {}", pair[0]) };
+        if key == sym::KW_TEST {
+            // TODO: support tests defined with `define-hash-table-test'
+            let Some(x) = HashTest::from_name(val) else { bail!("Invalid hash table test: {val}") };
+            test = x;
+        } else if key == sym::KW_SIZE && val != NIL {
+            size = val.try_into()?;
         }
+        // TODO, the rest of the keywords need to be supported here
     }
-    // TODO, the rest of the keywords need to be supported here
-    let map = HashTable::with_hasher(std::hash::BuildHasherDefault::default());
-    Ok(cx.add(map))
+    Ok(cx.add(HashTable::with_capacity(test, size)))
 }
 
 #[defun]
@@ -929,7 +1027,7 @@ fn maphash(
 }
 
 #[defun]
-fn copy_sequence<'ob>(arg: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
+pub(crate) fn copy_sequence<'ob>(arg: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>> {
     match arg.untag() {
         ObjectType::Vec(x) => Ok(cx.add(x.to_vec())),
         ObjectType::Cons(x) => {
@@ -945,27 +1043,38 @@ fn copy_sequence<'ob>(arg: Object<'ob>, cx: &'ob Context) -> Result<Object<'ob>>
             }
             Ok(slice_into_list(&elements, tail, cx))
         }
-        ObjectType::String(x) => Ok(cx.add(x.to_owned())),
+        ObjectType::String(x) => {
+            let copy: Gc<&LispString> = x.inner().into_obj(cx);
+            copy_string_textprops(x, 0, x.len(), copy.untag(), 0, cx)?;
+            Ok(copy.into())
+        }
         ObjectType::NIL => Ok(NIL),
         _ => Err(TypeError::new(Type::Sequence, arg).into()),
     }
 }
 
+/// Return a new string whose contents are a substring of STRING. FROM and TO
+/// are character indices, and negative values count from the end of the
+/// string. The text properties of the substring are copied along with it.
 #[defun]
-fn substring(string: &str, from: Option<usize>, to: Option<usize>) -> Result<String> {
-    if from.unwrap_or_default() > string.len() || to.unwrap_or_default() > string.len() {
-        bail!("substring args out of range for {string} : {from:?} {to:?}");
-    }
-    let new_string = match (from, to) {
-        (None, None) => string,
-        (None, Some(t)) => &string[..t],
-        (Some(f), None) => &string[f..],
-        (Some(f), Some(t)) => {
-            let range = if f > t { t..f } else { f..t };
-            &string[range]
-        }
-    };
-    Ok(new_string.to_owned())
+fn substring<'ob>(
+    string: &'ob LispString,
+    from: Option<i64>,
+    to: Option<i64>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let len = string.len() as i64;
+    let index = |x: i64| if x < 0 { x + len } else { x };
+    let (start, end) = (index(from.unwrap_or(0)), index(to.unwrap_or(len)));
+    if start < 0 || end > len || start > end {
+        let (from, to) = (from.map_or(NIL, Into::into), to.map_or(NIL, Into::into));
+        bail!(SignalError::args_out_of_range(&[string.into(), from, to]));
+    }
+    let (start, end) = (start as usize, end as usize);
+    let substring: String = string.chars().skip(start).take(end - start).collect();
+    let new: Gc<&LispString> = substring.into_obj(cx);
+    copy_string_textprops(string, start, end, new.untag(), 0, cx)?;
+    Ok(new.into())
 }
 
 defsym!(MD5);
@@ -1266,6 +1375,21 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_hash_table_test() {
+        let lookup = |test| {
+            format!(
+                "(let ((h (make-hash-table :test '{test} :size 3)))
+                   (puthash \"a\" 1 h)
+                   (puthash 1.5 2 h)
+                   (list (gethash (concat \"a\") h) (gethash (+ 1.0 0.5) h)))"
+            )
+        };
+        assert_lisp(&lookup("equal"), "(1 2)");
+        assert_lisp(&lookup("eql"), "(nil 2)");
+        assert_lisp(&lookup("eq"), "(nil nil)");
+    }
+
     #[test]
     fn test_legnth() {
         assert_lisp("(length nil)", "0");
@@ -1306,4 +1430,28 @@ mod test {
         );
         assert_lisp("(let ((str \"\")) (clear-string str) str)", "\"\"");
     }
+    #[test]
+    fn test_string_textprops() {
+        assert_lisp(
+            "(equal-including-properties (concat (propertize \"ab\" 'face 'bold) \"c\") #(\"abc\" 0 2 (face bold)))",
+            "t",
+        );
+        assert_lisp("(equal-including-properties (propertize \"ab\" 'face 'bold) \"ab\")", "nil");
+        assert_lisp(
+            "(equal-including-properties (substring #(\"abcd\" 1 3 (a 1)) 2) #(\"cd\" 0 1 (a 1)))",
+            "t",
+        );
+        assert_lisp(
+            "(equal-including-properties (copy-sequence #(\"abc\" 0 1 (a 1))) #(\"abc\" 0 1 (a 1)))",
+            "t",
+        );
+    }
+
+    #[test]
+    fn test_substring() {
+        assert_lisp("(substring \"hello\" 1 3)", "\"el\"");
+        assert_lisp("(substring \"hello\" -3)", "\"llo\"");
+        assert_lisp("(substring \"hello\" 1 -1)", "\"ell\"");
+        assert_lisp("(substring \"héllo\" 1 2)", "\"é\"");
+    }
 }
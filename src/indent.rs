@@ -0,0 +1,79 @@
+//! Indentation and column functions.
+use crate::cmds::find_newline_backward;
+use crate::core::{
+    env::{Env, sym},
+    gc::{Context, Rt},
+    object::ObjectType,
+};
+use rune_macros::defun;
+
+fn tab_width(env: &Rt<Env>, cx: &Context) -> usize {
+    match env.var(sym::TAB_WIDTH, cx).map(|x| x.untag()) {
+        Some(ObjectType::Int(x)) if (1..=1000).contains(&x) => x as usize,
+        _ => 8,
+    }
+}
+
+fn indent_tabs_mode(env: &Rt<Env>, cx: &Context) -> bool {
+    env.var(sym::INDENT_TABS_MODE, cx).is_some_and(|x| !x.is_nil())
+}
+
+#[defun]
+pub(crate) fn current_column(env: &Rt<Env>, cx: &Context) -> usize {
+    let tab_width = tab_width(env, cx);
+    let data = env.current_buffer.get();
+    let text = &data.text;
+    let point = text.cursor().chars();
+    let (bol, _) = find_newline_backward(text, point, data.begv(), 1);
+    let (a, b) = text.slice(bol..point);
+    a.chars().chain(b.chars()).fold(0, |col, chr| match chr {
+        '\t' => (col / tab_width + 1) * tab_width,
+        _ => col + 1,
+    })
+}
+
+#[defun]
+pub(crate) fn indent_to(
+    column: usize,
+    minimum: Option<usize>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> usize {
+    let mut fromcol = current_column(env, cx);
+    let mincol = column.max(fromcol + minimum.unwrap_or(0));
+    if fromcol == mincol {
+        return mincol;
+    }
+    let mut indent = String::new();
+    if indent_tabs_mode(env, cx) {
+        let tab_width = tab_width(env, cx);
+        let tabs = (mincol / tab_width).saturating_sub(fromcol / tab_width);
+        if tabs > 0 {
+            indent.extend(std::iter::repeat_n('\t', tabs));
+            fromcol = (mincol / tab_width) * tab_width;
+        }
+    }
+    indent.extend(std::iter::repeat_n(' ', mincol - fromcol));
+    env.current_buffer.get_mut().text.insert(&indent);
+    mincol
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_indent_to() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo\na\tb");
+        assert_eq!(current_column(env, cx), 9);
+        assert_eq!(indent_to(12, None, env, cx), 12);
+        assert_eq!(env.current_buffer.get(), "foo\na\tb   ");
+        assert_eq!(indent_to(4, Some(1), env, cx), 13);
+        assert_eq!(current_column(env, cx), 13);
+    }
+}
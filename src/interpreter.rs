@@ -3,12 +3,12 @@ use crate::{
     core::{
         cons::{Cons, ElemStreamIter, IntoArray},
         env::{CallFrame, Env, sym},
-        error::{Type, TypeError},
+        error::{SignalError, Type, TypeError},
         gc::{Context, Rt, Rto, Slot},
         object::{Function, Gc, List, ListType, NIL, Object, ObjectType, Symbol, TRUE, TagType},
     },
     data::LispError,
-    eval::{ErrorType, EvalError, EvalResult, add_trace},
+    eval::{ErrorType, EvalError, EvalResult, before_unwind, handles_error},
     rooted_iter,
 };
 use anyhow::Context as _;
@@ -30,7 +30,7 @@ pub(crate) fn eval<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> Result<Object<'ob>, anyhow::Error> {
-    cx.garbage_collect(false);
+    crate::alloc::maybe_garbage_collect(env, cx);
     root!(vars, new(Vec<Slot<&Cons>>), cx);
     if let Some(ObjectType::Cons(cons)) = lexical.map(|x| x.untag(cx)) {
         for var in cons.elements() {
@@ -85,6 +85,7 @@ impl Interpreter<'_, '_> {
                 sym::CONDITION_CASE => self.condition_case(forms, cx),
                 sym::SAVE_CURRENT_BUFFER => self.save_current_buffer(forms, cx),
                 sym::SAVE_EXCURSION => self.save_excursion(forms, cx),
+                sym::SAVE_RESTRICTION => self.save_restriction(forms, cx),
                 sym::UNWIND_PROTECT => self.unwind_protect(forms, cx),
                 _ => {
                     root!(sym, cx);
@@ -162,14 +163,13 @@ impl Interpreter<'_, '_> {
         cx: &'ob mut Context,
     ) -> EvalResult<'ob> {
         let Some(func) = sym.bind(cx).follow_indirect(cx) else {
-            bail_err!("Invalid function: {sym}")
+            bail_err!(SignalError::void_function(sym.bind(cx)))
         };
         root!(func, cx);
 
         match func.bind(cx).as_cons_pair() {
             Ok((sym::AUTOLOAD, _)) => {
-                crate::eval::autoload_do_load(func.cast(), None, None, self.env, cx)
-                    .map_err(|e| add_trace(e, "autoload", &[]))?;
+                crate::eval::autoload_do_load(func.cast(), None, None, self.env, cx)?;
                 func.set(sym.bind(cx).follow_indirect(cx).unwrap());
             }
             Ok((sym::MACRO, mcro)) => {
@@ -179,6 +179,8 @@ impl Interpreter<'_, '_> {
                     frame.push_arg(arg?);
                 }
                 root!(mcro, mcro.tag(), cx);
+                let binding_depth = frame.binding_depth();
+                frame.stack.set_frame_function(sym.bind(cx).into(), false, binding_depth);
                 let name = sym.bind(cx).name().to_owned();
                 let value = mcro.call(&mut frame, Some(&name), cx)?;
                 drop(frame);
@@ -196,6 +198,9 @@ impl Interpreter<'_, '_> {
         }
         let frame = &mut CallFrame::new(self.env);
         frame.push_arg_slice(Rt::bind_slice(args, cx));
+        // backtraces show the symbol that was called rather than its function
+        let binding_depth = frame.binding_depth();
+        frame.stack.set_frame_function(sym.bind(cx).into(), true, binding_depth);
         let name = sym.bind(cx).name().to_owned();
         func.call(frame, Some(&name), cx)
     }
@@ -432,9 +437,9 @@ impl Interpreter<'_, '_> {
             let mut iter = self.vars.iter().rev();
             match iter.find_map(|cons| (cons.car(cx) == sym).then(|| cons.cdr(cx))) {
                 Some(value) => Ok(value),
-                None => match self.env.vars.get(sym) {
-                    Some(v) => Ok(v.bind(cx)),
-                    None => Err(error!("Void variable: {sym}")),
+                None => match self.env.var(sym, cx) {
+                    Some(v) => Ok(v),
+                    None => Err(error!(SignalError::void_variable(sym))),
                 },
             }
         }
@@ -608,6 +613,18 @@ impl Interpreter<'_, '_> {
         Ok(result)
     }
 
+    fn save_restriction<'ob>(
+        &mut self,
+        form: &Rto<Object>,
+        cx: &'ob mut Context,
+    ) -> EvalResult<'ob> {
+        let saved = self.env.current_restriction();
+        // The restriction is restored even if the body exits non-locally
+        let result = self.eval_progn(form, cx);
+        self.env.restore_restriction(&saved);
+        result
+    }
+
     fn save_current_buffer<'ob>(
         &mut self,
         form: &Rto<Object>,
@@ -629,55 +646,36 @@ impl Interpreter<'_, '_> {
         let Some(bodyform) = forms.next()? else {
             bail_err!(LispError::arg_cnt(sym::CONDITION_CASE, 2, 1, cx))
         };
-        let err = match self.eval_form(bodyform, cx) {
-            Ok(x) => return Ok(rebind!(x, cx)),
-            Err(e) => e,
+        // Make the handler conditions visible to the debugger while the body
+        // runs, with the first clause innermost.
+        let depth = self.env.condition_stack.len();
+        let clauses: Vec<_> = form.bind(cx).as_list()?.skip(2).collect::<Result<_, _>>()?;
+        for clause in clauses.into_iter().rev() {
+            if let ObjectType::Cons(cons) = clause.untag() {
+                self.env.condition_stack.push(cons.car());
+            }
+        }
+        let result = self.eval_form(bodyform, cx);
+        let err = match result {
+            Ok(x) => {
+                self.env.condition_stack.truncate(depth);
+                return Ok(rebind!(x, cx));
+            }
+            Err(e) => before_unwind(e, self.env, cx),
         };
+        self.env.condition_stack.truncate(depth);
         if matches!(err.error, ErrorType::Throw(_)) {
             return Err(err);
         }
+        let error = err.error_object(self.env, cx);
+        root!(error, cx);
         while let Some(handler) = forms.next()? {
             match handler.untag(cx) {
                 ObjectType::Cons(cons) => {
-                    // Check that conditions match
-                    let condition = cons.car();
-                    match condition.untag() {
-                        ObjectType::Symbol(sym::ERROR | sym::VOID_VARIABLE) => {}
-                        // TODO: Remove this once error handling is correctly implemented
-                        ObjectType::Symbol(s) if s.name() == "cl--generic-cyclic-definition" => {}
-                        ObjectType::Cons(conditions) => {
-                            for condition in conditions {
-                                let condition = condition?;
-                                // TODO: Handle different error symbols
-                                if condition != sym::DEBUG && condition != sym::ERROR {
-                                    bail_err!("non-error conditions {condition} not yet supported")
-                                }
-                            }
-                        }
-                        _ => bail_err!("Invalid condition handler: {condition}"),
+                    if !handles_error(cons.car(), error.bind(cx), self.env, cx)? {
+                        continue;
                     }
-
-                    // Call handlers with error
-                    let error = match err.error {
-                        ErrorType::Signal(id) => {
-                            let Some((sym, data)) = self.env.get_exception(id) else {
-                                unreachable!("Exception not found")
-                            };
-                            Cons::new(sym, data, cx)
-                        }
-                        ErrorType::Err(err) => {
-                            let err_str = format!("{err}");
-                            if let Ok(lisp_error) = err.downcast::<LispError>() {
-                                lisp_error.bind(cx)
-                            } else {
-                                // TODO: Need to remove the anyhow branch once
-                                // full errors are implemented
-                                Cons::new(sym::ERROR, err_str, cx)
-                            }
-                        }
-                        _ => unreachable!("Error type throw was not handled"),
-                    };
-
+                    let error = error.bind(cx);
                     let binding = Cons::new(var, error, cx);
                     self.vars.push(binding);
                     let list: List = match cons.cdr().try_into() {
@@ -704,7 +702,7 @@ pub(crate) fn call_closure<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob mut Context,
 ) -> EvalResult<'ob> {
-    cx.garbage_collect(false);
+    crate::alloc::maybe_garbage_collect(env, cx);
     let closure: &Cons = closure.untag(cx);
     match closure.car().untag() {
         ObjectType::Symbol(sym::CLOSURE) => {
@@ -1104,11 +1102,167 @@ mod test {
             2,
             cx,
         );
+        // Ensure that errors raised from Rust survive collections in cleanup
+        // forms
+        let list = list![1, 2; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(condition-case e
+                 (unwind-protect (car (vector (list 1 2)))
+                   (garbage-collect)
+                   (let ((i 0))
+                     (while (< i 100)
+                       (setq i (1+ i))
+                       (vector i i))))
+               (error (aref (nth 2 e) 0)))",
+            list,
+            cx,
+        );
         check_error("(condition-case nil (if))", cx);
         check_error("(condition-case nil (if) nil)", cx);
         check_error("(condition-case nil (if) 5 (error 7))", cx);
     }
 
+    #[test]
+    fn test_error_conditions() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let list = list![sym::WRONG_TYPE_ARGUMENT, sym::CONSP, 1; cx];
+        root!(list, cx);
+        check_interpreter("(condition-case e (setcar 1 2) (wrong-type-argument e))", list, cx);
+        let list = list![sym::VOID_VARIABLE, crate::core::env::intern("foo", cx); cx];
+        root!(list, cx);
+        check_interpreter("(condition-case e foo (void-variable e))", list, cx);
+        check_interpreter("(condition-case nil (foo) (void-function 1))", 1, cx);
+        check_interpreter("(condition-case nil (car) (wrong-number-of-arguments 1))", 1, cx);
+        check_interpreter("(condition-case nil (delete-region 0 1) (args-out-of-range 1))", 1, cx);
+        check_interpreter("(condition-case nil (car 1) ((void-variable error) 1))", 1, cx);
+        check_interpreter("(condition-case nil (car 1) (void-variable 1) (error 2))", 2, cx);
+        check_interpreter("(condition-case nil (car 1) (t 1))", 1, cx);
+        check_interpreter(
+            "(condition-case nil (condition-case nil (car 1) (void-variable 1)) (error 2))",
+            2,
+            cx,
+        );
+        let list = list![1, 2, 4; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (put 'my-error 'error-conditions '(my-error arith-error error))
+                    (list (condition-case nil (signal 'my-error nil) (arith-error 1))
+                          (condition-case nil (signal 'my-error nil) (my-error 2))
+                          (condition-case nil
+                              (condition-case nil (signal 'my-error nil) (void-variable 3))
+                            (error 4))))",
+            list,
+            cx,
+        );
+        check_error("(condition-case nil (car 1) (void-variable 1))", cx);
+        check_error("(condition-case nil (signal 'undefined-error nil) (error 1))", cx);
+    }
+
+    #[test]
+    fn test_debugger() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let error = list![sym::WRONG_TYPE_ARGUMENT, sym::LISTP, 1; cx];
+        let debugged = list![sym::ERROR, error; cx];
+        let list = list![false, debugged, debugged, false, debugged; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (setq debugger #'(lambda (&rest args) (setq recorded args)))
+                    (setq debug-on-error t)
+                    (list (progn (setq recorded nil)
+                                 (condition-case nil (car 1) (error nil))
+                                 recorded)
+                          (progn (setq recorded nil)
+                                 (condition-case nil (car 1) ((debug error) nil))
+                                 recorded)
+                          (progn (setq recorded nil)
+                                 (condition-case nil
+                                     (condition-case nil (car 1) (void-variable nil))
+                                   ((debug error) nil))
+                                 recorded)
+                          (progn (setq recorded nil debug-on-error '(void-variable))
+                                 (condition-case nil (car 1) ((debug error) nil))
+                                 recorded)
+                          (progn (setq recorded nil debug-on-signal t debug-on-error t)
+                                 (condition-case nil (car 1) (error nil))
+                                 recorded)))",
+            list,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let frame = crate::core::env::intern("my-frame", cx);
+        let args = list![1, 2; cx];
+        let list = list![true, frame, args, false; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defalias 'my-frame
+                      #'(lambda (a b) (backtrace-frame--internal #'list 1 'backtrace-frame--internal)))
+                    (my-frame 1 2))",
+            list,
+            cx,
+        );
+        let frame = crate::core::env::intern("my-frame", cx);
+        let list = list![frame; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (setq frames nil)
+                    (defalias 'my-frame
+                      #'(lambda (a)
+                          (mapbacktrace #'(lambda (evald f args flags) (setq frames (cons f frames)))
+                                        'my-frame)))
+                    (my-frame 1)
+                    frames)",
+            list,
+            cx,
+        );
+        check_interpreter(
+            "(progn (defvar dyn 1)
+                    (defalias 'inner #'(lambda () (backtrace-eval 'dyn 1 'inner)))
+                    (let ((dyn 2)) (funcall #'(lambda () (let ((dyn 3)) (inner))))))",
+            2,
+            cx,
+        );
+    }
+
+    #[test]
+    fn test_backtrace_frames() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let mac = crate::core::env::intern("my-macro", cx);
+        let form = list![sym::QUOTE, 1; cx];
+        let args = list![form; cx];
+        let list = list![false, mac, args, false; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defalias 'my-macro
+                      (cons 'macro
+                            #'(lambda (x)
+                                (list 'quote
+                                      (backtrace-frame--internal #'list 1 'backtrace-frame--internal)))))
+                    (my-macro '1))",
+            list,
+            cx,
+        );
+        let dyn_var = crate::core::env::intern("dyn", cx);
+        let local = Cons::new(dyn_var, 3, cx);
+        let list = list![local; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (defvar dyn 1)
+                    (defalias 'inner #'(lambda () (let ((dyn 4)) (backtrace--locals 1 'inner))))
+                    (let ((dyn 2)) (funcall #'(lambda () (let ((dyn 3)) (inner))))))",
+            list,
+            cx,
+        );
+    }
+
     #[test]
     fn test_throw_catch() {
         let roots = &RootSet::default();
@@ -1125,4 +1279,38 @@ mod test {
         check_error("(throw 1 2)", cx);
         check_error("(catch 2 (throw 3 4))", cx);
     }
+
+    #[test]
+    fn test_save_restriction() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        check_interpreter(
+            "(progn (insert \"hello world\") (save-restriction (narrow-to-region 3 8) (point-min)))",
+            3,
+            cx,
+        );
+        check_interpreter(
+            "(progn (insert \"hello world\") (save-restriction (narrow-to-region 3 8)) (point-max))",
+            12,
+            cx,
+        );
+        let list = list![3, 9, true; cx];
+        root!(list, cx);
+        check_interpreter(
+            "(progn (insert \"hello world\")
+                    (narrow-to-region 3 8)
+                    (save-restriction (widen) (insert \"!\"))
+                    (list (point-min) (point-max) (buffer-narrowed-p)))",
+            list,
+            cx,
+        );
+        check_interpreter(
+            "(progn (insert \"hello world\")
+                    (condition-case nil
+                        (save-restriction (narrow-to-region 3 8) (error \"foo\"))
+                      (error (buffer-narrowed-p))))",
+            false,
+            cx,
+        );
+    }
 }
@@ -9,7 +9,7 @@ use crate::{
         gc::{IntoRoot, Slot, Trace},
         object::{NIL, Object, ObjectType, TagType, WithLifetime},
     },
-    fns::eq,
+    fns::{copy_sequence, eq},
     textprops::add_properties,
 };
 
@@ -49,18 +49,17 @@ impl<'ob> IntervalTree<'ob> {
     ///
     /// If the interval overlaps with existing intervals, their properties will be merged
     /// using `add_properties`. The resulting object will be stored in the tree.
+    /// Empty intervals are ignored.
     pub fn insert(&mut self, start: usize, end: usize, val: Slot<Object<'ob>>, cx: &'ob Context) {
-        self.tree
-            .insert((start, end), val, |a, b| {
-                add_properties(*a, *b, crate::textprops::PropertySetType::Append, false, cx)
-                    .map(Slot::new)
-                    .unwrap()
-            })
-            .unwrap();
+        self.tree.insert((start, end), val, |a, b| {
+            add_properties(*a, *b, crate::textprops::PropertySetType::Append, false, cx)
+                .map(Slot::new)
+                .unwrap()
+        });
     }
 
     pub fn set_properties(&mut self, start: usize, end: usize, properties: Object<'ob>) {
-        self.tree.insert((start, end), Slot::new(properties), |a, _b| a).unwrap();
+        self.tree.insert((start, end), Slot::new(properties), |a, _b| a);
     }
 
     pub fn find(&self, position: usize) -> Option<&Node<Slot<Object<'ob>>>> {
@@ -99,6 +98,30 @@ impl<'ob> IntervalTree<'ob> {
         self.tree.clean(|a, b| eq(**a, **b), |n| n.is_nil());
     }
 
+    /// Copies the properties of [start..end) into `dest`, shifted so that they
+    /// begin at `offset`. The property lists are copied so that changing the
+    /// properties of one tree does not affect the other.
+    pub(crate) fn copy_into(
+        &self,
+        start: usize,
+        end: usize,
+        dest: &mut IntervalTree<'ob>,
+        offset: usize,
+        cx: &'ob Context,
+    ) -> Result<()> {
+        for (range, plist) in self.iter(start, end) {
+            if !plist.is_nil() {
+                let plist = copy_sequence(plist, cx)?;
+                dest.set_properties(
+                    range.start - start + offset,
+                    range.end - start + offset,
+                    plist,
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn iter<'a>(&'a self, start: usize, end: usize) -> IntervalIntersections<'ob, 'a> {
         IntervalIntersections::new(self, start, end)
     }
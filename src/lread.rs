@@ -61,14 +61,36 @@ pub(crate) fn read_from_string<'ob>(
 }
 
 pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<bool> {
+    let prev_location = crate::debug::set_load_location(None);
+    let result = load_forms(contents, cx, env);
+    crate::debug::set_load_location(prev_location);
+    result
+}
+
+/// The offset of the first form in `text`, past any whitespace and comments.
+fn form_start(text: &str) -> usize {
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        match rest.strip_prefix(';') {
+            Some(comment) => rest = comment.find('\n').map_or("", |end| &comment[end..]),
+            None => return text.len() - rest.len(),
+        }
+    }
+}
+
+fn load_forms(contents: &str, cx: &mut Context, env: &mut Rt<Env>) -> Result<bool> {
     let mut pos = 0;
+    let mut line = 1;
     let macroexpand: Option<Function> = None;
     root!(macroexpand, cx);
     if let Some(fun) = sym::INTERNAL_MACROEXPAND_FOR_LOAD.func(cx) {
         macroexpand.set(Some(fun));
     }
     loop {
-        let (obj, new_pos) = match reader::read(&contents[pos..], cx) {
+        let load_file_name = env.var(sym::LOAD_FILE_NAME, cx).unwrap_or(NIL);
+        let result = reader::read_with_load_file(&contents[pos..], load_file_name, cx);
+        let (obj, new_pos) = match result {
             Ok((obj, pos)) => (obj, pos),
             Err(reader::Error::EmptyStream) => return Ok(true),
             Err(mut e) => {
@@ -81,6 +103,15 @@ pub(crate) fn load_internal(contents: &str, cx: &mut Context, env: &mut Rt<Env>)
             println!("-----READ START-----\n {content}");
             println!("-----READ END-----");
         }
+        if let (true, ObjectType::String(file)) =
+            (crate::debug::source_trace_enabled(), load_file_name.untag())
+        {
+            let file: &str = file.as_ref();
+            let form = &contents[pos..(new_pos + pos)];
+            let form_line = line + form[..form_start(form)].matches('\n').count();
+            crate::debug::set_load_location(Some(format!("{file}:{form_line}").into()));
+            line += form.matches('\n').count();
+        }
         root!(obj, cx);
         let result = if let Some(fun) = macroexpand.as_ref() {
             eager_expand(obj, fun, env, cx)
@@ -262,4 +293,21 @@ mod test {
         let val = interpreter::eval(obj, None, env, cx).unwrap();
         assert_eq!(val, 4.5);
     }
+
+    #[test]
+    fn test_definition_location() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        sym::init_symbols();
+        root!(env, new(Env), cx);
+        crate::debug::enable_source_trace();
+        let file = cx.add("test-location.el");
+        env.set_var(sym::LOAD_FILE_NAME, file).unwrap();
+        let contents = "(setq foo 1)\n\n;; (defalias 'first #'car)\n  (defalias\n  'location-first #'car)\n(defalias 'location-second #'cdr)";
+        load_internal(contents, cx, env).unwrap();
+        let location = crate::debug::definition_location;
+        assert_eq!(location("location-first").as_deref(), Some("test-location.el:4"));
+        assert_eq!(location("location-second").as_deref(), Some("test-location.el:6"));
+        assert_eq!(location("first"), None);
+    }
 }
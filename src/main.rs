@@ -12,6 +12,7 @@ mod bytecode;
 mod casefiddle;
 mod character;
 mod chartab;
+mod cmds;
 mod data;
 mod dired;
 mod editfns;
@@ -21,15 +22,20 @@ mod fileio;
 mod filelock;
 mod floatfns;
 mod fns;
+mod indent;
 mod interpreter;
 mod intervals;
 mod keymap;
 mod library;
 mod lisp;
 mod lread;
+mod marker;
+mod overlay;
 mod print;
 mod reader;
+mod regex;
 mod search;
+mod syntax;
 mod textprops;
 mod threads;
 mod timefns;
@@ -55,19 +61,27 @@ struct Args {
     no_bootstrap: bool,
     #[arg(long)]
     eval_stdin: bool,
+    /// Show where each function in a backtrace was defined
+    #[arg(long)]
+    source_trace: bool,
+    /// Run a collection at every garbage collection safe point, instead of
+    /// only when the heap has grown. Allocations are not safe points, so
+    /// nothing is collected in between them
+    #[arg(long)]
+    gc_stress: bool,
 }
 
 fn main() -> Result<(), ()> {
     let args = Args::parse();
+    if args.source_trace {
+        crate::debug::enable_source_trace();
+    }
 
     let roots = &RootSet::default();
     let cx = &mut Context::new(roots);
+    cx.set_gc_stress(args.gc_stress);
     root!(env, new(Env), cx);
-
-    sym::init_symbols();
-    crate::core::env::init_variables(cx, env);
-    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None)
-        .expect("null should be defined");
+    init(env, cx);
 
     if args.eval_stdin {
         return eval_stdin(cx, env);
@@ -87,6 +101,14 @@ fn main() -> Result<(), ()> {
     Ok(())
 }
 
+fn init(env: &mut Rt<Env>, cx: &mut Context) {
+    sym::init_symbols();
+    crate::core::env::init_variables(cx, env);
+    crate::data::init_errors(env, cx);
+    crate::data::defalias(intern("not", cx), (sym::NULL).into(), None)
+        .expect("null should be defined");
+}
+
 fn parens_closed(buffer: &str) -> bool {
     let open = buffer.chars().filter(|&x| x == '(').count();
     let close = buffer.chars().filter(|&x| x == ')').count();
@@ -193,3 +215,24 @@ fn verify_cli() {
     use clap::CommandFactory;
     Args::command().debug_assert()
 }
+
+#[test]
+fn min_bootstrap_gc_stress() {
+    let roots = &RootSet::default();
+    let cx = &mut Context::new(roots);
+    cx.set_gc_stress(true);
+    root!(env, new(Env), cx);
+    init(env, cx);
+    assert!(load("min-bootstrap.el", cx, env).is_ok());
+}
+
+#[test]
+#[ignore = "slow, run with `cargo test --release -- --ignored`"]
+fn bootstrap_gc_stress() {
+    let roots = &RootSet::default();
+    let cx = &mut Context::new(roots);
+    cx.set_gc_stress(true);
+    root!(env, new(Env), cx);
+    init(env, cx);
+    assert!(bootstrap(env, cx).is_ok());
+}
@@ -0,0 +1,185 @@
+//! Marker functions.
+use crate::core::{
+    env::Env,
+    gc::{Context, Rt},
+    object::{
+        Gc, IntOrMarker, LispBuffer, LispMarker, MarkerInner, MarkerLoc, Object, ObjectType,
+        WithLifetime,
+    },
+};
+use anyhow::Result;
+use rune_macros::defun;
+
+/// Remove `marker` from the buffer it points into, so that it points nowhere.
+pub(crate) fn unchain_marker(marker: &LispMarker, env: &mut Rt<Env>) {
+    if let Some(loc) = marker.loc() {
+        // If the buffer was killed, the marker has already been removed from it.
+        let _ = env.with_buffer_mut(loc.buffer, |b| b.text.remove_marker(&loc.marker));
+        marker.set_loc(None);
+    }
+}
+
+/// Point `marker` at the 1-based `position` in `buffer`. The position is
+/// clamped to the accessible range of the buffer.
+pub(crate) fn attach_marker(
+    marker: &LispMarker,
+    buffer: &LispBuffer,
+    position: i64,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    let pos = usize::try_from(position - 1).unwrap_or(0);
+    match marker.loc() {
+        Some(loc) if loc.buffer == buffer && loc.marker.position().is_some() => {
+            env.with_buffer_mut(buffer, |b| b.text.set_marker(&loc.marker, pos))?;
+        }
+        _ => {
+            unchain_marker(marker, env);
+            let advances = marker.insertion_type();
+            let text_marker = env.with_buffer_mut(buffer, |b| b.text.add_marker(pos, advances))?;
+            // SAFETY: buffers are allocated in the global block and never move
+            let buffer = unsafe { buffer.with_lifetime() };
+            marker.set_loc(Some(MarkerLoc { buffer, marker: text_marker }));
+        }
+    }
+    Ok(())
+}
+
+fn current_buffer<'ob>(env: &Rt<Env>, cx: &'ob Context) -> &'ob LispBuffer {
+    env.current_buffer.get().lisp_buffer(cx)
+}
+
+#[defun]
+pub(crate) fn make_marker() -> MarkerInner {
+    MarkerInner::new()
+}
+
+#[defun]
+pub(crate) fn marker_position(marker: &LispMarker) -> Option<usize> {
+    marker.position()
+}
+
+#[defun]
+pub(crate) fn marker_buffer(marker: &LispMarker) -> Option<&LispBuffer> {
+    marker.buffer()
+}
+
+#[defun]
+pub(crate) fn marker_insertion_type(marker: &LispMarker) -> bool {
+    marker.insertion_type()
+}
+
+#[defun]
+pub(crate) fn set_marker_insertion_type<'ob>(marker: &LispMarker, typ: Object<'ob>) -> Object<'ob> {
+    marker.set_insertion_type(!typ.is_nil());
+    typ
+}
+
+#[defun]
+pub(crate) fn set_marker<'ob>(
+    marker: &'ob LispMarker,
+    position: Option<IntOrMarker>,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispMarker> {
+    let Some(position) = position else {
+        unchain_marker(marker, env);
+        return Ok(marker);
+    };
+    let buffer = match buffer {
+        Some(buffer) => buffer.untag(),
+        None => current_buffer(env, cx),
+    };
+    attach_marker(marker, buffer, position.int(), env)?;
+    Ok(marker)
+}
+
+#[defun]
+pub(crate) fn copy_marker<'ob>(
+    marker: Option<Object<'ob>>,
+    typ: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispMarker> {
+    let new: &LispMarker = cx.add_as(MarkerInner::new()).untag();
+    new.set_insertion_type(typ.is_some_and(|x| !x.is_nil()));
+    let Some(marker) = marker else { return Ok(new) };
+    let buffer = match marker.untag() {
+        ObjectType::Marker(m) => match m.buffer() {
+            Some(buffer) => buffer,
+            // A copy of a marker that points nowhere also points nowhere
+            None => return Ok(new),
+        },
+        _ => current_buffer(env, cx),
+    };
+    let position = IntOrMarker::try_from(marker)?;
+    attach_marker(new, buffer, position.int(), env)?;
+    Ok(new)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::editfns::{delete_region, goto_char};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_marker_adjust() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let marker: &LispMarker = cx.add_as(make_marker()).untag();
+        assert_eq!(marker_position(marker), None);
+        assert!(marker_buffer(marker).is_none());
+        set_marker(marker, Some(7.into()), None, env, cx).unwrap();
+        assert_eq!(marker_position(marker), Some(7));
+        assert!(marker_buffer(marker).is_some());
+
+        goto_char(7.into(), env);
+        env.current_buffer.get_mut().text.insert("big ");
+        assert_eq!(marker_position(marker), Some(7));
+        set_marker_insertion_type(marker, crate::core::object::TRUE);
+        goto_char(cx.add_as(marker), env);
+        env.current_buffer.get_mut().text.insert("new ");
+        assert_eq!(env.current_buffer.get(), "hello new big world");
+        assert_eq!(marker_position(marker), Some(11));
+
+        delete_region(1.into(), 3.into(), env, cx).unwrap();
+        assert_eq!(marker_position(marker), Some(9));
+        delete_region(5.into(), 15.into(), env, cx).unwrap();
+        assert_eq!(marker_position(marker), Some(5));
+
+        let copy = copy_marker(Some(marker.into()), None, env, cx).unwrap();
+        assert_eq!(marker_position(copy), Some(5));
+        assert!(!marker_insertion_type(copy));
+
+        set_marker(marker, None, None, env, cx).unwrap();
+        assert_eq!(marker_position(marker), None);
+        assert_eq!(marker_position(copy), Some(5));
+
+        let copy = copy_marker(Some(marker.into()), None, env, cx).unwrap();
+        assert!(!std::ptr::eq(copy, marker));
+        assert_eq!(marker_position(copy), None);
+        assert!(marker_buffer(copy).is_none());
+    }
+
+    #[test]
+    fn test_dead_markers_are_removed() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let count = env.current_buffer.get().text.marker_count();
+        for _ in 0..3 {
+            for i in 0..100 {
+                let marker: &LispMarker = cx.add_as(make_marker()).untag();
+                set_marker(marker, Some(i.into()), None, env, cx).unwrap();
+            }
+            cx.garbage_collect(true);
+            env.current_buffer.get_mut().text.insert("!");
+            assert_eq!(env.current_buffer.get().text.marker_count(), count);
+        }
+    }
+}
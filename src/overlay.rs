@@ -0,0 +1,452 @@
+//! Overlay functions.
+use crate::core::{
+    cons::Cons,
+    env::{Env, sym},
+    gc::{Context, Rt},
+    object::{
+        Gc, IntOrMarker, IntoObject, LispBuffer, LispOverlay, ListType, NIL, Object, ObjectType,
+        OptionalFlag, OverlayInner, OverlayLoc, WithLifetime,
+    },
+};
+use crate::editfns::{point_max, point_min};
+use crate::fns::{copy_sequence, eq, slice_into_list};
+use crate::intervals::textget;
+use anyhow::Result;
+use rune_macros::defun;
+use std::cmp::Reverse;
+
+/// Remove `overlay` from the buffer it is in, so that it is in no buffer.
+pub(crate) fn detach_overlay(overlay: &LispOverlay, env: &mut Rt<Env>) {
+    if let Some(loc) = overlay.loc() {
+        // If the buffer was killed, the overlay has already been removed from it.
+        let _ = env.with_buffer_mut(loc.buffer, |b| {
+            b.text.remove_marker(&loc.start);
+            b.text.remove_marker(&loc.end);
+            b.overlays.remove(overlay);
+        });
+        overlay.set_loc(None);
+    }
+}
+
+/// Place `overlay` from the 1-based positions `beg` to `end` in `buffer`. The
+/// positions are swapped if they are out of order and clamped to the buffer.
+pub(crate) fn attach_overlay(
+    overlay: &LispOverlay,
+    buffer: &LispBuffer,
+    beg: i64,
+    end: i64,
+    env: &mut Rt<Env>,
+) -> Result<()> {
+    let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+    detach_overlay(overlay, env);
+    let (start, end) = env.with_buffer_mut(buffer, |b| {
+        let index = |pos: i64| usize::try_from(pos - 1).unwrap_or(0);
+        let start = b.text.add_marker(index(beg), overlay.front_advance());
+        let end = b.text.add_marker(index(end), overlay.rear_advance());
+        b.overlays.add(overlay);
+        (start, end)
+    })?;
+    // SAFETY: buffers are allocated in the global block and never move
+    let buffer = unsafe { buffer.with_lifetime() };
+    overlay.set_loc(Some(OverlayLoc { buffer, start, end }));
+    Ok(())
+}
+
+fn current_buffer<'ob>(env: &Rt<Env>, cx: &'ob Context) -> &'ob LispBuffer {
+    env.current_buffer.get().lisp_buffer(cx)
+}
+
+defsym!(PRIORITY);
+
+/// The priority of an overlay. Overlays without a priority have a priority of
+/// 0.
+fn priority(overlay: &LispOverlay) -> i64 {
+    match textget(overlay.plist(), sym::PRIORITY.into()).map(|x| x.untag()) {
+        Ok(ObjectType::Int(x)) => x,
+        _ => 0,
+    }
+}
+
+/// Sort `overlays` so that the ones that take precedence come first. Higher
+/// priorities take precedence, and if two overlays have the same priority the
+/// more specific one does.
+pub(crate) fn sort_overlays(overlays: &mut [&LispOverlay]) {
+    overlays.sort_by_cached_key(|x| {
+        let (start, end) = x.bounds().unwrap_or_default();
+        Reverse((priority(x), start, Reverse(end)))
+    });
+}
+
+/// The overlays in `buffer` that contain the 1-based position `pos`.
+pub(crate) fn overlays_at_pos<'ob>(
+    pos: i64,
+    buffer: &LispBuffer,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Vec<&'ob LispOverlay>> {
+    let Ok(index) = usize::try_from(pos - 1) else { return Ok(Vec::new()) };
+    env.with_buffer_mut(buffer, |b| b.overlays().at(index, cx))
+}
+
+fn copy_overlay<'ob>(
+    overlay: &LispOverlay,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispOverlay> {
+    let inner = OverlayInner::new(overlay.front_advance(), overlay.rear_advance());
+    let new: &LispOverlay = inner.into_obj(cx).untag();
+    new.set_plist(copy_sequence(overlay.plist(), cx)?)?;
+    if let Some((buffer, (start, end))) = overlay.buffer().zip(overlay.bounds()) {
+        attach_overlay(new, buffer, start as i64 + 1, end as i64 + 1, env)?;
+    }
+    Ok(new)
+}
+
+#[defun]
+fn make_overlay<'ob>(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    buffer: Option<Gc<&LispBuffer>>,
+    front_advance: OptionalFlag,
+    rear_advance: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispOverlay> {
+    let inner = OverlayInner::new(front_advance.is_some(), rear_advance.is_some());
+    let overlay: &LispOverlay = inner.into_obj(cx).untag();
+    let buffer = match buffer {
+        Some(buffer) => buffer.untag(),
+        None => current_buffer(env, cx),
+    };
+    attach_overlay(overlay, buffer, beg.int(), end.int(), env)?;
+    Ok(overlay)
+}
+
+#[defun]
+fn move_overlay<'ob>(
+    overlay: &'ob LispOverlay,
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    buffer: Option<Gc<&LispBuffer>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob LispOverlay> {
+    let buffer = match (buffer, overlay.buffer()) {
+        (Some(buffer), _) => buffer.untag(),
+        (None, Some(buffer)) => cx.bind(buffer),
+        (None, None) => current_buffer(env, cx),
+    };
+    attach_overlay(overlay, buffer, beg.int(), end.int(), env)?;
+    Ok(overlay)
+}
+
+#[defun]
+fn delete_overlay(overlay: &LispOverlay, env: &mut Rt<Env>) {
+    detach_overlay(overlay, env);
+}
+
+#[defun]
+fn overlay_start(overlay: &LispOverlay) -> Option<usize> {
+    overlay.start()
+}
+
+#[defun]
+fn overlay_end(overlay: &LispOverlay) -> Option<usize> {
+    overlay.end()
+}
+
+#[defun]
+fn overlay_buffer(overlay: &LispOverlay) -> Option<&LispBuffer> {
+    overlay.buffer()
+}
+
+#[defun]
+pub(crate) fn overlay_get<'ob>(
+    overlay: &'ob LispOverlay,
+    prop: Object<'ob>,
+) -> Result<Object<'ob>> {
+    textget(overlay.plist(), prop)
+}
+
+#[defun]
+fn overlay_put<'ob>(
+    overlay: &'ob LispOverlay,
+    prop: Object<'ob>,
+    value: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let plist = overlay.plist();
+    let mut conses = Gc::<ListType>::try_from(plist)?.conses();
+    while let Some(key) = conses.next() {
+        let Some(val) = conses.next() else { break };
+        if eq(key?.car(), prop) {
+            val?.set_car(value)?;
+            return Ok(value);
+        }
+    }
+    overlay.set_plist(Cons::new(prop, Cons::new(value, plist, cx), cx).into())?;
+    Ok(value)
+}
+
+#[defun]
+fn overlay_properties<'ob>(overlay: &'ob LispOverlay, cx: &'ob Context) -> Result<Object<'ob>> {
+    copy_sequence(overlay.plist(), cx)
+}
+
+#[defun]
+fn overlays_at<'ob>(
+    pos: IntOrMarker,
+    sorted: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let buffer = current_buffer(env, cx);
+    let mut overlays = overlays_at_pos(pos.int(), buffer, env, cx)?;
+    if sorted.is_some() {
+        sort_overlays(&mut overlays);
+    }
+    let overlays: Vec<Object> = overlays.into_iter().map(Into::into).collect();
+    Ok(slice_into_list(&overlays, None, cx))
+}
+
+/// The overlays in the current buffer that overlap the region from `beg` to
+/// `end`.
+fn overlays_in_region<'ob>(
+    beg: i64,
+    end: i64,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Vec<&'ob LispOverlay> {
+    let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+    let buffer = env.current_buffer.get_mut();
+    let (beg, end) = (usize::try_from(beg - 1).unwrap_or(0), usize::try_from(end - 1).unwrap_or(0));
+    let include_end = end >= buffer.zv();
+    buffer.overlays().overlapping(beg, end, include_end, cx)
+}
+
+#[defun]
+fn overlays_in<'ob>(
+    beg: IntOrMarker,
+    end: IntOrMarker,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Object<'ob> {
+    let overlays = overlays_in_region(beg.int(), end.int(), env, cx);
+    let overlays: Vec<Object> = overlays.into_iter().map(Into::into).collect();
+    slice_into_list(&overlays, None, cx)
+}
+
+#[defun]
+fn next_overlay_change(pos: IntOrMarker, env: &mut Rt<Env>) -> usize {
+    let max = point_max(env);
+    let index = usize::try_from(pos.int() - 1).unwrap_or(0);
+    match env.current_buffer.get_mut().overlays().next_change(index) {
+        Some(change) => (change + 1).min(max),
+        None => max,
+    }
+}
+
+/// Remove the overlays between `beg` and `end` whose property `name` has the
+/// value `val`. Overlays that extend outside of the region are moved or split
+/// so that they no longer cover it.
+#[defun]
+fn remove_overlays<'ob>(
+    beg: Option<IntOrMarker>,
+    end: Option<IntOrMarker>,
+    name: Option<Object<'ob>>,
+    val: Option<Object<'ob>>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let beg = beg.map_or(point_min(env) as i64, |x| x.int());
+    let end = end.map_or(point_max(env) as i64, |x| x.int());
+    let (beg, end) = if beg <= end { (beg, end) } else { (end, beg) };
+    let (name, val) = (name.unwrap_or(NIL), val.unwrap_or(NIL));
+    let buffer = current_buffer(env, cx);
+    for overlay in overlays_in_region(beg, end, env, cx) {
+        if !eq(overlay_get(overlay, name)?, val) {
+            continue;
+        }
+        let (Some(start), Some(stop)) = (overlay.start(), overlay.end()) else { continue };
+        let (start, stop) = (start as i64, stop as i64);
+        if start < beg {
+            if stop > end {
+                let copy = copy_overlay(overlay, env, cx)?;
+                attach_overlay(copy, buffer, end, stop, env)?;
+            }
+            attach_overlay(overlay, buffer, start, beg, env)?;
+        } else if stop > end {
+            attach_overlay(overlay, buffer, end, stop, env)?;
+        } else {
+            detach_overlay(overlay, env);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::core::object::TRUE;
+    use crate::editfns::{delete_region, goto_char};
+    use rune_core::macros::{list, root};
+
+    fn bounds(overlay: &LispOverlay) -> Option<(usize, usize)> {
+        overlay.start().zip(overlay.end())
+    }
+
+    #[test]
+    fn test_overlay_insertion() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let plain = make_overlay(3.into(), 6.into(), None, None, None, env, cx).unwrap();
+        let advance = make_overlay(3.into(), 6.into(), None, Some(()), Some(()), env, cx).unwrap();
+        let empty = make_overlay(8.into(), 8.into(), None, Some(()), None, env, cx).unwrap();
+
+        // Insert at the start of both overlays
+        goto_char(3.into(), env);
+        env.current_buffer.get_mut().text.insert("AB");
+        assert_eq!(bounds(plain), Some((3, 8)));
+        assert_eq!(bounds(advance), Some((5, 8)));
+
+        // Insert at the end of both overlays
+        goto_char(8.into(), env);
+        env.current_buffer.get_mut().text.insert("CD");
+        assert_eq!(bounds(plain), Some((3, 8)));
+        assert_eq!(bounds(advance), Some((5, 10)));
+
+        // An empty overlay stays empty
+        goto_char(12.into(), env);
+        assert_eq!(bounds(empty), Some((12, 12)));
+        env.current_buffer.get_mut().text.insert("E");
+        assert_eq!(bounds(empty), Some((12, 12)));
+
+        delete_region(1.into(), 6.into(), env, cx).unwrap();
+        assert_eq!(bounds(plain), Some((1, 3)));
+        assert_eq!(bounds(advance), Some((1, 5)));
+
+        delete_overlay(plain, env);
+        assert_eq!(bounds(plain), None);
+        assert!(overlay_buffer(plain).is_none());
+        move_overlay(plain, 2.into(), 1.into(), None, env, cx).unwrap();
+        assert_eq!(bounds(plain), Some((1, 2)));
+        assert!(overlay_buffer(plain).is_some());
+    }
+
+    #[test]
+    fn test_overlay_lookup() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let a = make_overlay(1.into(), 6.into(), None, None, None, env, cx).unwrap();
+        let b = make_overlay(4.into(), 9.into(), None, None, None, env, cx).unwrap();
+        let empty = make_overlay(10.into(), 10.into(), None, None, None, env, cx).unwrap();
+        let at = |pos: i64, env: &mut Rt<Env>| {
+            let mut overlays = overlays_at_pos(pos, current_buffer(env, cx), env, cx).unwrap();
+            overlays.sort_by_key(|x| x.start());
+            overlays.into_iter().map(|x| x as *const _).collect::<Vec<_>>()
+        };
+        let ptrs = |overlays: &[&LispOverlay]| -> Vec<*const LispOverlay> {
+            overlays.iter().map(|x| *x as *const _).collect()
+        };
+        assert_eq!(at(1, env), ptrs(&[a]));
+        assert_eq!(at(5, env), ptrs(&[a, b]));
+        assert_eq!(at(6, env), ptrs(&[b]));
+        assert_eq!(at(10, env), ptrs(&[]));
+
+        // Empty overlays are only found at the end of the region if it is the
+        // end of the buffer
+        let found = overlays_in_region(9, 10, env, cx);
+        assert_eq!(ptrs(&found), ptrs(&[]));
+        let found = overlays_in_region(10, 11, env, cx);
+        assert_eq!(ptrs(&found), ptrs(&[empty]));
+        let found = overlays_in_region(6, 9, env, cx);
+        assert_eq!(ptrs(&found), ptrs(&[b]));
+
+        assert_eq!(next_overlay_change(1.into(), env), 4);
+        assert_eq!(next_overlay_change(4.into(), env), 6);
+        assert_eq!(next_overlay_change(6.into(), env), 9);
+        assert_eq!(next_overlay_change(9.into(), env), 10);
+        assert_eq!(next_overlay_change(10.into(), env), 12);
+
+        // Changes to the text are reflected in the index
+        goto_char(1.into(), env);
+        env.current_buffer.get_mut().text.insert("xx");
+        assert_eq!(at(7, env), ptrs(&[a, b]));
+        assert_eq!(at(8, env), ptrs(&[b]));
+        assert_eq!(next_overlay_change(1.into(), env), 6);
+    }
+
+    #[test]
+    fn test_overlay_properties() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let overlay = make_overlay(1.into(), 6.into(), None, None, None, env, cx).unwrap();
+        let face = cx.add(crate::core::env::intern("face", cx));
+        overlay_put(overlay, face, cx.add("bold"), cx).unwrap();
+        overlay_put(overlay, sym::PRIORITY.into(), 3.into(), cx).unwrap();
+        overlay_put(overlay, face, cx.add("italic"), cx).unwrap();
+        assert_eq!(overlay_get(overlay, face).unwrap(), "italic");
+        assert_eq!(overlay_get(overlay, TRUE).unwrap(), NIL);
+        let props = overlay_properties(overlay, cx).unwrap();
+        assert_eq!(props, list![sym::PRIORITY, 3, face, "italic"; cx]);
+        // The buffer keeps the overlay and its properties alive
+        cx.garbage_collect(true);
+        let buffer = current_buffer(env, cx);
+        let overlays = overlays_at_pos(1, buffer, env, cx).unwrap();
+        let face = cx.add(crate::core::env::intern("face", cx));
+        assert_eq!(overlay_get(overlays[0], face).unwrap(), "italic");
+    }
+
+    #[test]
+    fn test_remove_overlays() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let outer = make_overlay(1.into(), 12.into(), None, None, None, env, cx).unwrap();
+        let inner = make_overlay(5.into(), 7.into(), None, None, None, env, cx).unwrap();
+        let end = make_overlay(6.into(), 10.into(), None, None, None, env, cx).unwrap();
+        let kept = make_overlay(5.into(), 7.into(), None, None, None, env, cx).unwrap();
+        overlay_put(kept, sym::PRIORITY.into(), 1.into(), cx).unwrap();
+        remove_overlays(Some(4.into()), Some(8.into()), Some(sym::PRIORITY.into()), None, env, cx)
+            .unwrap();
+        assert_eq!(bounds(outer), Some((1, 4)));
+        assert_eq!(bounds(inner), None);
+        assert_eq!(bounds(end), Some((8, 10)));
+        assert_eq!(bounds(kept), Some((5, 7)));
+        // The part of the outer overlay after the region is a copy
+        let split = overlays_in_region(8, 12, env, cx);
+        assert!(split.iter().any(|x| bounds(x) == Some((8, 12))));
+    }
+
+    #[test]
+    fn test_get_char_property() {
+        use crate::textprops::{get_char_property, put_text_property};
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("hello world");
+        let face = cx.add(crate::core::env::intern("face", cx));
+        put_text_property(1, 12, face, cx.add("text"), NIL, env, cx).unwrap();
+        let low = make_overlay(1.into(), 8.into(), None, None, None, env, cx).unwrap();
+        let high = make_overlay(3.into(), 6.into(), None, None, None, env, cx).unwrap();
+        overlay_put(low, face, cx.add("low"), cx).unwrap();
+        overlay_put(high, face, cx.add("high"), cx).unwrap();
+        overlay_put(high, sym::PRIORITY.into(), 5.into(), cx).unwrap();
+        assert_eq!(get_char_property(1, face, NIL, env, cx).unwrap(), "low");
+        assert_eq!(get_char_property(4, face, NIL, env, cx).unwrap(), "high");
+        assert_eq!(get_char_property(9, face, NIL, env, cx).unwrap(), "text");
+        // With equal priorities the more specific overlay wins
+        overlay_put(low, sym::PRIORITY.into(), 5.into(), cx).unwrap();
+        assert_eq!(get_char_property(4, face, NIL, env, cx).unwrap(), "high");
+        overlay_put(low, sym::PRIORITY.into(), 10.into(), cx).unwrap();
+        assert_eq!(get_char_property(4, face, NIL, env, cx).unwrap(), "low");
+    }
+}
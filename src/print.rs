@@ -1,6 +1,732 @@
-//! Printing utilities.
-use crate::core::object::Object;
+//! Printing lisp objects.
+use crate::arith::{NAN_PAYLOAD_MASK, parse_number};
+use crate::core::{
+    cons::Cons,
+    env::{Env, sym},
+    gc::{Context, Rt, Rto},
+    object::{
+        Function, HashTest, LispBuffer, LispMarker, LispString, NIL, Object, ObjectType,
+        OpenBuffer, TRUE,
+    },
+};
+use crate::editfns::insert_at_point;
+use anyhow::{Result, bail};
+use rune_core::hashmap::{HashMap, HashSet};
+use rune_core::macros::{call, root};
 use rune_macros::defun;
+use std::cell::Cell;
+use std::fmt::{self, Display, Write as _};
+use std::io::Write as _;
+
+/// Options that control how objects are printed. These are read from the
+/// `print-*` variables.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PrintOptions {
+    /// Print objects so that they can be read back by the reader (`prin1`)
+    /// instead of for humans (`princ`).
+    pub(crate) escape: bool,
+    pub(crate) length: Option<usize>,
+    pub(crate) level: Option<usize>,
+    pub(crate) escape_newlines: bool,
+    pub(crate) quoted: bool,
+    /// Label objects that appear more than once with `#N=` and `#N#`.
+    pub(crate) circle: bool,
+    /// Print uninterned symbols with a `#:` prefix.
+    pub(crate) gensym: bool,
+    pub(crate) float_format: Option<String>,
+}
+
+impl PrintOptions {
+    pub(crate) fn new(escape: bool, env: &Rt<Env>, cx: &Context) -> Self {
+        let limit = |var| match env.var(var, cx).map(|x| x.untag()) {
+            Some(ObjectType::Int(x)) => usize::try_from(x).ok(),
+            _ => None,
+        };
+        let is_set = |var| env.var(var, cx).is_some_and(|x| !x.is_nil());
+        Self {
+            escape,
+            length: limit(sym::PRINT_LENGTH),
+            level: limit(sym::PRINT_LEVEL),
+            escape_newlines: is_set(sym::PRINT_ESCAPE_NEWLINES),
+            quoted: is_set(sym::PRINT_QUOTED),
+            circle: is_set(sym::PRINT_CIRCLE),
+            gensym: is_set(sym::PRINT_GENSYM),
+            float_format: float_output_format(env, cx),
+        }
+    }
+}
+
+/// The value of `float-output-format`, if it is a string.
+fn float_output_format(env: &Rt<Env>, cx: &Context) -> Option<String> {
+    match env.var(sym::FLOAT_OUTPUT_FORMAT, cx).map(|x| x.untag()) {
+        Some(ObjectType::String(x)) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
+/// Format a float the way the printer would, using `float-output-format`.
+pub(crate) fn float_to_string(x: f64, env: &Rt<Env>, cx: &Context) -> String {
+    format_float(x, float_output_format(env, cx).as_deref())
+}
+
+/// An object that is displayed the way the lisp printer would print it.
+pub(crate) struct Printed<'a, 'ob> {
+    object: Object<'ob>,
+    options: &'a PrintOptions,
+}
+
+impl<'a, 'ob> Printed<'a, 'ob> {
+    pub(crate) fn new(object: Object<'ob>, options: &'a PrintOptions) -> Self {
+        Self { object, options }
+    }
+}
+
+impl Display for Printed<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let labels =
+            if self.options.circle { find_shared(self.object) } else { HashMap::default() };
+        let mut printer =
+            Printer { options: self.options, being_printed: Vec::new(), labels, next_label: 1 };
+        printer.print(self.object, f)
+    }
+}
+
+struct Printer<'a, 'ob> {
+    options: &'a PrintOptions,
+    /// The containers that we are currently inside of. This is used to detect
+    /// cycles and to limit the depth with `print-level`.
+    being_printed: Vec<Object<'ob>>,
+    /// Objects that appear more than once when `print-circle` is set, and the
+    /// label they were given when first printed.
+    labels: HashMap<*const u8, Option<usize>>,
+    next_label: usize,
+}
+
+impl<'ob> Printer<'_, 'ob> {
+    fn print(&mut self, obj: Object<'ob>, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(label) = container_ptr(obj).and_then(|ptr| self.labels.get_mut(&ptr)) {
+            match label {
+                Some(label) => return write!(f, "#{label}#"),
+                None => {
+                    *label = Some(self.next_label);
+                    write!(f, "#{}=", self.next_label)?;
+                    self.next_label += 1;
+                }
+            }
+        }
+        match obj.untag() {
+            ObjectType::Int(x) => write!(f, "{x}"),
+            ObjectType::BigInt(x) => write!(f, "{x}"),
+            ObjectType::Float(x) => f.write_str(&format_float(**x, self.float_format())),
+            ObjectType::Symbol(x) => {
+                if self.options.escape && self.options.gensym && !x.interned() {
+                    f.write_str("#:")?;
+                    if x.name().is_empty() {
+                        return Ok(());
+                    }
+                }
+                self.print_symbol(x.name(), f)
+            }
+            ObjectType::String(x) if self.options.escape => self.print_propertized(x, f),
+            ObjectType::String(x) => self.print_string(x, f),
+            ObjectType::ByteString(x) if self.options.escape => print_bytes(x, f),
+            ObjectType::ByteString(x) => write!(f, "{x}"),
+            ObjectType::Cons(x) => self.print_list(x, f),
+            ObjectType::Vec(x) => {
+                let items = x.iter().map(|x| x.get());
+                self.print_seq(obj, "[", items, "]", f)
+            }
+            ObjectType::Record(x) => {
+                let items = x.iter().map(|x| x.get());
+                self.print_seq(obj, "#s(", items, ")", f)
+            }
+            ObjectType::HashTable(x) => {
+                if !self.enter(obj, f)? {
+                    return Ok(());
+                }
+                f.write_str("#s(hash-table ")?;
+                if x.test() != HashTest::Eql {
+                    write!(f, "test {} ", x.test().name())?;
+                }
+                f.write_str("data (")?;
+                for i in 0..x.len() {
+                    let Some((key, value)) = x.get_index(i) else { break };
+                    if i != 0 {
+                        f.write_char(' ')?;
+                    }
+                    if self.options.length.is_some_and(|max| i >= max) {
+                        f.write_str("...")?;
+                        break;
+                    }
+                    self.print(key, f)?;
+                    f.write_char(' ')?;
+                    self.print(value, f)?;
+                }
+                self.being_printed.pop();
+                f.write_str("))")
+            }
+            ObjectType::ByteFn(x) => {
+                write!(f, "#[{} ", x.args.into_arg_spec())?;
+                print_bytes(x.codes(), f)?;
+                f.write_char(' ')?;
+                self.print_seq(obj, "[", x.consts().iter().copied(), "]", f)?;
+                write!(f, " {}]", x.depth)
+            }
+            ObjectType::Buffer(x) => self.print_buffer(x, f),
+            ObjectType::SubrFn(x) => write!(f, "{x}"),
+            ObjectType::CharTable(x) => write!(f, "{x}"),
+            ObjectType::Marker(x) => write!(f, "{x}"),
+            ObjectType::Overlay(x) => write!(f, "{x}"),
+        }
+    }
+
+    fn is_labelled(&self, obj: Object) -> bool {
+        container_ptr(obj).is_some_and(|ptr| self.labels.contains_key(&ptr))
+    }
+
+    fn float_format(&self) -> Option<&str> {
+        self.options.float_format.as_deref()
+    }
+
+    /// Start printing the container `obj`. Returns false if it should not be
+    /// printed, either because it is part of a cycle or because it is nested
+    /// deeper than `print-level`. In that case a placeholder has already been
+    /// written.
+    fn enter(&mut self, obj: Object<'ob>, f: &mut fmt::Formatter) -> Result<bool, fmt::Error> {
+        if let Some(idx) = self.being_printed.iter().position(|x| x.ptr_eq(obj)) {
+            write!(f, "#{idx}")?;
+            return Ok(false);
+        }
+        if self.options.level.is_some_and(|level| self.being_printed.len() >= level) {
+            f.write_str("...")?;
+            return Ok(false);
+        }
+        self.being_printed.push(obj);
+        Ok(true)
+    }
+
+    fn print_seq(
+        &mut self,
+        obj: Object<'ob>,
+        open: &str,
+        items: impl Iterator<Item = Object<'ob>>,
+        close: &str,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        if !self.enter(obj, f)? {
+            return Ok(());
+        }
+        f.write_str(open)?;
+        for (i, item) in items.enumerate() {
+            if i != 0 {
+                f.write_char(' ')?;
+            }
+            if self.options.length.is_some_and(|max| i >= max) {
+                f.write_str("...")?;
+                break;
+            }
+            self.print(item, f)?;
+        }
+        self.being_printed.pop();
+        f.write_str(close)
+    }
+
+    fn print_list(&mut self, cons: &'ob Cons, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.options.quoted {
+            if let Some((prefix, quoted)) = quote_prefix(cons) {
+                f.write_str(prefix)?;
+                return self.print(quoted, f);
+            }
+        }
+
+        let obj: Object = cons.into();
+        if !self.enter(obj, f)? {
+            return Ok(());
+        }
+        f.write_char('(')?;
+        // Detect cycles in the tail of the list by keeping a pointer that
+        // moves at half speed.
+        let mut slow = cons;
+        let mut slow_idx = 0;
+        let mut cons = cons;
+        let mut i = 0;
+        loop {
+            if self.options.length.is_some_and(|max| i >= max) {
+                f.write_str("...")?;
+                break;
+            }
+            self.print(cons.car(), f)?;
+            i += 1;
+            match cons.cdr().untag() {
+                // A shared tail has to be printed with its label
+                ObjectType::Cons(tail) if self.is_labelled(tail.into()) => {
+                    f.write_str(" . ")?;
+                    self.print(cons.cdr(), f)?;
+                    break;
+                }
+                ObjectType::Cons(tail) => {
+                    if i % 2 == 0 {
+                        let ObjectType::Cons(next) = slow.cdr().untag() else { unreachable!() };
+                        slow = next;
+                        slow_idx += 1;
+                    }
+                    if std::ptr::eq(tail, slow) {
+                        write!(f, " . #{slow_idx}")?;
+                        break;
+                    }
+                    cons = tail;
+                    f.write_char(' ')?;
+                }
+                ObjectType::NIL => break,
+                _ => {
+                    f.write_str(" . ")?;
+                    self.print(cons.cdr(), f)?;
+                    break;
+                }
+            }
+        }
+        self.being_printed.pop();
+        f.write_char(')')
+    }
+
+    fn print_string(&self, string: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.options.escape {
+            return f.write_str(string);
+        }
+        f.write_char('"')?;
+        for c in string.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{c}")?,
+                '\n' if self.options.escape_newlines => f.write_str("\\n")?,
+                '\x0c' if self.options.escape_newlines => f.write_str("\\f")?,
+                c => f.write_char(c)?,
+            }
+        }
+        f.write_char('"')
+    }
+
+    /// Print a string along with its text properties, as
+    /// `#("foo" 0 3 (face bold))`.
+    fn print_propertized(
+        &mut self,
+        string: &'ob LispString,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        let intervals: Vec<_> = match string.textprops() {
+            Some(props) => {
+                props.iter(0, string.len()).filter(|(_, plist)| !plist.is_nil()).collect()
+            }
+            None => Vec::new(),
+        };
+        if intervals.is_empty() {
+            return self.print_string(string, f);
+        }
+        f.write_str("#(")?;
+        self.print_string(string, f)?;
+        for (range, plist) in intervals {
+            write!(f, " {} {} ", range.start, range.end)?;
+            self.print(plist, f)?;
+        }
+        f.write_char(')')
+    }
+
+    fn print_symbol(&self, name: &str, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.options.escape {
+            return f.write_str(name);
+        }
+        if name.is_empty() {
+            return f.write_str("##");
+        }
+        // Symbols that would be read as a number need to be escaped.
+        if parse_number(name).is_some() || name == "." {
+            f.write_char('\\')?;
+        }
+        for (i, c) in name.chars().enumerate() {
+            let special =
+                matches!(c, '"' | '\\' | '\'' | ';' | '#' | '(' | ')' | ',' | '`' | '[' | ']');
+            if special || c.is_whitespace() || c.is_control() || (c == '?' && i == 0) {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        Ok(())
+    }
+
+    fn print_buffer(&self, buffer: &LispBuffer, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.options.escape {
+            return buffer.fmt_name(f);
+        }
+        f.write_str("#<buffer ")?;
+        buffer.fmt_name(f)?;
+        f.write_char('>')
+    }
+}
+
+/// Print a unibyte string, with octal escapes for bytes that are not ASCII.
+fn print_bytes(bytes: &[u8], f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_char('"')?;
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(f, "\\{}", char::from(*byte))?,
+            x if x.is_ascii() => f.write_char(char::from(*x))?,
+            x => write!(f, "\\{x:03o}")?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// The address of `obj` if it is an object that can contain other objects.
+fn container_ptr(obj: Object) -> Option<*const u8> {
+    match obj.untag() {
+        ObjectType::Cons(x) => Some(std::ptr::from_ref(x).cast()),
+        ObjectType::Vec(x) => Some(std::ptr::from_ref(x).cast()),
+        ObjectType::Record(x) => Some(std::ptr::from_ref(x).cast()),
+        ObjectType::HashTable(x) => Some(std::ptr::from_ref(x).cast()),
+        _ => None,
+    }
+}
+
+/// Find the containers that are reachable more than once from `obj`. These
+/// are the objects that need labels for `print-circle`.
+fn find_shared(obj: Object) -> HashMap<*const u8, Option<usize>> {
+    let mut seen = HashSet::default();
+    let mut shared = HashMap::default();
+    let mut stack = vec![obj];
+    while let Some(obj) = stack.pop() {
+        let Some(ptr) = container_ptr(obj) else { continue };
+        if !seen.insert(ptr) {
+            shared.insert(ptr, None);
+            continue;
+        }
+        match obj.untag() {
+            ObjectType::Cons(x) => {
+                stack.push(x.cdr());
+                stack.push(x.car());
+            }
+            ObjectType::Vec(x) => stack.extend(x.iter().map(|x| x.get())),
+            ObjectType::Record(x) => stack.extend(x.iter().map(|x| x.get())),
+            ObjectType::HashTable(x) => {
+                for i in 0..x.len() {
+                    if let Some((key, value)) = x.get_index(i) {
+                        stack.push(key);
+                        stack.push(value);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    shared
+}
+
+/// If `cons` is a quoted form like `(quote x)`, return the reader syntax for
+/// it and the quoted object.
+fn quote_prefix(cons: &Cons) -> Option<(&'static str, Object<'_>)> {
+    let ObjectType::Cons(rest) = cons.cdr().untag() else { return None };
+    if !rest.cdr().is_nil() {
+        return None;
+    }
+    let prefix = match cons.car().untag() {
+        ObjectType::Symbol(sym::QUOTE) => "'",
+        ObjectType::Symbol(sym::FUNCTION) => "#'",
+        ObjectType::Symbol(sym::BACKQUOTE) => "`",
+        ObjectType::Symbol(sym::UNQUOTE) => ",",
+        ObjectType::Symbol(sym::SPLICE) => ",@",
+        _ => return None,
+    };
+    Some((prefix, rest.car()))
+}
+
+/// Format a float the same way as Emacs. `format` is the value of
+/// `float-output-format`.
+fn format_float(x: f64, format: Option<&str>) -> String {
+    if x.is_infinite() {
+        return if x < 0.0 { "-1.0e+INF" } else { "1.0e+INF" }.to_owned();
+    }
+    if x.is_nan() {
+        let sign = if x.is_sign_negative() { "-" } else { "" };
+        return format!("{sign}{}.0e+NaN", x.to_bits() & NAN_PAYLOAD_MASK);
+    }
+    let (mut string, needs_point) = match format.and_then(parse_float_format) {
+        Some((precision, conversion)) => {
+            let needs_point = conversion != 'f' || precision != Some(0);
+            (format_float_with(x, precision, conversion, false), needs_point)
+        }
+        None => (format_float_shortest(x), true),
+    };
+    // Make sure the float will be read back as a float, and not as an integer
+    if needs_point {
+        match string
+            .trim_start_matches(|c: char| c.is_ascii_digit() || c == '-')
+            .chars()
+            .next()
+        {
+            None => string.push_str(".0"),
+            Some('.') if string.ends_with('.') => string.push('0'),
+            _ => {}
+        }
+    }
+    string
+}
+
+/// Parse a `float-output-format` of the form `%.PRECISIONc`, where `c` is one
+/// of `e`, `f` or `g` and the precision is optional.
+fn parse_float_format(format: &str) -> Option<(Option<usize>, char)> {
+    let spec = format.strip_prefix('%')?;
+    let (precision, conversion) = match spec.strip_prefix('.') {
+        Some(rest) => {
+            let digits = rest.len().checked_sub(1)?;
+            (Some(rest.get(..digits)?.parse().unwrap_or(0)), rest.get(digits..)?)
+        }
+        None => (None, spec),
+    };
+    match conversion {
+        "e" | "f" | "g" => Some((precision, conversion.chars().next().unwrap())),
+        _ => None,
+    }
+}
+
+/// Format a float like C's `printf` with the given precision and conversion.
+/// `alternate` is the `#` flag, which always includes a decimal point and
+/// keeps trailing zeros for `%g`.
+pub(crate) fn format_float_with(
+    x: f64,
+    precision: Option<usize>,
+    conversion: char,
+    alternate: bool,
+) -> String {
+    let precision = precision.unwrap_or(6);
+    let strip =
+        |string: &str| if alternate { string.to_owned() } else { strip_zeros(string).to_owned() };
+    let mut string = match conversion {
+        'f' => format!("{x:.precision$}"),
+        'e' => c_exponent(&format!("{x:.precision$e}")),
+        _ => {
+            let precision = precision.max(1);
+            let exp = exponent(&format!("{x:.0$e}", precision - 1));
+            if exp < -4 || exp >= precision as i32 {
+                let string = format!("{x:.0$e}", precision - 1);
+                let (mantissa, exp) = string.split_once('e').unwrap();
+                c_exponent(&format!("{}e{exp}", strip(mantissa)))
+            } else {
+                let decimals = (precision as i32 - 1 - exp) as usize;
+                strip(&format!("{x:.decimals$}"))
+            }
+        }
+    };
+    if alternate && !string.contains('.') {
+        let point = string.find('e').unwrap_or(string.len());
+        string.insert(point, '.');
+    }
+    string
+}
+
+/// Format a float with the fewest digits needed to read it back. Like Emacs,
+/// large and small numbers use exponential notation.
+fn format_float_shortest(x: f64) -> String {
+    let string = format!("{x:e}");
+    let (mantissa, _) = string.split_once('e').unwrap();
+    let digits = mantissa.chars().filter(char::is_ascii_digit).count();
+    let exp = exponent(&string);
+    if exp < -4 || exp >= digits.max(15) as i32 {
+        c_exponent(&string)
+    } else {
+        format!("{x}")
+    }
+}
+
+fn exponent(string: &str) -> i32 {
+    string.split_once('e').unwrap().1.parse().unwrap()
+}
+
+/// Convert Rust exponential notation (`1.5e3`) to C notation (`1.5e+03`).
+fn c_exponent(string: &str) -> String {
+    let (mantissa, _) = string.split_once('e').unwrap();
+    let exp = exponent(string);
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{mantissa}e{sign}{:02}", exp.abs())
+}
+
+/// Remove trailing zeros after the decimal point, like C's `%g`.
+fn strip_zeros(string: &str) -> &str {
+    if string.contains('.') {
+        string.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        string
+    }
+}
+
+thread_local! {
+    /// True if the last thing written to stdout was a newline.
+    static STDOUT_AT_LINE_START: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Where printed output is sent.
+enum Stream<'ob> {
+    Stdout,
+    Buffer(&'ob LispBuffer),
+    Marker(&'ob LispMarker),
+    Function(Function<'ob>),
+}
+
+impl<'ob> Stream<'ob> {
+    /// Find the stream for `printcharfun`, defaulting to `standard-output`.
+    fn new(printcharfun: Option<&Rto<Object>>, env: &Rt<Env>, cx: &'ob Context) -> Result<Self> {
+        let stream = match printcharfun.map(|x| x.bind(cx)) {
+            Some(stream) if !stream.is_nil() => stream,
+            _ => env.var(sym::STANDARD_OUTPUT, cx).unwrap_or(TRUE),
+        };
+        Ok(match stream.untag() {
+            ObjectType::TRUE | ObjectType::NIL => Stream::Stdout,
+            ObjectType::Buffer(buffer) => Stream::Buffer(buffer),
+            ObjectType::Marker(marker) => Stream::Marker(marker),
+            _ => Stream::Function(stream.try_into()?),
+        })
+    }
+
+    /// True if the stream is at the start of a line.
+    fn at_line_start(&self, env: &Rt<Env>) -> Result<bool> {
+        let line_start =
+            |b: &OpenBuffer, pos: usize| pos == 0 || b.text.char_at(pos - 1) == Some('\n');
+        match self {
+            Stream::Stdout => Ok(STDOUT_AT_LINE_START.get()),
+            Stream::Buffer(buffer) => {
+                env.with_buffer(buffer, |b| line_start(b, b.text.cursor().chars()))
+            }
+            Stream::Marker(marker) => {
+                let (Some(buffer), Some(pos)) = (marker.buffer(), marker.position()) else {
+                    bail!("Marker does not point anywhere")
+                };
+                env.with_buffer(buffer, |b| line_start(b, pos - 1))
+            }
+            Stream::Function(_) => Ok(false),
+        }
+    }
+}
+
+/// Send `text` to the output stream `printcharfun`.
+pub(crate) fn write_to_stream(
+    text: &str,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<()> {
+    match Stream::new(printcharfun, env, cx)? {
+        Stream::Stdout => {
+            print!("{text}");
+            std::io::stdout().flush()?;
+            if let Some(last) = text.chars().last() {
+                STDOUT_AT_LINE_START.set(last == '\n');
+            }
+        }
+        Stream::Buffer(buffer) => {
+            let text = cx.add(text);
+            let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+            env.with_buffer_mut(buffer, |b| {
+                insert_at_point(b, text, false, inhibit_read_only, None, cx)
+            })??;
+        }
+        Stream::Marker(marker) => {
+            let (Some(buffer), Some(pos)) = (marker.buffer(), marker.position()) else {
+                bail!("Marker does not point anywhere")
+            };
+            let text = cx.add(text);
+            let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+            // Insert at the marker and move it past the output. Point stays
+            // where it was, relative to the text around it.
+            let pos = pos - 1;
+            let end = env.with_buffer_mut(buffer, |b| {
+                if pos < b.begv() || pos > b.zv() {
+                    bail!("Marker is outside the accessible part of the buffer")
+                }
+                let point = b.text.cursor().chars();
+                b.text.set_cursor(pos);
+                insert_at_point(b, text, false, inhibit_read_only, None, cx)?;
+                let end = b.text.cursor().chars();
+                b.text.set_cursor(if point >= pos { point + end - pos } else { point });
+                Ok(end)
+            })??;
+            crate::marker::attach_marker(marker, buffer, end as i64 + 1, env)?;
+        }
+        Stream::Function(func) => {
+            root!(func, cx);
+            for c in text.chars() {
+                call!(func, c as i64; env, cx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn print_to_string(object: Object, escape: bool, env: &Rt<Env>, cx: &Context) -> String {
+    let options = PrintOptions::new(escape, env, cx);
+    Printed::new(object, &options).to_string()
+}
+
+#[defun]
+fn prin1<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    _overrides: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let text = print_to_string(object.bind(cx), true, env, cx);
+    write_to_stream(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn princ<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let text = print_to_string(object.bind(cx), false, env, cx);
+    write_to_stream(&text, printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn print<'ob>(
+    object: &Rto<Object>,
+    printcharfun: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &'ob mut Context,
+) -> Result<Object<'ob>> {
+    let text = print_to_string(object.bind(cx), true, env, cx);
+    write_to_stream(&format!("\n{text}\n"), printcharfun, env, cx)?;
+    Ok(object.bind(cx))
+}
+
+#[defun]
+fn terpri(
+    printcharfun: Option<&Rto<Object>>,
+    ensure: Option<&Rto<Object>>,
+    env: &mut Rt<Env>,
+    cx: &mut Context,
+) -> Result<bool> {
+    if ensure.is_some_and(|x| !x.bind(cx).is_nil())
+        && Stream::new(printcharfun, env, cx)?.at_line_start(env)?
+    {
+        return Ok(false);
+    }
+    write_to_stream("\n", printcharfun, env, cx)?;
+    Ok(true)
+}
+
+#[defun]
+fn prin1_to_string(
+    object: Object,
+    noescape: Option<Object>,
+    _overrides: Option<Object>,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> String {
+    let escape = noescape.is_none_or(|x| x.is_nil());
+    print_to_string(object, escape, env, cx)
+}
 
 #[defun]
 fn error_message_string(obj: Object) -> String {
@@ -11,3 +737,152 @@ fn error_message_string(obj: Object) -> String {
 defvar!(PRINT_LENGTH);
 defvar!(PRINT_LEVEL);
 defvar_bool!(PRINT_ESCAPE_NEWLINES, false);
+defvar_bool!(PRINT_QUOTED, true);
+defvar_bool!(PRINT_CIRCLE, false);
+defvar_bool!(PRINT_GENSYM, false);
+defvar!(FLOAT_OUTPUT_FORMAT);
+defvar!(STANDARD_OUTPUT, true);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::RootSet;
+    use crate::reader::read;
+
+    fn check(input: &str, expect: &str, options: &PrintOptions) {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        let (obj, _) = read(input, cx).unwrap();
+        assert_eq!(Printed::new(obj, options).to_string(), expect);
+    }
+
+    #[test]
+    fn test_prin1() {
+        let opts = PrintOptions { escape: true, quoted: true, ..PrintOptions::default() };
+        check("(1 \"a\\\"b\" foo)", "(1 \"a\\\"b\" foo)", &opts);
+        check("(quote x)", "'x", &opts);
+        check("(function (a . b))", "#'(a . b)", &opts);
+        check("(quote x y)", "(quote x y)", &opts);
+        check("[1 [2] (3)]", "[1 [2] (3)]", &opts);
+        check("\\1", "\\1", &opts);
+        check("a\\ b", "a\\ b", &opts);
+        let opts = PrintOptions { quoted: false, ..opts };
+        check("'x", "(quote x)", &opts);
+    }
+
+    #[test]
+    fn test_princ() {
+        let opts = PrintOptions::default();
+        check("(\"a\\\"b\" a\\ b)", "(a\"b a b)", &opts);
+        check("#(\"foo\" 0 3 (face bold))", "foo", &opts);
+    }
+
+    #[test]
+    fn test_print_limits() {
+        let opts = PrintOptions { length: Some(2), ..PrintOptions::default() };
+        check("(1 2 3)", "(1 2 ...)", &opts);
+        check("[1 2 3]", "[1 2 ...]", &opts);
+        check("(1 2)", "(1 2)", &opts);
+        let opts = PrintOptions { level: Some(1), ..PrintOptions::default() };
+        check("(1 (2) [3])", "(1 ... ...)", &opts);
+        let opts = PrintOptions { escape: true, escape_newlines: true, ..PrintOptions::default() };
+        check("\"a\nb\"", "\"a\\nb\"", &opts);
+    }
+
+    #[test]
+    fn test_print_circle() {
+        let opts = PrintOptions::default();
+        check("#1=(a . #1#)", "(a . #0)", &opts);
+        check("(#1=(a) #1#)", "((a) (a))", &opts);
+        let opts = PrintOptions { circle: true, ..PrintOptions::default() };
+        check("#1=(a . #1#)", "#1=(a . #1#)", &opts);
+        check("#1=(a b . #1#)", "#1=(a b . #1#)", &opts);
+        check("(a . #1=(b #1#))", "(a . #1=(b #1#))", &opts);
+        check("(#1=(a) #1# #2=[#2#])", "(#1=(a) #1# #2=[#2#])", &opts);
+        check("((a) (a))", "((a) (a))", &opts);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let opts = PrintOptions { escape: true, gensym: true, ..PrintOptions::default() };
+        check("#s(foo 1 \"a\")", "#s(foo 1 \"a\")", &opts);
+        check("#s(hash-table data (a 1 b (2)))", "#s(hash-table data (a 1 b (2)))", &opts);
+        check("#s(hash-table size 3 test eq data ())", "#s(hash-table test eq data ())", &opts);
+        check("#s(hash-table test eql data (1 2))", "#s(hash-table data (1 2))", &opts);
+        check("#[257 \"\\300\\207\" [1 foo] 2]", "#[257 \"\\300\\207\" [1 foo] 2]", &opts);
+        check("#[0 \"\\300\\207\" [] 1 \"doc\"]", "#[0 \"\\300\\207\" [] 1]", &opts);
+        check("#(\"foo\" 0 3 (face bold))", "#(\"foo\" 0 3 (face bold))", &opts);
+        check("#(\"foo\" 1 2 (a 1) 2 3 (b 2))", "#(\"foo\" 1 2 (a 1) 2 3 (b 2))", &opts);
+        check("#(\"foo\" 0 3 nil)", "\"foo\"", &opts);
+        check("#:foo", "#:foo", &opts);
+        check("#:", "#:", &opts);
+        check("##", "##", &opts);
+        check("#_foo", "foo", &opts);
+        check("(a #@4 xyz b)", "(a b)", &opts);
+        check("-18446744073709551616", "-18446744073709551616", &opts);
+        check("#x10000000000000000", "18446744073709551616", &opts);
+        check("\\18446744073709551616", "\\18446744073709551616", &opts);
+        check("\\1.", "\\1.", &opts);
+        check("\\1e3", "\\1e3", &opts);
+        check("1.e", "1.e", &opts);
+        check("inf", "inf", &opts);
+    }
+
+    #[test]
+    fn test_format_float() {
+        assert_eq!(format_float(1.0, None), "1.0");
+        assert_eq!(format_float(-0.0, None), "-0.0");
+        assert_eq!(format_float(0.5, None), "0.5");
+        assert_eq!(format_float(1e14, None), "100000000000000.0");
+        assert_eq!(format_float(1e15, None), "1e+15");
+        assert_eq!(format_float(1.5e-5, None), "1.5e-05");
+        assert_eq!(format_float(0.0001, None), "0.0001");
+        assert_eq!(format_float(f64::INFINITY, None), "1.0e+INF");
+        assert_eq!(format_float(f64::NAN, None), "0.0e+NaN");
+        assert_eq!(format_float(-f64::from_bits(f64::NAN.to_bits() | 7), None), "-7.0e+NaN");
+        assert_eq!(format_float(1.0, Some("%.3f")), "1.000");
+        assert_eq!(format_float(1.0, Some("%.0f")), "1");
+        assert_eq!(format_float(1234.5, Some("%.2e")), "1.23e+03");
+        assert_eq!(format_float(2.0, Some("%g")), "2.0");
+        assert_eq!(format_float(0.000_012_5, Some("%g")), "1.25e-05");
+        assert_eq!(format_float(3.25, Some("%.10g")), "3.25");
+        assert_eq!(format_float(1.0, Some("bad")), "1.0");
+    }
+
+    #[test]
+    fn test_print_streams() {
+        use crate::interpreter::assert_lisp;
+        // Output to a buffer is inserted like `insert`
+        assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"print-buffer\"))
+                    (insert \"ab\")
+                    (put-text-property 2 3 'face 'bold nil)
+                    (let ((end (copy-marker 3)))
+                      (goto-char 2)
+                      (princ 'x (current-buffer))
+                      (list (buffer-substring 1 4) (point) (marker-position end)
+                            (get-text-property 3 'face nil))))",
+            "(\"axb\" 3 4 bold)",
+        );
+        // Output to a marker is inserted at it and moves it past the output
+        assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"print-marker\"))
+                    (insert \"abc\")
+                    (let ((m (copy-marker 2)))
+                      (prin1 12 m)
+                      (list (buffer-substring 1 6) (marker-position m) (point))))",
+            "(\"a12bc\" 4 6)",
+        );
+        assert_lisp(
+            "(progn (set-buffer (get-buffer-create \"print-read-only\"))
+                    (insert \"abc\")
+                    (put-text-property 1 3 'read-only t nil)
+                    (list (condition-case nil (princ 'x (copy-marker 2))
+                            (text-read-only 'read-only))
+                          (progn (narrow-to-region 3 4)
+                                 (condition-case nil (princ 'x (copy-marker 1)) (error 'error)))
+                          (progn (widen) (buffer-substring 1 4))))",
+            "(read-only error \"abc\")",
+        );
+    }
+}
@@ -1,14 +1,21 @@
 //! Lisp reader that reads an object from a string.
+use crate::arith::{parse_integer, parse_number};
 use crate::core::{
+    cons::Cons,
     env::{intern, sym},
     gc::Context,
-    object::{Object, Symbol},
+    object::{
+        ByteFn, CharTable, CharTableInner, FnArgs, HashTable, HashTest, IntoObject, NIL, Object,
+        ObjectType, RecordBuilder, Symbol, TRUE,
+    },
 };
 use crate::fns;
+use rune_core::hashmap::{HashMap, HashSet};
 use rune_core::macros::list;
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::str;
-use std::{fmt, iter::Peekable, str::CharIndices};
+use std::{fmt, iter::Peekable, str::CharIndices, str::Chars};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -25,6 +32,8 @@ pub(crate) enum Error {
     UnexpectedChar(char, usize),
     UnknownMacroCharacter(char, usize),
     ParseInt(u8, usize),
+    InvalidLabel(usize, usize),
+    InvalidSyntax(&'static str, usize),
     MalformedUnicdoe(usize),
     EmptyStream,
 }
@@ -45,6 +54,8 @@ impl Display for Error {
             Error::ParseInt(radix, i) => {
                 write!(f, "invalid character for radix {radix}: at {i}")
             }
+            Error::InvalidLabel(label, i) => write!(f, "Invalid label #{label}: at {i}"),
+            Error::InvalidSyntax(syntax, i) => write!(f, "Invalid read syntax {syntax}: at {i}"),
             Error::UnknownMacroCharacter(chr, i) => {
                 write!(f, "Unkown reader macro character {chr}: at {i}")
             }
@@ -67,7 +78,9 @@ impl Error {
             | Error::ExtraCloseBracket(i)
             | Error::MissingQuotedItem(i)
             | Error::UnknownMacroCharacter(_, i)
-            | Error::ParseInt(_, i) => Some(i),
+            | Error::ParseInt(_, i)
+            | Error::InvalidLabel(_, i)
+            | Error::InvalidSyntax(_, i) => Some(i),
             Error::EmptyStream => None,
         }
     }
@@ -90,7 +103,7 @@ enum Token<'a> {
     Unquote(usize),
     Splice(usize),
     Sharp(usize),
-    QuestionMark(usize, char),
+    QuestionMark(usize, u32),
     Ident(&'a str),
     String(&'a str),
 }
@@ -107,7 +120,10 @@ impl Display for Token<'_> {
             Token::Unquote(_) => write!(f, ","),
             Token::Splice(_) => write!(f, ",@"),
             Token::Sharp(_) => write!(f, "#"),
-            Token::QuestionMark(_, chr) => write!(f, "?{chr}"),
+            Token::QuestionMark(_, chr) => match char::from_u32(*chr) {
+                Some(c) => write!(f, "?{c}"),
+                None => write!(f, "?{chr}"),
+            },
             Token::Ident(x) => write!(f, "{x}"),
             Token::String(x) => write!(f, "\"{x}\""),
         }
@@ -200,6 +216,32 @@ impl<'a> Tokenizer<'a> {
         Token::Ident(&self.slice[beg..end])
     }
 
+    /// Read the name of a symbol that directly follows the current position.
+    /// The name is empty if there is no symbol.
+    fn get_symbol_name(&mut self) -> &'a str {
+        match self.iter.next_if(|x| symbol_char(x.1)) {
+            Some((beg, chr)) => {
+                let Token::Ident(name) = self.get_symbol(beg, chr) else { unreachable!() };
+                name
+            }
+            None => "",
+        }
+    }
+
+    /// Skip a `#@NUMBER` docstring, which is the NUMBER bytes following the
+    /// number. `#@00` skips to the end of the input.
+    fn skip_docstring(&mut self) {
+        let start = self.cur_pos();
+        let end = self.skip_till(|c| !c.is_ascii_digit());
+        let digits = &self.slice[start..end];
+        if digits == "00" {
+            self.skip_till(|_| false);
+            return;
+        }
+        let stop = end + digits.parse::<usize>().unwrap_or(0);
+        while self.iter.next_if(|x| x.0 < stop).is_some() {}
+    }
+
     /// After having found a `,`, see if the next token is a `@` or not.
     fn get_macro_char(&mut self, idx: usize) -> Token<'a> {
         match self.iter.next_if(|(_, chr)| *chr == '@') {
@@ -209,50 +251,28 @@ impl<'a> Tokenizer<'a> {
     }
 
     fn read_quoted_char(&mut self, idx: usize) -> Result<Token<'a>> {
-        match self.iter.next() {
-            Some((start, item)) => {
-                if item == '\\' {
-                    let Token::Ident(tok) = self.get_symbol(start, item) else { unreachable!() };
-                    let Some(chr) = tok.chars().nth(1) else {
-                        return Err(Error::MissingQuotedItem(start));
-                    };
-                    if chr == 'u' || chr == 'x' {
-                        match u32::from_str_radix(&tok[2..], 16) {
-                            Ok(digits) => match char::from_u32(digits) {
-                                Some(c) => Ok(Token::QuestionMark(start, c)),
-                                None => Err(Error::MalformedUnicdoe(start)),
-                            },
-                            Err(_) => Err(Error::MalformedUnicdoe(start)),
-                        }
-                    } else if tok.chars().count() == 2 {
-                        let new = match chr {
-                            'a' => '\u{07}',
-                            'b' => '\u{08}',
-                            'd' => '\u{7F}',
-                            'e' => '\u{1B}',
-                            'f' => '\u{0C}',
-                            'n' => '\n',
-                            'r' => '\r',
-                            's' => ' ',
-                            't' => '\t',
-                            'v' => '\u{0B}',
-                            c => c,
-                        };
-                        Ok(Token::QuestionMark(start, new))
-                    } else {
-                        // TODO implement keycode parsing
-                        Ok(Token::QuestionMark(start, '\0'))
-                    }
-                } else {
-                    match self.iter.peek() {
-                        Some((i, chr)) if symbol_char(*chr) && *chr != '?' => {
-                            Err(Error::UnexpectedChar(*chr, *i)) // ?aa
-                        }
-                        _ => Ok(Token::QuestionMark(idx, item)), // ?a
+        let chr = match self.iter.next() {
+            Some((start, '\\')) => {
+                let mut chars = self.slice[start + 1..].chars();
+                let chr = match read_escape(&mut chars, false) {
+                    Ok(chr) => chr.expect("only strings have empty escapes"),
+                    Err(mut e) => {
+                        e.update_pos(start);
+                        return Err(e);
                     }
-                }
+                };
+                let end = self.slice.len() - chars.as_str().len();
+                while self.iter.next_if(|x| x.0 < end).is_some() {}
+                chr
+            }
+            Some((_, chr)) => u32::from(chr),
+            None => return Err(Error::MissingQuotedItem(idx)),
+        };
+        match self.iter.peek() {
+            Some((i, chr)) if symbol_char(*chr) && *chr != '?' => {
+                Err(Error::UnexpectedChar(*chr, *i)) // ?aa
             }
-            None => Err(Error::MissingQuotedItem(idx)),
+            _ => Ok(Token::QuestionMark(idx, chr)), // ?a
         }
     }
 
@@ -275,6 +295,10 @@ impl<'a> Iterator for Tokenizer<'a> {
             '\'' => Ok(Token::Quote(idx)),
             ',' => Ok(self.get_macro_char(idx)),
             '`' => Ok(Token::Backquote(idx)),
+            '#' if self.iter.next_if(|x| x.1 == '@').is_some() => {
+                self.skip_docstring();
+                return self.next();
+            }
             '#' => Ok(Token::Sharp(idx)),
             '?' => self.read_quoted_char(idx),
             '"' => self.get_string(idx),
@@ -285,7 +309,7 @@ impl<'a> Iterator for Tokenizer<'a> {
     }
 }
 
-fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+fn unescape_symbol(symbol: &str) -> Cow<'_, str> {
     let mut escaped = false;
     let is_not_escape = |c: &char| {
         if escaped {
@@ -299,52 +323,262 @@ fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
         }
     };
     if symbol.contains('\\') {
-        let escaped_slice: String = symbol.chars().filter(is_not_escape).collect();
-        intern(escaped_slice.as_str(), cx)
+        Cow::Owned(symbol.chars().filter(is_not_escape).collect())
     } else {
-        intern(symbol, cx)
+        Cow::Borrowed(symbol)
     }
 }
 
+fn intern_symbol<'ob>(symbol: &str, cx: &'ob Context) -> Symbol<'ob> {
+    intern(&unescape_symbol(symbol), cx)
+}
+
 /// Parse a symbol from a string. This will either by a true symbol or a number
 /// literal.
 fn parse_symbol<'a>(slice: &str, cx: &'a Context) -> Object<'a> {
-    match slice.parse::<i64>() {
-        Ok(num) => cx.add(num),
-        Err(_) => match slice.parse::<f64>() {
-            Ok(num) => cx.add(num),
-            Err(_) => cx.add(intern_symbol(slice, cx)),
-        },
+    match parse_number(slice) {
+        Some(num) => cx.add(num),
+        None => cx.add(intern_symbol(slice, cx)),
     }
 }
 
-/// process escape characters in the string slice and return the resulting
-/// string.
-fn unescape_string<'a>(string: &str, cx: &'a Context) -> Object<'a> {
-    let mut escaped = false;
-    let unescape = |c: char| {
-        if escaped {
-            escaped = false;
-            // TODO: Handle unicode, hex, and octal escapes
-            match c {
-                'n' => Some('\n'),
-                't' => Some('\t'),
-                'r' => Some('\r'),
-                '\n' | ' ' => None,
-                _ => Some(c),
+const CHAR_ALT: u32 = 0x0040_0000;
+const CHAR_SUPER: u32 = 0x0080_0000;
+const CHAR_HYPER: u32 = 0x0100_0000;
+const CHAR_SHIFT: u32 = 0x0200_0000;
+const CHAR_CTL: u32 = 0x0400_0000;
+const CHAR_META: u32 = 0x0800_0000;
+const CHAR_MODIFIER_MASK: u32 =
+    CHAR_ALT | CHAR_SUPER | CHAR_HYPER | CHAR_SHIFT | CHAR_CTL | CHAR_META;
+
+/// Apply the control modifier to `chr`. ASCII letters and `@[\]^_` become
+/// ASCII control characters, `?` becomes DEL, and anything else gets the
+/// control bit.
+fn make_ctrl(chr: u32) -> u32 {
+    let base = chr & !CHAR_MODIFIER_MASK;
+    if base == u32::from('?') {
+        0o177 | (chr & CHAR_MODIFIER_MASK)
+    } else if base >= 0x80 {
+        chr | CHAR_CTL
+    } else if (0o101..=0o132).contains(&(chr & 0o137)) || (0o100..=0o137).contains(&(chr & 0o177)) {
+        chr & (0o37 | !0o177)
+    } else {
+        chr | CHAR_CTL
+    }
+}
+
+/// Return the next character in `chars` without consuming it.
+fn peek(chars: &Chars) -> Option<char> {
+    chars.clone().next()
+}
+
+/// Consume the next character if it is `expected`.
+fn next_if_eq(chars: &mut Chars, expected: char) -> bool {
+    let matches = peek(chars) == Some(expected);
+    if matches {
+        chars.next();
+    }
+    matches
+}
+
+/// Read `max` or fewer digits in `radix` from `chars`. Returns `None` if there
+/// are no digits.
+fn read_digits(chars: &mut Chars, radix: u32, max: usize) -> Option<u32> {
+    let mut value: Option<u32> = None;
+    for _ in 0..max {
+        let Some(digit) = peek(chars).and_then(|c| c.to_digit(radix)) else { break };
+        chars.next();
+        value = Some(value.unwrap_or(0).saturating_mul(radix).saturating_add(digit));
+    }
+    value
+}
+
+/// Read a `\N{NAME}` escape, after the `N`. The name is either `U+` and a hex
+/// code point, or the unicode name of the character. Names are matched ignoring
+/// case, and any run of whitespace in them is treated as a single space.
+fn read_named_char(chars: &mut Chars) -> Result<u32> {
+    if chars.next() != Some('{') {
+        return Err(Error::MalformedUnicdoe(0));
+    }
+    let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+    let chr = match name.strip_prefix("U+") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+        None => {
+            let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            unicode_names2::character(&name)
+        }
+    };
+    chr.map(u32::from).ok_or(Error::MalformedUnicdoe(0))
+}
+
+/// Read the character that a modifier like `\C-` applies to.
+fn read_modified(chars: &mut Chars, in_string: bool, prefix: &'static str) -> Result<u32> {
+    match chars.next() {
+        Some('\\') => read_escape(chars, in_string)?.ok_or(Error::InvalidSyntax(prefix, 0)),
+        Some(chr) => Ok(u32::from(chr)),
+        None => Err(Error::InvalidSyntax(prefix, 0)),
+    }
+}
+
+/// Read a modifier like `\M-`, after the letter, and apply it.
+fn read_modifier(
+    chars: &mut Chars,
+    in_string: bool,
+    bit: u32,
+    prefix: &'static str,
+) -> Result<u32> {
+    if !next_if_eq(chars, '-') {
+        return Err(Error::InvalidSyntax(prefix, 0));
+    }
+    Ok(read_modified(chars, in_string, prefix)? | bit)
+}
+
+/// Read the escape sequence after a `\` in a string or character literal.
+/// Returns `None` for escapes that don't produce a character, like an escaped
+/// newline in a string. Outside of strings the character can have modifier
+/// bits set. Error positions are relative to the escape.
+fn read_escape(chars: &mut Chars, in_string: bool) -> Result<Option<u32>> {
+    let Some(chr) = chars.next() else { return Err(Error::MissingQuotedItem(0)) };
+    let code = match chr {
+        'a' => 0x07,
+        'b' => 0x08,
+        'd' => 0x7F,
+        'e' => 0x1B,
+        'f' => 0x0C,
+        'n' => u32::from('\n'),
+        'r' => u32::from('\r'),
+        't' => u32::from('\t'),
+        'v' => 0x0B,
+        '\n' | ' ' if in_string => return Ok(None),
+        's' if peek(chars) == Some('-') => read_modifier(chars, in_string, CHAR_SUPER, "\\s-")?,
+        's' => u32::from(' '),
+        'M' => read_modifier(chars, in_string, CHAR_META, "\\M-")?,
+        'S' => read_modifier(chars, in_string, CHAR_SHIFT, "\\S-")?,
+        'H' => read_modifier(chars, in_string, CHAR_HYPER, "\\H-")?,
+        'A' => read_modifier(chars, in_string, CHAR_ALT, "\\A-")?,
+        'C' => {
+            if !next_if_eq(chars, '-') {
+                return Err(Error::InvalidSyntax("\\C-", 0));
             }
-        } else if c == '\\' {
-            escaped = true;
-            None
-        } else {
-            Some(c)
+            make_ctrl(read_modified(chars, in_string, "\\C-")?)
+        }
+        '^' => make_ctrl(read_modified(chars, in_string, "\\^")?),
+        '0'..='7' => {
+            let mut code = chr.to_digit(8).unwrap();
+            for _ in 0..2 {
+                let Some(digit) = peek(chars).and_then(|c| c.to_digit(8)) else { break };
+                chars.next();
+                code = code * 8 + digit;
+            }
+            code
+        }
+        'x' => read_digits(chars, 16, usize::MAX).ok_or(Error::MalformedUnicdoe(0))?,
+        'u' | 'U' => {
+            let max = if chr == 'u' { 4 } else { 8 };
+            let code = read_digits(chars, 16, max).ok_or(Error::MalformedUnicdoe(0))?;
+            char::from_u32(code).ok_or(Error::MalformedUnicdoe(0))?;
+            code
         }
+        'N' => read_named_char(chars)?,
+        chr => u32::from(chr),
     };
+    Ok(Some(code))
+}
+
+/// process escape characters in the string slice and return the resulting
+/// string. If the string has no multibyte characters and uses octal, hex or
+/// meta escapes for raw bytes, it is read as a unibyte string.
+fn unescape_string<'a>(string: &str, cx: &'a Context) -> Result<Object<'a>> {
     let mut new = cx.string_with_capacity(string.len());
-    for c in string.chars().filter_map(unescape) {
-        new.push(c);
+    let mut raw_bytes = false;
+    let mut multibyte = false;
+    let mut chars = string.chars();
+    while let Some(chr) = chars.next() {
+        if chr != '\\' {
+            multibyte |= !chr.is_ascii();
+            new.push(chr);
+            continue;
+        }
+        let pos = string.len() - chars.as_str().len() - 1;
+        let kind = peek(&chars);
+        let code = match read_escape(&mut chars, true) {
+            Ok(Some(code)) => code,
+            Ok(None) => continue,
+            Err(mut e) => {
+                e.update_pos(pos);
+                return Err(e);
+            }
+        };
+        let code = match kind {
+            // Meta characters in strings are raw bytes with the high bit set
+            Some('M') if (code & !CHAR_META) < 0x80 => {
+                raw_bytes = true;
+                (code & !CHAR_META) | 0x80
+            }
+            _ if code & CHAR_MODIFIER_MASK != 0 => {
+                return Err(Error::InvalidSyntax("modifier in string", pos));
+            }
+            Some('0'..='7' | 'x') => {
+                raw_bytes |= (0x80..=0xFF).contains(&code);
+                code
+            }
+            _ => {
+                multibyte |= code > 0x7F;
+                code
+            }
+        };
+        new.push(char::from_u32(code).ok_or(Error::MalformedUnicdoe(pos))?);
+    }
+    if raw_bytes && !multibyte {
+        Ok(cx.add(new.chars().map(|c| c as u8).collect::<Vec<u8>>()))
+    } else {
+        Ok(cx.add(new))
+    }
+}
+
+/// Return the bytes of a unibyte string, or a string that only contains
+/// characters that fit in a byte.
+fn string_bytes(obj: Object) -> Option<Vec<u8>> {
+    match obj.untag() {
+        ObjectType::ByteString(x) => Some(x.to_vec()),
+        ObjectType::String(x) => x.chars().map(|c| u8::try_from(c).ok()).collect(),
+        _ => None,
+    }
+}
+
+/// The number of characters covered by each entry of a char table at the
+/// given depth. The top level table has depth 0.
+const CHAR_TABLE_CHARS: [usize; 4] = [65536, 4096, 128, 1];
+
+/// An element of a char table literal.
+enum CharTableItem<'ob> {
+    Value(Object<'ob>),
+    /// A `#^^[DEPTH MIN-CHAR ...]` sub char table.
+    Sub {
+        depth: usize,
+        min_char: usize,
+        items: Vec<CharTableItem<'ob>>,
+    },
+}
+
+/// Set the characters covered by `items` in `table`. The items start at
+/// `min_char` and have `depth` in the char table.
+fn fill_char_table(table: &CharTable, items: &[CharTableItem], depth: usize, min_char: usize) {
+    let chars = CHAR_TABLE_CHARS[depth];
+    for (i, item) in items.iter().enumerate() {
+        match item {
+            CharTableItem::Value(value) if value.is_nil() => {}
+            CharTableItem::Value(value) => {
+                let start = min_char + i * chars;
+                for c in start..start + chars {
+                    table.set(c, *value);
+                }
+            }
+            CharTableItem::Sub { depth, min_char, items } => {
+                fill_char_table(table, items, *depth, *min_char);
+            }
+        }
     }
-    cx.add(new)
 }
 
 /// Return true if `chr` is a valid symbol character.
@@ -370,6 +604,10 @@ struct Reader<'a, 'ob> {
     tokens: Tokenizer<'a>,
     /// New objects are allocated in the context.
     cx: &'ob Context<'ob>,
+    /// Objects labelled with `#N=`, so they can be referenced with `#N#`.
+    labels: HashMap<usize, Object<'ob>>,
+    /// The value of `load-file-name`, which is read by `#$`.
+    load_file_name: Object<'ob>,
 }
 
 impl<'a, 'ob> Reader<'a, 'ob> {
@@ -424,6 +662,188 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         Err(Error::MissingCloseBracket(delim))
     }
 
+    /// Read the elements of a list that can't be dotted, like `#s(...)`.
+    fn read_items(&mut self, delim: usize) -> Result<Vec<Object<'ob>>> {
+        let mut objects = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            match token? {
+                Token::CloseParen(_) => return Ok(objects),
+                tok => objects.push(self.read_sexp(tok)?),
+            }
+        }
+        Err(Error::MissingCloseParen(delim))
+    }
+
+    /// Read a `#s(...)` literal, which is either a hash table or a record.
+    fn read_record(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let Some(Ok(Token::OpenParen(delim))) = self.tokens.next() else {
+            return Err(Error::InvalidSyntax("#s", pos));
+        };
+        let items = self.read_items(delim)?;
+        match items.first() {
+            Some(x) if *x == sym::HASH_TABLE => self.make_hash_table(&items[1..], pos),
+            Some(_) => {
+                let mut record = self.cx.vec_with_capacity(items.len());
+                record.extend_from_slice(&items);
+                Ok(self.cx.add(RecordBuilder(record)))
+            }
+            None => Err(Error::InvalidSyntax("#s", pos)),
+        }
+    }
+
+    /// Read a string with text properties, written as
+    /// `#("foo" 0 3 (face bold))`.
+    fn read_propertized_string(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let invalid = Error::InvalidSyntax("#(", pos);
+        let items = self.read_items(pos)?;
+        let Some((string, props)) = items.split_first() else { return Err(invalid) };
+        let ObjectType::String(string) = string.untag() else { return Err(invalid) };
+        let len = string.len();
+        let Ok(mut tree) = string.textprops_mut(self.cx) else { return Err(invalid) };
+        for prop in props.chunks(3) {
+            let [start, end, plist] = prop else { return Err(invalid) };
+            let (Ok(start), Ok(end)) = (usize::try_from(*start), usize::try_from(*end)) else {
+                return Err(invalid);
+            };
+            if start > end || end > len {
+                return Err(invalid);
+            }
+            tree.set_properties(start, end, *plist);
+        }
+        Ok(string.into())
+    }
+
+    /// Create a hash table from the properties of a `#s(hash-table ...)`
+    /// literal. The `test`, `size` and `data` properties are used and the rest
+    /// are ignored.
+    fn make_hash_table(&self, props: &[Object<'ob>], pos: usize) -> Result<Object<'ob>> {
+        let invalid = Error::InvalidSyntax("#s(hash-table ...)", pos);
+        let mut test = HashTest::default();
+        let mut size = 0;
+        let mut data = Vec::new();
+        for prop in props.chunks(2) {
+            let [key, value] = *prop else { return Err(invalid) };
+            if key == sym::TEST {
+                test = HashTest::from_name(value).ok_or(invalid)?;
+            } else if key == sym::SIZE {
+                size = usize::try_from(value).map_err(|_| invalid)?;
+            } else if key == sym::DATA {
+                let list = value
+                    .as_list()
+                    .ok()
+                    .and_then(|x| x.collect::<std::result::Result<Vec<_>, _>>().ok());
+                data = list.ok_or(invalid)?;
+            }
+        }
+        let mut table = HashTable::with_capacity(test, size.max(data.len() / 2));
+        for pair in data.chunks(2) {
+            let [key, value] = pair else { return Err(invalid) };
+            table.insert(*key, *value);
+        }
+        Ok(self.cx.add(table))
+    }
+
+    /// Read a `#[...]` byte-code literal. This has the same elements as the
+    /// arguments to `make-byte-code`.
+    fn read_byte_code(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let invalid = Error::InvalidSyntax("#[", pos);
+        let obj = self.read_vec(pos)?;
+        let ObjectType::Vec(vec) = obj.untag() else { unreachable!() };
+        let items: Vec<Object> = vec.iter().map(|x| x.get()).collect();
+        let [arglist, code, constants, depth, ..] = &items[..] else { return Err(invalid) };
+        let ObjectType::Int(arglist) = arglist.untag() else { return Err(invalid) };
+        let args = FnArgs::from_arg_spec(arglist).map_err(|_| invalid)?;
+        let code = string_bytes(*code).ok_or(invalid)?;
+        let ObjectType::Vec(constants) = constants.untag() else { return Err(invalid) };
+        let ObjectType::Int(depth) = depth.untag() else { return Err(invalid) };
+        let depth = usize::try_from(depth).map_err(|_| invalid)?;
+        unsafe { Ok(self.cx.add(ByteFn::make(&code, constants, args, depth))) }
+    }
+
+    /// Read a `#&N"..."` bool-vector literal. Each byte of the string holds 8
+    /// elements, starting from the least significant bit.
+    fn read_bool_vector(&mut self, pos: usize) -> Result<Object<'ob>> {
+        let invalid = Error::InvalidSyntax("#&", pos);
+        let start = self.tokens.cur_pos();
+        let end = self.tokens.skip_till(|c| !c.is_ascii_digit());
+        let len: usize = self.tokens.slice[start..end].parse().map_err(|_| invalid)?;
+        let Some(Ok(Token::String(string))) = self.tokens.next() else { return Err(invalid) };
+        let bytes = string_bytes(unescape_string(string, self.cx)?).ok_or(invalid)?;
+        if bytes.len() != len.div_ceil(8) {
+            return Err(invalid);
+        }
+        // TODO: there is no bool-vector type yet, so it is read as a vector of
+        // booleans.
+        let bits = (0..len).map(|i| if (bytes[i / 8] >> (i % 8)) & 1 == 1 { TRUE } else { NIL });
+        Ok(self.cx.add(bits.collect::<Vec<_>>()))
+    }
+
+    /// Read the elements of a char table or sub char table literal, up to the
+    /// closing bracket.
+    fn read_char_table_items(&mut self, delim: usize) -> Result<Vec<CharTableItem<'ob>>> {
+        let mut items = Vec::new();
+        while let Some(token) = self.tokens.next() {
+            let item = match token? {
+                Token::CloseBracket(_) => return Ok(items),
+                Token::Sharp(i) if self.tokens.iter.next_if(|x| x.1 == '^').is_some() => {
+                    if self.tokens.iter.next_if(|x| x.1 == '^').is_some() {
+                        self.read_sub_char_table(i)?
+                    } else {
+                        CharTableItem::Value(self.read_char_table(i)?)
+                    }
+                }
+                tok => CharTableItem::Value(self.read_sexp(tok)?),
+            };
+            items.push(item);
+        }
+        Err(Error::MissingCloseBracket(delim))
+    }
+
+    /// Read a `#^^[DEPTH MIN-CHAR ...]` sub char table, after the `#^^`.
+    fn read_sub_char_table(&mut self, pos: usize) -> Result<CharTableItem<'ob>> {
+        let invalid = Error::InvalidSyntax("#^^[", pos);
+        if self.tokens.read_char() != Some('[') {
+            return Err(invalid);
+        }
+        let mut items = self.read_char_table_items(pos)?.into_iter();
+        let (Some(CharTableItem::Value(depth)), Some(CharTableItem::Value(min_char))) =
+            (items.next(), items.next())
+        else {
+            return Err(invalid);
+        };
+        let (ObjectType::Int(depth), ObjectType::Int(min_char)) = (depth.untag(), min_char.untag())
+        else {
+            return Err(invalid);
+        };
+        let depth = usize::try_from(depth).map_err(|_| invalid)?;
+        let min_char = usize::try_from(min_char).map_err(|_| invalid)?;
+        if !(1..CHAR_TABLE_CHARS.len()).contains(&depth) {
+            return Err(invalid);
+        }
+        Ok(CharTableItem::Sub { depth, min_char, items: items.collect() })
+    }
+
+    /// Read a `#^[...]` char table literal, after the `#^`. The elements are
+    /// the default value, the parent, the purpose, the ASCII sub table, and
+    /// then the contents. Any extra slots are ignored.
+    fn read_char_table(&mut self, pos: usize) -> Result<Object<'ob>> {
+        if self.tokens.read_char() != Some('[') {
+            return Err(Error::InvalidSyntax("#^[", pos));
+        }
+        let items = self.read_char_table_items(pos)?;
+        let value = |i: usize| match items.get(i) {
+            Some(CharTableItem::Value(x)) => *x,
+            _ => NIL,
+        };
+        let table = CharTableInner::new(value(2), Some(value(0)), 0).into_obj(self.cx).untag();
+        if let ObjectType::CharTable(parent) = value(1).untag() {
+            table.set_parent(Some(parent));
+        }
+        let contents = items.get(4..).unwrap_or_default();
+        fill_char_table(table, &contents[..contents.len().min(64)], 0, 0);
+        Ok(table.into())
+    }
+
     /// Quote an item using `symbol`.
     fn quote_item(&mut self, pos: usize, symbol: Symbol) -> Result<Object<'ob>> {
         match self.tokens.next() {
@@ -442,9 +862,9 @@ impl<'a, 'ob> Reader<'a, 'ob> {
         }
 
         match self.tokens.next() {
-            Some(Ok(Token::Ident(ident))) => match usize::from_str_radix(ident, radix.into()) {
-                Ok(x) => Ok(self.cx.add(x as i64)),
-                Err(_) => Err(Error::ParseInt(radix, pos)),
+            Some(Ok(Token::Ident(ident))) => match parse_integer(ident, radix.into()) {
+                Some(x) => Ok(self.cx.add(x)),
+                None => Err(Error::ParseInt(radix, pos)),
             },
             _ => Err(Error::ParseInt(radix, pos)),
         }
@@ -465,35 +885,83 @@ impl<'a, 'ob> Reader<'a, 'ob> {
                 }
                 None => Err(Error::MissingQuotedItem(pos)),
             },
+            Some('s') => self.read_record(pos),
+            Some('(') => self.read_propertized_string(pos),
+            Some('[') => self.read_byte_code(pos),
+            Some('&') => self.read_bool_vector(pos),
+            Some('^') => self.read_char_table(pos),
+            Some(':') => {
+                let name = unescape_symbol(self.tokens.get_symbol_name());
+                Ok(Symbol::new_uninterned(&name, self.cx).into())
+            }
+            // A symbol that is not affected by shorthands
+            Some('_') => Ok(intern_symbol(self.tokens.get_symbol_name(), self.cx).into()),
+            Some('#') => Ok(intern("", self.cx).into()),
+            Some('$') => Ok(self.load_file_name),
             Some('b') => self.read_radix(pos, 2),
             Some('o') => self.read_radix(pos, 8),
             Some('x') => self.read_radix(pos, 16),
             Some(chr) if chr.is_ascii_digit() => {
-                let mut radix = (chr as u8) - b'0';
+                let mut num = usize::from((chr as u8) - b'0');
+                // The digit that made the number too large to be a radix
+                let mut radix_overflow = None;
                 loop {
                     match self.tokens.read_char() {
-                        Some('r') => break,
+                        Some('r') => {
+                            // TODO: Better error for radix overflow
+                            if let Some(chr) = radix_overflow {
+                                return Err(Error::UnknownMacroCharacter(chr, pos));
+                            }
+                            return self.read_radix(pos, num as u8);
+                        }
+                        Some('=') => return self.read_labelled(pos, num),
+                        Some('#') => {
+                            return match self.labels.get(&num) {
+                                Some(obj) => Ok(*obj),
+                                None => Err(Error::InvalidLabel(num, pos)),
+                            };
+                        }
                         Some(chr) if chr.is_ascii_digit() => {
-                            match radix
+                            match num
                                 .checked_mul(10)
-                                .and_then(|r| r.checked_add(chr as u8 - b'0'))
+                                .and_then(|n| n.checked_add(usize::from(chr as u8 - b'0')))
                             {
-                                Some(r) => radix = r,
-                                // TODO: Better error for radix overflow
+                                Some(n) => num = n,
                                 None => return Err(Error::UnknownMacroCharacter(chr, pos)),
                             }
+                            if num > u8::MAX.into() && radix_overflow.is_none() {
+                                radix_overflow = Some(chr);
+                            }
                         }
                         Some(chr) => return Err(Error::UnknownMacroCharacter(chr, pos)),
                         None => return Err(Error::MissingQuotedItem(pos)),
                     }
                 }
-                self.read_radix(pos, radix)
             }
             Some(chr) => Err(Error::UnknownMacroCharacter(chr, pos)),
             None => Err(Error::MissingQuotedItem(pos)),
         }
     }
 
+    /// Read an object labelled with `#N=`. While the object is being read,
+    /// references to the label are read as a placeholder cons, which is
+    /// replaced with the finished object afterwards.
+    fn read_labelled(&mut self, pos: usize, label: usize) -> Result<Object<'ob>> {
+        let placeholder: Object = Cons::new(NIL, NIL, self.cx).into();
+        self.labels.insert(label, placeholder);
+        let obj = match self.tokens.next() {
+            Some(token) => self.read_sexp(token?)?,
+            None => return Err(Error::MissingQuotedItem(pos)),
+        };
+        // `#1=#1#` has nothing to refer to
+        if obj.ptr_eq(placeholder) {
+            return Err(Error::InvalidLabel(label, pos));
+        }
+        self.labels.insert(label, obj);
+        substitute(obj, placeholder, obj);
+        Ok(obj)
+    }
+
     fn read_sexp(&mut self, token: Token<'a>) -> Result<Object<'ob>> {
         match token {
             Token::OpenParen(i) => self.read_list(i),
@@ -505,9 +973,64 @@ impl<'a, 'ob> Reader<'a, 'ob> {
             Token::Splice(i) => self.quote_item(i, sym::SPLICE),
             Token::Backquote(i) => self.quote_item(i, sym::BACKQUOTE),
             Token::Sharp(i) => self.read_sharp(i),
-            Token::QuestionMark(_, c) => Ok((c as i64).into()),
+            Token::QuestionMark(_, c) => Ok(i64::from(c).into()),
             Token::Ident(x) => Ok(parse_symbol(x, self.cx)),
-            Token::String(x) => Ok(unescape_string(x, self.cx)),
+            Token::String(x) => unescape_string(x, self.cx).map_err(|mut e| {
+                e.update_pos(self.tokens.relative_pos(token));
+                e
+            }),
+        }
+    }
+}
+
+/// Replace every reference to `placeholder` inside of `obj` with `value`.
+/// Only objects that were just created by the reader are visited, so they are
+/// always mutable.
+fn substitute(obj: Object, placeholder: Object, value: Object) {
+    let mut seen: HashSet<*const u8> = HashSet::default();
+    let mut stack = vec![obj];
+    while let Some(obj) = stack.pop() {
+        match obj.untag() {
+            ObjectType::Cons(cons) => {
+                if !seen.insert(std::ptr::from_ref(cons).cast()) {
+                    continue;
+                }
+                if cons.car().ptr_eq(placeholder) {
+                    cons.set_car(value).expect("read objects should be mutable");
+                } else {
+                    stack.push(cons.car());
+                }
+                if cons.cdr().ptr_eq(placeholder) {
+                    cons.set_cdr(value).expect("read objects should be mutable");
+                } else {
+                    stack.push(cons.cdr());
+                }
+            }
+            ObjectType::Vec(vec) => {
+                if !seen.insert(std::ptr::from_ref(vec).cast()) {
+                    continue;
+                }
+                for cell in vec.try_mut().expect("read objects should be mutable") {
+                    if cell.get().ptr_eq(placeholder) {
+                        cell.set(value);
+                    } else {
+                        stack.push(cell.get());
+                    }
+                }
+            }
+            ObjectType::Record(record) => {
+                if !seen.insert(std::ptr::from_ref(record).cast()) {
+                    continue;
+                }
+                for cell in record.try_mut().expect("read objects should be mutable") {
+                    if cell.get().ptr_eq(placeholder) {
+                        cell.set(value);
+                    } else {
+                        stack.push(cell.get());
+                    }
+                }
+            }
+            _ => {}
         }
     }
 }
@@ -515,7 +1038,17 @@ impl<'a, 'ob> Reader<'a, 'ob> {
 /// read a lisp object from `slice`. Return the object and index of next
 /// remaining character in the slice.
 pub(crate) fn read<'ob>(slice: &str, cx: &'ob Context) -> Result<(Object<'ob>, usize)> {
-    let mut reader = Reader { tokens: Tokenizer::new(slice), cx };
+    read_with_load_file(slice, NIL, cx)
+}
+
+/// Like [`read`], but `#$` is read as `load_file_name`.
+pub(crate) fn read_with_load_file<'ob>(
+    slice: &str,
+    load_file_name: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<(Object<'ob>, usize)> {
+    let mut reader =
+        Reader { tokens: Tokenizer::new(slice), cx, labels: HashMap::default(), load_file_name };
     match reader.tokens.next() {
         Some(Ok(t)) => reader.read_sexp(t).map(|x| (x, reader.tokens.cur_pos())),
         Some(Err(e)) => Err(e),
@@ -523,6 +1056,10 @@ pub(crate) fn read<'ob>(slice: &str, cx: &'ob Context) -> Result<(Object<'ob>, u
     }
 }
 
+defsym!(DATA);
+defsym!(TEST);
+defsym!(SIZE);
+
 #[cfg(test)]
 mod test {
     use crate::core::{cons::Cons, gc::RootSet};
@@ -560,6 +1097,20 @@ mod test {
         check_reader!(-3.0, "-3.0", cx);
         check_reader!(1, "+1", cx);
         check_reader!(1, "001", cx);
+        check_reader!(1, "1.", cx);
+        check_reader!(0.5, ".5", cx);
+        check_reader!(1000.0, "1e3", cx);
+        check_reader!(1000.0, "1.e3", cx);
+        check_reader!(f64::INFINITY, "1.0e+INF", cx);
+        check_reader!(f64::NEG_INFINITY, "-1.0e+INF", cx);
+        check_reader!(intern("1.5.3", cx), "1.5.3", cx);
+        check_reader!(intern("1e", cx), "1e", cx);
+        check_reader!(intern("inf", cx), "inf", cx);
+        check_reader!(intern("nan", cx), "nan", cx);
+        check_reader!(intern("1.0e+INFINITY", cx), "1.0e+INFINITY", cx);
+        let (nan, _) = read("3.0e+NaN", cx).unwrap();
+        let ObjectType::Float(nan) = nan.untag() else { unreachable!() };
+        assert_eq!(nan.to_bits(), f64::NAN.to_bits() | 3);
         check_reader!(1, "#o001", cx);
         check_reader!(8, "#o10", cx);
         check_reader!(8, "#8r10", cx);
@@ -666,6 +1217,41 @@ baz""#,
         check_reader!(0xabc_u32, "?\\xabc", cx);
     }
 
+    #[test]
+    fn read_escapes() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        check_reader!(1, "?\\C-a", cx);
+        check_reader!(1, "?\\^a", cx);
+        check_reader!(1, "?\\^A", cx);
+        check_reader!(127, "?\\C-?", cx);
+        check_reader!(0x0400_0025, "?\\C-%", cx);
+        check_reader!(0x0800_0061, "?\\M-a", cx);
+        check_reader!(0x0800_0001, "?\\C-\\M-a", cx);
+        check_reader!(0x0800_0001, "?\\M-\\C-a", cx);
+        check_reader!(0x0200_0061, "?\\S-a", cx);
+        check_reader!(0x0100_0061, "?\\H-a", cx);
+        check_reader!(0x0080_0061, "?\\s-a", cx);
+        check_reader!(0x0040_0061, "?\\A-a", cx);
+        check_reader!(32, "?\\s", cx);
+        check_reader!(65, "?\\101", cx);
+        check_reader!(0xE9, "?\\N{U+E9}", cx);
+        check_reader!(0x1F600, "?\\U0001F600", cx);
+        check_reader!(list!(27, 40; cx), "(?\\e ?\\()", cx);
+        assert_error("?\\C", Error::InvalidSyntax("\\C-", 1), cx);
+        check_reader!(0x2603, "?\\N{SNOWMAN}", cx);
+        check_reader!(0xE9, "?\\N{latin small letter\n  e with acute}", cx);
+        assert_error("?\\N{NOT A CHARACTER}", Error::MalformedUnicdoe(1), cx);
+
+        check_reader!("\u{e9}\u{2603}", "\"\\u00e9\\N{U+2603}\"", cx);
+        check_reader!("\u{1}\u{7f}", "\"\\C-a\\^?\"", cx);
+        check_reader!("a\x07b", "\"a\\ab\"", cx);
+        check_reader!(vec![0xE1_u8, b'a'], "\"\\M-aa\"", cx);
+        check_reader!(vec![0xFF_u8], "\"\\xff\"", cx);
+        check_reader!("\u{ff}\u{e9}", "\"\\xff\u{e9}\"", cx);
+        assert_error("\"ab\\S-a\"", Error::InvalidSyntax("modifier in string", 3), cx);
+    }
+
     #[test]
     fn read_sharp() {
         let roots = &RootSet::default();
@@ -682,6 +1268,92 @@ baz""#,
         assert_error("#a", Error::UnknownMacroCharacter('a', 0), cx);
     }
 
+    #[test]
+    fn read_sharp_objects() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let (obj, _) = read("#s(foo 1 2)", cx).unwrap();
+        let ObjectType::Record(record) = obj.untag() else { unreachable!() };
+        assert_eq!(record.len(), 3);
+        assert_eq!(record[0].get(), intern("foo", cx));
+
+        let (obj, _) = read("#s(hash-table test equal data (a 1 \"b\" 2))", cx).unwrap();
+        let ObjectType::HashTable(table) = obj.untag() else { unreachable!() };
+        assert_eq!(table.get(intern("a", cx).into()), Some(1.into()));
+        assert_eq!(table.get(cx.add("b")), Some(2.into()));
+        assert_eq!(table.len(), 2);
+
+        let (obj, _) = read("#s(hash-table size 3 test eq data (\"b\" 2))", cx).unwrap();
+        let ObjectType::HashTable(table) = obj.untag() else { unreachable!() };
+        assert_eq!(table.get(cx.add("b")), None);
+        let error = Error::InvalidSyntax("#s(hash-table ...)", 0);
+        assert_error("#s(hash-table test foo)", error, cx);
+
+        let (obj, _) = read("#[257 \"\\300\\207\" [1] 3]", cx).unwrap();
+        let ObjectType::ByteFn(func) = obj.untag() else { unreachable!() };
+        assert_eq!(func.codes(), &[0o300, 0o207]);
+        assert_eq!(func.depth, 3);
+
+        let (obj, _) = read("#:foo", cx).unwrap();
+        let ObjectType::Symbol(symbol) = obj.untag() else { unreachable!() };
+        assert_eq!(symbol.name(), "foo");
+        assert!(!symbol.interned());
+        assert_ne!(symbol, intern("foo", cx));
+
+        check_reader!(intern("", cx), "##", cx);
+        check_reader!(intern("1", cx), "#_1", cx);
+        check_reader!(false, "#$", cx);
+        check_reader!(list!(1, 2; cx), "(1 #@5 abcd2)", cx);
+        assert_error("#@00 1", Error::EmptyStream, cx);
+        let bools = vec![TRUE, NIL, TRUE, TRUE, NIL, NIL, NIL, NIL, TRUE];
+        check_reader!(bools, "#&9\"\\r\\1\"", cx);
+        assert_error("#&9\"\\r\"", Error::InvalidSyntax("#&", 0), cx);
+        check_reader!(vec![0xC0_u8], "\"\\300\"", cx);
+        check_reader!("\u{1}A", "\"\\1\\x41\"", cx);
+
+        let (obj, _) = read("#^[3 nil foo nil #^^[1 0 #^^[2 0 #^^[3 0 nil 5]]] 6]", cx).unwrap();
+        let ObjectType::CharTable(table) = obj.untag() else { unreachable!() };
+        assert_eq!(table.get(0), 3);
+        assert_eq!(table.get(1), 5);
+        assert_eq!(table.get(2), 3);
+        assert_eq!(table.get(65536), 6);
+        assert_error("#^^[1 0]", Error::InvalidSyntax("#^[", 0), cx);
+
+        let (obj, _) = read("#(\"foo\" 0 1 (a 1) 1 3 (b 2))", cx).unwrap();
+        let ObjectType::String(string) = obj.untag() else { unreachable!() };
+        assert_eq!(string, "foo");
+        let props = string.textprops().unwrap();
+        assert_eq!(props.find(0).map(|x| *x.val), Some(list!(intern("a", cx), 1; cx)));
+        assert_eq!(props.find(2).map(|x| *x.val), Some(list!(intern("b", cx), 2; cx)));
+        assert_error("#(\"foo\" 0 4 (a 1))", Error::InvalidSyntax("#(", 0), cx);
+        assert_error("#(\"foo\" 0 1)", Error::InvalidSyntax("#(", 0), cx);
+        assert_error("#(foo)", Error::InvalidSyntax("#(", 0), cx);
+    }
+
+    #[test]
+    fn read_labels() {
+        let roots = &RootSet::default();
+        let cx = &Context::new(roots);
+        let (obj, _) = read("#1=(a . #1#)", cx).unwrap();
+        let ObjectType::Cons(cons) = obj.untag() else { unreachable!() };
+        assert!(cons.cdr().ptr_eq(obj));
+
+        let (obj, _) = read("(#1=(a) b #1#)", cx).unwrap();
+        let ObjectType::Cons(cons) = obj.untag() else { unreachable!() };
+        let third = cons.cddr().unwrap().as_cons().car();
+        assert!(cons.car().ptr_eq(third));
+
+        let (obj, _) = read("#12=[1 (#12#)]", cx).unwrap();
+        let ObjectType::Vec(vec) = obj.untag() else { unreachable!() };
+        let ObjectType::Cons(inner) = vec[1].get().untag() else { unreachable!() };
+        assert!(inner.car().ptr_eq(obj));
+
+        check_reader!(list!(1, 1; cx), "(#1=1 #1#)", cx);
+        check_reader!(5, "#10r5", cx);
+        assert_error("#1#", Error::InvalidLabel(1, 0), cx);
+        assert_error("#1=#1#", Error::InvalidLabel(1, 0), cx);
+    }
+
     #[test]
     fn test_read_vec() {
         let roots = &RootSet::default();
@@ -0,0 +1,1218 @@
+//! A backtracking matcher for Emacs regular expressions.
+//!
+//! Patterns are parsed into a syntax tree and compiled to a small program,
+//! which is run against the text with an explicit backtracking stack. The
+//! text is given in two halves so that a buffer can be searched without
+//! moving its gap.
+use anyhow::{Result, bail};
+use std::fmt;
+
+/// The largest count allowed in an interval like `\{2,5\}`.
+const DUP_MAX: u32 = 0xFFFF;
+/// The largest number of instructions in a compiled regex.
+const MAX_PROGRAM: usize = 1 << 20;
+/// The number of backtracking points after which the matcher gives up.
+const MAX_FAILURES: usize = 1 << 22;
+
+/// An error in the syntax of a regex. The messages are the ones that Emacs
+/// signals `invalid-regexp` with.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct RegexError(&'static str);
+
+impl std::error::Error for RegexError {}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+const BAD_PATTERN: RegexError = RegexError("Invalid regular expression");
+const BAD_CLASS: RegexError = RegexError("Invalid character class name");
+const TRAILING_BACKSLASH: RegexError = RegexError("Trailing backslash");
+const BAD_BACKREF: RegexError = RegexError("Invalid back reference");
+const UNMATCHED_BRACKET: RegexError = RegexError("Unmatched [ or [^");
+const UNMATCHED_OPEN: RegexError = RegexError("Unmatched ( or \\(");
+const UNMATCHED_CLOSE: RegexError = RegexError("Unmatched ) or \\)");
+const UNMATCHED_BRACE: RegexError = RegexError("Unmatched \\{");
+const BAD_INTERVAL: RegexError = RegexError("Invalid content of \\{\\}");
+const PREMATURE_END: RegexError = RegexError("Premature end of regular expression");
+const TOO_BIG: RegexError = RegexError("Regular expression too big");
+
+/// A position in a [`Text`], as both a byte and a character offset from the
+/// start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub(crate) struct Pos {
+    pub(crate) byte: usize,
+    pub(crate) char: usize,
+}
+
+impl Pos {
+    pub(crate) fn new(byte: usize, char: usize) -> Self {
+        Self { byte, char }
+    }
+
+    fn advance(self, chr: char) -> Self {
+        Self { byte: self.byte + chr.len_utf8(), char: self.char + 1 }
+    }
+
+    fn retreat(self, chr: char) -> Self {
+        Self { byte: self.byte - chr.len_utf8(), char: self.char - 1 }
+    }
+}
+
+/// The text that a regex is matched against. It is made of two halves so
+/// that both sides of a buffer gap can be searched without copying them.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Text<'a> {
+    front: &'a str,
+    back: &'a str,
+}
+
+impl<'a> Text<'a> {
+    pub(crate) fn new(front: &'a str, back: &'a str) -> Self {
+        Self { front, back }
+    }
+
+    /// The length of the text in bytes.
+    pub(crate) fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+
+    /// The position of the end of the text.
+    pub(crate) fn end(&self) -> Pos {
+        let chars = bytecount::num_chars(self.front.as_bytes())
+            + bytecount::num_chars(self.back.as_bytes());
+        Pos::new(self.len(), chars)
+    }
+
+    /// The position of the character at index `chr`, if it is in the text.
+    pub(crate) fn pos(&self, chr: usize) -> Option<Pos> {
+        let (front, back) = (self.front, self.back);
+        let bytes = front.char_indices().map(|(i, _)| i);
+        let bytes = bytes.chain(back.char_indices().map(|(i, _)| i + front.len()));
+        let byte = bytes.chain(std::iter::once(self.len())).nth(chr)?;
+        Some(Pos::new(byte, chr))
+    }
+
+    /// The text between the byte offsets `beg` and `end`, which may be split
+    /// over the two halves.
+    pub(crate) fn slice(&self, beg: usize, end: usize) -> (&'a str, &'a str) {
+        let split = self.front.len();
+        let front = &self.front[beg.min(split)..end.min(split)];
+        let back = &self.back[beg.saturating_sub(split)..end.saturating_sub(split)];
+        (front, back)
+    }
+
+    fn next_char(&self, byte: usize) -> Option<char> {
+        match byte.checked_sub(self.front.len()) {
+            None => self.front[byte..].chars().next(),
+            Some(byte) => self.back.get(byte..)?.chars().next(),
+        }
+    }
+
+    fn prev_char(&self, byte: usize) -> Option<char> {
+        match byte.checked_sub(self.front.len()) {
+            Some(back @ 1..) => self.back[..back].chars().next_back(),
+            _ => self.front[..byte].chars().next_back(),
+        }
+    }
+}
+
+/// The text a regex is matched against, along with the state of the editor
+/// that affects the match.
+pub(crate) struct Input<'a> {
+    pub(crate) text: Text<'a>,
+    /// Maps a character to its syntax class designator.
+    pub(crate) syntax: &'a dyn Fn(char) -> char,
+    /// The position that `\=` matches.
+    pub(crate) point: Option<Pos>,
+    /// Find the longest match at a position instead of the first one, as the
+    /// `posix-` search functions do.
+    pub(crate) posix: bool,
+    /// Maps a character to its canonical and uppercase forms when case is
+    /// ignored, as it is when `case-fold-search` is non-nil. Characters that
+    /// differ only in case have the same canonical form.
+    pub(crate) case_fold: Option<&'a dyn Fn(char) -> (char, char)>,
+}
+
+impl<'a> Input<'a> {
+    pub(crate) fn new(text: Text<'a>) -> Self {
+        Self {
+            text,
+            syntax: &crate::syntax::standard_syntax,
+            point: None,
+            posix: false,
+            case_fold: None,
+        }
+    }
+
+    /// Whether the characters are equal, ignoring case if case folding is on.
+    fn same(&self, x: char, y: char) -> bool {
+        x == y || self.case_fold.is_some_and(|fold| fold(x).0 == fold(y).0)
+    }
+}
+
+/// The positions matched by each group of a regex. Group 0 is the whole
+/// match, and groups that did not participate are `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Captures(Vec<Option<(Pos, Pos)>>);
+
+impl Captures {
+    pub(crate) fn get(&self, group: usize) -> Option<(Pos, Pos)> {
+        self.0.get(group).copied().flatten()
+    }
+
+    /// The groups up to the last one that matched.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Option<(Pos, Pos)>> + '_ {
+        let len = self.0.iter().rposition(Option::is_some).map_or(0, |x| x + 1);
+        self.0[..len].iter().copied()
+    }
+}
+
+/// The POSIX character classes that can appear in brackets, like `[[:alpha:]]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Alnum,
+    Alpha,
+    Ascii,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Multibyte,
+    Nonascii,
+    Print,
+    Punct,
+    Space,
+    Unibyte,
+    Upper,
+    Word,
+    Xdigit,
+}
+
+impl CharClass {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "alnum" => Self::Alnum,
+            "alpha" => Self::Alpha,
+            "ascii" => Self::Ascii,
+            "blank" => Self::Blank,
+            "cntrl" => Self::Cntrl,
+            "digit" => Self::Digit,
+            "graph" => Self::Graph,
+            "lower" => Self::Lower,
+            "multibyte" => Self::Multibyte,
+            "nonascii" => Self::Nonascii,
+            "print" => Self::Print,
+            "punct" => Self::Punct,
+            "space" => Self::Space,
+            "unibyte" => Self::Unibyte,
+            "upper" => Self::Upper,
+            "word" => Self::Word,
+            "xdigit" => Self::Xdigit,
+            _ => return None,
+        })
+    }
+
+    fn matches(self, chr: char, syntax: &dyn Fn(char) -> char) -> bool {
+        match self {
+            Self::Alnum => chr.is_alphanumeric(),
+            Self::Alpha => chr.is_alphabetic(),
+            Self::Ascii | Self::Unibyte => chr.is_ascii(),
+            Self::Blank => {
+                chr == '\t'
+                    || (chr.is_whitespace()
+                        && !matches!(chr, '\n'..='\r' | '\u{85}' | '\u{2028}' | '\u{2029}'))
+            }
+            Self::Cntrl => chr < ' ',
+            Self::Digit => chr.is_ascii_digit(),
+            Self::Graph if chr.is_ascii() => chr.is_ascii_graphic(),
+            Self::Graph => !chr.is_whitespace() && !chr.is_control(),
+            Self::Lower => chr.is_lowercase(),
+            Self::Multibyte | Self::Nonascii => !chr.is_ascii(),
+            Self::Print if chr.is_ascii() => (' '..='~').contains(&chr),
+            Self::Print => !chr.is_control(),
+            // Outside of ASCII, everything that is not a word is punctuation
+            Self::Punct if chr.is_ascii() => chr.is_ascii_punctuation(),
+            Self::Punct => syntax(chr) != 'w',
+            Self::Space => syntax(chr) == ' ',
+            Self::Upper => chr.is_uppercase(),
+            Self::Word => syntax(chr) == 'w',
+            Self::Xdigit => chr.is_ascii_hexdigit(),
+        }
+    }
+}
+
+/// Whether `chr` is in the character category `cat`. Only the categories of
+/// the standard category table are known, and they are approximated by the
+/// Unicode blocks of their scripts.
+fn in_category(chr: char, cat: char) -> bool {
+    let code = u32::from(chr);
+    let han =
+        |c| matches!(c, 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FFFF);
+    let hiragana = |c| matches!(c, 0x3040..=0x309F);
+    let katakana = |c| matches!(c, 0x30A0..=0x30FF | 0x31F0..=0x31FF);
+    let hangul = |c| matches!(c, 0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF);
+    let cjk_symbol = |c| matches!(c, 0x3000..=0x303F | 0xFF00..=0xFFEF);
+    let combining = |c| matches!(c, 0x300..=0x36F | 0x1AB0..=0x1AFF | 0x1DC0..=0x1DFF | 0x20D0..=0x20FF | 0xFE20..=0xFE2F);
+    let right_to_left = |c| matches!(c, 0x590..=0x8FF | 0xFB1D..=0xFDFF | 0xFE70..=0xFEFF);
+    match cat {
+        'a' => (0x20..0x7F).contains(&code),
+        'l' => (0x80..=0x24F).contains(&code) || (0x1E00..=0x1EFF).contains(&code),
+        'g' => (0x370..=0x3FF).contains(&code) || (0x1F00..=0x1FFF).contains(&code),
+        'y' => (0x400..=0x52F).contains(&code),
+        'w' => (0x590..=0x5FF).contains(&code),
+        'b' => (0x600..=0x6FF).contains(&code) || (0x750..=0x77F).contains(&code),
+        'i' => (0x900..=0xDFF).contains(&code),
+        't' => (0xE00..=0xE7F).contains(&code),
+        'o' => (0xE80..=0xEFF).contains(&code),
+        'q' => (0xF00..=0xFFF).contains(&code),
+        'e' => (0x1200..=0x139F).contains(&code),
+        'h' => hangul(code),
+        'H' => hiragana(code),
+        'K' => katakana(code),
+        'k' => (0xFF61..=0xFF9F).contains(&code),
+        'r' => (0xFF01..=0xFF5E).contains(&code),
+        'C' => han(code),
+        'c' => han(code) || cjk_symbol(code) || (0x3100..=0x312F).contains(&code),
+        'j' => han(code) || hiragana(code) || katakana(code) || cjk_symbol(code),
+        '|' => han(code) || hiragana(code) || katakana(code) || hangul(code),
+        '^' => combining(code),
+        '.' => !combining(code),
+        'R' => right_to_left(code),
+        'L' => chr.is_alphabetic() && !right_to_left(code),
+        _ => false,
+    }
+}
+
+/// A bracket expression like `[a-z_]`.
+#[derive(Debug, Clone)]
+struct CharSet {
+    negate: bool,
+    ranges: Vec<(char, char)>,
+    classes: Vec<CharClass>,
+}
+
+impl CharSet {
+    fn matches(&self, chr: char, input: &Input) -> bool {
+        let contains = |chr| {
+            self.ranges.iter().any(|(beg, end)| (*beg..=*end).contains(&chr))
+                || self.classes.iter().any(|class| class.matches(chr, input.syntax))
+        };
+        // When ignoring case, a set contains a character if it contains either
+        // of its cases
+        let found = contains(chr)
+            || input.case_fold.is_some_and(|fold| {
+                let (lower, upper) = fold(chr);
+                contains(lower) || contains(upper)
+            });
+        found != self.negate
+    }
+}
+
+/// The zero-width assertions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    /// `^`
+    LineStart,
+    /// `$`
+    LineEnd,
+    /// `` \` ``
+    TextStart,
+    /// `\'`
+    TextEnd,
+    /// `\=`
+    Point,
+    /// `\b`
+    WordBoundary,
+    /// `\B`
+    NotWordBoundary,
+    /// `\<`
+    WordStart,
+    /// `\>`
+    WordEnd,
+    /// `\_<`
+    SymbolStart,
+    /// `\_>`
+    SymbolEnd,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    /// `.`, which matches anything but a newline
+    Any,
+    Set(CharSet),
+    /// `\sC` or `\SC` when negated
+    Syntax(char, bool),
+    /// `\cC` or `\CC` when negated
+    Category(char, bool),
+    Assert(Assertion),
+    Backref(usize),
+    /// A group, which is captured unless it is shy.
+    Group(Option<usize>, Box<Node>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+impl Node {
+    /// Whether the node can match without consuming any text.
+    fn nullable(&self) -> bool {
+        match self {
+            Node::Empty | Node::Assert(_) | Node::Backref(_) => true,
+            Node::Char(_) | Node::Any | Node::Set(_) | Node::Syntax(..) | Node::Category(..) => {
+                false
+            }
+            Node::Group(_, node) => node.nullable(),
+            Node::Concat(nodes) => nodes.iter().all(Node::nullable),
+            Node::Alt(nodes) => nodes.iter().any(Node::nullable),
+            Node::Repeat { node, min, .. } => *min == 0 || node.nullable(),
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    idx: usize,
+    /// The highest group number used so far.
+    max_group: usize,
+    /// The numbered groups that have been opened but not closed.
+    open: Vec<usize>,
+    /// The number of groups, including shy ones, that are open.
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.idx).copied()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.chars.get(self.idx + n).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let chr = self.peek()?;
+        self.idx += 1;
+        Some(chr)
+    }
+
+    fn eat(&mut self, chr: char) -> bool {
+        let found = self.peek() == Some(chr);
+        if found {
+            self.idx += 1;
+        }
+        found
+    }
+
+    /// Whether the pattern is at `\` followed by `chr`.
+    fn at_escape(&self, chr: char) -> bool {
+        self.peek() == Some('\\') && self.peek_nth(1) == Some(chr)
+    }
+
+    /// Whether the pattern is at the end of an alternative.
+    fn at_branch_end(&self) -> bool {
+        self.peek().is_none() || self.at_escape('|') || self.at_escape(')')
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_branch()?];
+        while self.at_escape('|') {
+            self.idx += 2;
+            branches.push(self.parse_branch()?);
+        }
+        Ok(if branches.len() == 1 { branches.pop().unwrap() } else { Node::Alt(branches) })
+    }
+
+    fn parse_branch(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        let start = self.idx;
+        // Postfix operators are literal when there is nothing to repeat
+        let mut can_repeat = false;
+        while !self.at_branch_end() {
+            let chr = self.next().unwrap();
+            let node = match chr {
+                '^' if self.idx - 1 == start => {
+                    nodes.push(Node::Assert(Assertion::LineStart));
+                    continue;
+                }
+                '$' if self.at_branch_end() => Node::Assert(Assertion::LineEnd),
+                '*' | '+' | '?' if can_repeat => {
+                    let greedy = !self.eat('?');
+                    let (min, max) = match chr {
+                        '*' => (0, None),
+                        '+' => (1, None),
+                        _ => (0, Some(1)),
+                    };
+                    let node = Box::new(nodes.pop().unwrap());
+                    nodes.push(Node::Repeat { node, min, max, greedy });
+                    continue;
+                }
+                '.' => Node::Any,
+                '[' => self.parse_set()?,
+                '\\' => match self.next() {
+                    Some('{') if can_repeat => {
+                        let (min, max) = self.parse_interval()?;
+                        let node = Box::new(nodes.pop().unwrap());
+                        nodes.push(Node::Repeat { node, min, max, greedy: true });
+                        continue;
+                    }
+                    Some(chr) => self.parse_escape(chr)?,
+                    None => return Err(TRAILING_BACKSLASH),
+                },
+                chr => Node::Char(chr),
+            };
+            nodes.push(node);
+            can_repeat = true;
+        }
+        if self.at_escape(')') && self.depth == 0 {
+            return Err(UNMATCHED_CLOSE);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    /// Parse the sequence after a backslash, except for `\|`, `\)` and `\{`.
+    fn parse_escape(&mut self, chr: char) -> Result<Node, RegexError> {
+        Ok(match chr {
+            '(' => self.parse_group()?,
+            '1'..='9' => {
+                let group = chr as usize - '0' as usize;
+                if group > self.max_group || self.open.contains(&group) {
+                    return Err(BAD_BACKREF);
+                }
+                Node::Backref(group)
+            }
+            'w' => Node::Syntax('w', false),
+            'W' => Node::Syntax('w', true),
+            's' | 'S' => {
+                let class = match self.next().ok_or(PREMATURE_END)? {
+                    '-' => ' ',
+                    class => class,
+                };
+                Node::Syntax(class, chr == 'S')
+            }
+            'c' | 'C' => Node::Category(self.next().ok_or(PREMATURE_END)?, chr == 'C'),
+            '`' => Node::Assert(Assertion::TextStart),
+            '\'' => Node::Assert(Assertion::TextEnd),
+            '=' => Node::Assert(Assertion::Point),
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            '<' => Node::Assert(Assertion::WordStart),
+            '>' => Node::Assert(Assertion::WordEnd),
+            '_' => match self.next() {
+                Some('<') => Node::Assert(Assertion::SymbolStart),
+                Some('>') => Node::Assert(Assertion::SymbolEnd),
+                _ => return Err(BAD_PATTERN),
+            },
+            chr => Node::Char(chr),
+        })
+    }
+
+    /// Parse a group after the `\(`.
+    fn parse_group(&mut self) -> Result<Node, RegexError> {
+        let group = if self.eat('?') {
+            if self.eat(':') {
+                None
+            } else {
+                let mut num = 0usize;
+                while let Some(digit) = self.peek().and_then(|x| x.to_digit(10)) {
+                    self.idx += 1;
+                    num = num
+                        .checked_mul(10)
+                        .and_then(|x| x.checked_add(digit as usize))
+                        .ok_or(TOO_BIG)?;
+                }
+                if num == 0 || !self.eat(':') {
+                    return Err(BAD_PATTERN);
+                }
+                self.max_group = self.max_group.max(num);
+                Some(num)
+            }
+        } else {
+            // Implicitly numbered groups come after every earlier group
+            self.max_group += 1;
+            Some(self.max_group)
+        };
+        self.open.extend(group);
+        self.depth += 1;
+        let node = self.parse_alt()?;
+        if !self.at_escape(')') {
+            return Err(UNMATCHED_OPEN);
+        }
+        self.idx += 2;
+        self.depth -= 1;
+        if group.is_some() {
+            self.open.pop();
+        }
+        Ok(Node::Group(group, Box::new(node)))
+    }
+
+    /// Parse the bounds of an interval after the `\{`.
+    fn parse_interval(&mut self) -> Result<(u32, Option<u32>), RegexError> {
+        let min = self.parse_count()?;
+        let max = if self.eat(',') { self.parse_count()? } else { Some(min.unwrap_or(0)) };
+        let min = min.unwrap_or(0);
+        match (self.next(), self.next()) {
+            (Some('\\'), Some('}')) => {}
+            (None, _) | (Some('\\'), None) => return Err(UNMATCHED_BRACE),
+            _ => return Err(BAD_INTERVAL),
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(BAD_INTERVAL);
+        }
+        Ok((min, max))
+    }
+
+    /// Parse one of the optional numbers of an interval.
+    fn parse_count(&mut self) -> Result<Option<u32>, RegexError> {
+        let mut num: Option<u32> = None;
+        while let Some(digit) = self.peek().and_then(|x| x.to_digit(10)) {
+            self.idx += 1;
+            let next = num.unwrap_or(0) * 10 + digit;
+            if next > DUP_MAX {
+                return Err(TOO_BIG);
+            }
+            num = Some(next);
+        }
+        Ok(num)
+    }
+
+    /// Parse a bracket expression after the `[`.
+    fn parse_set(&mut self) -> Result<Node, RegexError> {
+        let negate = self.eat('^');
+        let mut set = CharSet { negate, ranges: Vec::new(), classes: Vec::new() };
+        let mut first = true;
+        loop {
+            let chr = self.next().ok_or(UNMATCHED_BRACKET)?;
+            if chr == ']' && !first {
+                break;
+            }
+            first = false;
+            if chr == '[' && self.peek() == Some(':') {
+                if let Some(class) = self.parse_class()? {
+                    set.classes.push(class);
+                    continue;
+                }
+            }
+            // A `-` is literal at the end of the set
+            if self.peek() == Some('-') && self.peek_nth(1).is_some_and(|x| x != ']') {
+                let end = self.peek_nth(1).unwrap();
+                self.idx += 2;
+                // Reversed ranges are empty
+                if chr <= end {
+                    set.ranges.push((chr, end));
+                }
+            } else {
+                set.ranges.push((chr, chr));
+            }
+        }
+        Ok(Node::Set(set))
+    }
+
+    /// Parse a character class like `[:alpha:]` after the first `[`. If the
+    /// class is not terminated, the `[` is literal and `None` is returned.
+    fn parse_class(&mut self) -> Result<Option<CharClass>, RegexError> {
+        let start = self.idx + 1;
+        let len = self.chars[start..].iter().take_while(|x| x.is_ascii_lowercase()).count();
+        let end = start + len;
+        if self.chars.get(end) != Some(&':') || self.chars.get(end + 1) != Some(&']') {
+            return Ok(None);
+        }
+        let name: String = self.chars[start..end].iter().collect();
+        let class = CharClass::from_name(&name).ok_or(BAD_CLASS)?;
+        self.idx = end + 2;
+        Ok(Some(class))
+    }
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Set(CharSet),
+    Syntax(char, bool),
+    Category(char, bool),
+    Assert(Assertion),
+    Backref(usize),
+    /// Record the current position in a slot.
+    Save(usize),
+    /// Continue at the first target, and try the second if that fails.
+    Split(usize, usize),
+    Jump(usize),
+    /// The end of an iteration of a loop whose body can match the empty
+    /// string. The loop is exited if the iteration did not advance past the
+    /// position saved at its start, so that it can't run forever.
+    Loop {
+        slot: usize,
+        head: usize,
+        exit: usize,
+    },
+    Match,
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+    /// The number of slots, which are pairs for each group followed by the
+    /// slots used by loops.
+    slots: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.prog.len() >= MAX_PROGRAM {
+            return Err(TOO_BIG);
+        }
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn compile(&mut self, node: Node) -> Result<(), RegexError> {
+        let inst = match node {
+            Node::Empty => return Ok(()),
+            Node::Char(chr) => Inst::Char(chr),
+            Node::Any => Inst::Any,
+            Node::Set(set) => Inst::Set(set),
+            Node::Syntax(class, negate) => Inst::Syntax(class, negate),
+            Node::Category(cat, negate) => Inst::Category(cat, negate),
+            Node::Assert(kind) => Inst::Assert(kind),
+            Node::Backref(group) => Inst::Backref(group),
+            Node::Group(Some(group), node) => {
+                self.emit(Inst::Save(group * 2))?;
+                self.compile(*node)?;
+                Inst::Save(group * 2 + 1)
+            }
+            Node::Group(None, node) => return self.compile(*node),
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+                return Ok(());
+            }
+            Node::Alt(mut nodes) => {
+                let last = nodes.pop().unwrap();
+                let mut jumps = Vec::new();
+                for node in nodes {
+                    let split = self.emit(Inst::Split(0, 0))?;
+                    self.compile(node)?;
+                    jumps.push(self.emit(Inst::Jump(0))?);
+                    self.prog[split] = Inst::Split(split + 1, self.prog.len());
+                }
+                self.compile(last)?;
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jump(end);
+                }
+                return Ok(());
+            }
+            Node::Repeat { node, min, max, greedy } => {
+                return self.compile_repeat(&node, min, max, greedy);
+            }
+        };
+        self.emit(inst)?;
+        Ok(())
+    }
+
+    fn compile_repeat(
+        &mut self,
+        node: &Node,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    ) -> Result<(), RegexError> {
+        // The node is compiled once for every repetition
+        for _ in 0..min {
+            self.compile(node.clone())?;
+        }
+        let split =
+            |body, exit| if greedy { Inst::Split(body, exit) } else { Inst::Split(exit, body) };
+        match max {
+            Some(max) => {
+                let mut splits = Vec::new();
+                for _ in min..max {
+                    splits.push(self.emit(Inst::Split(0, 0))?);
+                    self.compile(node.clone())?;
+                }
+                let exit = self.prog.len();
+                for idx in splits {
+                    self.prog[idx] = split(idx + 1, exit);
+                }
+            }
+            None => {
+                let head = self.emit(Inst::Split(0, 0))?;
+                if node.nullable() {
+                    let slot = self.slots;
+                    self.slots += 1;
+                    self.emit(Inst::Save(slot))?;
+                    self.compile(node.clone())?;
+                    let end = self.prog.len();
+                    self.emit(Inst::Loop { slot, head, exit: end + 1 })?;
+                } else {
+                    self.compile(node.clone())?;
+                    self.emit(Inst::Jump(head))?;
+                }
+                let exit = self.prog.len();
+                self.prog[head] = split(head + 1, exit);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A compiled Emacs regular expression.
+#[derive(Debug)]
+pub(crate) struct Regex {
+    prog: Vec<Inst>,
+    /// The number of groups, including group 0.
+    groups: usize,
+    slots: usize,
+    /// A character that every match starts with.
+    first: Option<char>,
+    /// Matches can only start at the beginning of the text.
+    anchored: bool,
+}
+
+enum Frame {
+    /// Retry at an instruction and position.
+    Retry(usize, Pos),
+    /// Restore the old value of a slot.
+    Restore(usize, Option<Pos>),
+}
+
+impl Regex {
+    pub(crate) fn new(pattern: &str) -> Result<Self, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            idx: 0,
+            max_group: 0,
+            open: Vec::new(),
+            depth: 0,
+        };
+        let node = parser.parse_alt()?;
+        debug_assert!(parser.peek().is_none());
+        let groups = parser.max_group + 1;
+        let mut compiler = Compiler { prog: Vec::new(), slots: groups * 2 };
+        compiler.emit(Inst::Save(0))?;
+        compiler.compile(node)?;
+        compiler.emit(Inst::Save(1))?;
+        compiler.emit(Inst::Match)?;
+        let Compiler { prog, slots } = compiler;
+
+        let mut first = None;
+        let mut anchored = false;
+        for inst in &prog {
+            match inst {
+                Inst::Save(_) => continue,
+                Inst::Char(chr) => first = Some(*chr),
+                Inst::Assert(Assertion::TextStart) => anchored = true,
+                _ => {}
+            }
+            break;
+        }
+        Ok(Self { prog, groups, slots, first, anchored })
+    }
+
+    /// Match the regex starting exactly at `start`, without consuming any
+    /// text at or after the byte offset `stop`.
+    pub(crate) fn match_at(
+        &self,
+        input: &Input,
+        start: Pos,
+        stop: usize,
+    ) -> Result<Option<Captures>> {
+        let text = input.text;
+        let syntax = input.syntax;
+        let next = |pos: Pos| if pos.byte < stop { text.next_char(pos.byte) } else { None };
+        let mut slots: Vec<Option<Pos>> = vec![None; self.slots];
+        let mut stack = Vec::new();
+        let mut best: Option<(Pos, Vec<Option<Pos>>)> = None;
+        let (mut pc, mut pos) = (0, start);
+        loop {
+            let matched = match &self.prog[pc] {
+                Inst::Char(chr) => next(pos).is_some_and(|x| input.same(x, *chr)),
+                Inst::Any => next(pos).is_some_and(|x| x != '\n'),
+                Inst::Set(set) => next(pos).is_some_and(|x| set.matches(x, input)),
+                Inst::Syntax(class, negate) => {
+                    next(pos).is_some_and(|x| (syntax(x) == *class) != *negate)
+                }
+                Inst::Category(cat, negate) => {
+                    next(pos).is_some_and(|x| in_category(x, *cat) != *negate)
+                }
+                Inst::Assert(kind) => {
+                    if self.assert(*kind, pos, input) {
+                        pc += 1;
+                        continue;
+                    }
+                    false
+                }
+                Inst::Backref(group) => {
+                    // Groups that did not match can't be referenced
+                    if let (Some(beg), Some(end)) = (slots[group * 2], slots[group * 2 + 1]) {
+                        let (front, back) = text.slice(beg.byte, end.byte);
+                        let mut end = pos;
+                        let found = front.chars().chain(back.chars()).all(|chr| {
+                            let found = next(end).is_some_and(|x| input.same(x, chr));
+                            end = end.advance(chr);
+                            found
+                        });
+                        if found {
+                            pos = end;
+                            pc += 1;
+                            continue;
+                        }
+                    }
+                    false
+                }
+                Inst::Save(slot) => {
+                    stack.push(Frame::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(pos);
+                    pc += 1;
+                    continue;
+                }
+                Inst::Split(first, second) => {
+                    if stack.len() >= MAX_FAILURES {
+                        bail!("Stack overflow in regexp matcher");
+                    }
+                    stack.push(Frame::Retry(*second, pos));
+                    pc = *first;
+                    continue;
+                }
+                Inst::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+                Inst::Loop { slot, head, exit } => {
+                    pc = if slots[*slot] == Some(pos) { *exit } else { *head };
+                    continue;
+                }
+                Inst::Match => {
+                    if !input.posix {
+                        return Ok(Some(self.captures(&slots)));
+                    }
+                    // Keep backtracking to find the longest match
+                    if best.as_ref().is_none_or(|(end, _)| pos > *end) {
+                        best = Some((pos, slots.clone()));
+                    }
+                    false
+                }
+            };
+            if matched {
+                // Only instructions that consume a character get here
+                pos = pos.advance(next(pos).unwrap());
+                pc += 1;
+                continue;
+            }
+            loop {
+                match stack.pop() {
+                    Some(Frame::Retry(retry_pc, retry_pos)) => {
+                        (pc, pos) = (retry_pc, retry_pos);
+                        break;
+                    }
+                    Some(Frame::Restore(slot, old)) => slots[slot] = old,
+                    None => return Ok(best.map(|(_, slots)| self.captures(&slots))),
+                }
+            }
+        }
+    }
+
+    fn assert(&self, kind: Assertion, pos: Pos, input: &Input) -> bool {
+        let text = input.text;
+        let prev = text.prev_char(pos.byte);
+        let next = text.next_char(pos.byte);
+        let syntax = |chr: Option<char>| chr.map(input.syntax);
+        let word = |chr| syntax(chr) == Some('w');
+        let symbol = |chr| matches!(syntax(chr), Some('w' | '_'));
+        match kind {
+            Assertion::LineStart => prev.is_none_or(|x| x == '\n'),
+            Assertion::LineEnd => next.is_none_or(|x| x == '\n'),
+            Assertion::TextStart => pos.byte == 0,
+            Assertion::TextEnd => pos.byte == text.len(),
+            Assertion::Point => input.point.is_some_and(|x| x.byte == pos.byte),
+            Assertion::WordBoundary => prev.is_none() || next.is_none() || word(prev) != word(next),
+            Assertion::NotWordBoundary => {
+                prev.is_some() && next.is_some() && word(prev) == word(next)
+            }
+            Assertion::WordStart => word(next) && !word(prev),
+            Assertion::WordEnd => word(prev) && !word(next),
+            Assertion::SymbolStart => symbol(next) && !symbol(prev),
+            Assertion::SymbolEnd => symbol(prev) && !symbol(next),
+        }
+    }
+
+    fn captures(&self, slots: &[Option<Pos>]) -> Captures {
+        let groups = slots[..self.groups * 2].chunks(2);
+        Captures(groups.map(|pair| pair[0].zip(pair[1])).collect())
+    }
+
+    /// Whether a match could start at `pos`.
+    fn can_start(&self, pos: Pos, input: &Input) -> bool {
+        if self.anchored && pos.byte != 0 {
+            return false;
+        }
+        let next = input.text.next_char(pos.byte);
+        self.first.is_none_or(|first| next.is_some_and(|x| input.same(x, first)))
+    }
+
+    /// Find the first match that starts between `start` and `bound`. The
+    /// match can't extend past `bound`.
+    pub(crate) fn search_forward(
+        &self,
+        input: &Input,
+        start: Pos,
+        bound: Pos,
+    ) -> Result<Option<Captures>> {
+        let mut pos = start;
+        loop {
+            if self.can_start(pos, input) {
+                if let Some(captures) = self.match_at(input, pos, bound.byte)? {
+                    return Ok(Some(captures));
+                }
+            }
+            if pos.byte >= bound.byte {
+                return Ok(None);
+            }
+            pos = pos.advance(input.text.next_char(pos.byte).unwrap());
+        }
+    }
+
+    /// Find the last match that starts between `bound` and `start`. The match
+    /// can't extend past `start`.
+    pub(crate) fn search_backward(
+        &self,
+        input: &Input,
+        start: Pos,
+        bound: Pos,
+    ) -> Result<Option<Captures>> {
+        let mut pos = start;
+        loop {
+            if self.can_start(pos, input) {
+                if let Some(captures) = self.match_at(input, pos, start.byte)? {
+                    return Ok(Some(captures));
+                }
+            }
+            if pos.byte <= bound.byte {
+                return Ok(None);
+            }
+            pos = pos.retreat(input.text.prev_char(pos.byte).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn search_in(re: &str, input: &Input) -> Option<Vec<Option<(usize, usize)>>> {
+        let re = Regex::new(re).unwrap();
+        let end = input.text.end();
+        let captures = re.search_forward(input, Pos::default(), end).unwrap()?;
+        Some(captures.iter().map(|x| x.map(|(beg, end)| (beg.char, end.char))).collect())
+    }
+
+    fn search(re: &str, text: &str) -> Option<Vec<Option<(usize, usize)>>> {
+        search_in(re, &Input::new(Text::new(text, "")))
+    }
+
+    fn span(re: &str, text: &str) -> Option<(usize, usize)> {
+        search(re, text).and_then(|x| x[0])
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(span("foo", "a foo"), Some((2, 5)));
+        assert_eq!(span("fooo", "a foo"), None);
+        assert_eq!(span("", "abc"), Some((0, 0)));
+        assert_eq!(span("a.c", "a\nc abc"), Some((4, 7)));
+        assert_eq!(span("(a)", "x(a)"), Some((1, 4)));
+        assert_eq!(span("\\.\\*", "a.*"), Some((1, 3)));
+        assert_eq!(span("λx", "(λx)"), Some((1, 3)));
+        // Postfix operators with nothing before them are literal
+        assert_eq!(span("*a", "a*a"), Some((1, 3)));
+        assert_eq!(span("^*", "*"), Some((0, 1)));
+        assert_eq!(span("a\\|+", "+"), Some((0, 1)));
+        assert_eq!(span("a^b$c", "a^b$c"), Some((0, 5)));
+    }
+
+    #[test]
+    fn test_repetition() {
+        assert_eq!(span("ab*", "abbbc"), Some((0, 4)));
+        assert_eq!(span("ab+", "ac abb"), Some((3, 6)));
+        assert_eq!(span("ab?c", "abc"), Some((0, 3)));
+        assert_eq!(span("a.*b", "a1b2b3"), Some((0, 5)));
+        assert_eq!(span("a.*?b", "a1b2b3"), Some((0, 3)));
+        assert_eq!(span("a.+?", "abc"), Some((0, 2)));
+        assert_eq!(span("ab??", "abc"), Some((0, 1)));
+        assert_eq!(span("a\\{2\\}", "aaaa"), Some((0, 2)));
+        assert_eq!(span("a\\{2,3\\}", "aaaa"), Some((0, 3)));
+        assert_eq!(span("a\\{,2\\}", "aaaa"), Some((0, 2)));
+        assert_eq!(span("a\\{2,\\}", "aaaa"), Some((0, 4)));
+        assert_eq!(span("ba\\{2,\\}", "bab"), None);
+        assert_eq!(span("x\\{\\}", "x"), Some((0, 0)));
+        // Loops that match the empty string terminate
+        assert_eq!(span("\\(a*\\)*b", "aab"), Some((0, 3)));
+        assert_eq!(search("\\(a*\\)*", "b"), Some(vec![Some((0, 0)), Some((0, 0))]));
+    }
+
+    #[test]
+    fn test_groups() {
+        assert_eq!(search("\\(a\\)\\(b\\)?c", "ac"), Some(vec![Some((0, 2)), Some((0, 1))]));
+        assert_eq!(
+            search("\\(a\\)\\(b\\)?\\(c\\)", "ac"),
+            Some(vec![Some((0, 2)), Some((0, 1)), None, Some((1, 2))])
+        );
+        assert_eq!(search("\\(?:ab\\)+", "abab"), Some(vec![Some((0, 4))]));
+        assert_eq!(
+            search("\\(?2:a\\)\\(b\\)", "ab"),
+            Some(vec![Some((0, 2)), None, Some((0, 1)), Some((1, 2))])
+        );
+        assert_eq!(span("foo\\|bar", "xbar"), Some((1, 4)));
+        assert_eq!(span("\\(a\\|ab\\)c", "abc"), Some((0, 3)));
+        assert_eq!(search("\\(.\\)*", "abc"), Some(vec![Some((0, 3)), Some((2, 3))]));
+        assert_eq!(span("\\(a+\\)b\\1", "aabaa"), Some((0, 5)));
+        assert_eq!(span("\\(a+\\)b\\1$", "aaba"), Some((1, 4)));
+        assert_eq!(span("\\(x\\)?y\\1", "y"), None);
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert_eq!(span("^b", "ab\nb"), Some((3, 4)));
+        assert_eq!(span("a$", "ab\na"), Some((3, 4)));
+        assert_eq!(span("\\`a", "ba"), None);
+        assert_eq!(span("a\\'", "a\na"), Some((2, 3)));
+        assert_eq!(span("\\(^a\\)", "ba\na"), Some((3, 4)));
+        assert_eq!(span("x\\|^a", "ba\na"), Some((3, 4)));
+        let mut input = Input::new(Text::new("aaa", ""));
+        input.point = Some(Pos::new(1, 1));
+        assert_eq!(search_in("\\=a", &input), Some(vec![Some((1, 2))]));
+        assert_eq!(search_in("a\\=", &input), Some(vec![Some((0, 1))]));
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(span("\\<bar", "foobar bar"), Some((7, 10)));
+        assert_eq!(span("foo\\>", "foobar foo"), Some((7, 10)));
+        assert_eq!(span("\\bb", "ab b"), Some((3, 4)));
+        assert_eq!(span("\\Bb", "b ab"), Some((3, 4)));
+        assert_eq!(span("\\w+", "-- abc --"), Some((3, 6)));
+        assert_eq!(span("\\W+", "abc --"), Some((3, 6)));
+        assert_eq!(span("\\_<bar\\_>", "foo-bar bar"), Some((8, 11)));
+        assert_eq!(span("\\s-+", "a \t b"), Some((1, 4)));
+        assert_eq!(span("\\s(\\S)*\\s)", "x (a b) y"), Some((2, 7)));
+        assert_eq!(span("\\s_", "ab-c"), Some((2, 3)));
+        // Syntax comes from the syntax function
+        let mut input = Input::new(Text::new("foo-bar", ""));
+        let syntax = |chr| if chr == '-' { 'w' } else { crate::syntax::standard_syntax(chr) };
+        input.syntax = &syntax;
+        assert_eq!(search_in("\\w+", &input), Some(vec![Some((0, 7))]));
+    }
+
+    #[test]
+    fn test_sets() {
+        assert_eq!(span("[abc]+", "xxbcay"), Some((2, 5)));
+        assert_eq!(span("[^abc]+", "abxy\nc"), Some((2, 5)));
+        assert_eq!(span("[]a]+", "b]a]"), Some((1, 4)));
+        assert_eq!(span("[a-]+", "b-a"), Some((1, 3)));
+        assert_eq!(span("[z-a]", "za"), None);
+        assert_eq!(span("[\\]+", "a\\\\"), Some((1, 3)));
+        assert_eq!(span("[[:digit:]]+", "ab123"), Some((2, 5)));
+        assert_eq!(span("[[:alpha:]]+", "12éa3"), Some((2, 4)));
+        assert_eq!(span("[[:space:]]+", "a  b"), Some((1, 3)));
+        assert_eq!(span("[[:upper:][:digit:]]+", "aB1c"), Some((1, 3)));
+        assert_eq!(span("[[:punct:]]", "ab,"), Some((2, 3)));
+        assert_eq!(span("[[:word:]]+", "é_x"), Some((0, 1)));
+        assert_eq!(span("[[:nonascii:]]", "aλ"), Some((1, 2)));
+        assert_eq!(span("[[:xdigit:]]+", "xBEEFy"), Some((1, 5)));
+        assert_eq!(span("[[:alpha]", "b:"), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_categories() {
+        assert_eq!(span("\\cg+", "abλμ"), Some((2, 4)));
+        assert_eq!(span("\\Ca+", "abλμ"), Some((2, 4)));
+        assert_eq!(span("\\cC", "a漢"), Some((1, 2)));
+        assert_eq!(span("\\cH", "aひ"), Some((1, 2)));
+    }
+
+    #[test]
+    fn test_gap() {
+        let input = Input::new(Text::new("hello wo", "rld λ"));
+        assert_eq!(search_in("wor", &input), Some(vec![Some((6, 9))]));
+        assert_eq!(search_in("o\\b", &input), Some(vec![Some((4, 5))]));
+        assert_eq!(search_in("d \\(λ\\)\\'", &input), Some(vec![Some((10, 13)), Some((12, 13))]));
+        assert_eq!(search_in("\\(o\\).*\\1r", &input), Some(vec![Some((4, 9)), Some((4, 5))]));
+        let input = Input::new(Text::new("ab", "ab"));
+        assert_eq!(search_in("\\(ab\\)\\1", &input), Some(vec![Some((0, 4)), Some((0, 2))]));
+        let text = Text::new("aλ", "bc");
+        assert_eq!(text.pos(2), Some(Pos::new(3, 2)));
+        assert_eq!(text.pos(4), Some(Pos::new(5, 4)));
+        assert_eq!(text.pos(5), None);
+        assert_eq!(text.end(), Pos::new(5, 4));
+        assert_eq!(text.slice(1, 4), ("λ", "b"));
+    }
+
+    #[test]
+    fn test_search_bounds() {
+        let re = Regex::new("a+").unwrap();
+        let input = Input::new(Text::new("xaaxaa", ""));
+        let pos = |x| input.text.pos(x).unwrap();
+        let found = re.search_forward(&input, pos(0), pos(2)).unwrap().unwrap();
+        assert_eq!(found.get(0), Some((pos(1), pos(2))));
+        let found = re.search_forward(&input, pos(2), pos(6)).unwrap().unwrap();
+        assert_eq!(found.get(0), Some((pos(2), pos(3))));
+        let found = re.search_backward(&input, pos(6), pos(0)).unwrap().unwrap();
+        assert_eq!(found.get(0), Some((pos(5), pos(6))));
+        let found = re.search_backward(&input, pos(3), pos(0)).unwrap().unwrap();
+        assert_eq!(found.get(0), Some((pos(2), pos(3))));
+        assert!(re.search_backward(&input, pos(6), pos(6)).unwrap().is_none());
+        assert!(re.match_at(&input, pos(0), 6).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_case_fold() {
+        let fold = |chr: char| (chr.to_ascii_lowercase(), chr.to_ascii_uppercase());
+        let mut input = Input::new(Text::new("xFoO Bar", ""));
+        assert_eq!(search_in("foo", &input), None);
+        input.case_fold = Some(&fold);
+        assert_eq!(search_in("foo", &input), Some(vec![Some((1, 4))]));
+        assert_eq!(search_in("[a-c]+r", &input), Some(vec![Some((5, 8))]));
+        assert_eq!(search_in("[^a-z]", &input), Some(vec![Some((4, 5))]));
+        assert_eq!(search_in("\\(o\\)\\1", &input), Some(vec![Some((2, 4)), Some((2, 3))]));
+    }
+
+    #[test]
+    fn test_posix() {
+        let mut input = Input::new(Text::new("abcd", ""));
+        assert_eq!(search_in("a\\|ab\\|abc", &input), Some(vec![Some((0, 1))]));
+        input.posix = true;
+        assert_eq!(search_in("a\\|ab\\|abc", &input), Some(vec![Some((0, 3))]));
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |re| Regex::new(re).unwrap_err().to_string();
+        assert_eq!(err("\\(a"), "Unmatched ( or \\(");
+        assert_eq!(err("a\\)"), "Unmatched ) or \\)");
+        assert_eq!(err("[a"), "Unmatched [ or [^");
+        assert_eq!(err("a\\"), "Trailing backslash");
+        assert_eq!(err("\\(a\\1\\)"), "Invalid back reference");
+        assert_eq!(err("\\2\\(a\\)"), "Invalid back reference");
+        assert_eq!(err("[[:foo:]]"), "Invalid character class name");
+        assert_eq!(err("a\\{2,1\\}"), "Invalid content of \\{\\}");
+        assert_eq!(err("a\\{2"), "Unmatched \\{");
+        assert_eq!(err("\\_a"), "Invalid regular expression");
+        assert_eq!(err("\\(?a\\)"), "Invalid regular expression");
+        assert!(Regex::new("\\{2\\}").is_ok());
+    }
+}
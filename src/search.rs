@@ -1,79 +1,520 @@
 //! Search utilities.
+use crate::casefiddle::{CaseMode, CaseTable, Casing};
 use crate::core::{
-    cons::Cons,
-    env::Env,
+    env::{Env, sym},
+    error::SignalError,
     gc::{Context, Rt},
-    object::{List, NIL, Object, ObjectType, OptionalFlag},
+    object::{
+        BufferData, IntOrMarker, LispBuffer, LispMarker, List, MarkerInner, NIL, Object,
+        ObjectType, OptionalFlag,
+    },
 };
-use anyhow::{Result, bail, ensure};
+use crate::data::LispError;
+use crate::regex::{Input, Pos, Regex, Text};
+use crate::syntax::{SyntaxClass, SyntaxTable};
+use crate::textprops::{insert_textprops, verify_modification};
+use anyhow::{Result, bail};
 use fallible_iterator::FallibleIterator;
-use fancy_regex::Regex;
+use rune_core::macros::list;
 use rune_macros::defun;
 
+/// Compile `regexp`, signaling `invalid-regexp` if it is malformed.
+fn compile_regex(regexp: &str, cx: &Context) -> Result<Regex> {
+    match Regex::new(regexp) {
+        Ok(re) => Ok(re),
+        Err(err) => {
+            let error = list![sym::INVALID_REGEXP, err.to_string(); cx];
+            bail!(LispError::new(error.try_into()?))
+        }
+    }
+}
+
+defvar!(CASE_FOLD_SEARCH, true);
+
+/// The case table used to ignore case in searches, which is only done if
+/// `case-fold-search` is non-nil.
+fn case_fold<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Option<CaseTable<'ob>> {
+    let fold = env.var(sym::CASE_FOLD_SEARCH, cx).is_some_and(|x| !x.is_nil());
+    fold.then(|| CaseTable::current(env, cx))
+}
+
 #[defun]
 fn string_match<'ob>(
     regexp: &str,
     string: &str,
     start: Option<i64>,
-    _inhibit_modify: OptionalFlag,
+    inhibit_modify: OptionalFlag,
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    // TODO: implement inhibit-modify
-    let re = Regex::new(&lisp_regex_to_rust(regexp))?;
-
-    let start = start.unwrap_or(0) as usize;
-    if let Some(matches) = re.captures_iter(&string[start..]).next() {
-        let mut all: Vec<Object> = Vec::new();
-        let matches = matches?;
-        let mut groups = matches.iter();
-        // TODO: match data should be char position, not byte
-        while let Some(Some(group)) = groups.next() {
-            all.push(group.start().into());
-            all.push(group.end().into());
-        }
-        let match_data = crate::fns::slice_into_list(&all, None, cx);
-        env.match_data.set(match_data);
-        let head: &Cons = match_data.try_into().unwrap();
-        Ok(head.car())
-    } else {
-        Ok(NIL)
+    let re = compile_regex(regexp, cx)?;
+    let text = Text::new(string, "");
+    let start_arg = start.unwrap_or(0);
+    let start = match start_arg {
+        ..0 => (string.chars().count() as i64 + start_arg).try_into().ok(),
+        _ => usize::try_from(start_arg).ok(),
+    };
+    let Some(start) = start.and_then(|x| text.pos(x)) else {
+        bail!(SignalError::args_out_of_range(&[cx.add(string), cx.add(start_arg)]))
+    };
+
+    let table = SyntaxTable::current(env, cx);
+    let syntax = |chr| table.class(chr).designator();
+    let fold = case_fold(env, cx);
+    let variants = |chr| fold.map_or((chr, chr), |table| table.variants(chr));
+    let mut input = Input::new(text);
+    if fold.is_some() {
+        input.case_fold = Some(&variants);
+    }
+    input.syntax = &syntax;
+    let Some(captures) = re.search_forward(&input, start, text.end())? else {
+        return Ok(NIL);
+    };
+    if inhibit_modify.is_none() {
+        let groups = captures.iter().map(|x| x.map(|(beg, end)| (beg.char, end.char)));
+        set_match_data_from(groups, None, env, cx);
+    }
+    let (beg, _) = captures.get(0).unwrap();
+    Ok(beg.char.into())
+}
+
+/// Set the match data to the start and end of each group. Groups that did not
+/// match are recorded as nil. `buffer` is the buffer that was searched, or
+/// `None` for a string.
+fn set_match_data_from(
+    groups: impl Iterator<Item = Option<(usize, usize)>>,
+    buffer: Option<&LispBuffer>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) {
+    let mut data: Vec<Object> = Vec::new();
+    for group in groups {
+        match group {
+            Some((beg, end)) => data.extend([cx.add(beg), cx.add(end)]),
+            None => data.extend([NIL, NIL]),
+        }
+    }
+    env.match_data.set(crate::fns::slice_into_list(&data, None, cx));
+    env.set_match_buffer(buffer);
+}
+
+/// The start and end of each group in the match data, or `None` for groups
+/// that did not match.
+fn match_groups(env: &Rt<Env>, cx: &Context) -> Result<Vec<Option<(usize, usize)>>> {
+    let mut data = Vec::new();
+    for elem in env.match_data.bind(cx).as_list()? {
+        data.push(elem?);
+    }
+    let pos = |obj: &Object| match obj.untag() {
+        ObjectType::Int(pos) => usize::try_from(pos).ok(),
+        _ => None,
+    };
+    Ok(data
+        .chunks(2)
+        .map(|pair| pos(&pair[0]).zip(pair.get(1).and_then(pos)))
+        .collect())
+}
+
+/// The position of the character index `chr` in the accessible portion of
+/// `buffer`.
+fn buffer_pos(buffer: &BufferData, chr: usize) -> Pos {
+    let begv = buffer.begv();
+    let (front, back) = buffer.text.slice(begv..chr);
+    Pos::new(front.len() + back.len(), chr - begv)
+}
+
+/// Search the current buffer for `re`, starting at point. This implements the
+/// BOUND, NOERROR and COUNT arguments shared by the search commands. A
+/// negative `count` searches backward. `string` is the pattern that is
+/// reported if the search fails.
+#[expect(clippy::too_many_arguments)]
+fn search_command<'ob>(
+    string: &str,
+    re: &Regex,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: i64,
+    posix: bool,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let buffer = env.current_buffer.get();
+    let (begv, zv) = (buffer.begv(), buffer.zv());
+    let point = buffer.text.cursor().chars();
+    let lim = match bound {
+        None if count > 0 => zv,
+        None => begv,
+        Some(bound) => {
+            let lim = bound.int() - 1;
+            let point = point as i64;
+            if (count > 0 && lim < point) || (count <= 0 && lim > point) {
+                bail!("Invalid search bound (wrong side of point)");
+            }
+            lim.clamp(begv as i64, zv as i64) as usize
+        }
+    };
+    let searched = buffer.lisp_buffer(cx);
+    if count == 0 {
+        let groups = std::iter::once(Some((point + 1, point + 1)));
+        set_match_data_from(groups, Some(searched), env, cx);
+        return Ok(cx.add(point + 1));
+    }
+
+    let (front, back) = buffer.text.slice(begv..zv);
+    let table = SyntaxTable::current(env, cx);
+    let syntax = |chr| table.class(chr).designator();
+    let fold = case_fold(env, cx);
+    let variants = |chr| fold.map_or((chr, chr), |table| table.variants(chr));
+    let mut input = Input::new(Text::new(front, back));
+    input.point = Some(buffer_pos(buffer, point));
+    input.posix = posix;
+    if fold.is_some() {
+        input.case_fold = Some(&variants);
+    }
+    input.syntax = &syntax;
+    let bound = buffer_pos(buffer, lim);
+    let mut pos = buffer_pos(buffer, point);
+    let mut found = None;
+    let mut remaining = count.unsigned_abs();
+    while remaining > 0 {
+        let captures = if count > 0 {
+            re.search_forward(&input, pos, bound)?
+        } else {
+            re.search_backward(&input, pos, bound)?
+        };
+        let Some(captures) = captures else { break };
+        let (beg, end) = captures.get(0).unwrap();
+        pos = if count > 0 { end } else { beg };
+        found = Some(captures);
+        remaining -= 1;
+    }
+
+    // The match data holds the last match, even if there were not enough
+    if let Some(captures) = found {
+        let to_buffer = |pos: Pos| begv + pos.char + 1;
+        let groups = captures.iter().map(|x| x.map(|(beg, end)| (to_buffer(beg), to_buffer(end))));
+        set_match_data_from(groups, Some(searched), env, cx);
+    }
+    if remaining == 0 {
+        let pos = begv + pos.char;
+        env.current_buffer.get_mut().text.set_cursor(pos);
+        return Ok(cx.add(pos + 1));
+    }
+    match noerror {
+        None => {
+            let error = list![sym::SEARCH_FAILED, string; cx];
+            bail!(LispError::new(error.try_into()?))
+        }
+        Some(noerror) if noerror == sym::TRUE => {}
+        Some(_) => env.current_buffer.get_mut().text.set_cursor(lim),
+    }
+    Ok(NIL)
+}
+
+#[defun]
+fn re_search_forward<'ob>(
+    regexp: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(regexp, cx)?;
+    search_command(regexp, &re, bound, noerror, count.unwrap_or(1), false, env, cx)
+}
+
+#[defun]
+fn re_search_backward<'ob>(
+    regexp: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(regexp, cx)?;
+    search_command(regexp, &re, bound, noerror, -count.unwrap_or(1), false, env, cx)
+}
+
+#[defun]
+fn posix_search_forward<'ob>(
+    regexp: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(regexp, cx)?;
+    search_command(regexp, &re, bound, noerror, count.unwrap_or(1), true, env, cx)
+}
+
+#[defun]
+fn posix_search_backward<'ob>(
+    regexp: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(regexp, cx)?;
+    search_command(regexp, &re, bound, noerror, -count.unwrap_or(1), true, env, cx)
+}
+
+#[defun]
+fn search_forward<'ob>(
+    string: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(&regexp_quote(string), cx)?;
+    search_command(string, &re, bound, noerror, count.unwrap_or(1), false, env, cx)
+}
+
+#[defun]
+fn search_backward<'ob>(
+    string: &str,
+    bound: Option<IntOrMarker>,
+    noerror: Option<Object>,
+    count: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let re = compile_regex(&regexp_quote(string), cx)?;
+    search_command(string, &re, bound, noerror, -count.unwrap_or(1), false, env, cx)
+}
+
+/// Whether the text after point matches `regexp`. The match data is only set
+/// if `modify` is true.
+fn looking_at_inner(
+    regexp: &str,
+    modify: bool,
+    posix: bool,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let re = compile_regex(regexp, cx)?;
+    let buffer = env.current_buffer.get();
+    let (begv, zv) = (buffer.begv(), buffer.zv());
+    let (front, back) = buffer.text.slice(begv..zv);
+    let point = buffer_pos(buffer, buffer.text.cursor().chars());
+    let table = SyntaxTable::current(env, cx);
+    let syntax = |chr| table.class(chr).designator();
+    let fold = case_fold(env, cx);
+    let variants = |chr| fold.map_or((chr, chr), |table| table.variants(chr));
+    let mut input = Input::new(Text::new(front, back));
+    input.point = Some(point);
+    input.posix = posix;
+    if fold.is_some() {
+        input.case_fold = Some(&variants);
+    }
+    input.syntax = &syntax;
+    let Some(captures) = re.match_at(&input, point, input.text.len())? else {
+        return Ok(false);
+    };
+    if modify {
+        let to_buffer = |pos: Pos| begv + pos.char + 1;
+        let groups = captures.iter().map(|x| x.map(|(beg, end)| (to_buffer(beg), to_buffer(end))));
+        let searched = buffer.lisp_buffer(cx);
+        set_match_data_from(groups, Some(searched), env, cx);
     }
+    Ok(true)
+}
+
+#[defun]
+fn looking_at(
+    regexp: &str,
+    inhibit_modify: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    looking_at_inner(regexp, inhibit_modify.is_none(), false, env, cx)
+}
+
+#[defun]
+fn posix_looking_at(
+    regexp: &str,
+    inhibit_modify: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    looking_at_inner(regexp, inhibit_modify.is_none(), true, env, cx)
 }
 
 #[defun]
-fn replace_match(
+fn replace_match<'ob>(
     newtext: &str,
-    _fixedcase: OptionalFlag,
-    _literal: OptionalFlag,
+    fixedcase: OptionalFlag,
+    literal: OptionalFlag,
     string: Option<&str>,
-    subexp: Option<usize>,
-    env: &Rt<Env>,
-    cx: &Context,
+    subexp: Option<i64>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let groups = match_groups(env, cx)?;
+    let sub = subexp.unwrap_or(0);
+    let (beg, end) = match usize::try_from(sub).ok().and_then(|x| groups.get(x)) {
+        Some(Some(range)) => *range,
+        Some(None) | None if subexp.is_none() => {
+            bail!("replace-match called before any match found")
+        }
+        Some(None) => bail!("replace-match subexpression does not exist"),
+        None => bail!(SignalError::args_out_of_range(&[cx.add(sub), cx.add(groups.len())])),
+    };
+    let (fixedcase, literal) = (fixedcase.is_some(), literal.is_some());
+    let casing = Casing::current(env, cx);
+
+    let Some(string) = string else {
+        let buffer = env.current_buffer.get();
+        if beg > end || beg <= buffer.begv() || end > buffer.zv() + 1 {
+            bail!(SignalError::args_out_of_range(&[cx.add(beg), cx.add(end)]));
+        }
+        let len = buffer.text.len_chars();
+        let group_text = |beg: usize, end: usize| {
+            let beg = beg.saturating_sub(1).min(len);
+            let (front, back) = buffer.text.slice(beg..(end.saturating_sub(1).min(len)).max(beg));
+            [front, back].concat()
+        };
+        let text =
+            replacement(newtext, fixedcase, literal, &groups, (beg, end), group_text, casing)?;
+        replace_in_buffer(&text, beg, end, env, cx)?;
+        return Ok(NIL);
+    };
+    if beg > end || end > string.chars().count() {
+        bail!(SignalError::args_out_of_range(&[cx.add(beg), cx.add(end)]));
+    }
+    let byte = |chr| string.char_indices().nth(chr).map_or(string.len(), |(idx, _)| idx);
+    let group_text = |beg: usize, end: usize| {
+        let beg = byte(beg);
+        string[beg..byte(end).max(beg)].to_owned()
+    };
+    let text = replacement(newtext, fixedcase, literal, &groups, (beg, end), group_text, casing)?;
+    Ok(cx.add([&string[..byte(beg)], &text, &string[byte(end)..]].concat()))
+}
+
+/// The text that replaces the match from `beg` to `end`. Unless `literal` is
+/// true, the backslash constructs in `newtext` are substituted, and unless
+/// `fixedcase` is true, the case of the replacement follows the case of the
+/// text it replaces. `group_text` returns the text between two positions.
+fn replacement(
+    newtext: &str,
+    fixedcase: bool,
+    literal: bool,
+    groups: &[Option<(usize, usize)>],
+    (beg, end): (usize, usize),
+    group_text: impl Fn(usize, usize) -> String,
+    casing: Casing,
 ) -> Result<String> {
-    // TODO: Handle newtext interpolation. Treat \ as special. See docstring for more.
-    //
-    // TODO: Handle automatic case adjustment
-    let Some(string) = string else { bail!("replace-match for buffers not yet implemented") };
-    let mut match_data = env.match_data.bind(cx).as_list()?.fallible();
-    let subexp = subexp.unwrap_or(0);
-    let sub_err = || format!("replace-match subexpression {subexp} does not exist");
-    for _ in 0..(subexp * 2) {
-        ensure!(match_data.next()?.is_some(), sub_err());
+    let mut text = String::with_capacity(newtext.len());
+    let mut chars = newtext.chars();
+    while let Some(chr) = chars.next() {
+        if literal || chr != '\\' {
+            text.push(chr);
+            continue;
+        }
+        match chars.next() {
+            Some('&') => text.push_str(&group_text(beg, end)),
+            Some(digit @ '1'..='9') => {
+                let group = digit.to_digit(10).unwrap() as usize;
+                // Groups that did not match are replaced with nothing
+                if let Some(Some((beg, end))) = groups.get(group) {
+                    text.push_str(&group_text(*beg, *end));
+                }
+            }
+            Some('\\') => text.push('\\'),
+            Some('?') => text.push_str("\\?"),
+            _ => bail!("Invalid use of `\\' in replacement text"),
+        }
+    }
+    if fixedcase {
+        return Ok(text);
+    }
+    Ok(match replacement_case(&group_text(beg, end), casing) {
+        Some(mode) => casing.string(&text, mode),
+        None => text,
+    })
+}
+
+/// How to change the case of a replacement for `matched`. It is made all caps
+/// if the replaced text is all caps with a word of more than one letter, and
+/// capitalized if every word of the replaced text is capitalized.
+fn replacement_case(matched: &str, casing: Casing) -> Option<CaseMode> {
+    let word = |chr| casing.syntax.class(chr) == SyntaxClass::Word;
+    let (mut some_lowercase, mut some_uppercase) = (false, false);
+    let (mut some_multiletter_word, mut some_nonuppercase_initial) = (false, false);
+    let mut prev = '\n';
+    for chr in matched.chars() {
+        if casing.table.is_lowercase(chr) {
+            some_lowercase = true;
+            if word(prev) {
+                some_multiletter_word = true;
+            } else {
+                some_nonuppercase_initial = true;
+            }
+        } else if casing.table.is_uppercase(chr) {
+            some_uppercase = true;
+            if word(prev) {
+                some_multiletter_word = true;
+            }
+        } else if !word(prev) && word(chr) {
+            // A caseless initial is treated like a lowercase one
+            some_nonuppercase_initial = true;
+        }
+        prev = chr;
     }
-    let Some(beg) = match_data.next()? else { bail!(sub_err()) };
-    let Some(end) = match_data.next()? else { bail!(sub_err()) };
+    if !some_lowercase && some_multiletter_word {
+        Some(CaseMode::Upcase)
+    } else if !some_nonuppercase_initial && some_multiletter_word {
+        Some(CaseMode::UpcaseInitials)
+    } else if !some_nonuppercase_initial && some_uppercase {
+        Some(CaseMode::Upcase)
+    } else {
+        None
+    }
+}
 
-    // TODO: match data should be char position, not byte
-    let beg: usize = beg.try_into()?;
-    let end: usize = end.try_into()?;
+/// Replace the text from `beg` to `end` in the current buffer with `newtext`,
+/// which inherits the properties around it. The range must be in the
+/// accessible portion of the buffer. Point is left after the replacement, and
+/// the match data is adjusted for the change in length.
+fn replace_in_buffer(
+    newtext: &str,
+    beg: usize,
+    end: usize,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<()> {
+    let inhibit_read_only = env.var(sym::INHIBIT_READ_ONLY, cx).unwrap_or(NIL);
+    let nonsticky = env.var(sym::TEXT_PROPERTY_DEFAULT_NONSTICKY, cx).unwrap_or(NIL);
+    let newtext = cx.add(newtext);
+    let buffer = env.current_buffer.get_mut();
+    verify_modification(buffer.get_mut(), beg, end, inhibit_read_only)?;
+    buffer.delete(beg, end)?;
+    buffer.get_mut().text.set_cursor(beg - 1);
+    buffer.insert(newtext)?;
+    let new_end = buffer.get().text.cursor().chars() + 1;
+    insert_textprops(buffer.get_mut(), beg, new_end, newtext, Some(nonsticky), cx)?;
 
-    // replace the range beg..end in string with newtext
-    let mut new_string = String::new();
-    new_string.push_str(&string[..beg]);
-    new_string.push_str(newtext);
-    new_string.push_str(&string[end..]);
-    Ok(new_string)
+    // Positions after the replaced text move with it, and positions inside it
+    // move to its start.
+    let adjust = |pos: usize| {
+        if pos >= end { pos + new_end - end } else { pos.min(beg) }
+    };
+    let mut data = Vec::new();
+    for elem in env.match_data.bind(cx).as_list()? {
+        let elem = elem?;
+        match elem.untag() {
+            ObjectType::Int(pos) if pos > 0 => data.push(cx.add(adjust(pos as usize))),
+            _ => data.push(elem),
+        }
+    }
+    env.match_data.set(crate::fns::slice_into_list(&data, None, cx));
+    Ok(())
 }
 
 #[defun]
@@ -88,85 +529,140 @@ fn regexp_quote(string: &str) -> String {
     quoted
 }
 
-fn lisp_regex_to_rust(regexp: &str) -> String {
-    let mut norm_regex = String::new();
-    let mut chars = regexp.char_indices();
-    while let Some((idx, ch)) = chars.next() {
-        match ch {
-            // Invert the escaping of parens. i.e. \( => ( and ( => \(
-            '(' | ')' | '{' | '}' => {
-                norm_regex.push('\\');
-                norm_regex.push(ch);
-            }
-            '\\' => match chars.next() {
-                Some((_, c @ '('..=')' | c @ '{' | c @ '}')) => norm_regex.push(c),
-                Some((_, '`')) => norm_regex += "\\A",
-                Some((_, '\'')) => norm_regex += "\\z",
-                Some((_, c)) => {
-                    norm_regex.push('\\');
-                    norm_regex.push(c);
+#[defun]
+fn match_data<'ob>(
+    integers: OptionalFlag,
+    reuse: Option<Object<'ob>>,
+    reseat: OptionalFlag,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let reuse = reuse.and_then(|x| List::try_from(x).ok());
+    if let Some(reuse) = reuse {
+        if reseat.is_some() {
+            for cons in reuse.conses() {
+                let cons = cons?;
+                if let ObjectType::Marker(marker) = cons.car().untag() {
+                    crate::marker::unchain_marker(marker, env);
+                    cons.set_car(NIL)?;
                 }
-                None => norm_regex.push('\\'),
-            },
-            '[' => {
-                let word = "[:word:]";
-                if regexp[idx..].starts_with(word) {
-                    chars.nth(word.len() - 2);
-                    norm_regex.push_str("a-zA-Z");
-                } else {
-                    norm_regex.push('[');
+            }
+        }
+    }
+
+    // Positions in a buffer are returned as markers unless INTEGERS is non-nil
+    let mut data = Vec::new();
+    for group in match_groups(env, cx)? {
+        let Some((beg, end)) = group else {
+            data.extend([NIL, NIL]);
+            continue;
+        };
+        match env.match_buffer {
+            Some(buffer) if integers.is_none() => {
+                for pos in [beg, end] {
+                    let marker: &LispMarker = cx.add_as(MarkerInner::new()).untag();
+                    // Markers into a killed buffer point nowhere
+                    let _ = crate::marker::attach_marker(marker, buffer, pos as i64, env);
+                    data.push(marker.into());
                 }
             }
-            c => norm_regex.push(c),
+            _ => data.extend([cx.add(beg), cx.add(end)]),
         }
     }
-    norm_regex
+    while data.last().is_some_and(|x| x.is_nil()) {
+        data.pop();
+    }
+    if integers.is_some() {
+        if let Some(buffer) = env.match_buffer {
+            data.push(cx.bind(buffer).into());
+        }
+    }
+
+    // Store as much of the data as fits in REUSE, and add the rest to the end
+    let Some(reuse) = reuse else { return Ok(crate::fns::slice_into_list(&data, None, cx)) };
+    let mut rest = data.iter();
+    let mut last = None;
+    for cons in reuse.conses() {
+        let cons = cons?;
+        cons.set_car(rest.next().copied().unwrap_or(NIL))?;
+        last = Some(cons);
+    }
+    let Some(last) = last else { return Ok(crate::fns::slice_into_list(&data, None, cx)) };
+    if rest.len() > 0 {
+        last.set_cdr(crate::fns::slice_into_list(rest.as_slice(), None, cx))?;
+    }
+    Ok(reuse.into())
 }
 
 #[defun]
-fn match_data<'ob>(
-    integer: OptionalFlag,
-    reuse: OptionalFlag,
+fn set_match_data<'ob>(
+    list: List,
     reseat: OptionalFlag,
-    env: &Rt<Env>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    ensure!(integer.is_none(), "match-data integer field is not implemented");
-    ensure!(reuse.is_none(), "match-data reuse field is not implemented");
-    ensure!(reseat.is_none(), "match-data reseat field is not implemented");
-    Ok(env.match_data.bind(cx))
+    // Markers are stored as their positions. Markers that point nowhere end the
+    // match data. The data refers to the buffer of the markers, or to a buffer
+    // at the end of the list.
+    let mut data = Vec::new();
+    let mut buffer = None;
+    for cons in list.conses() {
+        let cons = cons?;
+        match cons.car().untag() {
+            ObjectType::Buffer(searched) => {
+                buffer = Some(searched);
+                break;
+            }
+            ObjectType::Marker(marker) => {
+                let Some(pos) = marker.position() else { break };
+                buffer = marker.buffer();
+                data.push(cx.add(pos));
+                if reseat.is_some() {
+                    crate::marker::unchain_marker(marker, env);
+                    cons.set_car(NIL)?;
+                }
+            }
+            _ => data.push(cons.car()),
+        }
+    }
+    env.match_data.set(crate::fns::slice_into_list(&data, None, cx));
+    env.set_match_buffer(buffer);
+    Ok(NIL)
 }
 
-#[defun]
-fn set_match_data<'ob>(list: List, _reseat: OptionalFlag, env: &mut Rt<Env>) -> Object<'ob> {
-    // TODO: add reseat when markers implemented
-    let obj: Object = list.into();
-    env.match_data.set(obj);
-    NIL
+/// The element of the match data at `offset` in the pair for group `subexp`.
+fn match_position<'ob>(
+    subexp: i64,
+    offset: usize,
+    env: &Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let Ok(subexp) = usize::try_from(subexp) else {
+        bail!(SignalError::args_out_of_range(&[cx.add(subexp), cx.add(0)]))
+    };
+    let list = env.match_data.bind(cx).as_list()?;
+    Ok(list.fallible().nth(subexp * 2 + offset)?.unwrap_or_default())
 }
 
 #[defun]
-fn match_beginning<'ob>(subexp: usize, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
-    let list = env.match_data.bind(cx).as_list()?;
-    Ok(list.fallible().nth(subexp)?.unwrap_or_default())
+fn match_beginning<'ob>(subexp: i64, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    match_position(subexp, 0, env, cx)
 }
 
 #[defun]
-fn match_end<'ob>(subexp: usize, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
-    let list = env.match_data.bind(cx).as_list()?;
-    Ok(list.fallible().nth(subexp + 1)?.unwrap_or_default())
+fn match_end<'ob>(subexp: i64, env: &Rt<Env>, cx: &'ob Context) -> Result<Object<'ob>> {
+    match_position(subexp, 1, env, cx)
 }
 
 #[defun]
 #[expect(non_snake_case)]
 fn match_data__translate(n: i64, env: &Rt<Env>, cx: &Context) -> Result<()> {
     let search_regs: List = env.match_data.bind(cx).try_into()?;
+    // Groups that did not match are nil
     for reg in search_regs.conses() {
         let reg = reg?;
         if let ObjectType::Int(old) = reg.car().untag() {
             reg.set_car((old + n).into())?;
-        } else {
-            bail!("match data was not int");
         }
     }
     Ok(())
@@ -180,15 +676,57 @@ mod test {
     use super::*;
 
     #[test]
-    fn lisp_regex() {
-        assert_eq!(lisp_regex_to_rust("foo"), "foo");
-        assert_eq!(lisp_regex_to_rust("\\foo"), "\\foo");
-        assert_eq!(lisp_regex_to_rust("\\(foo\\)"), "(foo)");
-        assert_eq!(lisp_regex_to_rust("(foo)"), "\\(foo\\)");
-        assert_eq!(lisp_regex_to_rust("\\`"), "\\A");
-        assert_eq!(lisp_regex_to_rust("\\'"), "\\z");
-        assert_eq!(lisp_regex_to_rust("[[:word:]]"), "[a-zA-Z]");
-        assert_eq!(lisp_regex_to_rust("[[:word:]_]"), "[a-zA-Z_]");
+    fn test_string_match() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        let result = string_match("\\(a\\)\\(x\\)?\\(b+\\)", "cabbd", None, None, env, cx);
+        assert_eq!(result.unwrap(), 1);
+        let data = env.match_data.bind(cx);
+        assert_eq!(data, list![1, 4, 1, 2, NIL, NIL, 2, 4; cx]);
+        let result = string_match("\\<b", "ab b", Some(-2), None, env, cx);
+        assert_eq!(result.unwrap(), 3);
+        let result = string_match("\\`b", "ab b", Some(1), None, env, cx);
+        assert_eq!(result.unwrap(), NIL);
+        assert!(string_match("a", "ab", Some(3), None, env, cx).is_err());
+        assert!(string_match("\\(a", "ab", None, None, env, cx).is_err());
+        // Positions are in characters
+        assert_eq!(string_match("b\\(.\\)", "λμbνc", None, None, env, cx).unwrap(), 2);
+        assert_eq!(env.match_data.bind(cx), list![2, 4, 3, 4; cx]);
+        assert_eq!(string_match("c", "λμbνc", None, Some(()), env, cx).unwrap(), 4);
+        assert_eq!(env.match_data.bind(cx), list![2, 4, 3, 4; cx]);
+        assert_eq!(match_beginning(1, env, cx).unwrap(), 3);
+        assert_eq!(match_end(0, env, cx).unwrap(), 4);
+        assert_eq!(match_end(2, env, cx).unwrap(), NIL);
+        assert!(match_beginning(-1, env, cx).is_err());
+    }
+
+    #[test]
+    fn test_case_fold_search() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(string_match("abc", "xABC", None, None, env, cx).unwrap(), NIL);
+        env.set_var(sym::CASE_FOLD_SEARCH, sym::TRUE.into()).unwrap();
+        assert_eq!(string_match("abc", "xABC", None, None, env, cx).unwrap(), 1);
+        assert_eq!(string_match("[b-c]+", "xABC", None, None, env, cx).unwrap(), 2);
+        env.current_buffer.get_mut().text.insert("Foo BAR");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(search_forward("bar", None, None, None, env, cx).unwrap(), 8);
+        assert_eq!(re_search_backward("f\\(O\\)", None, None, None, env, cx).unwrap(), 1);
+        assert!(looking_at("FOO", None, env, cx).unwrap());
+    }
+
+    #[test]
+    fn test_syntax_table_search() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(string_match("\\w+$", "foo-bar", None, None, env, cx).unwrap(), 4);
+        let table = crate::syntax::copy_syntax_table(None, env, cx).unwrap();
+        crate::syntax::modify_syntax_entry(cx.add('-'), "w", Some(table), env, cx).unwrap();
+        crate::syntax::set_syntax_table(table, env, cx).unwrap();
+        assert_eq!(string_match("\\w+$", "foo-bar", None, None, env, cx).unwrap(), 0);
     }
 
     #[test]
@@ -201,5 +739,197 @@ mod test {
         string_match("bar", string, None, None, env, cx).unwrap();
         let result = replace_match(newtext, None, None, Some(string), None, env, cx).unwrap();
         assert_eq!(result, "foo quux baz");
+
+        let string = "λ foo-bar";
+        string_match("\\(foo\\)-\\(x\\)?\\(bar\\)", string, None, None, env, cx).unwrap();
+        let replace = |newtext, fixedcase, literal, subexp, env: &mut Rt<Env>| {
+            replace_match(newtext, fixedcase, literal, Some(string), subexp, env, cx)
+        };
+        assert_eq!(replace("<\\&>", None, None, None, env).unwrap(), "λ <foo-bar>");
+        assert_eq!(replace("\\3\\2\\1", None, None, None, env).unwrap(), "λ barfoo");
+        assert_eq!(replace("\\3\\\\\\?", None, None, Some(1), env).unwrap(), "λ bar\\\\?-bar");
+        assert_eq!(replace("\\&", None, Some(()), Some(3), env).unwrap(), "λ foo-\\&");
+        assert!(replace("\\x", None, None, None, env).is_err());
+        assert!(replace("x", None, None, Some(2), env).is_err());
+        assert!(replace("x", None, None, Some(4), env).is_err());
+        assert!(replace("x", None, None, Some(-1), env).is_err());
+        assert!(replace_match("x", None, None, Some("foo"), None, env, cx).is_err());
+
+        // The case of the replacement follows the case of the replaced text
+        let replace_in = |string, newtext, fixedcase, env: &mut Rt<Env>| {
+            string_match("[a-z]+ *[a-z]*", string, None, None, env, cx).unwrap();
+            replace_match(newtext, fixedcase, None, Some(string), None, env, cx).unwrap()
+        };
+        env.set_var(sym::CASE_FOLD_SEARCH, sym::TRUE.into()).unwrap();
+        assert_eq!(replace_in("FOO", "bar baz", None, env), "BAR BAZ");
+        assert_eq!(replace_in("Foo Bar", "bar baz", None, env), "Bar Baz");
+        assert_eq!(replace_in("F", "bar baz", None, env), "BAR BAZ");
+        assert_eq!(replace_in("foo Bar", "bar baz", None, env), "bar baz");
+        assert_eq!(replace_in("FOO", "bar baz", Some(()), env), "bar baz");
+    }
+
+    #[test]
+    fn test_match_data() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        string_match("\\(a\\)\\|b", "xb", None, None, env, cx).unwrap();
+        assert_eq!(match_data(None, None, None, env, cx).unwrap(), list![1, 2; cx]);
+        let reuse = list![1, 2, 3; cx];
+        let data = match_data(None, Some(reuse), None, env, cx).unwrap();
+        assert_eq!(data, list![1, 2, NIL; cx]);
+        assert!(data.ptr_eq(reuse));
+        let reuse = list![1; cx];
+        assert_eq!(match_data(None, Some(reuse), None, env, cx).unwrap(), list![1, 2; cx]);
+
+        // Matches in a buffer are returned as markers
+        env.current_buffer.get_mut().text.insert("foo bar");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        re_search_forward("b\\(a\\)", None, None, None, env, cx).unwrap();
+        let buffer = env.current_buffer.get().lisp_buffer(cx);
+        let data = match_data(Some(()), None, None, env, cx).unwrap();
+        assert_eq!(data, list![5, 7, 6, 7, buffer; cx]);
+        let data = match_data(None, None, None, env, cx).unwrap();
+        let markers: Vec<_> = data.as_list().unwrap().map(|x| x.unwrap()).collect();
+        let ObjectType::Marker(marker) = markers[2].untag() else { panic!("not a marker") };
+        assert_eq!(marker.position(), Some(6));
+        assert_eq!(marker.buffer(), Some(buffer));
+        let data = match_data(None, Some(data), Some(()), env, cx).unwrap();
+        assert_eq!(marker.position(), None);
+        let ObjectType::Marker(marker) = data.as_list().unwrap().next().unwrap().unwrap().untag()
+        else {
+            panic!("not a marker")
+        };
+
+        // Setting the match data records the buffer of the markers
+        string_match("x", "x", None, None, env, cx).unwrap();
+        set_match_data(list![marker, 7; cx].try_into().unwrap(), None, env, cx).unwrap();
+        assert_eq!(match_data(Some(()), None, None, env, cx).unwrap(), list![5, 7, buffer; cx]);
+        set_match_data(list![1, 2; cx].try_into().unwrap(), None, env, cx).unwrap();
+        assert_eq!(match_data(None, None, None, env, cx).unwrap(), list![1, 2; cx]);
+        set_match_data(list![1, 2, buffer; cx].try_into().unwrap(), None, env, cx).unwrap();
+        assert_eq!(match_data(Some(()), None, None, env, cx).unwrap(), list![1, 2, buffer; cx]);
+    }
+
+    fn point(env: &Rt<Env>) -> usize {
+        crate::editfns::point(env)
+    }
+
+    #[test]
+    fn test_re_search_forward() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo bar foo baz");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(re_search_forward("fo+", None, None, None, env, cx).unwrap(), 4);
+        assert_eq!(env.match_data.bind(cx), list![1, 4; cx]);
+        assert_eq!(re_search_forward("fo+", None, None, None, env, cx).unwrap(), 12);
+        assert!(re_search_forward("fo+", None, None, None, env, cx).is_err());
+        let noerror = Some(sym::TRUE.into());
+        assert_eq!(re_search_forward("fo+", None, noerror, None, env, cx).unwrap(), NIL);
+        assert_eq!(point(env), 12);
+        // A NOERROR that is not t moves to the bound
+        let noerror = Some(cx.add(1));
+        assert_eq!(re_search_forward("fo+", None, noerror, None, env, cx).unwrap(), NIL);
+        assert_eq!(point(env), 16);
+
+        crate::editfns::goto_char(1.into(), env);
+        assert_eq!(re_search_forward("ba.", None, None, Some(2), env, cx).unwrap(), 16);
+        assert_eq!(env.match_data.bind(cx), list![13, 16; cx]);
+        crate::editfns::goto_char(1.into(), env);
+        let noerror = Some(sym::TRUE.into());
+        assert_eq!(re_search_forward("baz", Some(15.into()), noerror, None, env, cx).unwrap(), NIL);
+        assert_eq!(re_search_forward("bar", Some(8.into()), None, None, env, cx).unwrap(), 8);
+        assert!(re_search_forward("foo", Some(2.into()), None, None, env, cx).is_err());
+        // Matches can't extend past the bound
+        crate::editfns::goto_char(1.into(), env);
+        assert_eq!(re_search_forward("o+", Some(3.into()), None, None, env, cx).unwrap(), 3);
+        assert_eq!(env.match_data.bind(cx), list![2, 3; cx]);
+    }
+
+    #[test]
+    fn test_re_search_backward() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo bar foo baz");
+        let re = "\\(b\\)\\(a\\)\\(r\\|z\\)";
+        assert_eq!(re_search_backward(re, None, None, None, env, cx).unwrap(), 13);
+        assert_eq!(env.match_data.bind(cx), list![13, 16, 13, 14, 14, 15, 15, 16; cx]);
+        assert_eq!(point(env), 13);
+        assert_eq!(re_search_backward("o+", None, None, None, env, cx).unwrap(), 11);
+        assert_eq!(env.match_data.bind(cx), list![11, 12; cx]);
+        crate::editfns::goto_char(16.into(), env);
+        assert_eq!(re_search_backward("ba", None, None, Some(2), env, cx).unwrap(), 5);
+        assert_eq!(search_backward("o", None, None, Some(2), env, cx).unwrap(), 2);
+        assert!(search_backward("foo", None, None, None, env, cx).is_err());
+        crate::editfns::goto_char(10.into(), env);
+        assert!(re_search_backward("foo", Some(12.into()), None, None, env, cx).is_err());
+        crate::editfns::goto_char(11.into(), env);
+        assert_eq!(re_search_backward("o\\=", None, None, None, env, cx).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_search_forward() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("a.b a*b λ.b");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(search_forward("a*", None, None, None, env, cx).unwrap(), 7);
+        assert_eq!(search_forward(".b", None, None, None, env, cx).unwrap(), 12);
+        assert_eq!(env.match_data.bind(cx), list![10, 12; cx]);
+        // Searches are limited to the accessible portion of the buffer
+        env.current_buffer.get_mut().narrow(4, 8);
+        env.current_buffer.get_mut().text.set_cursor(4);
+        assert!(search_forward("λ", None, None, None, env, cx).is_err());
+        assert_eq!(search_forward("a", None, None, None, env, cx).unwrap(), 6);
+        assert_eq!(re_search_backward("\\`a", None, None, None, env, cx).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_looking_at() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo bar");
+        env.current_buffer.get_mut().text.set_cursor(4);
+        assert!(looking_at("b\\(a\\)", None, env, cx).unwrap());
+        assert_eq!(env.match_data.bind(cx), list![5, 7, 6, 7; cx]);
+        assert!(!looking_at("a", None, env, cx).unwrap());
+        assert!(looking_at("\\=bar\\'", Some(()), env, cx).unwrap());
+        assert_eq!(env.match_data.bind(cx), list![5, 7, 6, 7; cx]);
+        assert_eq!(point(env), 5);
+        // posix matching finds the longest alternative
+        assert!(posix_looking_at("b\\|bar", None, env, cx).unwrap());
+        assert_eq!(env.match_data.bind(cx), list![5, 8; cx]);
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(posix_search_forward("o\\|oo", None, None, None, env, cx).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_replace_match_buffer() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo bar baz");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        re_search_forward("\\(b\\)\\(ar\\) \\(baz\\)", None, None, None, env, cx).unwrap();
+        assert_eq!(replace_match("xyz", None, None, None, Some(2), env, cx).unwrap(), NIL);
+        assert_eq!(env.current_buffer.get(), "foo bxyz baz");
+        assert_eq!(point(env), 9);
+        assert_eq!(env.match_data.bind(cx), list![5, 13, 5, 6, 6, 9, 10, 13; cx]);
+        replace_match("q", None, None, None, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "foo q");
+        assert_eq!(point(env), 6);
+        assert!(replace_match("q", None, None, None, Some(4), env, cx).is_err());
+
+        env.current_buffer.get_mut().text.set_cursor(0);
+        re_search_forward("\\(fo\\)o", None, None, None, env, cx).unwrap();
+        replace_match("[\\1\\&]", None, None, None, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get(), "[fofoo] q");
+        env.current_buffer.get_mut().narrow(3, 10);
+        assert!(replace_match("x", None, None, None, None, env, cx).is_err());
     }
 }
@@ -0,0 +1,1866 @@
+//! Syntax tables and syntax-based motion.
+use crate::core::{
+    cons::Cons,
+    env::{Env, sym},
+    error::SignalError,
+    gc::{Context, Rt},
+    object::{
+        BufferData, CharTable, CharTableInner, IntoObject, NIL, Object, ObjectType, OptionalFlag,
+    },
+};
+use crate::data::LispError;
+use crate::intervals::textget;
+use anyhow::{Result, bail};
+use rune_core::macros::list;
+use rune_macros::defun;
+
+/// The classes of characters in a syntax table. The discriminant is the code
+/// of the class in a raw syntax descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SyntaxClass {
+    Whitespace,
+    Punct,
+    Word,
+    Symbol,
+    Open,
+    Close,
+    Quote,
+    String,
+    Math,
+    Escape,
+    CharQuote,
+    Comment,
+    EndComment,
+    Inherit,
+    CommentFence,
+    StringFence,
+}
+
+use SyntaxClass::*;
+
+const CLASSES: [SyntaxClass; 16] = [
+    Whitespace,
+    Punct,
+    Word,
+    Symbol,
+    Open,
+    Close,
+    Quote,
+    String,
+    Math,
+    Escape,
+    CharQuote,
+    Comment,
+    EndComment,
+    Inherit,
+    CommentFence,
+    StringFence,
+];
+
+/// The designator characters of the syntax classes, indexed by their code.
+const DESIGNATORS: [char; 16] =
+    [' ', '.', 'w', '_', '(', ')', '\'', '"', '$', '\\', '/', '<', '>', '@', '!', '|'];
+
+impl SyntaxClass {
+    fn from_code(code: i64) -> Option<Self> {
+        usize::try_from(code).ok().and_then(|x| CLASSES.get(x)).copied()
+    }
+
+    fn from_designator(chr: char) -> Option<Self> {
+        match chr {
+            '-' => Some(Whitespace),
+            _ => DESIGNATORS.iter().position(|x| *x == chr).map(|x| CLASSES[x]),
+        }
+    }
+
+    pub(crate) fn designator(self) -> char {
+        DESIGNATORS[self as usize]
+    }
+}
+
+// The flags of a syntax descriptor, in the order of their characters in
+// `FLAG_CHARS`. The first four make a character part of a two character
+// comment delimiter.
+const COMSTART_FIRST: u8 = 1 << 0;
+const COMSTART_SECOND: u8 = 1 << 1;
+const COMEND_FIRST: u8 = 1 << 2;
+const COMEND_SECOND: u8 = 1 << 3;
+const PREFIX: u8 = 1 << 4;
+const STYLE_B: u8 = 1 << 5;
+const NESTED: u8 = 1 << 6;
+const STYLE_C: u8 = 1 << 7;
+const FLAG_CHARS: [char; 8] = ['1', '2', '3', '4', 'p', 'b', 'n', 'c'];
+
+/// The style of comments delimited by comment fences.
+const FENCE_STYLE: u16 = 257;
+
+/// The syntax of a character: its class, flags and matching character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Syntax {
+    pub(crate) class: SyntaxClass,
+    flags: u8,
+    pub(crate) matching: Option<char>,
+}
+
+impl Syntax {
+    const fn new(class: SyntaxClass) -> Self {
+        Self { class, flags: 0, matching: None }
+    }
+
+    const fn paired(class: SyntaxClass, matching: char) -> Self {
+        Self { class, flags: 0, matching: Some(matching) }
+    }
+
+    /// Decode a raw syntax descriptor, which is a cons of the code of the class
+    /// and flags and the matching character. Returns `None` if the descriptor
+    /// is nil or malformed.
+    fn from_descriptor(descriptor: Object) -> Option<Self> {
+        let ObjectType::Cons(cons) = descriptor.untag() else { return None };
+        let ObjectType::Int(code) = cons.car().untag() else { return None };
+        let mut syntax = Self::from_code(code)?;
+        if let ObjectType::Int(chr) = cons.cdr().untag() {
+            syntax.matching = u32::try_from(chr).ok().and_then(char::from_u32);
+        }
+        Some(syntax)
+    }
+
+    /// Decode the code of a syntax descriptor, which holds the class in the
+    /// low bits and the flags above them.
+    fn from_code(code: i64) -> Option<Self> {
+        let class = SyntaxClass::from_code(code & 0xFFFF)?;
+        let flags = u8::try_from(code >> 16).ok()?;
+        Some(Self { class, flags, matching: None })
+    }
+
+    fn code(self) -> i64 {
+        self.class as i64 | i64::from(self.flags) << 16
+    }
+
+    fn descriptor<'ob>(self, cx: &'ob Context) -> Object<'ob> {
+        match self.matching {
+            Some(chr) => Cons::new(self.code(), chr, cx).into(),
+            None => Cons::new1(self.code(), cx).into(),
+        }
+    }
+
+    fn has(self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn nested(self) -> bool {
+        self.has(NESTED)
+    }
+
+    /// The style of a comment delimited by a character with this syntax. For
+    /// two character delimiters this is the main character (the second of a
+    /// comment starter or the first of a comment ender) and `other` is the
+    /// other one.
+    fn comment_style(self, other: Option<Syntax>) -> u16 {
+        let style_c = |syntax: Syntax| if syntax.has(STYLE_C) { 2 } else { 0 };
+        u16::from(self.has(STYLE_B)) | style_c(self) | other.map_or(0, style_c)
+    }
+}
+
+/// The entry for `chr` in the standard syntax table.
+fn standard_entry(chr: char) -> Syntax {
+    match chr {
+        ' ' | '\t' | '\n' | '\r' | '\x0c' => Syntax::new(Whitespace),
+        '\0'..='\x1f' | '\x7f' => Syntax::new(Punct),
+        'a'..='z' | 'A'..='Z' | '0'..='9' | '$' | '%' => Syntax::new(Word),
+        '(' => Syntax::paired(Open, ')'),
+        ')' => Syntax::paired(Close, '('),
+        '[' => Syntax::paired(Open, ']'),
+        ']' => Syntax::paired(Close, '['),
+        '{' => Syntax::paired(Open, '}'),
+        '}' => Syntax::paired(Close, '{'),
+        '"' => Syntax::new(String),
+        '\\' => Syntax::new(Escape),
+        '_' | '-' | '+' | '*' | '/' | '&' | '|' | '<' | '>' | '=' => Syntax::new(Symbol),
+        '.' | ',' | ';' | ':' | '?' | '!' | '#' | '@' | '~' | '^' | '\'' | '`' => {
+            Syntax::new(Punct)
+        }
+        _ => Syntax::new(Word),
+    }
+}
+
+/// Return the syntax class designator of `chr` in the standard syntax table.
+pub(crate) fn standard_syntax(chr: char) -> char {
+    standard_entry(chr).class.designator()
+}
+
+/// The syntax table used to look up the syntax of characters. The standard
+/// table is only created when lisp asks for it, and until then its entries
+/// come from [`standard_entry`].
+#[derive(Clone, Copy)]
+pub(crate) struct SyntaxTable<'ob>(Option<&'ob CharTable>);
+
+impl<'ob> SyntaxTable<'ob> {
+    /// The syntax table of the current buffer.
+    pub(crate) fn current(env: &Rt<Env>, cx: &'ob Context) -> Self {
+        let table = env.current_buffer.get().syntax_table(cx);
+        Self(table.or_else(|| created_standard_table(env, cx)))
+    }
+
+    pub(crate) fn entry(self, chr: char) -> Syntax {
+        match self.0 {
+            Some(table) => {
+                Syntax::from_descriptor(table.get(chr as usize)).unwrap_or(Syntax::new(Whitespace))
+            }
+            None => standard_entry(chr),
+        }
+    }
+
+    pub(crate) fn class(self, chr: char) -> SyntaxClass {
+        self.entry(chr).class
+    }
+}
+
+fn created_standard_table<'ob>(env: &Rt<Env>, cx: &'ob Context) -> Option<&'ob CharTable> {
+    match env.standard_syntax_table.bind(cx).untag() {
+        ObjectType::CharTable(table) => Some(table),
+        _ => None,
+    }
+}
+
+/// The standard syntax table, which is created the first time it is needed.
+pub(crate) fn standard_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    if let Some(table) = created_standard_table(env, cx) {
+        return table;
+    }
+    let table = CharTableInner::new(sym::SYNTAX_TABLE.into(), None, 0).into_obj(cx).untag();
+    for chr in (0..0x80u8).map(char::from) {
+        table.set(chr as usize, standard_entry(chr).descriptor(cx));
+    }
+    table.set_range(0x80, char::MAX as usize, Syntax::new(Word).descriptor(cx));
+    env.standard_syntax_table.set(Object::from(table));
+    table
+}
+
+/// The syntax table of the current buffer, which is the standard table unless
+/// the buffer has its own.
+fn buffer_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    match env.current_buffer.get().syntax_table(cx) {
+        Some(table) => table,
+        None => standard_table(env, cx),
+    }
+}
+
+fn check_syntax_table<'ob>(table: &'ob CharTable, cx: &'ob Context) -> Result<&'ob CharTable> {
+    if syntax_table_p(table.into()) {
+        Ok(table)
+    } else {
+        let error = list![sym::WRONG_TYPE_ARGUMENT, sym::SYNTAX_TABLE_P, table; cx];
+        bail!(LispError::new(error.try_into()?))
+    }
+}
+
+defvar!(PARSE_SEXP_IGNORE_COMMENTS);
+defvar!(PARSE_SEXP_LOOKUP_PROPERTIES);
+defvar!(OPEN_PAREN_IN_COLUMN_0_IS_DEFUN_START, true);
+
+#[defun]
+fn syntax_table_p(object: Object) -> bool {
+    match object.untag() {
+        ObjectType::CharTable(table) => table.purpose() == sym::SYNTAX_TABLE,
+        _ => false,
+    }
+}
+
+#[defun]
+fn standard_syntax_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    standard_table(env, cx)
+}
+
+#[defun]
+fn syntax_table<'ob>(env: &mut Rt<Env>, cx: &'ob Context) -> &'ob CharTable {
+    buffer_table(env, cx)
+}
+
+#[defun]
+pub(crate) fn set_syntax_table<'ob>(
+    table: &'ob CharTable,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob CharTable> {
+    let table = check_syntax_table(table, cx)?;
+    env.current_buffer.get_mut().set_syntax_table(table);
+    Ok(table)
+}
+
+#[defun]
+pub(crate) fn copy_syntax_table<'ob>(
+    table: Option<&'ob CharTable>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<&'ob CharTable> {
+    let standard = standard_table(env, cx);
+    let table = match table {
+        Some(table) => check_syntax_table(table, cx)?,
+        None => standard,
+    };
+    let copy = table.copy().into_obj(cx).untag();
+    // Only the standard table may lack a parent
+    if copy.parent().is_none() {
+        copy.set_parent(Some(standard));
+    }
+    Ok(copy)
+}
+
+#[defun]
+pub(crate) fn char_syntax(character: char, env: &Rt<Env>, cx: &Context) -> char {
+    SyntaxTable::current(env, cx).class(character).designator()
+}
+
+#[defun]
+fn syntax_class_to_char(syntax: i64) -> Result<char> {
+    match SyntaxClass::from_code(syntax) {
+        Some(class) => Ok(class.designator()),
+        None => bail!(SignalError::args_out_of_range(&[15.into(), syntax.into()])),
+    }
+}
+
+#[defun]
+fn matching_paren(character: char, env: &Rt<Env>, cx: &Context) -> Option<char> {
+    let syntax = SyntaxTable::current(env, cx).entry(character);
+    match syntax.class {
+        Open | Close => syntax.matching,
+        _ => None,
+    }
+}
+
+#[defun]
+fn string_to_syntax<'ob>(string: &str, cx: &'ob Context) -> Result<Object<'ob>> {
+    let mut chars = string.chars();
+    let Some(designator) = chars.next() else { bail!("Invalid syntax description letter: ") };
+    let Some(class) = SyntaxClass::from_designator(designator) else {
+        bail!("Invalid syntax description letter: {designator}")
+    };
+    if class == Inherit {
+        return Ok(NIL);
+    }
+    let mut syntax = Syntax::new(class);
+    syntax.matching = chars.next().filter(|x| *x != ' ');
+    for chr in chars {
+        if let Some(idx) = FLAG_CHARS.iter().position(|x| *x == chr) {
+            syntax.flags |= 1 << idx;
+        }
+    }
+    Ok(syntax.descriptor(cx))
+}
+
+#[defun]
+pub(crate) fn modify_syntax_entry(
+    char: Object,
+    newentry: &str,
+    syntax_table: Option<&CharTable>,
+    env: &mut Rt<Env>,
+    cx: &Context,
+) -> Result<bool> {
+    let table = match syntax_table {
+        Some(table) => check_syntax_table(table, cx)?,
+        None => buffer_table(env, cx),
+    };
+    let descriptor = string_to_syntax(newentry, cx)?;
+    match char.untag() {
+        ObjectType::Cons(range) => {
+            let beg: char = range.car().try_into()?;
+            let end: char = range.cdr().try_into()?;
+            table.set_range(beg as usize, end as usize, descriptor);
+        }
+        _ => {
+            let chr: char = char.try_into()?;
+            table.set(chr as usize, descriptor);
+        }
+    }
+    Ok(false)
+}
+
+/// A failed scan over balanced expressions, which is signaled as a
+/// `scan-error` with the bounds of the text that could not be scanned.
+struct ScanError {
+    message: &'static str,
+    last_good: usize,
+    pos: usize,
+}
+
+impl ScanError {
+    fn unbalanced(last_good: usize, pos: usize) -> Self {
+        Self { message: "Unbalanced parentheses", last_good, pos }
+    }
+
+    fn signal(&self, cx: &Context) -> Result<LispError> {
+        let error = list![sym::SCAN_ERROR, self.message, self.last_good + 1, self.pos + 1; cx];
+        Ok(LispError::new(error.try_into()?))
+    }
+}
+
+/// Where a scan to the end of a comment stopped.
+struct CommentEnd {
+    /// The last character of the comment ender if it was found, otherwise the
+    /// position where the scan stopped.
+    pos: usize,
+    found: bool,
+    /// The nesting of the comment where the scan stopped.
+    nesting: i64,
+    /// The syntax of the last character if it could start a two character
+    /// construct that continues after the stop.
+    last: Option<Syntax>,
+}
+
+/// When a forward parse stops at a comment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CommentStop {
+    Never,
+    /// Stop at the start of a comment.
+    Start,
+    /// Stop after the start of a comment or string.
+    Boundary,
+}
+
+/// The state of a forward parse, as described by `parse-partial-sexp`.
+#[derive(Debug, Default, Clone)]
+struct ParseState {
+    depth: i64,
+    /// The character that ends the string we are in, or `Some(None)` if the
+    /// string was started by a string fence.
+    instring: Option<Option<char>>,
+    /// The nesting of the comment we are in. It is -1 in a comment that does
+    /// not nest and 0 outside of comments.
+    incomment: i64,
+    comstyle: u16,
+    quoted: bool,
+    mindepth: i64,
+    /// The start of the comment or string we are in.
+    comstr_start: usize,
+    /// The starts of the open lists, outermost first.
+    levelstarts: Vec<usize>,
+    /// The syntax of the last character if it could start a two character
+    /// construct.
+    prev_syntax: Option<Syntax>,
+    /// The start of the innermost containing list.
+    prevlevelstart: Option<usize>,
+    /// The start of the last complete subexpression.
+    thislevelstart: Option<usize>,
+}
+
+impl ParseState {
+    fn from_lisp(state: Object) -> Result<Self> {
+        let mut elems = Vec::new();
+        for elem in state.as_list()? {
+            elems.push(elem?);
+        }
+        let elem = |idx: usize| elems.get(idx).copied().unwrap_or(NIL);
+        let int = |idx: usize| match elem(idx).untag() {
+            ObjectType::Int(x) => Some(x),
+            _ => None,
+        };
+        let pos = |obj: Object| match obj.untag() {
+            ObjectType::Int(x) => usize::try_from(x - 1).ok(),
+            _ => None,
+        };
+        let depth = int(0).unwrap_or(0);
+        let instring = match elem(3).untag() {
+            ObjectType::NIL => None,
+            ObjectType::Int(chr) => Some(u32::try_from(chr).ok().and_then(char::from_u32)),
+            _ => Some(None),
+        };
+        let incomment = match elem(4).untag() {
+            ObjectType::NIL => 0,
+            ObjectType::Int(x) => x,
+            _ => -1,
+        };
+        let comstyle = match elem(7).untag() {
+            ObjectType::NIL => 0,
+            ObjectType::Int(x) if (0..i64::from(FENCE_STYLE)).contains(&x) => x as u16,
+            _ => FENCE_STYLE,
+        };
+        let mut levelstarts = Vec::new();
+        for start in elem(9).as_list()? {
+            levelstarts.extend(pos(start?));
+        }
+        Ok(Self {
+            depth,
+            instring,
+            incomment,
+            comstyle,
+            quoted: !elem(5).is_nil(),
+            mindepth: depth,
+            comstr_start: pos(elem(8)).unwrap_or(0),
+            levelstarts,
+            prev_syntax: int(10).and_then(Syntax::from_code),
+            ..Self::default()
+        })
+    }
+
+    fn into_lisp<'ob>(self, cx: &'ob Context) -> Object<'ob> {
+        let pos = |pos: Option<usize>| pos.map_or(NIL, |x| cx.add(x + 1));
+        let instring = match self.instring {
+            Some(Some(chr)) => cx.add(chr),
+            Some(None) => true.into(),
+            None => NIL,
+        };
+        let incomment = match self.incomment {
+            0 => NIL,
+            ..0 => true.into(),
+            nesting => cx.add(nesting),
+        };
+        let comstyle = match self.comstyle {
+            0 => NIL,
+            FENCE_STYLE => sym::SYNTAX_TABLE.into(),
+            style => cx.add(i64::from(style)),
+        };
+        let in_construct = self.incomment != 0 || self.instring.is_some();
+        let levelstarts: Vec<_> = self.levelstarts.iter().map(|x| cx.add(x + 1)).collect();
+        list![
+            self.depth,
+            pos(self.prevlevelstart),
+            pos(self.thislevelstart),
+            instring,
+            incomment,
+            self.quoted,
+            self.mindepth,
+            comstyle,
+            pos(in_construct.then_some(self.comstr_start)),
+            crate::fns::slice_into_list(&levelstarts, None, cx),
+            self.prev_syntax.map_or(NIL, |x| cx.add(x.code()));
+            cx
+        ]
+    }
+}
+
+/// Reads the syntax of the accessible portion of a buffer. Positions are
+/// 0-based character positions.
+struct Scanner<'a, 'ob> {
+    buffer: &'a BufferData,
+    table: SyntaxTable<'ob>,
+    /// Whether `syntax-table` text properties override the syntax table.
+    lookup_properties: bool,
+    /// Whether comments are skipped over like whitespace when scanning lists.
+    ignore_comments: bool,
+    /// Whether an open paren at the start of a line is outside of any comment
+    /// or string.
+    open_paren_defun: bool,
+    begv: usize,
+    zv: usize,
+}
+
+impl<'a, 'ob> Scanner<'a, 'ob> {
+    fn new(env: &'a Rt<Env>, cx: &'ob Context) -> Self {
+        let buffer = env.current_buffer.get();
+        let flag = |var| env.var(var, cx).is_some_and(|x| !x.is_nil());
+        Self {
+            buffer,
+            table: SyntaxTable::current(env, cx),
+            lookup_properties: flag(sym::PARSE_SEXP_LOOKUP_PROPERTIES),
+            ignore_comments: flag(sym::PARSE_SEXP_IGNORE_COMMENTS),
+            open_paren_defun: flag(sym::OPEN_PAREN_IN_COLUMN_0_IS_DEFUN_START),
+            begv: buffer.begv(),
+            zv: buffer.zv(),
+        }
+    }
+
+    fn point(&self) -> usize {
+        self.buffer.text.cursor().chars()
+    }
+
+    /// Convert a lisp position to a position in the accessible portion.
+    fn clamp(&self, pos: i64) -> usize {
+        usize::try_from(pos - 1).unwrap_or(0).clamp(self.begv, self.zv)
+    }
+
+    fn char_at(&self, pos: usize) -> char {
+        self.buffer.text.char_at(pos).expect("position should be in the buffer")
+    }
+
+    fn syntax_at(&self, pos: usize) -> Syntax {
+        let chr = self.char_at(pos);
+        if self.lookup_properties {
+            if let Some(node) = self.buffer.textprops.find(pos + 1) {
+                let value = textget(*node.val, sym::SYNTAX_TABLE.into()).unwrap_or(NIL);
+                match value.untag() {
+                    ObjectType::CharTable(table) => return SyntaxTable(Some(table)).entry(chr),
+                    ObjectType::Cons(_) => {
+                        if let Some(syntax) = Syntax::from_descriptor(value) {
+                            return syntax;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.table.entry(chr)
+    }
+
+    fn class_at(&self, pos: usize) -> SyntaxClass {
+        self.syntax_at(pos).class
+    }
+
+    /// Whether the character at `pos` is quoted by an odd number of escape
+    /// characters before it.
+    fn char_quoted(&self, mut pos: usize) -> bool {
+        let mut quoted = false;
+        while pos > self.begv && matches!(self.class_at(pos - 1), Escape | CharQuote) {
+            quoted = !quoted;
+            pos -= 1;
+        }
+        quoted
+    }
+
+    /// Move over `count` words from `from`. Returns the new position and
+    /// whether all the words were found.
+    fn scan_words(&self, from: usize, count: i64) -> (usize, bool) {
+        let is_word = |pos| self.class_at(pos) == Word;
+        let mut pos = from;
+        if count >= 0 {
+            for _ in 0..count {
+                while pos < self.zv && !is_word(pos) {
+                    pos += 1;
+                }
+                if pos == self.zv {
+                    return (pos, false);
+                }
+                while pos < self.zv && is_word(pos) {
+                    pos += 1;
+                }
+            }
+        } else {
+            for _ in count..0 {
+                while pos > self.begv && !is_word(pos - 1) {
+                    pos -= 1;
+                }
+                if pos == self.begv {
+                    return (pos, false);
+                }
+                while pos > self.begv && is_word(pos - 1) {
+                    pos -= 1;
+                }
+            }
+        }
+        (pos, true)
+    }
+
+    /// Move from `from` toward `lim` over characters whose syntax class is
+    /// described by `spec`, as for `skip-syntax-forward`.
+    fn skip_syntax(&self, spec: &str, from: usize, lim: usize) -> usize {
+        let (negate, spec) = match spec.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let classes: Vec<_> = spec.chars().filter_map(SyntaxClass::from_designator).collect();
+        let skip = |pos| classes.contains(&self.class_at(pos)) != negate;
+        let mut pos = from;
+        if lim >= from {
+            while pos < lim && skip(pos) {
+                pos += 1;
+            }
+        } else {
+            while pos > lim && skip(pos - 1) {
+                pos -= 1;
+            }
+        }
+        pos
+    }
+
+    /// A position before `pos` that is outside of any comment or string. This
+    /// is the last open paren at the start of a line if those start defuns, or
+    /// else the start of the accessible portion.
+    fn defun_start(&self, pos: usize) -> usize {
+        if self.open_paren_defun {
+            for pos in (self.begv..pos).rev() {
+                let line_start = pos == self.begv || self.char_at(pos - 1) == '\n';
+                if line_start && self.class_at(pos) == Open {
+                    return pos;
+                }
+            }
+        }
+        self.begv
+    }
+
+    /// Scan from `from` to the end of a comment of `style`, stopping at
+    /// `stop`. `nesting` is the nesting of the comment if it nests. `prev` is
+    /// the syntax of the character before `from`, which may start a two
+    /// character comment ender.
+    fn comment_end(
+        &self,
+        mut from: usize,
+        stop: usize,
+        nesting: i64,
+        style: u16,
+        prev: Option<Syntax>,
+    ) -> CommentEnd {
+        let mut nesting = if nesting <= 0 { -1 } else { nesting };
+        let mut syntax = prev;
+        let mut class = None;
+        // Continue a two character delimiter started by the previous character
+        let mut resume = prev.is_some_and(|x| x.code() != 0);
+        loop {
+            if !resume {
+                if from == stop {
+                    let continues = |x: &Syntax| {
+                        matches!(class, Some(Escape | CharQuote))
+                            || x.has(COMEND_FIRST)
+                            || (nesting > 0 && x.has(COMSTART_FIRST))
+                    };
+                    let last = syntax.filter(continues);
+                    return CommentEnd { pos: from, found: false, nesting, last };
+                }
+                let current = self.syntax_at(from);
+                syntax = Some(current);
+                class = Some(current.class);
+                if current.class == EndComment && current.comment_style(None) == style {
+                    let ends = if current.nested() {
+                        nesting > 0 && {
+                            nesting -= 1;
+                            nesting == 0
+                        }
+                    } else {
+                        nesting < 0
+                    };
+                    if ends {
+                        break;
+                    }
+                }
+                if current.class == CommentFence && style == FENCE_STYLE {
+                    break;
+                }
+                if nesting > 0
+                    && current.class == Comment
+                    && current.nested()
+                    && current.comment_style(None) == style
+                {
+                    nesting += 1;
+                }
+                from += 1;
+            }
+            resume = false;
+            if let Some(first) = syntax.filter(|x| x.has(COMEND_FIRST) && from < stop) {
+                let second = self.syntax_at(from);
+                let nests = first.nested() || second.nested();
+                if second.has(COMEND_SECOND)
+                    && first.comment_style(Some(second)) == style
+                    && (if nests { nesting > 0 } else { nesting < 0 })
+                {
+                    syntax = None;
+                    nesting -= 1;
+                    if nesting <= 0 {
+                        break;
+                    }
+                    from += 1;
+                }
+            }
+            if let Some(first) = syntax.filter(|x| x.has(COMSTART_FIRST) && from < stop) {
+                let second = self.syntax_at(from);
+                if nesting > 0
+                    && second.has(COMSTART_SECOND)
+                    && second.comment_style(Some(first)) == style
+                {
+                    syntax = None;
+                    nesting += 1;
+                    from += 1;
+                }
+            }
+        }
+        CommentEnd { pos: from, found: true, nesting, last: None }
+    }
+
+    /// The start of the comment of `style` that ends at `pos`, which is found
+    /// by parsing forward from a position outside of any comment. Returns
+    /// `None` if `pos` is not in such a comment or it starts before `stop`.
+    fn comment_start(&self, pos: usize, stop: usize, style: u16) -> Option<usize> {
+        let start = self.defun_start(pos);
+        let state = self.parse(ParseState::default(), start, pos, None, false, CommentStop::Never);
+        let (state, _) = state;
+        let found = state.incomment != 0 && state.comstyle == style && state.comstr_start >= stop;
+        found.then_some(state.comstr_start)
+    }
+
+    /// Move over `count` balanced expressions from `from`, or over lists if
+    /// `sexpflag` is false. The scan stops early if it reaches `depth` 0.
+    /// Returns `None` if the scan reaches the edge of the accessible portion
+    /// outside of any list.
+    fn scan_lists(
+        &self,
+        mut from: usize,
+        mut count: i64,
+        mut depth: i64,
+        sexpflag: bool,
+    ) -> Result<Option<usize>, ScanError> {
+        let min_depth = depth.min(0);
+        let mut last_good = from;
+        let mut mathexit = false;
+        while count > 0 {
+            let stop = self.zv;
+            'object: {
+                while from < stop {
+                    let syntax = self.syntax_at(from);
+                    let chr = self.char_at(from);
+                    let mut class = syntax.class;
+                    let mut style = syntax.comment_style(None);
+                    let mut nested = syntax.nested();
+                    if depth == min_depth {
+                        last_good = from;
+                    }
+                    from += 1;
+                    if from < stop && syntax.has(COMSTART_FIRST) && self.ignore_comments {
+                        let other = self.syntax_at(from);
+                        if other.has(COMSTART_SECOND) {
+                            class = Comment;
+                            style = other.comment_style(Some(syntax));
+                            nested |= other.nested();
+                            from += 1;
+                        }
+                    }
+                    if syntax.has(PREFIX) {
+                        continue;
+                    }
+                    let class = match class {
+                        Math if !sexpflag => continue,
+                        Math => {
+                            if from != stop && self.char_at(from) == chr {
+                                from += 1;
+                            }
+                            mathexit = !mathexit;
+                            if mathexit { Open } else { Close }
+                        }
+                        class => class,
+                    };
+                    match class {
+                        Escape | CharQuote | Word | Symbol => {
+                            if matches!(class, Escape | CharQuote) {
+                                if from == stop {
+                                    return Err(ScanError::unbalanced(last_good, from));
+                                }
+                                from += 1;
+                            }
+                            if depth != 0 || !sexpflag {
+                                continue;
+                            }
+                            while from < stop {
+                                match self.class_at(from) {
+                                    Escape | CharQuote => {
+                                        from += 1;
+                                        if from == stop {
+                                            return Err(ScanError::unbalanced(last_good, from));
+                                        }
+                                    }
+                                    Word | Symbol | Quote => {}
+                                    _ => break 'object,
+                                }
+                                from += 1;
+                            }
+                            break 'object;
+                        }
+                        Comment | CommentFence => {
+                            if !self.ignore_comments {
+                                continue;
+                            }
+                            let style = if class == CommentFence { FENCE_STYLE } else { style };
+                            let end = self.comment_end(from, stop, i64::from(nested), style, None);
+                            from = end.pos;
+                            if !end.found {
+                                if depth == 0 {
+                                    break 'object;
+                                }
+                                return Err(ScanError::unbalanced(last_good, from));
+                            }
+                            from += 1;
+                        }
+                        Open => {
+                            depth += 1;
+                            if depth == 0 {
+                                break 'object;
+                            }
+                        }
+                        Close => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break 'object;
+                            }
+                            if depth < min_depth {
+                                let message = "Containing expression ends prematurely";
+                                return Err(ScanError { message, last_good, pos: from });
+                            }
+                        }
+                        String | StringFence => {
+                            let term = self.char_at(from - 1);
+                            loop {
+                                if from >= stop {
+                                    return Err(ScanError::unbalanced(last_good, from));
+                                }
+                                let current = self.syntax_at(from);
+                                let ends = match class {
+                                    String => current.class == String && self.char_at(from) == term,
+                                    _ => current.class == StringFence,
+                                };
+                                if ends {
+                                    break;
+                                }
+                                if matches!(current.class, Escape | CharQuote) {
+                                    from += 1;
+                                }
+                                from += 1;
+                            }
+                            from += 1;
+                            if depth == 0 && sexpflag {
+                                break 'object;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                // Reached the end of the accessible portion
+                if depth != 0 {
+                    return Err(ScanError::unbalanced(last_good, from));
+                }
+                return Ok(None);
+            }
+            count -= 1;
+        }
+
+        while count < 0 {
+            let stop = self.begv;
+            'object: {
+                while from > stop {
+                    from -= 1;
+                    let syntax = self.syntax_at(from);
+                    let chr = self.char_at(from);
+                    let mut class = syntax.class;
+                    if depth == min_depth {
+                        last_good = from;
+                    }
+                    let mut style =
+                        if class == EndComment { syntax.comment_style(None) } else { 0 };
+                    if from > stop
+                        && syntax.has(COMEND_SECOND)
+                        && self.syntax_at(from - 1).has(COMEND_FIRST)
+                        && self.ignore_comments
+                    {
+                        from -= 1;
+                        class = EndComment;
+                        style = self.syntax_at(from).comment_style(Some(syntax));
+                    }
+                    if class != EndComment && self.char_quoted(from) {
+                        from -= 1;
+                        class = Word;
+                    } else if syntax.has(PREFIX) {
+                        continue;
+                    }
+                    let class = match class {
+                        Math if !sexpflag => continue,
+                        Math => {
+                            if from > stop && self.char_at(from - 1) == chr {
+                                from -= 1;
+                            }
+                            mathexit = !mathexit;
+                            if mathexit { Close } else { Open }
+                        }
+                        class => class,
+                    };
+                    match class {
+                        Word | Symbol | Escape | CharQuote => {
+                            if depth != 0 || !sexpflag {
+                                continue;
+                            }
+                            while from > stop {
+                                let before = from - 1;
+                                if self.class_at(before) == EndComment {
+                                    break 'object;
+                                }
+                                if self.char_quoted(before) {
+                                    from -= 2;
+                                    continue;
+                                }
+                                if !matches!(self.class_at(before), Word | Symbol | Quote) {
+                                    break 'object;
+                                }
+                                from -= 1;
+                            }
+                            break 'object;
+                        }
+                        Close => {
+                            depth += 1;
+                            if depth == 0 {
+                                break 'object;
+                            }
+                        }
+                        Open => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break 'object;
+                            }
+                            if depth < min_depth {
+                                let message = "Containing expression ends prematurely";
+                                return Err(ScanError { message, last_good, pos: from });
+                            }
+                        }
+                        EndComment => {
+                            if !self.ignore_comments {
+                                continue;
+                            }
+                            if let Some(start) = self.comment_start(from, stop, style) {
+                                from = start;
+                            }
+                        }
+                        CommentFence | StringFence => {
+                            loop {
+                                if from == stop {
+                                    return Err(ScanError::unbalanced(last_good, from));
+                                }
+                                from -= 1;
+                                if !self.char_quoted(from) && self.class_at(from) == class {
+                                    break;
+                                }
+                            }
+                            if class == StringFence && depth == 0 && sexpflag {
+                                break 'object;
+                            }
+                        }
+                        String => {
+                            loop {
+                                if from == stop {
+                                    return Err(ScanError::unbalanced(last_good, from));
+                                }
+                                from -= 1;
+                                if !self.char_quoted(from)
+                                    && self.char_at(from) == chr
+                                    && self.class_at(from) == String
+                                {
+                                    break;
+                                }
+                            }
+                            if depth == 0 && sexpflag {
+                                break 'object;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                // Reached the start of the accessible portion
+                if depth != 0 {
+                    return Err(ScanError::unbalanced(last_good, from));
+                }
+                return Ok(None);
+            }
+            count += 1;
+        }
+        Ok(Some(from))
+    }
+
+    /// Move over `count` comments and the whitespace around them from `from`.
+    /// Returns the new position and whether all the comments were found.
+    fn forward_comment(&self, mut from: usize, mut count: i64) -> (usize, bool) {
+        while count > 0 {
+            let stop = self.zv;
+            let (mut class, mut style, mut nested);
+            loop {
+                if from == stop {
+                    return (from, false);
+                }
+                let chr = self.char_at(from);
+                let syntax = self.syntax_at(from);
+                class = syntax.class;
+                style = syntax.comment_style(None);
+                nested = syntax.nested();
+                from += 1;
+                if from < stop && syntax.has(COMSTART_FIRST) {
+                    let other = self.syntax_at(from);
+                    if other.has(COMSTART_SECOND) {
+                        class = Comment;
+                        style = other.comment_style(Some(syntax));
+                        nested |= other.nested();
+                        from += 1;
+                    }
+                }
+                if !(class == Whitespace || (class == EndComment && chr == '\n')) {
+                    break;
+                }
+            }
+            match class {
+                CommentFence => style = FENCE_STYLE,
+                Comment => {}
+                _ => return (from - 1, false),
+            }
+            let end = self.comment_end(from, stop, i64::from(nested), style, None);
+            if !end.found {
+                return (end.pos, false);
+            }
+            from = end.pos + 1;
+            count -= 1;
+        }
+
+        while count < 0 {
+            let stop = self.begv;
+            loop {
+                if from <= stop {
+                    return (self.begv, false);
+                }
+                from -= 1;
+                let quoted = self.char_quoted(from);
+                let chr = self.char_at(from);
+                let syntax = self.syntax_at(from);
+                let mut class = syntax.class;
+                let mut style = if class == EndComment { syntax.comment_style(None) } else { 0 };
+                if from > stop
+                    && syntax.has(COMEND_SECOND)
+                    && self.syntax_at(from - 1).has(COMEND_FIRST)
+                    && !self.char_quoted(from - 1)
+                {
+                    from -= 1;
+                    class = EndComment;
+                    style = self.syntax_at(from).comment_style(Some(syntax));
+                }
+                if class == CommentFence {
+                    let end = from;
+                    let start = (stop..end)
+                        .rev()
+                        .find(|x| self.class_at(*x) == CommentFence && !self.char_quoted(*x));
+                    match start {
+                        Some(start) => {
+                            from = start;
+                            break;
+                        }
+                        None => return (end + 1, false),
+                    }
+                } else if class == EndComment {
+                    match self.comment_start(from, stop, style) {
+                        Some(start) => {
+                            from = start;
+                            break;
+                        }
+                        // A newline that does not end a comment is whitespace
+                        None if chr == '\n' => {}
+                        None => {
+                            // Go back to the end of a two character ender
+                            if syntax.class != class {
+                                from += 1;
+                            }
+                            return (from + 1, false);
+                        }
+                    }
+                } else if class != Whitespace || quoted {
+                    return (from + 1, false);
+                }
+            }
+            count += 1;
+        }
+        (from, true)
+    }
+
+    /// Parse forward from `from` to `end` starting in `state`. The parse stops
+    /// early if it reaches `target` depth, at the start of a sexp if
+    /// `stop_before` is true, or at a comment as described by `comment_stop`.
+    /// Returns the final state and position.
+    fn parse(
+        &self,
+        mut state: ParseState,
+        from: usize,
+        end: usize,
+        target: Option<i64>,
+        stop_before: bool,
+        comment_stop: CommentStop,
+    ) -> (ParseState, usize) {
+        let mut levels = vec![Level::default()];
+        for start in std::mem::take(&mut state.levelstarts) {
+            levels.last_mut().unwrap().last = Some(start);
+            levels.push(Level::default());
+        }
+        state.mindepth = state.depth;
+        let prev_syntax = state.prev_syntax;
+        let mut parse = Parse {
+            scanner: self,
+            state,
+            levels,
+            end,
+            from,
+            prev_from: from,
+            prev_syntax,
+            prev_prev_syntax: None,
+            target,
+            stop_before,
+            comment_stop,
+        };
+        let stopped = parse.run();
+        parse.finish(stopped)
+    }
+}
+
+/// The starts of the expressions at one level of list structure.
+#[derive(Debug, Default, Clone, Copy)]
+struct Level {
+    /// The start of the last expression started at this level.
+    last: Option<usize>,
+    /// The start of the last complete expression at this level.
+    prev: Option<usize>,
+}
+
+/// How a forward parse ended.
+enum Stopped {
+    Done,
+    /// Stopped at the start of a sexp, which should not be included.
+    BeforeSexp,
+    /// Stopped right after a quoting character.
+    Quoted,
+}
+
+/// A forward parse in progress, as done by `parse-partial-sexp`.
+struct Parse<'s, 'a, 'ob> {
+    scanner: &'s Scanner<'a, 'ob>,
+    state: ParseState,
+    levels: Vec<Level>,
+    end: usize,
+    from: usize,
+    prev_from: usize,
+    prev_syntax: Option<Syntax>,
+    prev_prev_syntax: Option<Syntax>,
+    target: Option<i64>,
+    stop_before: bool,
+    comment_stop: CommentStop,
+}
+
+impl Parse<'_, '_, '_> {
+    /// Move over the next character.
+    fn advance(&mut self) {
+        self.prev_from = self.from;
+        self.prev_prev_syntax = self.prev_syntax;
+        self.prev_syntax = Some(self.scanner.syntax_at(self.from));
+        self.from += 1;
+    }
+
+    fn level(&mut self) -> &mut Level {
+        self.levels.last_mut().unwrap()
+    }
+
+    /// Mark the last expression of the current level as complete.
+    fn complete_level(&mut self) {
+        let level = self.level();
+        level.prev = level.last;
+    }
+
+    fn run(&mut self) -> Stopped {
+        // Resume in the construct the parse was in when it stopped
+        let quoted = std::mem::take(&mut self.state.quoted);
+        let resumed = if self.state.incomment != 0 {
+            self.comment()
+        } else if self.state.instring.is_some() {
+            self.string(quoted)
+        } else if quoted {
+            self.quoted()
+        } else {
+            None
+        };
+        if let Some(stopped) = resumed {
+            return stopped;
+        }
+
+        let scanner = self.scanner;
+        loop {
+            if self.from >= self.end {
+                return Stopped::Done;
+            }
+            let comment_start = self
+                .prev_syntax
+                .filter(|x| x.has(COMSTART_FIRST))
+                .map(|first| (first, scanner.syntax_at(self.from)))
+                .filter(|(_, second)| second.has(COMSTART_SECOND));
+            let class = if let Some((first, second)) = comment_start {
+                self.state.comstyle = second.comment_style(Some(first));
+                self.state.incomment = if first.nested() || second.nested() { 1 } else { -1 };
+                self.state.comstr_start = self.prev_from;
+                self.advance();
+                self.prev_syntax = None;
+                Comment
+            } else {
+                self.advance();
+                let syntax = self.prev_syntax.unwrap();
+                match syntax.class {
+                    CommentFence => {
+                        self.state.comstyle = FENCE_STYLE;
+                        self.state.incomment = -1;
+                        self.state.comstr_start = self.prev_from;
+                        Comment
+                    }
+                    Comment => {
+                        self.state.comstyle = syntax.comment_style(None);
+                        self.state.incomment = if syntax.nested() { 1 } else { -1 };
+                        self.state.comstr_start = self.prev_from;
+                        Comment
+                    }
+                    class => class,
+                }
+            };
+            if self.prev_syntax.is_some_and(|x| x.has(PREFIX)) {
+                continue;
+            }
+            let stopped = match class {
+                Escape | CharQuote | Word | Symbol => {
+                    if self.stop_before {
+                        return Stopped::BeforeSexp;
+                    }
+                    self.level().last = Some(self.prev_from);
+                    if matches!(class, Escape | CharQuote) { self.quoted() } else { self.symbol() }
+                }
+                Comment => {
+                    if self.comment_stop != CommentStop::Never {
+                        return Stopped::Done;
+                    }
+                    self.comment()
+                }
+                Open => {
+                    if self.stop_before {
+                        return Stopped::BeforeSexp;
+                    }
+                    self.state.depth += 1;
+                    self.level().last = Some(self.prev_from);
+                    self.levels.push(Level::default());
+                    if self.target == Some(self.state.depth) {
+                        return Stopped::Done;
+                    }
+                    None
+                }
+                Close => {
+                    self.state.depth -= 1;
+                    self.state.mindepth = self.state.mindepth.min(self.state.depth);
+                    if self.levels.len() > 1 {
+                        self.levels.pop();
+                    }
+                    self.complete_level();
+                    if self.target == Some(self.state.depth) {
+                        return Stopped::Done;
+                    }
+                    None
+                }
+                String | StringFence => {
+                    self.state.comstr_start = self.from - 1;
+                    if self.stop_before {
+                        return Stopped::BeforeSexp;
+                    }
+                    self.level().last = Some(self.prev_from);
+                    let term = (class == String).then(|| scanner.char_at(self.prev_from));
+                    self.state.instring = Some(term);
+                    if self.comment_stop == CommentStop::Boundary {
+                        return Stopped::Done;
+                    }
+                    self.string(false)
+                }
+                _ => None,
+            };
+            if let Some(stopped) = stopped {
+                return stopped;
+            }
+        }
+    }
+
+    /// Move over the character quoted by the previous one and the rest of the
+    /// symbol it is in.
+    fn quoted(&mut self) -> Option<Stopped> {
+        if self.from == self.end {
+            return Some(Stopped::Quoted);
+        }
+        self.advance();
+        self.symbol()
+    }
+
+    /// Move over the rest of a symbol.
+    fn symbol(&mut self) -> Option<Stopped> {
+        let scanner = self.scanner;
+        while self.from < self.end {
+            let comment_start = self.prev_syntax.is_some_and(|x| x.has(COMSTART_FIRST))
+                && scanner.syntax_at(self.from).has(COMSTART_SECOND);
+            if comment_start {
+                break;
+            }
+            match scanner.class_at(self.from) {
+                Escape | CharQuote => {
+                    self.advance();
+                    if self.from == self.end {
+                        return Some(Stopped::Quoted);
+                    }
+                }
+                Word | Symbol | Quote => {}
+                _ => break,
+            }
+            self.advance();
+        }
+        self.complete_level();
+        None
+    }
+
+    /// Move over the rest of the comment described by the state.
+    fn comment(&mut self) -> Option<Stopped> {
+        let prev = if self.from == self.scanner.begv { None } else { self.prev_syntax };
+        let (nesting, style) = (self.state.incomment, self.state.comstyle);
+        let end = self.scanner.comment_end(self.from, self.end, nesting, style, prev);
+        self.from = end.pos;
+        if !end.found {
+            self.state.incomment = end.nesting;
+            self.prev_syntax = end.last;
+            return Some(Stopped::Done);
+        }
+        self.advance();
+        self.state.incomment = 0;
+        self.state.comstyle = 0;
+        self.prev_syntax = None;
+        (self.comment_stop == CommentStop::Boundary).then_some(Stopped::Done)
+    }
+
+    /// Move over the rest of the string described by the state. If `quoted`
+    /// is true, the next character is quoted.
+    fn string(&mut self, mut quoted: bool) -> Option<Stopped> {
+        let scanner = self.scanner;
+        let term = self.state.instring.flatten();
+        loop {
+            if !quoted {
+                if self.from >= self.end {
+                    return Some(Stopped::Done);
+                }
+                let class = scanner.class_at(self.from);
+                if term.is_some_and(|x| x == scanner.char_at(self.from)) && class == String {
+                    break;
+                }
+                match class {
+                    StringFence if term.is_none() => break,
+                    Escape | CharQuote => {
+                        self.advance();
+                        quoted = true;
+                    }
+                    _ => {}
+                }
+            }
+            if std::mem::take(&mut quoted) && self.from >= self.end {
+                return Some(Stopped::Quoted);
+            }
+            self.advance();
+        }
+        self.state.instring = None;
+        self.complete_level();
+        self.advance();
+        (self.comment_stop == CommentStop::Boundary).then_some(Stopped::Done)
+    }
+
+    fn finish(mut self, stopped: Stopped) -> (ParseState, usize) {
+        match stopped {
+            Stopped::Done => {}
+            Stopped::BeforeSexp => {
+                self.from = self.prev_from;
+                self.prev_syntax = self.prev_prev_syntax;
+            }
+            Stopped::Quoted => self.state.quoted = true,
+        }
+        let mut state = self.state;
+        let (current, outer) = self.levels.split_last().unwrap();
+        state.thislevelstart = current.prev;
+        state.prevlevelstart = outer.last().and_then(|x| x.last);
+        state.levelstarts = outer.iter().filter_map(|x| x.last).collect();
+        let quoted = state.quoted;
+        state.prev_syntax =
+            self.prev_syntax.filter(|x| quoted || x.has(COMSTART_FIRST | COMEND_FIRST));
+        (state, self.from)
+    }
+}
+
+fn set_point(pos: usize, env: &mut Rt<Env>) {
+    env.current_buffer.get_mut().text.set_cursor(pos);
+}
+
+/// The position `count` words away from point, or the edge of the accessible
+/// portion of the buffer if there are not that many words.
+pub(crate) fn scan_words(count: i64, env: &Rt<Env>, cx: &Context) -> usize {
+    let scanner = Scanner::new(env, cx);
+    scanner.scan_words(scanner.point(), count).0
+}
+
+#[defun]
+pub(crate) fn forward_word(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> bool {
+    let (pos, found) = {
+        let scanner = Scanner::new(env, cx);
+        scanner.scan_words(scanner.point(), arg.unwrap_or(1))
+    };
+    set_point(pos, env);
+    found
+}
+
+#[defun]
+fn backward_word(arg: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> bool {
+    forward_word(Some(-arg.unwrap_or(1)), env, cx)
+}
+
+#[defun]
+fn skip_syntax_forward(syntax: &str, lim: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> i64 {
+    let (start, pos) = {
+        let scanner = Scanner::new(env, cx);
+        let start = scanner.point();
+        let lim = lim.map_or(scanner.zv, |x| scanner.clamp(x));
+        (start, if lim > start { scanner.skip_syntax(syntax, start, lim) } else { start })
+    };
+    set_point(pos, env);
+    (pos - start) as i64
+}
+
+#[defun]
+fn skip_syntax_backward(syntax: &str, lim: Option<i64>, env: &mut Rt<Env>, cx: &Context) -> i64 {
+    let (start, pos) = {
+        let scanner = Scanner::new(env, cx);
+        let start = scanner.point();
+        let lim = lim.map_or(scanner.begv, |x| scanner.clamp(x));
+        (start, if lim < start { scanner.skip_syntax(syntax, start, lim) } else { start })
+    };
+    set_point(pos, env);
+    -((start - pos) as i64)
+}
+
+#[defun]
+fn scan_lists(
+    from: i64,
+    count: i64,
+    depth: i64,
+    env: &Rt<Env>,
+    cx: &Context,
+) -> Result<Option<usize>> {
+    let scanner = Scanner::new(env, cx);
+    match scanner.scan_lists(scanner.clamp(from), count, depth, false) {
+        Ok(pos) => Ok(pos.map(|x| x + 1)),
+        Err(err) => bail!(err.signal(cx)?),
+    }
+}
+
+#[defun]
+fn scan_sexps(from: i64, count: i64, env: &Rt<Env>, cx: &Context) -> Result<Option<usize>> {
+    let scanner = Scanner::new(env, cx);
+    match scanner.scan_lists(scanner.clamp(from), count, 0, true) {
+        Ok(pos) => Ok(pos.map(|x| x + 1)),
+        Err(err) => bail!(err.signal(cx)?),
+    }
+}
+
+#[defun]
+fn forward_comment(count: i64, env: &mut Rt<Env>, cx: &Context) -> bool {
+    let (pos, found) = {
+        let scanner = Scanner::new(env, cx);
+        scanner.forward_comment(scanner.point(), count)
+    };
+    set_point(pos, env);
+    found
+}
+
+#[defun]
+fn backward_prefix_chars(env: &mut Rt<Env>, cx: &Context) -> bool {
+    let point = {
+        let scanner = Scanner::new(env, cx);
+        let mut point = scanner.point();
+        let mut pos = point;
+        while pos > scanner.begv {
+            pos -= 1;
+            let syntax = scanner.syntax_at(pos);
+            if scanner.char_quoted(pos) || !(syntax.class == Quote || syntax.has(PREFIX)) {
+                break;
+            }
+            point = pos;
+        }
+        point
+    };
+    set_point(point, env);
+    false
+}
+
+#[defun]
+#[expect(clippy::too_many_arguments)]
+fn parse_partial_sexp<'ob>(
+    from: i64,
+    to: i64,
+    targetdepth: Option<i64>,
+    stopbefore: OptionalFlag,
+    oldstate: Option<Object>,
+    commentstop: Option<Object>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    if to < from {
+        bail!("End position is smaller than start position");
+    }
+    let (state, pos) = {
+        let scanner = Scanner::new(env, cx);
+        let in_range = |x: i64| (scanner.begv as i64 + 1..=scanner.zv as i64 + 1).contains(&x);
+        if !in_range(from) || !in_range(to) {
+            bail!(SignalError::args_out_of_range(&[from.into(), to.into()]));
+        }
+        let state = ParseState::from_lisp(oldstate.unwrap_or(NIL))?;
+        let comment_stop = match commentstop {
+            None => CommentStop::Never,
+            Some(x) if x == sym::SYNTAX_TABLE => CommentStop::Boundary,
+            Some(_) => CommentStop::Start,
+        };
+        let (from, to) = (from as usize - 1, to as usize - 1);
+        scanner.parse(state, from, to, targetdepth, stopbefore.is_some(), comment_stop)
+    };
+    set_point(pos, env);
+    Ok(state.into_lisp(cx))
+}
+
+/// A set of characters as described by the STRING argument of
+/// `skip-chars-forward`.
+struct CharSet {
+    negate: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl CharSet {
+    fn new(spec: &str) -> Result<Self> {
+        let (negate, spec) = match spec.strip_prefix('^') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+        let mut ranges = Vec::new();
+        let mut chars = spec.chars().peekable();
+        while let Some(mut chr) = chars.next() {
+            if chr == '\\' {
+                let Some(next) = chars.next() else { break };
+                chr = next;
+            }
+            if chars.next_if_eq(&'-').is_some() {
+                match chars.next() {
+                    Some(mut end) => {
+                        if end == '\\' {
+                            let Some(next) = chars.next() else { bail!("Invalid range in {spec}") };
+                            end = next;
+                        }
+                        // Ranges where the end is before the start are empty
+                        if chr <= end {
+                            ranges.push((chr, end));
+                        }
+                    }
+                    // A trailing `-` is literal
+                    None => {
+                        ranges.push((chr, chr));
+                        ranges.push(('-', '-'));
+                    }
+                }
+            } else {
+                ranges.push((chr, chr));
+            }
+        }
+        Ok(Self { negate, ranges })
+    }
+
+    fn contains(&self, chr: char) -> bool {
+        let found = self.ranges.iter().any(|(beg, end)| (*beg..=*end).contains(&chr));
+        found != self.negate
+    }
+}
+
+#[defun]
+pub(crate) fn skip_chars_forward(
+    string: &str,
+    lim: Option<usize>,
+    env: &mut Rt<Env>,
+) -> Result<i64> {
+    let set = CharSet::new(string)?;
+    let data = env.current_buffer.get_mut();
+    let zv = data.zv();
+    let text = &mut data.text;
+    let start = text.cursor().chars();
+    let lim = lim.map_or(zv, |x| x.saturating_sub(1).min(zv));
+    if lim <= start {
+        return Ok(0);
+    }
+    let (a, b) = text.slice(start..lim);
+    let moved = a.chars().chain(b.chars()).take_while(|c| set.contains(*c)).count();
+    text.set_cursor(start + moved);
+    Ok(moved as i64)
+}
+
+#[defun]
+pub(crate) fn skip_chars_backward(
+    string: &str,
+    lim: Option<usize>,
+    env: &mut Rt<Env>,
+) -> Result<i64> {
+    let set = CharSet::new(string)?;
+    let data = env.current_buffer.get_mut();
+    let (begv, zv) = (data.begv(), data.zv());
+    let text = &mut data.text;
+    let start = text.cursor().chars();
+    let lim = lim.map_or(begv, |x| x.saturating_sub(1).clamp(begv, zv));
+    if lim >= start {
+        return Ok(0);
+    }
+    let (a, b) = text.slice(lim..start);
+    let moved = a.chars().chain(b.chars()).rev().take_while(|c| set.contains(*c)).count();
+    text.set_cursor(start - moved);
+    Ok(-(moved as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{Context, RootSet};
+    use rune_core::macros::root;
+
+    #[test]
+    fn test_forward_word() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("  foo-bar baz ");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert!(forward_word(Some(2), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 9);
+        assert!(!forward_word(Some(2), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 14);
+        assert!(forward_word(Some(-1), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 10);
+        assert!(!backward_word(Some(4), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 0);
+    }
+
+    #[test]
+    fn test_skip_chars() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("abc-123 xyz");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(skip_chars_forward("a-z", None, env).unwrap(), 3);
+        assert_eq!(skip_chars_forward("^ ", None, env).unwrap(), 4);
+        assert_eq!(skip_chars_forward(" ", Some(8), env).unwrap(), 0);
+        assert_eq!(skip_chars_forward("a-z ", None, env).unwrap(), 4);
+        assert_eq!(skip_chars_backward("xyz", None, env).unwrap(), -3);
+        assert_eq!(skip_chars_backward("^a", None, env).unwrap(), -7);
+        assert_eq!(skip_chars_backward("a", None, env).unwrap(), -1);
+        assert_eq!(skip_chars_forward("\\-a-c", None, env).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_narrowed_skip() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("abc def ghi");
+        env.current_buffer.get_mut().narrow(5, 9);
+        env.current_buffer.get_mut().text.set_cursor(6);
+        assert_eq!(skip_chars_forward("a-z ", None, env).unwrap(), 3);
+        assert_eq!(skip_chars_backward("a-z ", Some(1), env).unwrap(), -4);
+        assert!(forward_word(Some(2), env, cx));
+        assert!(!forward_word(Some(1), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 9);
+        assert!(!forward_word(Some(-3), env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 5);
+    }
+
+    #[test]
+    fn test_char_syntax() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        assert_eq!(char_syntax('a', env, cx), 'w');
+        assert_eq!(char_syntax(' ', env, cx), ' ');
+        assert_eq!(char_syntax('(', env, cx), '(');
+        assert_eq!(char_syntax('-', env, cx), '_');
+        assert_eq!(char_syntax('λ', env, cx), 'w');
+        assert_eq!(matching_paren('[', env, cx), Some(']'));
+
+        let table = copy_syntax_table(None, env, cx).unwrap();
+        modify_syntax_entry(cx.add('-'), "w", Some(table), env, cx).unwrap();
+        assert_eq!(char_syntax('-', env, cx), '_');
+        set_syntax_table(table, env, cx).unwrap();
+        assert_eq!(char_syntax('-', env, cx), 'w');
+        assert_eq!(char_syntax('a', env, cx), 'w');
+        let range = Cons::new('0', '9', cx).into();
+        modify_syntax_entry(range, ".", None, env, cx).unwrap();
+        assert_eq!(char_syntax('5', env, cx), '.');
+        assert_eq!(standard_table(env, cx).get('5' as usize), Syntax::new(Word).descriptor(cx));
+    }
+
+    #[test]
+    fn test_string_to_syntax() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        assert_eq!(string_to_syntax("w", cx).unwrap(), list![2; cx]);
+        assert_eq!(string_to_syntax("()", cx).unwrap(), Object::from(Cons::new(4, ')', cx)));
+        let code = 11 | 1 << 16 | 1 << 21;
+        assert_eq!(string_to_syntax("< 1b", cx).unwrap(), list![code; cx]);
+        assert_eq!(string_to_syntax("@", cx).unwrap(), NIL);
+        assert!(string_to_syntax("Z", cx).is_err());
+        assert_eq!(syntax_class_to_char(5).unwrap(), ')');
+        assert!(syntax_class_to_char(16).is_err());
+    }
+
+    /// Set up a buffer with lisp comment syntax.
+    fn lisp_buffer(env: &mut Rt<Env>, text: &str, cx: &Context) {
+        let table = copy_syntax_table(None, env, cx).unwrap();
+        modify_syntax_entry(cx.add(';'), "<", Some(table), env, cx).unwrap();
+        modify_syntax_entry(cx.add('\n'), ">", Some(table), env, cx).unwrap();
+        modify_syntax_entry(cx.add('\''), "'", Some(table), env, cx).unwrap();
+        set_syntax_table(table, env, cx).unwrap();
+        env.set_var(sym::PARSE_SEXP_IGNORE_COMMENTS, true.into()).unwrap();
+        env.current_buffer.get_mut().text.insert(text);
+    }
+
+    #[test]
+    fn test_scan_lists() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        lisp_buffer(env, "(a (b \"c)\") ; d)\n 'e) (f", cx);
+        assert_eq!(scan_sexps(1, 1, env, cx).unwrap(), Some(22));
+        assert_eq!(scan_lists(1, 1, 0, env, cx).unwrap(), Some(22));
+        assert_eq!(scan_lists(2, 1, 1, env, cx).unwrap(), Some(22));
+        assert_eq!(scan_sexps(4, 1, env, cx).unwrap(), Some(12));
+        assert_eq!(scan_sexps(21, -1, env, cx).unwrap(), Some(19));
+        assert_eq!(scan_sexps(22, -1, env, cx).unwrap(), Some(1));
+        assert_eq!(scan_sexps(1, -1, env, cx).unwrap(), None);
+        assert!(scan_sexps(23, 1, env, cx).is_err());
+        assert!(scan_lists(5, 1, 0, env, cx).is_err());
+    }
+
+    #[test]
+    fn test_forward_comment() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        lisp_buffer(env, "  ; one\n ; two\nx ;three", cx);
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert!(forward_comment(2, env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 15);
+        assert!(!forward_comment(1, env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 15);
+        assert!(forward_comment(-2, env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 2);
+        env.current_buffer.get_mut().text.set_cursor(17);
+        assert!(!forward_comment(2, env, cx));
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 23);
+    }
+
+    #[test]
+    fn test_parse_partial_sexp() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        lisp_buffer(env, "(a (b \"c d\" ; e\n", cx);
+        let state = parse_partial_sexp(1, 10, None, None, None, None, env, cx).unwrap();
+        let expect = list![2, 4, 5, '"', NIL, NIL, 0, NIL, 7, list![1, 4; cx], NIL; cx];
+        assert_eq!(state, expect);
+        let state = parse_partial_sexp(10, 16, None, None, Some(state), None, env, cx).unwrap();
+        let expect = list![2, 4, NIL, NIL, true, NIL, 2, NIL, 13, list![1, 4; cx], NIL; cx];
+        assert_eq!(state, expect);
+        let state = parse_partial_sexp(1, 17, Some(1), None, None, None, env, cx).unwrap();
+        assert_eq!(state.as_list().unwrap().next().unwrap().unwrap(), 1);
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 1);
+        parse_partial_sexp(2, 17, None, Some(()), None, None, env, cx).unwrap();
+        assert_eq!(env.current_buffer.get().text.cursor().chars(), 1);
+        assert!(parse_partial_sexp(5, 2, None, None, None, None, env, cx).is_err());
+    }
+
+    #[test]
+    fn test_skip_syntax() {
+        let roots = &RootSet::default();
+        let cx = &mut Context::new(roots);
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("foo-bar  (baz)");
+        env.current_buffer.get_mut().text.set_cursor(0);
+        assert_eq!(skip_syntax_forward("w_", None, env, cx), 7);
+        assert_eq!(skip_syntax_forward("-", None, env, cx), 2);
+        assert_eq!(skip_syntax_forward("^)", Some(12), env, cx), 2);
+        assert_eq!(skip_syntax_backward("^ ", None, env, cx), -2);
+        assert_eq!(skip_syntax_backward("w_ ", None, env, cx), -9);
+    }
+}
@@ -1,17 +1,20 @@
 use crate::{
     core::{
         cons::Cons,
-        env::Env,
-        error::{Type, TypeError},
+        env::{Env, sym},
+        error::{SignalError, Type, TypeError},
         gc::{Context, Rt, Slot},
-        object::{Gc, ListType, NIL, Object, ObjectType, WithLifetime},
+        object::{Gc, IntoObject, LispString, List, ListType, NIL, Object, ObjectType},
     },
-    fns::eq,
-    intervals::textget,
+    data::LispError,
+    fns::{self, eq},
+    intervals::{IntervalTree, textget},
+    overlay,
 };
 use anyhow::{Result, anyhow, bail};
 use rune_core::macros::list;
 use rune_macros::defun;
+use std::cell::Ref;
 
 use crate::core::object::BufferData;
 
@@ -84,9 +87,9 @@ pub fn add_properties<'ob>(
 ///
 /// # Returns
 /// Result containing the return value from func or an error
-fn modify_buffer_data<'ob, T>(
-    object: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+fn modify_buffer_data<T>(
+    object: Object,
+    env: &mut Rt<Env>,
     func: impl FnOnce(&mut BufferData) -> Result<T>,
 ) -> Result<T> {
     if object.is_nil() {
@@ -108,6 +111,367 @@ fn modify_buffer_data<'ob, T>(
     }
 }
 
+/// The text properties of a buffer or string, along with the bounds of the
+/// part of it that is accessible.
+struct TextProps<'a, 'ob> {
+    tree: &'a mut IntervalTree<'ob>,
+    /// The first accessible position.
+    min: usize,
+    /// The last accessible position.
+    max: usize,
+    /// Whether positions outside of `min..=max` are an error. Buffer positions
+    /// are only checked while the buffer is narrowed.
+    checked: bool,
+}
+
+impl TextProps<'_, '_> {
+    /// Check that the region from `start` to `end` is within the accessible
+    /// portion of the buffer or string.
+    fn validate_region(&self, start: usize, end: usize) -> Result<()> {
+        if self.checked {
+            let (beg, end_) = if start <= end { (start, end) } else { (end, start) };
+            if beg < self.min || end_ > self.max {
+                bail!(SignalError::args_out_of_range(&[start.into(), end.into()]));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply `func` to the text properties of OBJECT, which is a string, a buffer,
+/// or nil for the current buffer. Strings are indexed from 0 and buffers from
+/// 1.
+fn modify_textprops<'ob, T>(
+    object: Object<'ob>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
+    func: impl FnOnce(TextProps<'_, 'ob>) -> Result<T>,
+) -> Result<T> {
+    if let ObjectType::String(string) = object.untag() {
+        let max = string.len();
+        let tree = &mut *string.textprops_mut(cx)?;
+        return func(TextProps { tree, min: 0, max, checked: true });
+    }
+    modify_buffer_data(object, env, |data| {
+        let (min, max, checked) = (data.begv() + 1, data.zv() + 1, data.is_narrowed());
+        func(TextProps { tree: data.textprops_with_lifetime(), min, max, checked })
+    })
+}
+
+/// Copy the text properties of [start..end) in `from` onto the string `to`,
+/// starting at `offset`.
+pub(crate) fn copy_textprops<'ob>(
+    from: &IntervalTree<'ob>,
+    start: usize,
+    end: usize,
+    to: &'ob LispString,
+    offset: usize,
+    cx: &'ob Context,
+) -> Result<()> {
+    // Don't give the string a tree if there is nothing to copy
+    if from.iter(start, end).all(|(_, plist)| plist.is_nil()) {
+        return Ok(());
+    }
+    from.copy_into(start, end, &mut *to.textprops_mut(cx)?, offset, cx)
+}
+
+/// Copy the text properties of [start..end) in the string `from` onto the
+/// string `to`, starting at `offset`.
+pub(crate) fn copy_string_textprops<'ob>(
+    from: &'ob LispString,
+    start: usize,
+    end: usize,
+    to: &'ob LispString,
+    offset: usize,
+    cx: &'ob Context,
+) -> Result<()> {
+    match from.textprops() {
+        Some(props) => copy_textprops(&props, start, end, to, offset, cx),
+        None => Ok(()),
+    }
+}
+
+defsym!(FRONT_STICKY);
+defsym!(REAR_NONSTICKY);
+defsym!(READ_ONLY);
+defsym!(DISPLAY);
+defsym!(COMPOSITION);
+defsym!(CURSOR);
+
+defvar!(
+    TEXT_PROPERTY_DEFAULT_NONSTICKY,
+    list![
+        crate::core::cons::Cons::new(sym::SYNTAX_TABLE, true, cx),
+        crate::core::cons::Cons::new(sym::DISPLAY, true, cx),
+        crate::core::cons::Cons::new(sym::COMPOSITION, true, cx),
+        crate::core::cons::Cons::new(sym::CURSOR, true, cx),
+    ]
+);
+defvar!(INHIBIT_READ_ONLY);
+
+/// Whether `set` includes `prop`. A list includes its members, and any other
+/// non-nil value includes everything. This is how the values of `front-sticky`,
+/// `rear-nonsticky` and `inhibit-read-only` are interpreted.
+fn includes(set: Object, prop: Object) -> bool {
+    match set.untag() {
+        ObjectType::Cons(cons) => cons.elements().any(|x| x.is_ok_and(|x| eq(x, prop))),
+        _ => !set.is_nil(),
+    }
+}
+
+/// The key and value pairs of `plist`.
+fn plist_pairs(plist: Object) -> Result<Vec<(Object, Object)>> {
+    let Ok(plist) = Gc::<ListType>::try_from(plist) else { return Ok(Vec::new()) };
+    let mut pairs = Vec::new();
+    let mut iter = plist.elements();
+    while let Some(key) = iter.next() {
+        let value = iter.next().unwrap_or(Ok(NIL))?;
+        pairs.push((key?, value));
+    }
+    Ok(pairs)
+}
+
+/// The properties that text inserted between a character with the properties
+/// `left` and one with the properties `right` inherits. A property is
+/// inherited from the character before unless it is rear-nonsticky, and from
+/// the character after if it is front-sticky. `nonsticky` is the value of
+/// `text-property-default-nonsticky`, which sets the stickiness of properties
+/// that the characters don't specify. See `merge_properties_sticky` in Emacs.
+fn sticky_properties<'ob>(
+    left: Object<'ob>,
+    right: Object<'ob>,
+    nonsticky: Object<'ob>,
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    let (lfront, lrear) = (
+        textget(left, sym::FRONT_STICKY.into())?,
+        textget(left, sym::REAR_NONSTICKY.into())?,
+    );
+    let (rfront, rrear) = (
+        textget(right, sym::FRONT_STICKY.into())?,
+        textget(right, sym::REAR_NONSTICKY.into())?,
+    );
+    let is_stickiness =
+        |prop| eq(prop, sym::FRONT_STICKY.into()) || eq(prop, sym::REAR_NONSTICKY.into());
+    // `Some(true)` if the default makes `prop` nonsticky, and `Some(false)` if
+    // it makes it sticky on both sides
+    let default = |prop| -> Result<Option<bool>> {
+        let Ok(alist) = List::try_from(nonsticky) else { return Ok(None) };
+        match fns::assq(prop, alist)?.untag() {
+            ObjectType::Cons(cons) => Ok(Some(!cons.cdr().is_nil())),
+            _ => Ok(None),
+        }
+    };
+    let mut props = Vec::new();
+    let mut front = Vec::new();
+    let mut rear = Vec::new();
+    for (prop, rval) in plist_pairs(right)? {
+        if is_stickiness(prop) {
+            continue;
+        }
+        let lval = match fns::plist_member(left, prop, None)?.untag() {
+            ObjectType::Cons(cons) => Some(cons.cadr().unwrap_or(NIL)),
+            _ => None,
+        };
+        let default = default(prop)?;
+        let mut use_left = lval.is_some() && !(includes(lrear, prop) || default == Some(true));
+        let mut use_right = includes(rfront, prop) || default == Some(false);
+        let lval = lval.unwrap_or(NIL);
+        if use_left && use_right {
+            if lval.is_nil() {
+                use_left = false;
+            } else if rval.is_nil() {
+                use_right = false;
+            }
+        }
+        let (value, front_sticky, rear_nonsticky) = if use_left {
+            (lval, lfront, lrear)
+        } else if use_right {
+            (rval, rfront, rrear)
+        } else {
+            continue;
+        };
+        props.extend([prop, value]);
+        if includes(front_sticky, prop) {
+            front.push(prop);
+        }
+        if includes(rear_nonsticky, prop) {
+            rear.push(prop);
+        }
+    }
+    for (prop, lval) in plist_pairs(left)? {
+        if is_stickiness(prop) || !fns::plist_member(right, prop, None)?.is_nil() {
+            continue;
+        }
+        let default = default(prop)?;
+        if !(includes(lrear, prop) || default == Some(true)) {
+            props.extend([prop, lval]);
+            if includes(lfront, prop) {
+                front.push(prop);
+            }
+        } else if includes(rfront, prop) || default == Some(false) {
+            // The value is nil, but the stickiness is still inherited
+            front.push(prop);
+            if includes(rrear, prop) {
+                rear.push(prop);
+            }
+        }
+    }
+    let mut stickiness = Vec::new();
+    if !front.is_empty() {
+        stickiness.extend([sym::FRONT_STICKY.into(), fns::slice_into_list(&front, None, cx)]);
+    }
+    if !rear.is_empty() {
+        stickiness.extend([sym::REAR_NONSTICKY.into(), fns::slice_into_list(&rear, None, cx)]);
+    }
+    stickiness.extend(props);
+    Ok(fns::slice_into_list(&stickiness, None, cx))
+}
+
+/// Signal `text-read-only` if the `read-only` property `value` prevents
+/// modifying text. `inhibit` is the value of `inhibit-read-only`.
+fn check_read_only(value: Object, inhibit: Object) -> Result<()> {
+    if value.is_nil() || includes(inhibit, value) {
+        return Ok(());
+    }
+    bail!(SignalError::text_read_only(value))
+}
+
+/// Check that the text of `data` from `start` to `end` can be modified, or if
+/// they are equal, that text can be inserted at `start`. Inserted text cannot
+/// inherit a `read-only` property. `inhibit` is the value of
+/// `inhibit-read-only`. See `verify_interval_modification` in Emacs.
+pub(crate) fn verify_modification(
+    data: &mut BufferData,
+    start: usize,
+    end: usize,
+    inhibit: Object,
+) -> Result<()> {
+    if !inhibit.is_nil() && !matches!(inhibit.untag(), ObjectType::Cons(_)) {
+        return Ok(());
+    }
+    let tree = data.textprops_with_lifetime();
+    let read_only = sym::READ_ONLY.into();
+    if start < end {
+        for (_, plist) in tree.iter(start, end) {
+            check_read_only(textget(plist, read_only)?, inhibit)?;
+        }
+        return Ok(());
+    }
+    let after = tree.find(start);
+    if let Some(node) = after {
+        if node.key.start < start {
+            // Text inserted inside of an interval is part of it
+            return check_read_only(textget(*node.val, read_only)?, inhibit);
+        }
+    }
+    if let Some(before) = start.checked_sub(1).and_then(|pos| tree.find(pos)) {
+        let plist = *before.val;
+        if !includes(textget(plist, sym::REAR_NONSTICKY.into())?, read_only) {
+            check_read_only(textget(plist, read_only)?, inhibit)?;
+        }
+    }
+    if let Some(after) = after {
+        let plist = *after.val;
+        if includes(textget(plist, sym::FRONT_STICKY.into())?, read_only) {
+            check_read_only(textget(plist, read_only)?, inhibit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Update the text properties of a buffer after the text from `start` to `end`
+/// was inserted. The properties after it are moved forward, and the new text
+/// gets the properties of `inserted` if it is a string. If `inherit` is the
+/// value of `text-property-default-nonsticky`, the new text also inherits the
+/// sticky properties of the text around it, which take precedence over its
+/// own.
+pub(crate) fn insert_textprops<'ob>(
+    data: &mut BufferData,
+    start: usize,
+    end: usize,
+    inserted: Object<'ob>,
+    inherit: Option<Object<'ob>>,
+    cx: &'ob Context,
+) -> Result<()> {
+    let tree = data.textprops_with_lifetime();
+    let inherited = match inherit {
+        Some(nonsticky) => match tree.find(start) {
+            // Text inserted inside of an interval inherits all of its properties
+            Some(node) if node.key.start < start => fns::copy_sequence(*node.val, cx)?,
+            after => {
+                let before = start.checked_sub(1).and_then(|pos| tree.find(pos));
+                let left = before.map_or(NIL, |node| *node.val);
+                let right = after.map_or(NIL, |node| *node.val);
+                sticky_properties(left, right, nonsticky, cx)?
+            }
+        },
+        None => NIL,
+    };
+    tree.tree.advance(start, end - start);
+    if !inherited.is_nil() || tree.tree.find_intersect_min(start..end).is_some() {
+        tree.set_properties(start, end, inherited);
+        tree.clean();
+    }
+    if let ObjectType::String(string) = inserted.untag() {
+        if let Some(props) = string.textprops() {
+            if inherited.is_nil() {
+                props.copy_into(0, string.len(), tree, start, cx)?;
+            } else {
+                for (range, plist) in props.iter(0, string.len()) {
+                    if plist.is_nil() {
+                        continue;
+                    }
+                    let own = fns::copy_sequence(plist, cx)?;
+                    let inherited = fns::copy_sequence(inherited, cx)?;
+                    let plist =
+                        add_properties(inherited, own, PropertySetType::Replace, false, cx)?;
+                    tree.set_properties(start + range.start, start + range.end, plist);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether two strings with the same contents also have the same text
+/// properties. Property values are compared with `eq`, and the order of the
+/// properties does not matter.
+pub(crate) fn string_textprops_equal<'ob>(
+    s1: &'ob LispString,
+    s2: &'ob LispString,
+) -> Result<bool> {
+    let (props1, props2) = (s1.textprops(), s2.textprops());
+    let len = s1.len();
+    let mut boundaries: Vec<usize> = [&props1, &props2]
+        .into_iter()
+        .flatten()
+        .flat_map(|props| props.iter(0, len).map(|(range, _)| range.start))
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    let plist_at = |props: &Option<Ref<IntervalTree<'ob>>>, pos| {
+        props.as_ref().and_then(|props| props.find(pos)).map_or(NIL, |node| *node.val)
+    };
+    for pos in boundaries {
+        let (plist1, plist2) = (plist_at(&props1, pos), plist_at(&props2, pos));
+        let (Ok(list1), Ok(list2)) = (plist1.as_list(), plist2.as_list()) else {
+            return Ok(false);
+        };
+        if list1.count() != list2.count() {
+            return Ok(false);
+        }
+        let mut iter = plist1.as_list()?;
+        while let Some(key) = iter.next() {
+            let value = iter.next().unwrap_or(Ok(NIL))?;
+            if !eq(textget(plist2, key?)?, value) {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
 /// Return the list of properties of the character at POSITION in OBJECT.
 /// If the optional second argument OBJECT is a buffer (or nil, which means
 /// the current buffer), POSITION is a buffer position (integer or marker).
@@ -125,24 +489,17 @@ fn modify_buffer_data<'ob, T>(
 pub fn text_properties_at<'ob>(
     position: usize,
     object: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    let tree = if eq(object, NIL) {
-        &env.current_buffer.get().textprops
-    } else {
-        let obj = object.untag();
-        match obj {
-            ObjectType::Buffer(buf) => &buf.lock().unwrap().textprops,
-            ObjectType::String(_str) => {
-                todo!()
-            }
-            _ => {
-                bail!(TypeError::new(Type::BufferOrString, obj))
-            }
+    if let ObjectType::String(string) = object.untag() {
+        if position > string.len() {
+            bail!(SignalError::args_out_of_range(&[position.into()]));
         }
-    };
-    let a = tree.find(position).map(|a| *a.val).unwrap_or(NIL);
-    Ok(unsafe { a.with_lifetime() })
+    }
+    modify_textprops(object, env, cx, |props| {
+        Ok(props.tree.find(position).map(|a| *a.val).unwrap_or(NIL))
+    })
 }
 
 /// Return the value of POSITION's property PROP, in OBJECT.
@@ -158,9 +515,10 @@ pub fn get_text_property<'ob>(
     position: usize,
     prop: Object<'ob>,
     object: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    let props = text_properties_at(position, object, env)?;
+    let props = text_properties_at(position, object, env, cx)?;
     // TODO see lookup_char_property, should also lookup
     // 1. category
     // 2. char_property_alias_alist
@@ -168,26 +526,57 @@ pub fn get_text_property<'ob>(
     textget(props, prop)
 }
 
+/// Like `get-char-property', but with extra overlay information.
+/// The value is a cons cell.  Its car is the return value of `get-char-property'
+/// with the same arguments--that is, the value of POSITION's property
+/// PROP in OBJECT.  Its cdr is the overlay in which the property was
+/// found, or nil, if it was found as a text property or not found at all.
 #[defun]
-#[allow(unused)]
 pub fn get_char_property_and_overlay<'ob>(
     position: usize,
     prop: Object<'ob>,
     object: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    todo!()
+    let buffer = match object.untag() {
+        ObjectType::Buffer(buffer) => Some(buffer),
+        ObjectType::NIL => Some(env.current_buffer.get().lisp_buffer(cx)),
+        _ => None,
+    };
+    if let Some(buffer) = buffer {
+        let mut overlays = overlay::overlays_at_pos(position as i64, buffer, env, cx)?;
+        overlay::sort_overlays(&mut overlays);
+        for overlay in overlays {
+            let value = overlay::overlay_get(overlay, prop)?;
+            if value != NIL {
+                return Ok(Cons::new(value, overlay, cx).into());
+            }
+        }
+    }
+    let value = get_text_property(position, prop, object, env, cx)?;
+    Ok(Cons::new(value, NIL, cx).into())
 }
 
+/// Return the value of POSITION's property PROP, in OBJECT.
+/// Both overlay properties and text properties are checked.
+/// OBJECT is optional and defaults to the current buffer.
+/// If POSITION is at the end of OBJECT, the value is nil.
+/// If OBJECT is a buffer, then overlay properties are considered as well as
+/// text properties.
+/// If OBJECT is a window, then that window's buffer is used, but window-specific
+/// overlays are considered only if they are associated with OBJECT.
 #[defun]
-#[allow(unused)]
 pub fn get_char_property<'ob>(
     position: usize,
     prop: Object<'ob>,
     object: Object<'ob>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    todo!()
+    let value = get_char_property_and_overlay(position, prop, object, env, cx)?;
+    let ObjectType::Cons(value) = value.untag() else { unreachable!() };
+    Ok(value.car())
 }
 
 // TODO also missing `next-char-property-change` and 3 other similar functions.
@@ -210,9 +599,9 @@ pub fn put_text_property<'ob>(
 ) -> Result<()> {
     let prop = list!(property, value; cx);
     let prop = Slot::new(prop);
-    modify_buffer_data(object, env, |data| {
-        let tree = &mut data.textprops_with_lifetime();
-        tree.insert(start, end, prop, cx);
+    modify_textprops(object, env, cx, |props| {
+        props.validate_region(start, end)?;
+        props.tree.insert(start, end, prop, cx);
         Ok(())
     })
 }
@@ -233,13 +622,12 @@ pub fn next_property_change<'ob>(
     position: usize,
     object: Object<'ob>,
     limit: Option<usize>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let point_max = data.text.len_chars() + 1;
-        let end = limit.unwrap_or(point_max);
-        let tree = data.textprops_with_lifetime();
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        let end = limit.unwrap_or(props.max);
+        let tree = props.tree;
         // NOTE this can be optimized
         tree.clean();
         let prop = tree.tree.find_intersect_min(position..end);
@@ -287,13 +675,12 @@ pub fn next_single_property_change<'ob>(
     prop: Object<'ob>,
     object: Object<'ob>,
     limit: Option<usize>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let point_max = data.text.len_chars() + 1;
-        let end = limit.unwrap_or(point_max);
-        let tree = data.textprops_with_lifetime();
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        let end = limit.unwrap_or(props.max);
+        let tree = props.tree;
         // NOTE this can be optimized
         tree.clean();
         let iter = tree.iter(position, end);
@@ -333,14 +720,13 @@ pub fn previous_property_change<'ob>(
     position: usize,
     object: Object<'ob>,
     limit: Option<usize>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let point_min = 1;
-        let start = limit.unwrap_or(point_min);
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        let start = limit.unwrap_or(props.min);
         let end = position;
-        let tree = data.textprops_with_lifetime();
+        let tree = props.tree;
         // NOTE this can be optimized
         tree.clean();
         let prop = tree.tree.find_intersect_max(start..end);
@@ -392,13 +778,12 @@ pub fn previous_single_property_change<'ob>(
     prop: Object<'ob>,
     object: Object<'ob>,
     limit: Option<usize>,
-    env: &'ob mut Rt<Env>,
+    env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let point_min = 1;
-        let start = limit.unwrap_or(point_min);
-        let tree = data.textprops_with_lifetime();
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        let start = limit.unwrap_or(props.min);
+        let tree = props.tree;
         // NOTE this can be optimized
         tree.clean();
         let iter = tree.iter_reverse(start, position);
@@ -435,10 +820,11 @@ pub fn set_text_properties<'ob>(
     properties: Object<'ob>,
     object: Object<'ob>,
     env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<()> {
-    modify_buffer_data(object, env, |data| -> Result<()> {
-        let tree = data.textprops_with_lifetime();
-        tree.set_properties(start, end, properties);
+    modify_textprops(object, env, cx, |props| -> Result<()> {
+        props.validate_region(start, end)?;
+        props.tree.set_properties(start, end, properties);
         Ok(())
     })
 }
@@ -462,9 +848,9 @@ pub fn remove_text_properties<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<()> {
-    modify_buffer_data(object, env, |data| -> Result<()> {
-        let tree = data.textprops_with_lifetime();
-        tree.delete(start, end, list![properties; cx])
+    modify_textprops(object, env, cx, |props| -> Result<()> {
+        props.validate_region(start, end)?;
+        props.tree.delete(start, end, list![properties; cx])
     })
 }
 
@@ -481,10 +867,11 @@ pub fn remove_list_of_text_properties<'ob>(
     list_of_properties: Object<'ob>,
     object: Object<'ob>,
     env: &mut Rt<Env>,
+    cx: &'ob Context,
 ) -> Result<()> {
-    modify_buffer_data(object, env, |data| -> Result<()> {
-        let tree = data.textprops_with_lifetime();
-        tree.delete(start, end, list_of_properties)
+    modify_textprops(object, env, cx, |props| -> Result<()> {
+        props.validate_region(start, end)?;
+        props.tree.delete(start, end, list_of_properties)
     })
 }
 
@@ -504,9 +891,9 @@ pub fn text_properties_any<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let tree = data.textprops_with_lifetime();
-        let iter = tree.iter(start, end);
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        props.validate_region(start, end)?;
+        let iter = props.tree.iter(start, end);
         for (interval, props) in iter {
             let val = textget(props, property)?;
             if !eq(val, value) {
@@ -533,9 +920,9 @@ pub fn text_properties_not_all<'ob>(
     env: &mut Rt<Env>,
     cx: &'ob Context,
 ) -> Result<Object<'ob>> {
-    modify_buffer_data(object, env, |data| -> Result<Object<'ob>> {
-        let tree = data.textprops_with_lifetime();
-        let iter = tree.iter(start, end);
+    modify_textprops(object, env, cx, |props| -> Result<Object<'ob>> {
+        props.validate_region(start, end)?;
+        let iter = props.tree.iter(start, end);
         for (interval, props) in iter {
             let val = textget(props, property)?;
             if eq(val, value) {
@@ -546,6 +933,32 @@ pub fn text_properties_not_all<'ob>(
     })
 }
 
+/// Return a copy of STRING with text properties added.
+/// First argument is the string to copy.
+/// Remaining arguments form a sequence of PROPERTY VALUE pairs for text
+/// properties to add to the result.
+#[defun]
+pub(crate) fn propertize<'ob>(
+    string: &'ob LispString,
+    properties: &[Object<'ob>],
+    cx: &'ob Context,
+) -> Result<Object<'ob>> {
+    if !properties.len().is_multiple_of(2) {
+        let nargs = properties.len() as i64 + 1;
+        let error = list![sym::WRONG_NUMBER_OF_ARGUMENTS, sym::PROPERTIZE, nargs; cx];
+        bail!(LispError::new(error.try_into()?));
+    }
+    let len = string.len();
+    let new: Gc<&LispString> = string.inner().into_obj(cx);
+    let new = new.untag();
+    copy_string_textprops(string, 0, len, new, 0, cx)?;
+    if !properties.is_empty() {
+        let plist = fns::slice_into_list(properties, None, cx);
+        new.textprops_mut(cx)?.insert(0, len, Slot::new(plist), cx);
+    }
+    Ok(new.into())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
@@ -626,7 +1039,7 @@ mod tests {
         let change = next_property_change(20, buf, None, env, cx)?;
         assert!(change.is_nil()); // No changes after last property
 
-        BUFFERS.lock().unwrap().clear();
+        BUFFERS.lock().unwrap().remove("test_next_property_change");
         Ok(())
     }
 
@@ -688,7 +1101,7 @@ mod tests {
         let change = next_single_property_change(10, b, buf, None, env, cx)?;
         assert_eq!(change, cx.add(15)); // Change at end of b range
 
-        BUFFERS.lock().unwrap().clear();
+        BUFFERS.lock().unwrap().remove("test_next_single_property_change");
         Ok(())
     }
 
@@ -717,18 +1130,18 @@ mod tests {
         remove_text_properties(1, 10, a, buf, env, cx)?;
 
         // Verify :a was removed
-        let props = text_properties_at(3, buf, env)?;
+        let props = text_properties_at(3, buf, env, cx)?;
         assert!(props.is_nil());
 
         // Verify :b remains
-        let props = text_properties_at(5, buf, env)?;
+        let props = text_properties_at(5, buf, env, cx)?;
         let val = plist_get(props, b)?;
         assert_eq!(val, cx.add(2));
 
         // Try removing non-existent property
         remove_text_properties(0, 10, a, buf, env, cx)?;
 
-        BUFFERS.lock().unwrap().clear();
+        BUFFERS.lock().unwrap().remove("test_remove_text_properties");
         Ok(())
     }
 
@@ -740,17 +1153,107 @@ mod tests {
         root!(env, new(Env), cx);
 
         let buf = get_buffer_create(cx.add("test_text_properties_at"), None, cx)?;
-        let n = text_properties_at(0, buf, env)?;
+        let n = text_properties_at(0, buf, env, cx)?;
         assert!(n.is_nil());
 
         let a = intern(":a", cx);
         let a = cx.add(a);
         put_text_property(0, 1, a, cx.add(3), buf, env, cx)?;
-        let n = text_properties_at(0, buf, env)?;
+        let n = text_properties_at(0, buf, env, cx)?;
         let val = plist_get(n, a)?;
         assert!(eq(val, cx.add(3)));
 
-        BUFFERS.lock().unwrap().clear();
+        BUFFERS.lock().unwrap().remove("test_text_properties_at");
         Ok(())
     }
+
+    #[test]
+    fn test_narrowed_text_properties() -> Result<()> {
+        let roots = &RootSet::default();
+        let mut context = Context::new(roots);
+        let cx = &mut context;
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("lorem ipsum dolor");
+        env.current_buffer.get_mut().narrow(6, 11);
+
+        let a = intern(":a", cx);
+        let a = cx.add(a);
+        assert!(put_text_property(1, 8, a, cx.add(1), NIL, env, cx).is_err());
+        assert!(put_text_property(8, 13, a, cx.add(1), NIL, env, cx).is_err());
+        put_text_property(8, 10, a, cx.add(1), NIL, env, cx)?;
+        assert!(set_text_properties(1, 3, NIL, NIL, env, cx).is_err());
+
+        // The default limit is the end of the accessible region
+        let change = next_property_change(8, NIL, None, env, cx)?;
+        assert_eq!(change, cx.add(10));
+        let change = next_single_property_change(8, a, NIL, None, env, cx)?;
+        assert_eq!(change, cx.add(10));
+        let change = previous_property_change(10, NIL, None, env, cx)?;
+        assert_eq!(change, cx.add(8));
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_properties_across_collections() -> Result<()> {
+        let roots = &RootSet::default();
+        let mut context = Context::new(roots);
+        let cx = &mut context;
+        root!(env, new(Env), cx);
+        env.current_buffer.get_mut().text.insert("lorem ipsum dolor");
+        let a = cx.add(intern(":a", cx));
+        put_text_property(0, 5, a, cx.add("old"), NIL, env, cx)?;
+        // Promote the property list to the old generation
+        cx.garbage_collect(false);
+        for i in 0..20 {
+            // Overlapping intervals update the old property list in place
+            let a = cx.add(intern(":a", cx));
+            put_text_property(0, 5, a, cx.add(format!("young {i}")), NIL, env, cx)?;
+            cx.garbage_collect(i % 5 == 4);
+            for _ in 0..20 {
+                cx.add("garbage");
+            }
+            let a = cx.add(intern(":a", cx));
+            let val = get_text_property(2, a, NIL, env, cx)?;
+            assert_eq!(val, format!("young {i}").as_str());
+        }
+        Ok(())
+    }
+    #[test]
+    fn test_string_text_properties() -> Result<()> {
+        let roots = &RootSet::default();
+        let mut context = Context::new(roots);
+        let cx = &mut context;
+        root!(env, new(Env), cx);
+        let string = cx.add("lorem ipsum");
+        root!(string, cx);
+        let a = cx.add(intern(":a", cx));
+        put_text_property(0, 5, a, cx.add("young"), string.bind(cx), env, cx)?;
+        // Promoting the string moves its properties with it
+        cx.garbage_collect(false);
+        let a = cx.add(intern(":a", cx));
+        let val = get_text_property(2, a, string.bind(cx), env, cx)?;
+        assert_eq!(val, "young");
+        put_text_property(6, 11, a, cx.add("old"), string.bind(cx), env, cx)?;
+        cx.garbage_collect(true);
+        for _ in 0..20 {
+            cx.add("garbage");
+        }
+        let a = cx.add(intern(":a", cx));
+        let val = get_text_property(7, a, string.bind(cx), env, cx)?;
+        assert_eq!(val, "old");
+        let change = next_single_property_change(0, a, string.bind(cx), None, env, cx)?;
+        assert_eq!(change, cx.add(5));
+        assert!(put_text_property(0, 12, a, NIL, string.bind(cx), env, cx).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_propertize() {
+        use crate::interpreter::assert_lisp;
+        assert_lisp("(get-text-property 1 'face (propertize \"ab\" 'face 'bold))", "bold");
+        assert_lisp(
+            "(let ((s (propertize \"abc\" 'a 1))) (list (get-text-property 0 'b (propertize s 'b 2)) (get-text-property 0 'b s)))",
+            "(2 nil)",
+        );
+    }
 }